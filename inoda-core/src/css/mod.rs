@@ -10,11 +10,12 @@
 //! returns `Option<PropertyName>`; unrecognized property names are discarded
 //! during the cascade rather than falling back to a catch-all variant.
 //! Supports compound selectors, comma-separated lists, CSS inheritance for text
-//! properties, and shorthand expansion for `margin`, `padding`, and
+//! properties (see `ComputedStyle::inherit_from`), and shorthand expansion for `margin`, `padding`, `outline`, and
 //! `background`. Color parsing supports named colors, hex (3/4/6/8-digit),
 //! `rgb()`, `rgba()`, `hsl()`, and `hsla()`. Inline `style` attributes are
-//! parsed via `cssparser`'s `DeclarationParser` trait; `margin` and `padding`
-//! shorthands are expanded to longhands at parse time within `InlineStyleParser`.
+//! parsed via `cssparser`'s `DeclarationParser` trait. Stylesheet and inline
+//! declarations share `expand_declaration`, so shorthands are expanded to
+//! longhands at parse time on both paths.
 
 use cssparser::{
     AtRuleParser, DeclarationParser, ParserState, QualifiedRuleParser, RuleBodyItemParser,
@@ -59,6 +60,52 @@ pub struct ComplexSelector {
     pub last: CompoundSelector,
     pub ancestors: Vec<(Combinator, CompoundSelector)>,
     pub specificity: (u32, u32, u32),
+    /// Trailing `::before` / `::after`. Such selectors style the generated
+    /// node, never the element that `last` matches.
+    pub pseudo_element: Option<crate::dom::PseudoElement>,
+}
+
+impl std::fmt::Display for CompoundSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for part in &self.parts {
+            match part {
+                SimpleSelector::Tag(t) => f.write_str(t)?,
+                SimpleSelector::Class(c) => write!(f, ".{}", c)?,
+                SimpleSelector::Id(id) => write!(f, "#{}", id)?,
+                SimpleSelector::Attribute(k, None) => write!(f, "[{}]", k)?,
+                SimpleSelector::Attribute(k, Some(v)) => write!(f, "[{}=\"{}\"]", k, v)?,
+                SimpleSelector::PseudoClass(p) => write!(f, ":{}", p)?,
+                SimpleSelector::Universal => f.write_str("*")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Re-serializes the parsed selector (normalized spacing), e.g. for
+/// cascade traces.
+impl std::fmt::Display for ComplexSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // `ancestors` is nearest-first; each combinator joins its compound to
+        // the compound on its right.
+        for (comb, compound) in self.ancestors.iter().rev() {
+            let sep = match comb {
+                Combinator::Descendant => " ",
+                Combinator::Child => " > ",
+                Combinator::NextSibling => " + ",
+                Combinator::SubsequentSibling => " ~ ",
+            };
+            write!(f, "{}{}", compound, sep)?;
+        }
+        write!(f, "{}", self.last)?;
+        match self.pseudo_element {
+            Some(crate::dom::PseudoElement::Before) => f.write_str("::before"),
+            Some(crate::dom::PseudoElement::After) => f.write_str("::after"),
+            Some(crate::dom::PseudoElement::FirstLetter) => f.write_str("::first-letter"),
+            Some(crate::dom::PseudoElement::Marker) => f.write_str("::marker"),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -87,6 +134,17 @@ pub struct StyleSheet {
     pub by_tag: std::collections::HashMap<string_cache::DefaultAtom, Vec<IndexedRule>>,
     pub universal: Vec<IndexedRule>,
     pub next_rule_index: usize,
+    /// `@keyframes` blocks by name, steps sorted by offset. A later block
+    /// with the same name replaces an earlier one.
+    pub keyframes: std::collections::HashMap<String, std::rc::Rc<Vec<Keyframe>>>,
+}
+
+/// One step of an `@keyframes` block: `offset` is 0..=1 (`from` is 0,
+/// `to` is 1) and a selector list like `0%, 50%` yields one step each.
+#[derive(Debug, Clone)]
+pub struct Keyframe {
+    pub offset: f32,
+    pub declarations: Vec<Declaration>,
 }
 
 impl StyleSheet {
@@ -148,6 +206,31 @@ impl StyleSheet {
         }
     }
 
+    /// Append every rule of `other` after this sheet's rules, keeping
+    /// `other`'s relative source order, then re-sort the buckets.
+    pub fn extend_from(&mut self, other: &StyleSheet) {
+        let offset = self.next_rule_index;
+        fn shifted(rules: &[IndexedRule], offset: usize) -> impl Iterator<Item = IndexedRule> + '_ {
+            rules.iter().map(move |r| IndexedRule {
+                rule_index: r.rule_index + offset,
+                ..r.clone()
+            })
+        }
+        for (id, rules) in &other.by_id {
+            self.by_id.entry(id.clone()).or_default().extend(shifted(rules, offset));
+        }
+        for (class, rules) in &other.by_class {
+            self.by_class.entry(class.clone()).or_default().extend(shifted(rules, offset));
+        }
+        for (tag, rules) in &other.by_tag {
+            self.by_tag.entry(tag.clone()).or_default().extend(shifted(rules, offset));
+        }
+        self.universal.extend(shifted(&other.universal, offset));
+        self.next_rule_index += other.next_rule_index;
+        self.keyframes.extend(other.keyframes.iter().map(|(name, steps)| (name.clone(), steps.clone())));
+        self.sort_rules();
+    }
+
     pub fn sort_rules(&mut self) {
         let sort_fn = |a: &IndexedRule, b: &IndexedRule| {
             a.selector
@@ -322,15 +405,33 @@ pub fn parse_style_value(val: &str) -> crate::dom::StyleValue {
     if let Ok(num) = trimmed.parse::<f32>() {
         return crate::dom::StyleValue::Number(num);
     }
+    if let Some(s) = parse_string_literal(trimmed) {
+        return crate::dom::StyleValue::String(s);
+    }
+    if let Some(url) = parse_url(trimmed) {
+        return crate::dom::StyleValue::Url(url);
+    }
+    if let Some(math) = parse_math_function(trimmed) {
+        return math;
+    }
+    if let Some(t) = parse_transform(trimmed) {
+        return crate::dom::StyleValue::Transform(t);
+    }
 
     let known_keywords = [
-        "auto", "none", "block", "inline", "inline-block", "list-item", "flex", "grid",
-        "row", "column", "inherit",
+        "auto", "none", "block", "inline", "inline-block", "list-item", "flex", "grid", "contents",
+        "row", "column", "inherit", "initial", "unset", "revert",
         "absolute", "relative", "static", "fixed", "sticky",
         "hidden", "visible", "scroll", "clip",
         "center", "start", "end", "flex-start", "flex-end", "baseline", "stretch", 
         "space-between", "space-around", "space-evenly",
-        "wrap", "nowrap", "wrap-reverse"
+        "wrap", "nowrap", "wrap-reverse",
+        "solid", "dashed", "dotted", "double", "thin", "medium", "thick",
+        "normal", "break-all", "keep-all", "break-word", "anywhere",
+        "ltr", "rtl", "collapse", "disc", "circle", "decimal",
+        "uppercase", "lowercase", "capitalize", "left", "right", "justify",
+        "italic", "oblique", "bold", "bolder", "lighter",
+        "fill", "contain", "cover", "scale-down", "pre", "pre-wrap", "pre-line"
     ];
 
     if known_keywords.contains(&trimmed) {
@@ -344,18 +445,20 @@ pub fn parse_style_value(val: &str) -> crate::dom::StyleValue {
 // Shorthand expansion — shared by both stylesheet rules and inline styles
 // ---------------------------------------------------------------------------
 
-/// Expand `margin` or `padding` shorthand into longhand declarations.
-/// Pushes results into `declarations` to avoid extra allocation.
-fn expand_margin_padding_shorthand(
+/// Expand the four-sided `margin`, `padding`, `inset` or `border-width` shorthand into
+/// longhand declarations. Pushes results into `declarations` to avoid extra
+/// allocation.
+fn expand_box_shorthand(
     name_str: &str,
     value_trimmed: &str,
     declarations: &mut Vec<Declaration>,
 ) {
     let parts: Vec<&str> = value_trimmed.split_whitespace().collect();
-    let (top, right, bottom, left) = if name_str == "margin" {
-        ("margin-top", "margin-right", "margin-bottom", "margin-left")
-    } else {
-        ("padding-top", "padding-right", "padding-bottom", "padding-left")
+    let (top, right, bottom, left) = match name_str {
+        "margin" => ("margin-top", "margin-right", "margin-bottom", "margin-left"),
+        "inset" => ("top", "right", "bottom", "left"),
+        "border-width" => ("border-top-width", "border-right-width", "border-bottom-width", "border-left-width"),
+        _ => ("padding-top", "padding-right", "padding-bottom", "padding-left"),
     };
 
     match parts.len() {
@@ -396,19 +499,805 @@ fn expand_margin_padding_shorthand(
     }
 }
 
+/// Expand the `background` shorthand, a comma-separated list of layers. The
+/// first layer's `url()` becomes `background-image` (`none` without one, as
+/// only one image is kept) and a color in the last layer, the only one that
+/// may hold it, becomes `background-color`. Positions, sizes and repeat
+/// keywords are dropped.
+fn expand_background_shorthand(value_trimmed: &str, declarations: &mut Vec<Declaration>) {
+    use crate::dom::{PropertyName, StyleValue};
+    if matches!(value_trimmed, "inherit" | "initial" | "unset" | "revert") {
+        for name in [PropertyName::BackgroundColor, PropertyName::BackgroundImage] {
+            declarations.push(Declaration { name, value: parse_style_value(value_trimmed) });
+        }
+        return;
+    }
+    let layers = split_top_level(value_trimmed, |c| c == ',');
+    let components = |layer: &str| -> Vec<StyleValue> {
+        split_top_level(layer.trim(), char::is_whitespace).into_iter().map(parse_style_value).collect()
+    };
+    let image = components(layers[0]).into_iter().find(|v| matches!(v, StyleValue::Url(_)));
+    declarations.push(Declaration { name: PropertyName::BackgroundImage, value: image.unwrap_or(StyleValue::None) });
+    if let Some(color) = layers.last().map(|layer| components(layer)).and_then(|values| {
+        values.into_iter().find(|v| matches!(v, StyleValue::Color(..)))
+    }) {
+        declarations.push(Declaration { name: PropertyName::BackgroundColor, value: color });
+    }
+}
+
+/// Expand the `outline` shorthand. Components may appear in any order; each
+/// is classified as a style keyword, a color, or (otherwise) a width.
+fn expand_outline_shorthand(value_trimmed: &str, declarations: &mut Vec<Declaration>) {
+    for part in value_trimmed.split_whitespace() {
+        let name = match part {
+            "none" | "solid" | "dashed" | "dotted" | "double" => crate::dom::PropertyName::OutlineStyle,
+            _ if parse_color(part).is_some() => crate::dom::PropertyName::OutlineColor,
+            _ => crate::dom::PropertyName::OutlineWidth,
+        };
+        declarations.push(Declaration { name, value: parse_style_value(part) });
+    }
+}
+
+/// Expand `flex-flow` into `flex-direction` and `flex-wrap`. Each keyword is
+/// assigned by which set it belongs to, in either order; an omitted one is
+/// reset to its initial value. Unknown or repeated keywords drop the
+/// declaration.
+fn expand_flex_flow_shorthand(value_trimmed: &str, declarations: &mut Vec<Declaration>) {
+    use crate::dom::PropertyName;
+    if matches!(value_trimmed, "inherit" | "initial" | "unset" | "revert") {
+        let value = parse_style_value(value_trimmed);
+        declarations.push(Declaration { name: PropertyName::FlexDirection, value: value.clone() });
+        declarations.push(Declaration { name: PropertyName::FlexWrap, value });
+        return;
+    }
+    let (mut direction, mut wrap) = (None, None);
+    for part in value_trimmed.split_whitespace() {
+        let slot = match part {
+            "row" | "row-reverse" | "column" | "column-reverse" => &mut direction,
+            "nowrap" | "wrap" | "wrap-reverse" => &mut wrap,
+            _ => return,
+        };
+        if slot.replace(part).is_some() {
+            return;
+        }
+    }
+    if direction.is_none() && wrap.is_none() {
+        return;
+    }
+    declarations.push(Declaration {
+        name: PropertyName::FlexDirection,
+        value: parse_style_value(direction.unwrap_or("row")),
+    });
+    declarations.push(Declaration { name: PropertyName::FlexWrap, value: parse_style_value(wrap.unwrap_or("nowrap")) });
+}
+
+/// Expand a two-axis shorthand (`gap`, `overflow`, `place-*`). One value sets
+/// both longhands; two values set `first` then `second`. Other counts are
+/// ignored.
+fn expand_pair_shorthand(
+    value_trimmed: &str,
+    first: crate::dom::PropertyName,
+    second: crate::dom::PropertyName,
+    declarations: &mut Vec<Declaration>,
+) {
+    let parts: Vec<&str> = value_trimmed.split_whitespace().collect();
+    let (a, b) = match parts.as_slice() {
+        [v] => (*v, *v),
+        [a, b] => (*a, *b),
+        _ => return,
+    };
+    declarations.push(Declaration { name: first, value: parse_style_value(a) });
+    declarations.push(Declaration { name: second, value: parse_style_value(b) });
+}
+
+/// Turn one `name: value` pair into longhand declarations, expanding the
+/// supported shorthands. Returns false if the property is not recognized.
+fn expand_declaration(name_str: &str, value_trimmed: &str, declarations: &mut Vec<Declaration>) -> bool {
+    use crate::dom::PropertyName;
+    match name_str {
+        "margin" | "padding" | "inset" | "border-width" => expand_box_shorthand(name_str, value_trimmed, declarations),
+        "gap" => expand_pair_shorthand(value_trimmed, PropertyName::RowGap, PropertyName::ColumnGap, declarations),
+        "overflow" => {
+            expand_pair_shorthand(value_trimmed, PropertyName::OverflowX, PropertyName::OverflowY, declarations)
+        }
+        "place-content" => expand_pair_shorthand(
+            value_trimmed,
+            PropertyName::AlignContent,
+            PropertyName::JustifyContent,
+            declarations,
+        ),
+        "place-items" => expand_pair_shorthand(
+            value_trimmed,
+            PropertyName::AlignItems,
+            PropertyName::JustifyItems,
+            declarations,
+        ),
+        "background" => expand_background_shorthand(value_trimmed, declarations),
+        "outline" => expand_outline_shorthand(value_trimmed, declarations),
+        "flex-flow" => expand_flex_flow_shorthand(value_trimmed, declarations),
+        // Only the type component of `list-style` is supported; position and
+        // image are dropped.
+        "list-style" => {
+            let ty = value_trimmed
+                .split_whitespace()
+                .find(|p| matches!(*p, "disc" | "circle" | "decimal" | "none" | "inherit" | "initial" | "unset" | "revert"));
+            if let Some(ty) = ty {
+                declarations.push(Declaration { name: PropertyName::ListStyleType, value: parse_style_value(ty) });
+            }
+        }
+        // `all` only takes a CSS-wide keyword, applied to every property. The
+        // spec's exclusions (`direction`, `unicode-bidi`, custom properties)
+        // are not properties here.
+        "all" => {
+            if matches!(value_trimmed, "inherit" | "initial" | "unset" | "revert") {
+                let value = parse_style_value(value_trimmed);
+                declarations.extend(PropertyName::ALL.iter().map(|&name| Declaration { name, value: value.clone() }));
+            }
+        }
+        // Only the final keyword of a `cursor` list is used; `url()` images
+        // before it are host-specific and dropped.
+        "cursor" => {
+            let last = value_trimmed.rsplit(',').next().unwrap_or("").trim();
+            if !last.is_empty() && last.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                declarations.push(Declaration {
+                    name: PropertyName::Cursor,
+                    value: crate::dom::StyleValue::Keyword(string_cache::DefaultAtom::from(last.to_ascii_lowercase())),
+                });
+            }
+        }
+        // Keyframes names are author identifiers, so they are kept as strings
+        // rather than interned keywords.
+        "animation-name" => {
+            let value = match parse_string_literal(value_trimmed) {
+                Some(name) => crate::dom::StyleValue::String(name),
+                None if matches!(value_trimmed, "none" | "inherit" | "initial" | "unset" | "revert") => {
+                    parse_style_value(value_trimmed)
+                }
+                None if !value_trimmed.is_empty()
+                    && value_trimmed.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') =>
+                {
+                    crate::dom::StyleValue::String(value_trimmed.to_string())
+                }
+                None => return true,
+            };
+            declarations.push(Declaration { name: PropertyName::AnimationName, value });
+        }
+        "animation-duration" => {
+            let value = match parse_time(value_trimmed) {
+                Some(secs) => crate::dom::StyleValue::Number(secs),
+                None => parse_style_value(value_trimmed),
+            };
+            declarations.push(Declaration { name: PropertyName::AnimationDuration, value });
+        }
+        "transition" => declarations.push(Declaration {
+            name: PropertyName::Transition,
+            value: parse_transitions(value_trimmed).unwrap_or_else(|| parse_style_value(value_trimmed)),
+        }),
+        "box-shadow" => {
+            let value = match parse_box_shadows(value_trimmed) {
+                Some(value) => value,
+                None if matches!(value_trimmed, "inherit" | "initial" | "unset" | "revert") => {
+                    parse_style_value(value_trimmed)
+                }
+                None => return true,
+            };
+            declarations.push(Declaration { name: PropertyName::BoxShadow, value });
+        }
+        "content" => declarations.push(Declaration {
+            name: PropertyName::Content,
+            value: parse_content(value_trimmed).unwrap_or_else(|| parse_style_value(value_trimmed)),
+        }),
+        "counter-reset" | "counter-increment" => {
+            let (name, default) = if name_str == "counter-reset" {
+                (PropertyName::CounterReset, 0)
+            } else {
+                (PropertyName::CounterIncrement, 1)
+            };
+            let value = match parse_counters(value_trimmed, default) {
+                Some(value) => value,
+                None if matches!(value_trimmed, "inherit" | "initial" | "unset" | "revert") => {
+                    parse_style_value(value_trimmed)
+                }
+                None => return true,
+            };
+            declarations.push(Declaration { name, value });
+        }
+        "font-family" => declarations.push(Declaration {
+            name: PropertyName::FontFamily,
+            value: parse_font_family(value_trimmed).unwrap_or_else(|| parse_style_value(value_trimmed)),
+        }),
+        _ => match crate::dom::PropertyName::from_str(name_str) {
+            Some(name) => declarations.push(Declaration { name, value: parse_style_value(value_trimmed) }),
+            None => return false,
+        },
+    }
+    true
+}
+
+/// Parse a `transition` list such as `width 200ms, color 1s linear`. The
+/// first time in an entry is its duration and a second (the delay) is
+/// ignored, as are timing functions. Shorthand property names are not
+/// recognized and make the whole list invalid.
+fn parse_transitions(value: &str) -> Option<crate::dom::StyleValue> {
+    if value == "none" {
+        return Some(crate::dom::StyleValue::Transitions(std::sync::Arc::from([])));
+    }
+    let mut specs = Vec::new();
+    for entry in split_top_level(value, |c| c == ',') {
+        let mut spec = crate::dom::TransitionSpec { property: None, duration: 0.0 };
+        let mut seen_time = false;
+        for part in entry.split_whitespace() {
+            if let Some(secs) = parse_time(part) {
+                if !seen_time {
+                    spec.duration = secs;
+                    seen_time = true;
+                }
+            } else if let Some(property) = crate::dom::PropertyName::from_str(part) {
+                spec.property = Some(property);
+            } else if !(part == "all"
+                || matches!(part, "linear" | "ease" | "ease-in" | "ease-out" | "ease-in-out" | "step-start" | "step-end")
+                || part.starts_with("cubic-bezier(")
+                || part.starts_with("steps("))
+            {
+                return None;
+            }
+        }
+        specs.push(spec);
+    }
+    Some(crate::dom::StyleValue::Transitions(specs.into()))
+}
+
+/// Split `value` at each char matching `is_sep` outside parentheses and
+/// quoted strings, so `rgba(0, 0, 0, 0.5)` and `"a,b"` stay one piece.
+fn split_top_level(value: &str, is_sep: impl Fn(char) -> bool) -> Vec<&str> {
+    let (mut start, mut depth) = (0, 0);
+    let mut quote = None;
+    let mut escaped = false;
+    let mut pieces = Vec::new();
+    for (i, c) in value.char_indices() {
+        if let Some(q) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                _ if c == q => quote = None,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ if depth == 0 && is_sep(c) => {
+                pieces.push(&value[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    pieces.push(&value[start..]);
+    pieces
+}
+
+/// Parse a `box-shadow` list such as `2px 2px 4px black, inset 0 0 1px
+/// #0003`. Each entry takes two to four px lengths (offsets, blur,
+/// spread), an optional color and an optional `inset`; the lengths must be
+/// adjacent and blur may not be negative.
+fn parse_box_shadows(value: &str) -> Option<crate::dom::StyleValue> {
+    fn length(part: &str) -> Option<f32> {
+        match part.strip_suffix("px") {
+            Some(n) => n.parse().ok(),
+            None => part.parse::<f32>().ok().filter(|n| *n == 0.0),
+        }
+    }
+
+    if value == "none" {
+        return Some(crate::dom::StyleValue::BoxShadows(std::sync::Arc::from([])));
+    }
+    let mut shadows = Vec::new();
+    for entry in split_top_level(value, |c| c == ',') {
+        let mut lengths = Vec::new();
+        let (mut color, mut inset, mut lengths_done) = (None, false, false);
+        for part in split_top_level(entry.trim(), char::is_whitespace) {
+            if part.is_empty() {
+                continue;
+            }
+            if let Some(px) = length(part) {
+                if lengths_done || lengths.len() == 4 {
+                    return None;
+                }
+                lengths.push(px);
+                continue;
+            }
+            lengths_done = !lengths.is_empty();
+            if part == "inset" && !inset {
+                inset = true;
+            } else if color.is_none() {
+                color = Some(parse_color(part)?);
+            } else {
+                return None;
+            }
+        }
+        let (offset_x, offset_y, blur, spread) = match lengths[..] {
+            [x, y] => (x, y, 0.0, 0.0),
+            [x, y, blur] => (x, y, blur, 0.0),
+            [x, y, blur, spread] => (x, y, blur, spread),
+            _ => return None,
+        };
+        if blur < 0.0 {
+            return None;
+        }
+        shadows.push(crate::dom::BoxShadow { offset_x, offset_y, blur, spread, color, inset });
+    }
+    Some(crate::dom::StyleValue::BoxShadows(shadows.into()))
+}
+
+/// Parse a `content` list that uses `counter()` or `counters()`, such as
+/// `"Section " counter(h2) ". "`. Lists of plain strings (and anything
+/// else) return `None` and go through `parse_style_value`. A counter style
+/// argument is accepted but ignored: counters always print as decimals.
+fn parse_content(value: &str) -> Option<crate::dom::StyleValue> {
+    let mut input = ParserInput::new(value);
+    let mut parser = Parser::new(&mut input);
+    let mut items = Vec::new();
+    while !parser.is_exhausted() {
+        let item = match parser.next().ok()?.clone() {
+            Token::QuotedString(text) => crate::dom::ContentItem::Text(text.to_string()),
+            Token::Function(function) if function.eq_ignore_ascii_case("counter") => parser
+                .parse_nested_block(|p| {
+                    let name = p.expect_ident()?.to_string();
+                    if p.try_parse(|p| p.expect_comma()).is_ok() {
+                        p.expect_ident()?;
+                    }
+                    Ok::<_, cssparser::ParseError<()>>(crate::dom::ContentItem::Counter(name))
+                })
+                .ok()?,
+            Token::Function(function) if function.eq_ignore_ascii_case("counters") => parser
+                .parse_nested_block(|p| {
+                    let name = p.expect_ident()?.to_string();
+                    p.expect_comma()?;
+                    let separator = p.expect_string()?.to_string();
+                    if p.try_parse(|p| p.expect_comma()).is_ok() {
+                        p.expect_ident()?;
+                    }
+                    Ok::<_, cssparser::ParseError<()>>(crate::dom::ContentItem::Counters(name, separator))
+                })
+                .ok()?,
+            _ => return None,
+        };
+        items.push(item);
+    }
+    items
+        .iter()
+        .any(|item| !matches!(item, crate::dom::ContentItem::Text(_)))
+        .then(|| crate::dom::StyleValue::Content(items.into()))
+}
+
+/// Parse `counter-reset` / `counter-increment`: `none`, or counter names
+/// each optionally followed by an integer (`default` when omitted).
+fn parse_counters(value: &str, default: i32) -> Option<crate::dom::StyleValue> {
+    if value == "none" {
+        return Some(crate::dom::StyleValue::Counters(std::sync::Arc::from([])));
+    }
+    let mut counters: Vec<(String, i32)> = Vec::new();
+    for part in value.split_whitespace() {
+        match part.parse::<i32>() {
+            Ok(n) => counters.last_mut()?.1 = n,
+            Err(_) if !matches!(part, "none" | "inherit" | "initial" | "unset" | "revert")
+                && part.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+                && !part.starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                counters.push((part.to_string(), default));
+            }
+            Err(_) => return None,
+        }
+    }
+    (!counters.is_empty()).then(|| crate::dom::StyleValue::Counters(counters.into()))
+}
+
+/// A CSS `<time>` (`2s`, `150ms`) in seconds.
+fn parse_time(value: &str) -> Option<f32> {
+    let (number, scale) = match value.strip_suffix("ms") {
+        Some(n) => (n, 0.001),
+        None => (value.strip_suffix('s')?, 1.0),
+    };
+    number.parse::<f32>().ok().filter(|n| *n >= 0.0).map(|n| n * scale)
+}
+
+/// Re-serialize the tokens of one declaration value (up to `;` or the end of
+/// the block), keeping whitespace so shorthand components stay separated.
+fn collect_value_text<'i, 't>(input: &mut Parser<'i, 't>) -> String {
+    let mut value = String::new();
+    push_value_tokens(input, &mut value, true);
+    value
+}
+
+fn push_quoted(value: &mut String, s: &str) {
+    value.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            value.push('\\');
+        }
+        value.push(c);
+    }
+    value.push('"');
+}
+
+/// Appends the tokens of a declaration value to `value`. Function arguments
+/// (`rgb(...)`, `translate(...)`) are written without whitespace so the
+/// shorthand expanders can keep splitting the top level on spaces.
+fn push_value_tokens<'i, 't>(input: &mut Parser<'i, 't>, value: &mut String, top_level: bool) {
+    while let Ok(token) = input.next_including_whitespace() {
+        match token {
+            Token::Semicolon => break,
+            Token::Function(name) => {
+                value.push_str(name);
+                value.push('(');
+                let _ = input.parse_nested_block(|p| {
+                    push_value_tokens(p, value, false);
+                    Ok::<(), cssparser::ParseError<()>>(())
+                });
+                value.push(')');
+            }
+            Token::Ident(n) => value.push_str(n),
+            Token::Number { value: v, .. } => value.push_str(&v.to_string()),
+            Token::Dimension { value: v, unit, .. } => {
+                value.push_str(&v.to_string());
+                value.push_str(unit.as_ref());
+            }
+            Token::Percentage { unit_value, .. } => {
+                value.push_str(&(unit_value * 100.0).to_string());
+                value.push('%');
+            }
+            Token::Hash(s) | Token::IDHash(s) => {
+                value.push('#');
+                value.push_str(s);
+            }
+            Token::QuotedString(s) => push_quoted(value, s),
+            // `url(a.png)` is a single token; it is written quoted so
+            // `parse_url` reads it like `url("a.png")`.
+            Token::UnquotedUrl(s) => {
+                value.push_str("url(");
+                push_quoted(value, s);
+                value.push(')');
+            }
+            Token::WhiteSpace(_) if top_level => value.push(' '),
+            Token::Comma => value.push(','),
+            Token::Delim(c) => value.push(*c),
+            _ => {}
+        }
+    }
+}
+
+/// Parse a comma-separated `font-family` list. Entries are quoted strings,
+/// generic family keywords, or runs of identifiers (`Times New Roman`).
+/// Returns `None` for CSS-wide keywords and malformed lists.
+fn parse_font_family(val: &str) -> Option<crate::dom::StyleValue> {
+    use crate::dom::FontFamilyName;
+
+    // Split at commas outside quotes.
+    let mut entries = Vec::new();
+    let (mut start, mut quote, mut escaped) = (0, None, false);
+    for (i, c) in val.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), _) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, ',') => {
+                entries.push(&val[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(&val[start..]);
+
+    let mut families = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let entry = entry.trim();
+        if let Some(name) = parse_string_literal(entry) {
+            families.push(FontFamilyName::Named(name));
+            continue;
+        }
+        let words: Vec<&str> = entry.split_whitespace().collect();
+        if words.is_empty() || words.iter().any(|w| w.contains(['"', '\'', '(', ')'])) {
+            return None;
+        }
+        families.push(match words.as_slice() {
+            ["serif"] => FontFamilyName::Serif,
+            ["sans-serif"] => FontFamilyName::SansSerif,
+            ["monospace"] => FontFamilyName::Monospace,
+            ["cursive"] => FontFamilyName::Cursive,
+            ["fantasy"] => FontFamilyName::Fantasy,
+            ["inherit" | "initial" | "unset"] => return None,
+            _ => FontFamilyName::Named(words.join(" ")),
+        });
+    }
+    Some(crate::dom::StyleValue::FontFamily(families.into()))
+}
+
+/// Parse `min(...)`, `max(...)` or `clamp(min, preferred, max)`. Arguments
+/// are lengths, percentages, a unitless 0, or nested comparison functions.
+fn parse_math_function(val: &str) -> Option<crate::dom::StyleValue> {
+    use crate::dom::{MathFunction, StyleValue};
+
+    let open = val.find('(')?;
+    let function = match &val[..open] {
+        "min" => MathFunction::Min,
+        "max" => MathFunction::Max,
+        "clamp" => MathFunction::Clamp,
+        _ => return None,
+    };
+    let inner = val[open + 1..].strip_suffix(')')?;
+
+    // Split at commas outside nested parentheses.
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0_u32, 0);
+    for (i, c) in inner.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                parts.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&inner[start..]);
+
+    let mut args = Vec::with_capacity(parts.len());
+    for part in parts {
+        let arg = parse_style_value(part);
+        match arg {
+            StyleValue::LengthPx(_)
+            | StyleValue::Percent(_)
+            | StyleValue::ViewportWidth(_)
+            | StyleValue::ViewportHeight(_)
+            | StyleValue::Em(_)
+            | StyleValue::Rem(_)
+            | StyleValue::Math(..) => args.push(arg),
+            StyleValue::Number(0.0) => args.push(StyleValue::LengthPx(0.0)),
+            _ => return None,
+        }
+    }
+    match (function, args.len()) {
+        (MathFunction::Clamp, 3) | (MathFunction::Min | MathFunction::Max, 1..) => {
+            Some(StyleValue::Math(function, args.into()))
+        }
+        _ => None,
+    }
+}
+
+/// Parse a `transform` function list such as `translate(10px,5px) scale(2)`.
+/// Supports `translate`, `translateX`/`Y`, `scale` and `scaleX`/`Y`; lengths
+/// must be px (or a unitless 0). Returns `None` if any function is unsupported.
+fn parse_transform(val: &str) -> Option<crate::dom::Transform> {
+    fn length(arg: &str) -> Option<f32> {
+        match arg.strip_suffix("px") {
+            Some(n) => n.parse().ok(),
+            None => arg.parse::<f32>().ok().filter(|n| *n == 0.0),
+        }
+    }
+
+    let mut transform = crate::dom::Transform::IDENTITY;
+    let mut rest = val.trim();
+    if !rest.ends_with(')') {
+        return None;
+    }
+    while !rest.is_empty() {
+        let open = rest.find('(')?;
+        let close = open + rest[open..].find(')')?;
+        let name = rest[..open].trim();
+        let args: Vec<&str> = rest[open + 1..close].split(',').map(str::trim).collect();
+        let step = match (name, args.as_slice()) {
+            ("translate", [x]) => (length(x)?, 0.0, 1.0, 1.0),
+            ("translate", [x, y]) => (length(x)?, length(y)?, 1.0, 1.0),
+            ("translateX", [x]) => (length(x)?, 0.0, 1.0, 1.0),
+            ("translateY", [y]) => (0.0, length(y)?, 1.0, 1.0),
+            ("scale", [s]) => {
+                let s = s.parse().ok()?;
+                (0.0, 0.0, s, s)
+            }
+            ("scale", [sx, sy]) => (0.0, 0.0, sx.parse().ok()?, sy.parse().ok()?),
+            ("scaleX", [s]) => (0.0, 0.0, s.parse().ok()?, 1.0),
+            ("scaleY", [s]) => (0.0, 0.0, 1.0, s.parse().ok()?),
+            _ => return None,
+        };
+        // Functions apply right to left, so each one is composed inside the
+        // transform accumulated so far.
+        transform = transform.then(&crate::dom::Transform {
+            translate_x: step.0,
+            translate_y: step.1,
+            scale_x: step.2,
+            scale_y: step.3,
+        });
+        rest = rest[close + 1..].trim_start();
+    }
+    Some(transform)
+}
+
+/// The URL of `url(...)`, quoted or not, with surrounding whitespace
+/// stripped. An unquoted URL may not contain whitespace or quotes.
+fn parse_url(val: &str) -> Option<String> {
+    let head = val.get(..4)?;
+    if !head.eq_ignore_ascii_case("url(") {
+        return None;
+    }
+    let inner = val[4..].strip_suffix(')')?.trim();
+    match parse_string_literal(inner) {
+        Some(url) => Some(url),
+        None if inner.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '(') => None,
+        None => Some(inner.to_string()),
+    }
+}
+
+/// Unquote a `"..."` or `'...'` literal, resolving backslash escapes.
+fn parse_string_literal(val: &str) -> Option<String> {
+    let quote = val.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let inner = val.strip_prefix(quote)?.strip_suffix(quote)?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            out.extend(chars.next());
+        } else {
+            out.push(c);
+        }
+    }
+    Some(out)
+}
+
 // ---------------------------------------------------------------------------
 // Selector parsing
 // ---------------------------------------------------------------------------
 
 /// Parse a raw selector string like `"div.card, #main"` into a Vec of ComplexSelectors.
-fn parse_selector_list(raw: &str) -> Vec<ComplexSelector> {
+/// Unparseable entries are dropped, so an invalid list yields an empty Vec.
+pub fn parse_selector_list(raw: &str) -> Vec<ComplexSelector> {
     raw.split(',')
         .map(|s| parse_complex_selector(s.trim()))
         .filter(|cs| !cs.last.parts.is_empty())
         .collect()
 }
 
+/// Split a trailing `::before` / `::after` / `::first-letter` (or legacy
+/// single-colon form) off a selector. A bare pseudo-element such as `::before` or `div > ::after`
+/// applies to any element, so `*` is substituted for the missing compound.
+fn split_pseudo_element(raw: &str) -> (std::borrow::Cow<'_, str>, Option<crate::dom::PseudoElement>) {
+    const SUFFIXES: [(&str, crate::dom::PseudoElement); 6] = [
+        ("::before", crate::dom::PseudoElement::Before),
+        ("::after", crate::dom::PseudoElement::After),
+        ("::first-letter", crate::dom::PseudoElement::FirstLetter),
+        (":before", crate::dom::PseudoElement::Before),
+        (":after", crate::dom::PseudoElement::After),
+        (":first-letter", crate::dom::PseudoElement::FirstLetter),
+    ];
+    for (suffix, pseudo) in SUFFIXES {
+        if let Some(rest) = raw.strip_suffix(suffix) {
+            let needs_universal = rest.is_empty()
+                || rest.ends_with(|c: char| c.is_whitespace() || c == '>' || c == '+' || c == '~');
+            let rest = if needs_universal {
+                std::borrow::Cow::Owned(format!("{}*", rest))
+            } else {
+                std::borrow::Cow::Borrowed(rest)
+            };
+            return (rest, Some(pseudo));
+        }
+    }
+    (std::borrow::Cow::Borrowed(raw), None)
+}
+
+/// Parse a single selector such as `div.card > span` into its AST, for
+/// tools that inspect selectors. `None` for an empty or malformed selector
+/// (a dangling combinator, or a compound that is not made of a tag, `*`,
+/// `#id`, `.class`, `:pseudo` and `[attr]` parts) or a comma-separated list
+/// (use [`parse_selector_list`] for those); commas inside parentheses or
+/// quotes, as in `:not(a, b)` or `[title="a,b"]`, do not make a list. The
+/// `Display` impl of [`ComplexSelector`] serializes the result back.
+pub fn parse_selector(raw: &str) -> Option<ComplexSelector> {
+    if split_top_level(raw, |c| c == ',').len() > 1 || !selector_is_well_formed(raw) {
+        return None;
+    }
+    Some(parse_complex_selector(raw))
+}
+
+/// Structural check behind [`parse_selector`]: compounds separated by single
+/// combinators, with none leading or trailing, and balanced brackets.
+fn selector_is_well_formed(raw: &str) -> bool {
+    let (raw, _) = split_pseudo_element(raw.trim());
+    let mut expect_compound = true;
+    let mut current = String::new();
+    let mut depth = 0i32;
+    let end_compound = |current: &mut String, expect_compound: &mut bool| {
+        if current.is_empty() {
+            return true;
+        }
+        let valid = compound_selector_is_well_formed(current);
+        current.clear();
+        *expect_compound = false;
+        valid
+    };
+    for ch in raw.chars() {
+        match ch {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth -= 1,
+            _ => {}
+        }
+        if depth < 0 {
+            return false;
+        }
+        if depth > 0 || matches!(ch, ']' | ')') {
+            current.push(ch);
+            continue;
+        }
+        if ch.is_whitespace() || matches!(ch, '>' | '+' | '~') {
+            if !end_compound(&mut current, &mut expect_compound) {
+                return false;
+            }
+            if !ch.is_whitespace() {
+                if expect_compound {
+                    return false;
+                }
+                expect_compound = true;
+            }
+            continue;
+        }
+        current.push(ch);
+    }
+    end_compound(&mut current, &mut expect_compound) && depth == 0 && !expect_compound
+}
+
+/// Whether `s` is a compound selector [`parse_compound_selector`] reads in
+/// full: an optional tag or `*`, then `#id`, `.class`, `:pseudo` (optionally
+/// with `(arguments)`) and `[attr]` parts with non-empty names.
+fn compound_selector_is_well_formed(s: &str) -> bool {
+    fn ident_len(s: &str) -> usize {
+        s.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()))
+            .unwrap_or(s.len())
+    }
+    let mut remaining = s;
+    if let Some(rest) = remaining.strip_prefix('*') {
+        remaining = rest;
+    } else {
+        remaining = &remaining[ident_len(remaining)..];
+    }
+    while let Some(prefix) = remaining.chars().next() {
+        remaining = &remaining[1..];
+        match prefix {
+            '#' | '.' | ':' => {
+                let len = ident_len(remaining);
+                if len == 0 {
+                    return false;
+                }
+                remaining = &remaining[len..];
+                if prefix == ':' && remaining.starts_with('(') {
+                    let end = simple_selector_end(remaining);
+                    if !remaining[..end].ends_with(')') {
+                        return false;
+                    }
+                    remaining = &remaining[end..];
+                }
+            }
+            '[' => {
+                let Some(end) = remaining.find(']') else {
+                    return false;
+                };
+                let name = remaining[..end].split(['=', '~', '|', '^', '$', '*']).next().unwrap_or("").trim();
+                if name.is_empty() || ident_len(name) != name.len() {
+                    return false;
+                }
+                remaining = &remaining[end + 1..];
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
 fn parse_complex_selector(raw: &str) -> ComplexSelector {
+    let (raw, pseudo_element) = split_pseudo_element(raw.trim());
     let mut list: Vec<(Combinator, String)> = Vec::new();
     let mut current = String::new();
     let mut next_combinator = Combinator::Descendant;
@@ -423,12 +1312,15 @@ fn parse_complex_selector(raw: &str) -> ComplexSelector {
         };
 
     let mut in_attr = false;
+    let mut paren_depth = 0usize;
 
     for ch in raw.trim().chars() {
         if ch == '[' { in_attr = true; current.push(ch); continue; }
         if ch == ']' { in_attr = false; current.push(ch); continue; }
+        if ch == '(' { paren_depth += 1; current.push(ch); continue; }
+        if ch == ')' { paren_depth = paren_depth.saturating_sub(1); current.push(ch); continue; }
 
-        if in_attr {
+        if in_attr || paren_depth > 0 {
             current.push(ch);
             continue;
         }
@@ -470,12 +1362,14 @@ fn parse_complex_selector(raw: &str) -> ComplexSelector {
             last: parse_compound_selector(""),
             ancestors: vec![],
             specificity: (0, 0, 0),
+            pseudo_element: None,
         };
     }
 
     list[0].0 = Combinator::Descendant; // Dummy
 
-    let mut total_spec = (0, 0, 0);
+    // Pseudo-elements carry type-level specificity.
+    let mut total_spec = (0, 0, u32::from(pseudo_element.is_some()));
     let mut parsed_list = Vec::new();
 
     for (comb, txt) in list {
@@ -498,10 +1392,27 @@ fn parse_complex_selector(raw: &str) -> ComplexSelector {
         last,
         ancestors,
         specificity: total_spec,
+        pseudo_element,
+    }
+}
+
+/// Length of the simple selector at the start of `s`: up to the next `.`,
+/// `#`, `:` or `[` outside parentheses, so `:not(.a)` stays whole.
+fn simple_selector_end(s: &str) -> usize {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '.' | '#' | ':' | '[' if depth == 0 => return i,
+            _ => {}
+        }
     }
+    s.len()
 }
 
-/// Parse a single compound selector string like `"div.card#main"`.
+/// Parse a single compound selector string like `"div.card#main"` or
+/// `"input[disabled].primary"`.
 fn parse_compound_selector(s: &str) -> CompoundSelector {
     let mut parts = Vec::new();
     let mut spec = (0u32, 0u32, 0u32);
@@ -512,10 +1423,9 @@ fn parse_compound_selector(s: &str) -> CompoundSelector {
         && !remaining.starts_with('.')
         && !remaining.starts_with('#')
         && !remaining.starts_with(':')
+        && !remaining.starts_with('[')
     {
-        let end = remaining
-            .find(|c| c == '.' || c == '#' || c == ':')
-            .unwrap_or(remaining.len());
+        let end = simple_selector_end(remaining);
         let tag = &remaining[..end];
         if tag == "*" {
             parts.push(SimpleSelector::Universal);
@@ -530,25 +1440,19 @@ fn parse_compound_selector(s: &str) -> CompoundSelector {
     while !remaining.is_empty() {
         if remaining.starts_with('#') {
             remaining = &remaining[1..];
-            let end = remaining
-                .find(|c| c == '.' || c == '#' || c == ':')
-                .unwrap_or(remaining.len());
+            let end = simple_selector_end(remaining);
             parts.push(SimpleSelector::Id(remaining[..end].to_string()));
             spec.0 += 1;
             remaining = &remaining[end..];
         } else if remaining.starts_with('.') {
             remaining = &remaining[1..];
-            let end = remaining
-                .find(|c| c == '.' || c == '#' || c == ':')
-                .unwrap_or(remaining.len());
+            let end = simple_selector_end(remaining);
             parts.push(SimpleSelector::Class(remaining[..end].to_string()));
             spec.1 += 1;
             remaining = &remaining[end..];
         } else if remaining.starts_with(':') {
             remaining = &remaining[1..];
-            let end = remaining
-                .find(|c| c == '.' || c == '#' || c == ':')
-                .unwrap_or(remaining.len());
+            let end = simple_selector_end(remaining);
             parts.push(SimpleSelector::PseudoClass(remaining[..end].to_string()));
             spec.1 += 1; // pseudo-classes have class-level specificity
             remaining = &remaining[end..];
@@ -593,17 +1497,15 @@ fn match_ancestors_recursive(
     
     match comb {
         Combinator::Descendant | Combinator::Child => {
-            let mut check_id = document.parent_of(current_node_id);
-            while let Some(pid) = check_id {
+            let limit = if *comb == Combinator::Child { 1 } else { usize::MAX };
+            for pid in document.ancestors(current_node_id).take(limit) {
                 if let Some(crate::dom::Node::Element(data)) = document.nodes.get(pid) {
-                    if match_compound_selector(compound, &data.tag_name, &data.attributes, &data.classes, document) {
+                    if match_compound_selector(compound, pid, data, document) {
                         if match_ancestors_recursive(ancestors, ancestor_idx + 1, pid, document) {
                             return true;
                         }
                     }
                 }
-                if *comb == Combinator::Child { break; }
-                check_id = document.parent_of(pid);
             }
         }
         Combinator::NextSibling | Combinator::SubsequentSibling => {
@@ -614,7 +1516,7 @@ fn match_ancestors_recursive(
             });
             while let Some(sid) = check_id {
                 if let Some(crate::dom::Node::Element(data)) = document.nodes.get(sid) {
-                    if match_compound_selector(compound, &data.tag_name, &data.attributes, &data.classes, document) {
+                    if match_compound_selector(compound, sid, data, document) {
                         if match_ancestors_recursive(ancestors, ancestor_idx + 1, sid, document) {
                             return true;
                         }
@@ -641,16 +1543,9 @@ fn match_complex_selector(
     complex: &ComplexSelector,
     node_id: crate::dom::NodeId,
     document: &crate::dom::Document,
-    classes_str: &str,
 ) -> bool {
     if let Some(crate::dom::Node::Element(data)) = document.nodes.get(node_id) {
-        if !match_compound_selector(
-            &complex.last,
-            &data.tag_name,
-            &data.attributes,
-            classes_str,
-            document,
-        ) {
+        if !match_compound_selector(&complex.last, node_id, data, document) {
             return false;
         }
     } else {
@@ -660,44 +1555,72 @@ fn match_complex_selector(
     match_ancestors_recursive(&complex.ancestors, 0, node_id, document)
 }
 
-fn match_compound_selector(
-    compound: &CompoundSelector,
-    tag_name: &crate::dom::LocalName,
-    attributes: &[(String, String)],
-    classes_str: &str,
-    _document: &crate::dom::Document,
+/// True if the element `node_id` matches any selector in `selectors`.
+/// Selectors ending in a pseudo-element never match the element itself.
+pub fn element_matches(
+    document: &crate::dom::Document,
+    node_id: crate::dom::NodeId,
+    selectors: &[ComplexSelector],
 ) -> bool {
-    if compound.parts.is_empty() {
-        return false;
-    }
+    selectors
+        .iter()
+        .any(|sel| sel.pseudo_element.is_none() && match_complex_selector(sel, node_id, document))
+}
 
-    for part in &compound.parts {
+/// A selector list parsed once for repeated matching, e.g. event delegation
+/// over many nodes. A list that fails to parse matches nothing.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    selectors: Vec<ComplexSelector>,
+}
+
+impl Matcher {
+    pub fn compile(selector: &str) -> Matcher {
+        Matcher { selectors: parse_selector_list(selector) }
+    }
+
+    /// True if the element `node_id` matches any selector in the list, as
+    /// [`element_matches`].
+    pub fn matches(&self, node_id: crate::dom::NodeId, document: &crate::dom::Document) -> bool {
+        element_matches(document, node_id, &self.selectors)
+    }
+
+    pub fn selectors(&self) -> &[ComplexSelector] {
+        &self.selectors
+    }
+}
+
+fn match_compound_selector(
+    compound: &CompoundSelector,
+    node_id: crate::dom::NodeId,
+    data: &crate::dom::ElementData,
+    document: &crate::dom::Document,
+) -> bool {
+    if compound.parts.is_empty() {
+        return false;
+    }
+
+    for part in &compound.parts {
         match part {
             SimpleSelector::Tag(t) => {
                 // LocalName PartialEq: pointer comparison for Standard tags, string for Custom
-                if t != tag_name {
+                if *t != data.tag_name {
                     return false;
                 }
             }
             SimpleSelector::Class(c) => {
-                if !has_class(classes_str, c) {
+                if !has_class(&data.classes, c) {
                     return false;
                 }
             }
             SimpleSelector::Id(id) => {
-                // Use the documented safe lookup
-                let mut found = false;
-                for (k, v) in attributes {
-                    if k == "id" {
-                        if v == id { found = true; }
-                        break;
-                    }
+                if data.id() != Some(id.as_str()) {
+                    return false;
                 }
-                if !found { return false; }
             }
             SimpleSelector::Attribute(key, opt_val) => {
                 let mut found = false;
-                for (k, v) in attributes {
+                for (k, v) in &data.attributes {
                     if k == key {
                         if let Some(expected_val) = opt_val {
                             if v == expected_val { found = true; }
@@ -709,6 +1632,40 @@ fn match_compound_selector(
                 }
                 if !found { return false; }
             }
+            SimpleSelector::PseudoClass(p) if p == "root" => {
+                // The document element: an element directly under the document root.
+                if document.parent_of(node_id) != Some(document.root_id) {
+                    return false;
+                }
+            }
+            SimpleSelector::PseudoClass(p) if p == "empty" => {
+                // Any child counts, whitespace-only text included (as in
+                // Selectors Level 3); comments are never stored.
+                if document.first_child_of(node_id).is_some() {
+                    return false;
+                }
+            }
+            SimpleSelector::PseudoClass(p) if p == "focus" => {
+                if document.focused != Some(node_id) {
+                    return false;
+                }
+            }
+            SimpleSelector::PseudoClass(p) if p.starts_with("lang(") && p.ends_with(')') => {
+                // Matches the element's language or any subtag of it:
+                // `:lang(en)` matches `en` and `en-US`.
+                let Some(lang) = document.lang(node_id) else {
+                    return false;
+                };
+                let range = &p["lang(".len()..p.len() - 1];
+                let matches = !range.is_empty()
+                    && lang.len() >= range.len()
+                    && lang.is_char_boundary(range.len())
+                    && lang[..range.len()].eq_ignore_ascii_case(range)
+                    && (lang.len() == range.len() || lang.as_bytes()[range.len()] == b'-');
+                if !matches {
+                    return false;
+                }
+            }
             SimpleSelector::PseudoClass(_) => {
                 // Not supported
             }
@@ -723,20 +1680,237 @@ fn match_compound_selector(
 // ---------------------------------------------------------------------------
 
 pub fn parse_stylesheet(css: &str) -> StyleSheet {
+    parse_stylesheet_with_errors(css).0
+}
+
+/// A problem met while parsing a stylesheet: the rule or declaration it
+/// concerns was skipped. `line` and `column` are 1-based and point at its
+/// start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CssError {
+    pub message: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl CssError {
+    fn at(location: cssparser::SourceLocation, message: String) -> Self {
+        CssError { message, line: location.line + 1, column: location.column }
+    }
+}
+
+impl std::fmt::Display for CssError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// [`parse_stylesheet`], also returning what was skipped and why: malformed
+/// rules, selectors that match nothing parseable, and declarations with an
+/// unknown property, a missing `:` or an empty value. Values of known
+/// properties are not validated.
+pub fn parse_stylesheet_with_errors(css: &str) -> (StyleSheet, Vec<CssError>) {
     let mut input = ParserInput::new(css);
     let mut parser = Parser::new(&mut input);
     let mut stylesheet = StyleSheet::default();
+    let mut errors = Vec::new();
 
-    parse_rules_list(&mut parser, &mut stylesheet);
+    parse_rules_list(&mut parser, &mut stylesheet, &mut errors);
     stylesheet.sort_rules();
-    stylesheet
+    (stylesheet, errors)
 }
 
+/// Cascade origin of a stylesheet. Declarations from a later origin beat
+/// those from an earlier one regardless of selector specificity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Origin {
+    UserAgent,
+    Author,
+}
+
+/// Resolve styles against `base_stylesheet` followed by the document's own
+/// `<style>` rules, both as author origin.
+///
+/// Viewport-relative `font-size` values resolve against a zero viewport here
+/// (falling back to the default size); use [`compute_styles_ex`] when the
+/// viewport is known.
+///
+/// The two sheets are cascaded in place, without the clone and merge of
+/// [`prepare_sheet`], so calling this on every frame costs no more than the
+/// cascade itself.
 pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &StyleSheet) {
-    let mut stack = vec![(document.root_id, None::<crate::dom::ComputedStyle>, true, true)];
+    let doc_sheet = std::mem::take(&mut document.stylesheet);
+    compute_styles_ex(
+        document,
+        &[(Origin::Author, base_stylesheet), (Origin::Author, &doc_sheet)],
+        (0.0, 0.0),
+    );
+    document.stylesheet = doc_sheet;
+}
+
+/// Compile `base` and the document's `<style>` rules into one sorted sheet.
+///
+/// The result only depends on the stylesheets, not on the DOM, so a host can
+/// build it once and pass it to [`apply_styles`] for every restyle until a
+/// `<style>` element changes (`document.styles_dirty`).
+pub fn prepare_sheet(document: &crate::dom::Document, base: &StyleSheet) -> StyleSheet {
+    let mut compiled = base.clone();
+    compiled.extend_from(&document.stylesheet);
+    compiled
+}
+
+/// Cascade a sheet built by [`prepare_sheet`] onto the document as author
+/// origin. Unlike [`compute_styles`], the document's `<style>` rules are not
+/// consulted again; they are already part of `compiled`.
+pub fn apply_styles(document: &mut crate::dom::Document, compiled: &StyleSheet) {
+    compute_styles_ex(document, &[(Origin::Author, compiled)], (0.0, 0.0));
+}
+
+/// Resolve styles against an explicit, ordered list of stylesheets.
+///
+/// Rules are cascaded by origin first, then specificity, then position (sheet
+/// order, then source order within a sheet). The document's `<style>` rules are
+/// **not** implicitly included; pass `&document.stylesheet` (or a clone) in
+/// `sheets` to apply them. `viewport` is `(width, height)` in px and resolves
+/// `vw`/`vh` font sizes.
+pub fn compute_styles_ex(
+    document: &mut crate::dom::Document,
+    sheets: &[(Origin, &StyleSheet)],
+    viewport: (f32, f32),
+) {
+    cascade(document, sheets, viewport, None);
+}
+
+/// One declaration applied to an element during the cascade.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CascadeTraceEntry {
+    pub property: crate::dom::PropertyName,
+    pub value: crate::dom::StyleValue,
+    /// Selector text of the rule, or `None` for the element's inline `style`.
+    pub selector: Option<String>,
+    pub specificity: (u32, u32, u32),
+    pub origin: Origin,
+    /// True if a later entry for the same property replaced this value.
+    pub overridden: bool,
+}
+
+/// Per-element log of applied declarations, in cascade (application) order.
+pub type CascadeTrace = std::collections::HashMap<crate::dom::NodeId, Vec<CascadeTraceEntry>>;
+
+/// Like [`compute_styles_ex`], but also records which rule set each property
+/// on each element, including declarations that were later overridden.
+///
+/// Every element is re-matched regardless of dirty flags so the trace is
+/// complete. Intended for diagnosing specificity issues, not per-frame use.
+pub fn compute_styles_debug(
+    document: &mut crate::dom::Document,
+    sheets: &[(Origin, &StyleSheet)],
+    viewport: (f32, f32),
+) -> CascadeTrace {
+    let mut trace = CascadeTrace::new();
+    cascade(document, sheets, viewport, Some(&mut trace));
+    trace
+}
+
+/// Every rule of `stylesheet` whose selector matches the element `node_id`,
+/// in cascade order (specificity, then source order), so the last entry for a
+/// property wins. Rules that end in `::before`/`::after` are included when
+/// their originating element matches; check `selector.pseudo_element` to tell
+/// them apart. Non-element nodes match nothing.
+///
+/// Unlike [`compute_styles_debug`] this reports whole rules rather than the
+/// declarations they applied, and leaves the document untouched.
+pub fn matched_rules<'a>(
+    document: &crate::dom::Document,
+    stylesheet: &'a StyleSheet,
+    node_id: crate::dom::NodeId,
+) -> Vec<&'a IndexedRule> {
+    let Some(crate::dom::Node::Element(data)) = document.nodes.get(node_id) else {
+        return Vec::new();
+    };
+    let mut matched = Vec::new();
+    for_each_bucket(stylesheet, data, |rules| {
+        matched.extend(rules.iter().filter(|rule| match_complex_selector(&rule.selector, node_id, document)));
+    });
+    matched.sort_by(|a, b| {
+        a.selector.specificity.cmp(&b.selector.specificity).then_with(|| a.rule_index.cmp(&b.rule_index))
+    });
+    // A repeated class token visits its bucket twice.
+    matched.dedup_by_key(|rule| rule.rule_index);
+    matched
+}
+
+/// Calls `f` with each bucket of `stylesheet` that may hold rules for the
+/// element: its id, each class, its tag, then the universal rules. Mirrors the
+/// single-bucket placement in [`StyleSheet::add_rule`].
+fn for_each_bucket<'a>(
+    stylesheet: &'a StyleSheet,
+    data: &crate::dom::ElementData,
+    mut f: impl FnMut(&'a [IndexedRule]),
+) {
+    if let Some(rules) = data.id().and_then(|id| stylesheet.by_id.get(id)) {
+        f(rules);
+    }
+    for class in data.classes.split_whitespace() {
+        if let Some(rules) = stylesheet.by_class.get(class) {
+            f(rules);
+        }
+    }
+    match &data.tag_name {
+        crate::dom::LocalName::Standard(atom) => {
+            if let Some(rules) = stylesheet.by_tag.get(atom) {
+                f(rules);
+            }
+        }
+        crate::dom::LocalName::Custom(s) => {
+            if let Some((_, rules)) = stylesheet.by_tag.iter().find(|(k, _)| &***k == s.as_str()) {
+                f(rules);
+            }
+        }
+    }
+    if !stylesheet.universal.is_empty() {
+        f(&stylesheet.universal);
+    }
+}
+
+/// Shared cascade walk. `trace` is `None` on the normal path; all trace
+/// bookkeeping is skipped in that case.
+fn cascade(
+    document: &mut crate::dom::Document,
+    sheets: &[(Origin, &StyleSheet)],
+    viewport: (f32, f32),
+    mut trace: Option<&mut CascadeTrace>,
+) {
+    let mut stack = vec![(document.root_id, None::<crate::dom::ComputedStyle>, true, true, 0_usize)];
+    // 1-based position of each `li` among the `li` children of its `ul`/`ol`,
+    // filled in when the list is visited and consumed by the item.
+    let mut list_ordinals: std::collections::HashMap<crate::dom::NodeId, u32> = std::collections::HashMap::new();
+    // Live CSS counters as (name, value, depth of the resetting element),
+    // innermost last. Nodes are visited in document order, so a counter goes
+    // out of scope once the walk climbs above its element's depth: its scope
+    // is that element's subtree plus its following siblings'.
+    let mut counters: Vec<(String, i32, usize)> = Vec::new();
+    // `::first-letter` style waiting for the first text in its element's
+    // subtree, with that element's depth; dropped once the walk leaves it.
+    let mut first_letter: Option<(Box<crate::dom::TextComputedStyle>, usize)> = None;
+    let quirks_mode = document.quirks_mode;
+    // `rem` is the document element's font size, which itself resolves `rem`
+    // against the configured default. If it changed, everything after it is
+    // restyled and relaid, since `rem` lengths are kept unresolved.
+    let root_font_size = document.root_font_size;
+    let mut rem_base = root_font_size;
+    let mut rem_changed = false;
+    let mut document_element_seen = false;
+
+    while let Some((node_id, parent_computed, parent_inheritable_changed, ancestor_attr_changed, depth)) = stack.pop() {
+        while counters.last().is_some_and(|&(_, _, d)| d > depth) {
+            counters.pop();
+        }
+        if first_letter.as_ref().is_some_and(|&(_, d)| d >= depth) {
+            first_letter = None;
+        }
 
-    while let Some((node_id, parent_computed, parent_inheritable_changed, ancestor_attr_changed)) = stack.pop() {
-        let mut property_mask: u64 = 0;
+        let mut property_mask: u128 = 0;
         let mut property_array: [Option<crate::dom::StyleValue>; crate::dom::NUM_PROPERTIES] =
             core::array::from_fn(|_| None);
 
@@ -752,8 +1926,22 @@ pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &Sty
             _ => {}
         }
 
-        let must_rematch = document.styles_dirty || ancestor_attr_changed || data_styles_dirty;
+        let counter_content = matches!(node, crate::dom::Node::Element(data) if data.counter_content);
+        // Generated content inherits from the element, so its styles are
+        // resolved again whenever the element's inherited values may change.
+        let generates = matches!(node, crate::dom::Node::Element(data)
+            if data.pseudo_before.is_some() || data.pseudo_after.is_some() || data.first_letter.is_some());
+        let must_rematch = trace.is_some()
+            || document.styles_dirty
+            || rem_changed
+            || ancestor_attr_changed
+            || data_styles_dirty
+            || counter_content
+            || (generates && parent_inheritable_changed);
         let mut next_ancestor_attr_changed = ancestor_attr_changed;
+        // Matched `::before` / `::after` / `::first-letter` declarations, in
+        // cascade order.
+        let mut pseudo_decls: [Vec<&Declaration>; 3] = [Vec::new(), Vec::new(), Vec::new()];
 
         if must_rematch {
             if let crate::dom::Node::Element(data) = node {
@@ -761,54 +1949,24 @@ pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &Sty
                     next_ancestor_attr_changed = true;
                 }
 
-                let id_attr = data
-                    .attributes
-                    .iter()
-                    .find(|(k, _)| k == "id")
-                    .map(|(_, v)| v.as_str());
-
-                let mut lists: smallvec::SmallVec<[&[IndexedRule]; 8]> = smallvec::SmallVec::new();
-                let doc_sheet = &document.stylesheet;
-                let sheets = [base_stylesheet, doc_sheet];
+                // Each bucket slice is tagged with its sheet's origin and position so
+                // the merge below orders by (origin, specificity, sheet, rule_index).
+                let mut lists: smallvec::SmallVec<[(Origin, usize, &[IndexedRule]); 8]> = smallvec::SmallVec::new();
 
-                for stylesheet in sheets {
-                    if let Some(id) = id_attr {
-                        if let Some(rules) = stylesheet.by_id.get(id) {
-                            lists.push(rules.as_slice());
-                        }
-                    }
-                    for class in data.classes.split_whitespace() {
-                        if let Some(rules) = stylesheet.by_class.get(class) {
-                            lists.push(rules.as_slice());
-                        }
-                    }
-                    match &data.tag_name {
-                        crate::dom::LocalName::Standard(atom) => {
-                            if let Some(rules) = stylesheet.by_tag.get(atom) {
-                                lists.push(rules.as_slice());
-                            }
-                        }
-                        crate::dom::LocalName::Custom(s) => {
-                            if let Some((_, rules)) =
-                                stylesheet.by_tag.iter().find(|(k, _)| &***k == s.as_str())
-                            {
-                                lists.push(rules.as_slice());
-                            }
-                        }
-                    }
-                    if !stylesheet.universal.is_empty() {
-                        lists.push(stylesheet.universal.as_slice());
-                    }
+                for (sheet_idx, &(origin, stylesheet)) in sheets.iter().enumerate() {
+                    for_each_bucket(stylesheet, data, |rules| lists.push((origin, sheet_idx, rules)));
                 }
 
                 while !lists.is_empty() {
                     let mut min_idx = 0;
                     for i in 1..lists.len() {
-                        let a = &lists[i][0];
-                        let b = &lists[min_idx][0];
-                        if a.selector
-                            .specificity
-                            .cmp(&b.selector.specificity)
+                        let (a_origin, a_sheet, a_rules) = lists[i];
+                        let (b_origin, b_sheet, b_rules) = lists[min_idx];
+                        let (a, b) = (&a_rules[0], &b_rules[0]);
+                        if a_origin
+                            .cmp(&b_origin)
+                            .then_with(|| a.selector.specificity.cmp(&b.selector.specificity))
+                            .then_with(|| a_sheet.cmp(&b_sheet))
                             .then_with(|| a.rule_index.cmp(&b.rule_index))
                             == std::cmp::Ordering::Less
                         {
@@ -816,20 +1974,38 @@ pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &Sty
                         }
                     }
 
-                    let rule = &lists[min_idx][0];
-                    if match_complex_selector(&rule.selector, node_id, document, &data.classes) {
-                        for decl in rule.declarations.iter() {
-                            let idx = decl.name.to_index();
-                            property_array[idx] = Some(decl.value.clone());
-                            property_mask |= 1_u64 << idx;
+                    let rule = &lists[min_idx].2[0];
+                    if match_complex_selector(&rule.selector, node_id, document) {
+                        match rule.selector.pseudo_element {
+                            None => {
+                                if let Some(trace) = trace.as_deref_mut() {
+                                    let selector = rule.selector.to_string();
+                                    trace.entry(node_id).or_default().extend(rule.declarations.iter().map(|decl| {
+                                        CascadeTraceEntry {
+                                            property: decl.name,
+                                            value: decl.value.clone(),
+                                            selector: Some(selector.clone()),
+                                            specificity: rule.selector.specificity,
+                                            origin: lists[min_idx].0,
+                                            overridden: false,
+                                        }
+                                    }));
+                                }
+                                for decl in rule.declarations.iter() {
+                                    let idx = decl.name.to_index();
+                                    property_array[idx] = Some(decl.value.clone());
+                                    property_mask |= 1_u128 << idx;
+                                }
+                            }
+                            Some(pseudo) => pseudo_decls[pseudo as usize].extend(rule.declarations.iter()),
                         }
                     }
 
-                    let next_list = &lists[min_idx][1..];
+                    let next_list = &lists[min_idx].2[1..];
                     if next_list.is_empty() {
                         lists.swap_remove(min_idx);
                     } else {
-                        lists[min_idx] = next_list;
+                        lists[min_idx].2 = next_list;
                     }
                 }
 
@@ -837,20 +2013,43 @@ pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &Sty
                     for (name, value) in inline_decls {
                         let idx = name.to_index();
                         property_array[idx] = Some(value.clone());
-                        property_mask |= 1_u64 << idx;
+                        property_mask |= 1_u128 << idx;
+                    }
+                    if let Some(trace) = trace.as_deref_mut() {
+                        trace.entry(node_id).or_default().extend(inline_decls.iter().map(|(name, value)| {
+                            CascadeTraceEntry {
+                                property: *name,
+                                value: value.clone(),
+                                selector: None,
+                                specificity: (0, 0, 0),
+                                origin: Origin::Author,
+                                overridden: false,
+                            }
+                        }));
+                    }
+                }
+
+                if let Some(entries) = trace.as_deref_mut().and_then(|t| t.get_mut(&node_id)) {
+                    let mut seen: u128 = 0;
+                    for entry in entries.iter_mut().rev() {
+                        let bit = 1_u128 << entry.property.to_index();
+                        entry.overridden = seen & bit != 0;
+                        seen |= bit;
                     }
                 }
             }
         }
 
         // --- Element path: full ComputedStyle cascade ---
-        // Text nodes use a lightweight TextComputedStyle (only font_size + color).
-        // We track both in parallel so that the shared_style pushed to children
-        // always carries the full ComputedStyle regardless of the current node type.
-        let _is_text = matches!(node, crate::dom::Node::Text(_));
+        // Text nodes use a lightweight TextComputedStyle (the inherited text
+        // properties only), derived from the full style once it is resolved so
+        // the shared_style pushed to children is always a full ComputedStyle.
     
         let mut next_computed = crate::dom::ComputedStyle::default();
         let mut next_text_computed = crate::dom::TextComputedStyle::default();
+        if matches!(node, crate::dom::Node::Root(_)) {
+            next_computed.font_size = root_font_size;
+        }
     
         if !must_rematch {
             match node {
@@ -863,27 +2062,90 @@ pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &Sty
         // Default to inheriting from parent if possible
         if must_rematch || parent_inheritable_changed {
             if let Some(pc) = &parent_computed {
-                next_computed.font_size = pc.font_size;
-                next_computed.color = pc.color;
-                next_text_computed.font_size = pc.font_size;
-                next_text_computed.color = pc.color;
+                next_computed.inherit_from(pc);
+                // Not inherited, but a text node's lines are clamped by its parent.
+                if matches!(node, crate::dom::Node::Text(_)) {
+                    next_computed.line_clamp = pc.line_clamp;
+                }
+            }
+            // Built-in emphasis defaults, applied before author declarations so
+            // any rule (or `inherit`) can override them.
+            if let crate::dom::Node::Element(data) = node {
+                if let Some((_, lang)) = data.attributes.iter().find(|(k, _)| k == "lang") {
+                    next_computed.lang = (!lang.is_empty()).then(|| std::sync::Arc::from(lang.as_str()));
+                }
+                match &*data.tag_name {
+                    "b" | "strong" => next_computed.font_weight = 700,
+                    "i" | "em" => next_computed.font_style = crate::dom::FontStyleKeyword::Italic,
+                    "ul" => next_computed.list_style_type = crate::dom::ListStyleTypeKeyword::Disc,
+                    "ol" => next_computed.list_style_type = crate::dom::ListStyleTypeKeyword::Decimal,
+                    "pre" => next_computed.white_space = crate::dom::WhiteSpaceKeyword::Pre,
+                    _ => {}
+                }
             }
         }
 
         if must_rematch {
-            let parent_font_size = parent_computed.as_ref().map(|pc| pc.font_size).unwrap_or(16.0);
+            // Phrasing elements are inline-level unless an author rule says otherwise.
+            if let crate::dom::Node::Element(data) = node
+                && INLINE_TAGS.contains(&*data.tag_name)
+            {
+                next_computed.display = crate::dom::DisplayKeyword::Inline;
+            }
+            let parent_font_size = parent_computed.as_ref().map(|pc| pc.font_size).unwrap_or(root_font_size);
             if property_mask != 0 {
             for i in 0..crate::dom::NUM_PROPERTIES {
-                if (property_mask & (1_u64 << i)) != 0 {
+                if (property_mask & (1_u128 << i)) != 0 {
                     if let Some(val) = &property_array[i] {
+                        // CSS-wide keywords: copy the parent's or the initial value.
+                        if let crate::dom::StyleValue::Keyword(kw) = val {
+                            // `revert` rolls back to what the element had before
+                            // any declaration: the inherited or initial value plus
+                            // built-in tag defaults. User-agent-origin sheets are
+                            // merged with author rules here, so they are rolled
+                            // back too.
+                            if &**kw == "revert" {
+                                continue;
+                            }
+                            let inherits = match &**kw {
+                                "inherit" => Some(true),
+                                "initial" => Some(false),
+                                "unset" => Some(crate::dom::PropertyName::ALL[i].is_inheritable()),
+                                _ => None,
+                            };
+                            if let Some(inherits) = inherits {
+                                let initial = crate::dom::ComputedStyle::default();
+                                let source = match &parent_computed {
+                                    Some(pc) if inherits => pc,
+                                    _ => &initial,
+                                };
+                                next_computed.copy_indexed_property(i, source);
+                                continue;
+                            }
+                        }
+                        // Quirks mode reads bare numbers as px; `0` is a length anyway.
+                        let quirk_px;
+                        let val = match val {
+                            crate::dom::StyleValue::Number(n)
+                                if quirks_mode
+                                    && *n != 0.0
+                                    && crate::dom::PropertyName::ALL[i].takes_unitless_length_quirk() =>
+                            {
+                                quirk_px = crate::dom::StyleValue::LengthPx(*n);
+                                &quirk_px
+                            }
+                            _ => val,
+                        };
                         match i {
                             0 => if let crate::dom::StyleValue::Keyword(v) = val {
                                 next_computed.display = match &**v {
                                     "flex" => crate::dom::DisplayKeyword::Flex,
                                     "grid" => crate::dom::DisplayKeyword::Grid,
                                     "none" => crate::dom::DisplayKeyword::None,
-                                    "inline" | "inline-block" => crate::dom::DisplayKeyword::InlineBlock,
+                                    "inline" => crate::dom::DisplayKeyword::Inline,
+                                    "inline-block" => crate::dom::DisplayKeyword::InlineBlock,
                                     "list-item" => crate::dom::DisplayKeyword::ListItem,
+                                    "contents" => crate::dom::DisplayKeyword::Contents,
                                     _ => crate::dom::DisplayKeyword::Block,
                                 };
                             },
@@ -911,48 +2173,86 @@ pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &Sty
                             17 => if let crate::dom::StyleValue::Color(r, g, b, a) = val { next_computed.border_color = Some((*r, *g, *b, *a)); },
                             18 => if let crate::dom::StyleValue::Color(r, g, b, a) = val {
                                 next_computed.color = (*r, *g, *b, *a);
-                                next_text_computed.color = (*r, *g, *b, *a);
                             },
                             19 => {
                                 match val {
-                                    crate::dom::StyleValue::LengthPx(px) => {
-                                        next_computed.font_size = *px;
-                                        next_text_computed.font_size = *px;
-                                    }
-                                    crate::dom::StyleValue::Number(num) => {
-                                        next_computed.font_size = *num;
-                                        next_text_computed.font_size = *num;
-                                    }
-                                    crate::dom::StyleValue::Em(num) => {
-                                        next_computed.font_size = num * parent_font_size;
-                                        next_text_computed.font_size = num * parent_font_size;
-                                    }
-                                    crate::dom::StyleValue::Rem(num) => {
-                                        next_computed.font_size = num * document.root_font_size;
-                                        next_text_computed.font_size = num * document.root_font_size;
+                                    crate::dom::StyleValue::LengthPx(px) => next_computed.font_size = *px,
+                                    crate::dom::StyleValue::Number(num) => next_computed.font_size = *num,
+                                    crate::dom::StyleValue::Em(num) => next_computed.font_size = num * parent_font_size,
+                                    crate::dom::StyleValue::Rem(num) => next_computed.font_size = num * rem_base,
+                                    crate::dom::StyleValue::ViewportWidth(num) => next_computed.font_size = num / 100.0 * viewport.0,
+                                    crate::dom::StyleValue::ViewportHeight(num) => next_computed.font_size = num / 100.0 * viewport.1,
+                                    _ => {}
+                                }
+                            }
+                            20 => if let crate::dom::StyleValue::FontFamily(families) = val {
+                                next_computed.font_family = families.clone();
+                            },
+                            21 => {
+                                let parent_weight = parent_computed.as_ref().map_or(400, |pc| pc.font_weight);
+                                match val {
+                                    crate::dom::StyleValue::Number(n) if (1.0..=1000.0).contains(n) => {
+                                        next_computed.font_weight = *n as u16;
                                     }
+                                    crate::dom::StyleValue::Keyword(v) => match &**v {
+                                        "normal" => next_computed.font_weight = 400,
+                                        "bold" => next_computed.font_weight = 700,
+                                        // Relative weights per the CSS Fonts table.
+                                        "bolder" => {
+                                            next_computed.font_weight = match parent_weight {
+                                                0..350 => 400,
+                                                350..550 => 700,
+                                                550..900 => 900,
+                                                w => w,
+                                            }
+                                        }
+                                        "lighter" => {
+                                            next_computed.font_weight = match parent_weight {
+                                                0..100 => parent_weight,
+                                                100..550 => 100,
+                                                550..750 => 400,
+                                                _ => 700,
+                                            }
+                                        }
+                                        _ => {}
+                                    },
                                     _ => {}
                                 }
                             }
-                            25 => if let crate::dom::StyleValue::Keyword(v) = val {
-                                next_computed.align_items = match &**v {
-                                    "flex-end" | "end" => crate::dom::AlignItemsKeyword::FlexEnd,
-                                    "center" => crate::dom::AlignItemsKeyword::Center,
-                                    "baseline" => crate::dom::AlignItemsKeyword::Baseline,
-                                    "flex-start" | "start" => crate::dom::AlignItemsKeyword::FlexStart,
-                                    _ => crate::dom::AlignItemsKeyword::Stretch,
+                            22 => {
+                                let font_size = next_computed.font_size;
+                                next_computed.line_height = match val {
+                                    crate::dom::StyleValue::Number(n) if *n >= 0.0 => crate::dom::LineHeight::Number(*n),
+                                    crate::dom::StyleValue::LengthPx(px) => crate::dom::LineHeight::Px(*px),
+                                    crate::dom::StyleValue::Em(n) => crate::dom::LineHeight::Px(n * font_size),
+                                    crate::dom::StyleValue::Rem(n) => crate::dom::LineHeight::Px(n * rem_base),
+                                    crate::dom::StyleValue::Percent(p) => crate::dom::LineHeight::Px(p / 100.0 * font_size),
+                                    _ => crate::dom::LineHeight::Normal,
+                                };
+                            }
+                            23 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.text_align = match &**v {
+                                    "end" => crate::dom::TextAlignKeyword::End,
+                                    "left" => crate::dom::TextAlignKeyword::Left,
+                                    "right" => crate::dom::TextAlignKeyword::Right,
+                                    "center" => crate::dom::TextAlignKeyword::Center,
+                                    "justify" => crate::dom::TextAlignKeyword::Justify,
+                                    _ => crate::dom::TextAlignKeyword::Start,
                                 };
                             },
-                            26 => if let crate::dom::StyleValue::Keyword(v) = val {
-                                next_computed.justify_content = match &**v {
-                                    "flex-end" | "end" => crate::dom::JustifyContentKeyword::FlexEnd,
-                                    "center" => crate::dom::JustifyContentKeyword::Center,
-                                    "space-between" => crate::dom::JustifyContentKeyword::SpaceBetween,
-                                    "space-around" => crate::dom::JustifyContentKeyword::SpaceAround,
-                                    "space-evenly" => crate::dom::JustifyContentKeyword::SpaceEvenly,
-                                    _ => crate::dom::JustifyContentKeyword::FlexStart,
+                            24 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.visibility = match &**v {
+                                    "hidden" => crate::dom::VisibilityKeyword::Hidden,
+                                    "collapse" => crate::dom::VisibilityKeyword::Collapse,
+                                    _ => crate::dom::VisibilityKeyword::Visible,
                                 };
                             },
+                            25 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.align_items = align_items_keyword(v);
+                            },
+                            26 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.justify_content = justify_content_keyword(v);
+                            },
                             27 => if let crate::dom::StyleValue::Keyword(v) = val {
                                 next_computed.flex_wrap = match &**v {
                                     "wrap" => crate::dom::FlexWrapKeyword::Wrap,
@@ -979,6 +2279,211 @@ pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &Sty
                             38 => next_computed.inset[1] = val.clone(),
                             39 => next_computed.inset[2] = val.clone(),
                             40 => next_computed.inset[3] = val.clone(),
+                            41 => next_computed.outline_width = match val {
+                                crate::dom::StyleValue::Keyword(v) => match &**v {
+                                    "thin" => crate::dom::StyleValue::LengthPx(1.0),
+                                    "thick" => crate::dom::StyleValue::LengthPx(5.0),
+                                    _ => crate::dom::StyleValue::LengthPx(3.0),
+                                },
+                                // Font-relative widths are absolute once computed:
+                                // `font-size` (19) is already resolved here.
+                                crate::dom::StyleValue::Em(n) => crate::dom::StyleValue::LengthPx(n * next_computed.font_size),
+                                crate::dom::StyleValue::Rem(n) => crate::dom::StyleValue::LengthPx(n * rem_base),
+                                crate::dom::StyleValue::ViewportWidth(n) => crate::dom::StyleValue::LengthPx(n / 100.0 * viewport.0),
+                                crate::dom::StyleValue::ViewportHeight(n) => crate::dom::StyleValue::LengthPx(n / 100.0 * viewport.1),
+                                _ => val.clone(),
+                            },
+                            42 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.outline_style = match &**v {
+                                    "solid" => crate::dom::OutlineStyleKeyword::Solid,
+                                    "dashed" => crate::dom::OutlineStyleKeyword::Dashed,
+                                    "dotted" => crate::dom::OutlineStyleKeyword::Dotted,
+                                    "double" => crate::dom::OutlineStyleKeyword::Double,
+                                    _ => crate::dom::OutlineStyleKeyword::None,
+                                };
+                            },
+                            43 => if let crate::dom::StyleValue::Color(r, g, b, a) = val { next_computed.outline_color = Some((*r, *g, *b, *a)); },
+                            44 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.word_break = match &**v {
+                                    "break-all" => crate::dom::WordBreakKeyword::BreakAll,
+                                    "keep-all" => crate::dom::WordBreakKeyword::KeepAll,
+                                    "break-word" => crate::dom::WordBreakKeyword::BreakWord,
+                                    _ => crate::dom::WordBreakKeyword::Normal,
+                                };
+                            },
+                            45 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.overflow_wrap = match &**v {
+                                    "break-word" => crate::dom::OverflowWrapKeyword::BreakWord,
+                                    "anywhere" => crate::dom::OverflowWrapKeyword::Anywhere,
+                                    _ => crate::dom::OverflowWrapKeyword::Normal,
+                                };
+                            },
+                            46 => {
+                                next_computed.line_clamp = match val {
+                                    crate::dom::StyleValue::Number(n) if *n >= 1.0 => Some(*n as u32),
+                                    _ => None,
+                                };
+                            }
+                            48 | 49 => {
+                                next_computed.overflow[i - 48] = match val {
+                                    crate::dom::StyleValue::Auto => crate::dom::OverflowKeyword::Auto,
+                                    crate::dom::StyleValue::Keyword(v) => match &**v {
+                                        "hidden" => crate::dom::OverflowKeyword::Hidden,
+                                        "clip" => crate::dom::OverflowKeyword::Clip,
+                                        "scroll" => crate::dom::OverflowKeyword::Scroll,
+                                        _ => crate::dom::OverflowKeyword::Visible,
+                                    },
+                                    _ => crate::dom::OverflowKeyword::Visible,
+                                };
+                            }
+                            50 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.align_content =
+                                    (&**v != "normal").then(|| justify_content_keyword(v));
+                            },
+                            51 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.justify_items = (&**v != "normal").then(|| align_items_keyword(v));
+                            },
+                            52 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.font_style = match &**v {
+                                    "italic" => crate::dom::FontStyleKeyword::Italic,
+                                    "oblique" => crate::dom::FontStyleKeyword::Oblique,
+                                    _ => crate::dom::FontStyleKeyword::Normal,
+                                };
+                            },
+                            53 => {
+                                next_computed.transform = match val {
+                                    crate::dom::StyleValue::Transform(t) => Some(*t),
+                                    _ => None,
+                                };
+                            }
+                            54 => {
+                                next_computed.content_visibility = match val {
+                                    crate::dom::StyleValue::Auto => crate::dom::ContentVisibilityKeyword::Auto,
+                                    crate::dom::StyleValue::Keyword(v) if &**v == "hidden" => {
+                                        crate::dom::ContentVisibilityKeyword::Hidden
+                                    }
+                                    _ => crate::dom::ContentVisibilityKeyword::Visible,
+                                };
+                            }
+                            55 => {
+                                next_computed.pointer_events = match val {
+                                    crate::dom::StyleValue::Keyword(v) if &**v == "none" => {
+                                        crate::dom::PointerEventsKeyword::None
+                                    }
+                                    _ => crate::dom::PointerEventsKeyword::Auto,
+                                };
+                            }
+                            56 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.direction = match &**v {
+                                    "rtl" => crate::dom::DirectionKeyword::Rtl,
+                                    _ => crate::dom::DirectionKeyword::Ltr,
+                                };
+                            },
+                            57 => {
+                                next_computed.opacity = match val {
+                                    crate::dom::StyleValue::Number(n) => n.clamp(0.0, 1.0),
+                                    crate::dom::StyleValue::Percent(p) => (p / 100.0).clamp(0.0, 1.0),
+                                    _ => 1.0,
+                                };
+                            }
+                            58 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.list_style_type = match &**v {
+                                    "circle" => crate::dom::ListStyleTypeKeyword::Circle,
+                                    "decimal" => crate::dom::ListStyleTypeKeyword::Decimal,
+                                    "none" => crate::dom::ListStyleTypeKeyword::None,
+                                    _ => crate::dom::ListStyleTypeKeyword::Disc,
+                                };
+                            },
+                            59 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.cursor = v.clone();
+                            },
+                            61 | 62 => {
+                                let font_size = next_computed.font_size;
+                                let px = match val {
+                                    crate::dom::StyleValue::LengthPx(px) => *px,
+                                    crate::dom::StyleValue::Em(n) => n * font_size,
+                                    crate::dom::StyleValue::Rem(n) => n * rem_base,
+                                    _ => 0.0,
+                                };
+                                if i == 61 {
+                                    next_computed.letter_spacing = px;
+                                } else {
+                                    next_computed.word_spacing = px;
+                                }
+                            }
+                            60 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.text_transform = match &**v {
+                                    "uppercase" => crate::dom::TextTransformKeyword::Uppercase,
+                                    "lowercase" => crate::dom::TextTransformKeyword::Lowercase,
+                                    "capitalize" => crate::dom::TextTransformKeyword::Capitalize,
+                                    _ => crate::dom::TextTransformKeyword::None,
+                                };
+                            },
+                            63 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.float = match &**v {
+                                    "left" => crate::dom::FloatKeyword::Left,
+                                    "right" => crate::dom::FloatKeyword::Right,
+                                    _ => crate::dom::FloatKeyword::None,
+                                };
+                            },
+                            64 => if let crate::dom::StyleValue::Number(n) = val
+                                && *n >= 0.0
+                            {
+                                next_computed.tab_size = *n as u32;
+                            },
+                            65 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.object_fit = match &**v {
+                                    "contain" => crate::dom::ObjectFitKeyword::Contain,
+                                    "cover" => crate::dom::ObjectFitKeyword::Cover,
+                                    "none" => crate::dom::ObjectFitKeyword::None,
+                                    "scale-down" => crate::dom::ObjectFitKeyword::ScaleDown,
+                                    _ => crate::dom::ObjectFitKeyword::Fill,
+                                };
+                            },
+                            66 => next_computed.animation_name = match val {
+                                crate::dom::StyleValue::String(name) => Some(std::sync::Arc::from(name.as_str())),
+                                _ => None,
+                            },
+                            67 => if let crate::dom::StyleValue::Number(secs) = val {
+                                next_computed.animation_duration = secs.max(0.0);
+                            },
+                            68 => if let crate::dom::StyleValue::Transitions(specs) = val {
+                                next_computed.transitions = specs.clone();
+                            },
+                            69 => if let crate::dom::StyleValue::Counters(counters) = val {
+                                next_computed.counter_reset = counters.clone();
+                            },
+                            70 => if let crate::dom::StyleValue::Counters(counters) = val {
+                                next_computed.counter_increment = counters.clone();
+                            },
+                            71 => if let crate::dom::StyleValue::Keyword(v) = val {
+                                next_computed.white_space = match &**v {
+                                    "nowrap" => crate::dom::WhiteSpaceKeyword::Nowrap,
+                                    "pre" => crate::dom::WhiteSpaceKeyword::Pre,
+                                    "pre-wrap" => crate::dom::WhiteSpaceKeyword::PreWrap,
+                                    "pre-line" => crate::dom::WhiteSpaceKeyword::PreLine,
+                                    _ => crate::dom::WhiteSpaceKeyword::Normal,
+                                };
+                            },
+                            72 => if let crate::dom::StyleValue::BoxShadows(shadows) = val {
+                                next_computed.box_shadow = shadows.clone();
+                            },
+                            73 => if let crate::dom::StyleValue::Number(n) = val
+                                && n.fract() == 0.0
+                            {
+                                next_computed.order = *n as i32;
+                            },
+                            74 => match val {
+                                crate::dom::StyleValue::Color(r, g, b, a) => next_computed.caret_color = Some((*r, *g, *b, *a)),
+                                crate::dom::StyleValue::Auto => next_computed.caret_color = None,
+                                _ => {}
+                            },
+                            75 => match val {
+                                crate::dom::StyleValue::Url(url) => next_computed.background_image = Some(url.as_str().into()),
+                                crate::dom::StyleValue::None => next_computed.background_image = None,
+                                crate::dom::StyleValue::Keyword(k) if &**k == "none" => next_computed.background_image = None,
+                                _ => {}
+                            },
                             _ => {}
                         }
                     }
@@ -988,29 +2493,49 @@ pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &Sty
             
             if next_computed.font_size == 0.0 {
                 next_computed.font_size = 16.0;
-                next_text_computed.font_size = 16.0;
             }
         }
 
+        if must_rematch || parent_inheritable_changed {
+            next_text_computed = crate::dom::TextComputedStyle::from_computed(&next_computed);
+        }
+
         let mut next_inheritable_changed = false;
+        let mut structure_changed = false;
 
         if let Some(node_mut) = document.nodes.get_mut(node_id) {
             match node_mut {
                 crate::dom::Node::Element(data) => {
+                    // `Document::set_hidden` overrides whatever display the
+                    // rules give; a fresh match updates the value to restore.
+                    if let Some(display) = &mut data.hidden_display {
+                        let cascaded = std::mem::replace(&mut next_computed.display, crate::dom::DisplayKeyword::None);
+                        if must_rematch {
+                            *display = cascaded;
+                        }
+                    }
                     if data.computed != next_computed {
-                        if data.computed.font_size != next_computed.font_size || data.computed.color != next_computed.color {
+                        if !data.computed.inherited_eq(&next_computed) {
                             next_inheritable_changed = true;
                         }
+                        // Toggling `content-visibility: hidden` attaches or
+                        // detaches the element's children in the layout tree.
+                        structure_changed = (data.computed.content_visibility
+                            == crate::dom::ContentVisibilityKeyword::Hidden)
+                            != (next_computed.content_visibility == crate::dom::ContentVisibilityKeyword::Hidden);
+                        // Floats are regrouped and reordered within their line,
+                        // and `order` reorders the item among its siblings.
+                        structure_changed |= data.computed.float != next_computed.float;
+                        structure_changed |= data.computed.order != next_computed.order;
                         data.computed = next_computed.clone();
                         data.layout_dirty = true;
                     }
+                    data.layout_dirty |= rem_changed;
                     data.styles_dirty = false;
                 }
                 crate::dom::Node::Text(data) => {
                     if data.computed != next_text_computed {
-                        if data.computed.font_size != next_text_computed.font_size || data.computed.color != next_text_computed.color {
-                            next_inheritable_changed = true;
-                        }
+                        next_inheritable_changed = true;
                         data.computed = next_text_computed.clone();
                         data.layout_dirty = true;
                     }
@@ -1019,6 +2544,81 @@ pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &Sty
                 crate::dom::Node::Root(_) => {}
             }
         }
+        if structure_changed {
+            document.dirty = true;
+        }
+
+        let is_element = matches!(document.nodes.get(node_id), Some(crate::dom::Node::Element(_)));
+        if is_element && depth == 1 && !document_element_seen {
+            document_element_seen = true;
+            rem_base = next_computed.font_size;
+            if document.rem_base != rem_base {
+                document.rem_base = rem_base;
+                rem_changed = true;
+            }
+        }
+        if is_element && next_computed.display != crate::dom::DisplayKeyword::None {
+            for (name, value) in next_computed.counter_reset.iter() {
+                // A sibling resetting the same counter replaces its instance.
+                match counters.iter_mut().rev().take_while(|c| c.2 == depth).find(|c| c.0 == *name) {
+                    Some(counter) => counter.1 = *value,
+                    None => counters.push((name.clone(), *value, depth)),
+                }
+            }
+            for (name, value) in next_computed.counter_increment.iter() {
+                match counters.iter_mut().rev().find(|c| c.0 == *name) {
+                    Some(counter) => counter.1 = counter.1.wrapping_add(*value),
+                    None => counters.push((name.clone(), *value, depth)),
+                }
+            }
+        }
+
+        if must_rematch && is_element {
+            let uses_counters = pseudo_decls[..2].iter().any(|decls| {
+                matches!(pseudo_content(decls), Some(crate::dom::StyleValue::Content(_)))
+            });
+            let letter_decls = &pseudo_decls[crate::dom::PseudoElement::FirstLetter as usize];
+            if let Some(crate::dom::Node::Element(data)) = document.nodes.get_mut(node_id) {
+                data.counter_content = uses_counters;
+                data.first_letter = (!letter_decls.is_empty())
+                    .then(|| Box::new(pseudo_text_style(letter_decls, &next_computed, rem_base)));
+            }
+            for (pseudo, decls) in [crate::dom::PseudoElement::Before, crate::dom::PseudoElement::After]
+                .into_iter()
+                .zip(&pseudo_decls)
+            {
+                let content = resolve_pseudo_element(decls, &next_computed, rem_base, &counters);
+                document.set_pseudo_element(node_id, pseudo, content);
+            }
+        }
+
+        // Like markers, the first letter is placed on every pass: inserting
+        // text ahead of it moves it without dirtying any style.
+        match document.nodes.get_mut(node_id) {
+            Some(crate::dom::Node::Element(data)) => {
+                if let Some(style) = data.first_letter.as_ref().filter(|_| next_computed.display != crate::dom::DisplayKeyword::None) {
+                    first_letter = Some((style.clone(), depth));
+                }
+            }
+            Some(crate::dom::Node::Text(data)) => {
+                let letter = if data.text.trim().is_empty() { None } else { first_letter.take().map(|(style, _)| style) };
+                if data.first_letter != letter {
+                    data.first_letter = letter;
+                    data.layout_dirty = true;
+                }
+            }
+            _ => {}
+        }
+
+        // Markers are refreshed on every pass, since inserting or removing an
+        // item renumbers its siblings without dirtying their styles.
+        if let Some(ordinal) = list_ordinals.remove(&node_id) {
+            let marker = list_marker_text(next_computed.list_style_type, ordinal)
+                .map(|text| (text, crate::dom::TextComputedStyle::from_computed(&next_computed)));
+            document.set_pseudo_element(node_id, crate::dom::PseudoElement::Marker, marker);
+        } else if matches!(document.nodes.get(node_id), Some(crate::dom::Node::Element(data)) if data.pseudo_marker.is_some()) {
+            document.set_pseudo_element(node_id, crate::dom::PseudoElement::Marker, None);
+        }
 
         let shared_style = next_computed; // For pushing to stack
 
@@ -1029,25 +2629,180 @@ pub fn compute_styles(document: &mut crate::dom::Document, base_stylesheet: &Sty
             children.push(c);
             child = document.next_sibling_of(c);
         }
+        if matches!(document.nodes.get(node_id), Some(crate::dom::Node::Element(data)) if matches!(&*data.tag_name, "ul" | "ol")) {
+            let items = children
+                .iter()
+                .filter(|&&c| matches!(document.nodes.get(c), Some(crate::dom::Node::Element(d)) if &*d.tag_name == "li"));
+            list_ordinals.extend(items.zip(1..).map(|(&c, n)| (c, n)));
+        }
         for c in children.into_iter().rev() {
-            stack.push((c, Some(shared_style.clone()), next_inheritable_changed, next_ancestor_attr_changed));
+            stack.push((c, Some(shared_style.clone()), next_inheritable_changed, next_ancestor_attr_changed, depth + 1));
+        }
+    }
+    if !document_element_seen {
+        document.rem_base = root_font_size;
+    }
+}
+
+/// Elements that default to `display: inline` in the absence of a UA stylesheet.
+static INLINE_TAGS: phf::Set<&'static str> = phf::phf_set! {
+    "a", "abbr", "b", "bdi", "bdo", "br", "cite", "code", "data", "dfn", "em", "i", "img",
+    "kbd", "label", "mark", "q", "s", "samp", "small", "span", "strong", "sub", "sup",
+    "time", "u", "var", "wbr",
+};
+
+fn align_items_keyword(v: &str) -> crate::dom::AlignItemsKeyword {
+    match v {
+        "flex-end" | "end" => crate::dom::AlignItemsKeyword::FlexEnd,
+        "center" => crate::dom::AlignItemsKeyword::Center,
+        "baseline" => crate::dom::AlignItemsKeyword::Baseline,
+        "flex-start" | "start" => crate::dom::AlignItemsKeyword::FlexStart,
+        _ => crate::dom::AlignItemsKeyword::Stretch,
+    }
+}
+
+fn justify_content_keyword(v: &str) -> crate::dom::JustifyContentKeyword {
+    match v {
+        "flex-end" | "end" => crate::dom::JustifyContentKeyword::FlexEnd,
+        "center" => crate::dom::JustifyContentKeyword::Center,
+        "space-between" => crate::dom::JustifyContentKeyword::SpaceBetween,
+        "space-around" => crate::dom::JustifyContentKeyword::SpaceAround,
+        "space-evenly" => crate::dom::JustifyContentKeyword::SpaceEvenly,
+        _ => crate::dom::JustifyContentKeyword::FlexStart,
+    }
+}
+
+/// Marker text for the `ordinal`-th item of a list, or `None` for
+/// `list-style-type: none`.
+fn list_marker_text(ty: crate::dom::ListStyleTypeKeyword, ordinal: u32) -> Option<String> {
+    match ty {
+        crate::dom::ListStyleTypeKeyword::Disc => Some("\u{2022} ".to_string()),
+        crate::dom::ListStyleTypeKeyword::Circle => Some("\u{25e6} ".to_string()),
+        crate::dom::ListStyleTypeKeyword::Decimal => Some(format!("{ordinal}. ")),
+        crate::dom::ListStyleTypeKeyword::None => None,
+    }
+}
+
+/// The winning `content` value among a pseudo-element's declarations.
+fn pseudo_content<'a>(decls: &[&'a Declaration]) -> Option<&'a crate::dom::StyleValue> {
+    decls
+        .iter()
+        .rev()
+        .find(|d| d.name == crate::dom::PropertyName::Content)
+        .map(|d| &d.value)
+}
+
+/// Resolve one pseudo-element from its matched declarations. Returns `None`
+/// unless the winning `content` is a string literal or a list of strings and
+/// counters, which are read from `counters` (a missing counter is 0). The
+/// generated text inherits from the element and honours its own `color` and
+/// `font-size`.
+fn resolve_pseudo_element(
+    decls: &[&Declaration],
+    element: &crate::dom::ComputedStyle,
+    root_font_size: f32,
+    counters: &[(String, i32, usize)],
+) -> Option<(String, crate::dom::TextComputedStyle)> {
+    let text = match pseudo_content(decls)? {
+        crate::dom::StyleValue::String(text) => text.clone(),
+        crate::dom::StyleValue::Content(items) => {
+            use std::fmt::Write;
+            let mut text = String::new();
+            for item in items.iter() {
+                match item {
+                    crate::dom::ContentItem::Text(s) => text.push_str(s),
+                    crate::dom::ContentItem::Counter(name) => {
+                        let value = counters.iter().rev().find(|c| c.0 == *name).map_or(0, |c| c.1);
+                        let _ = write!(text, "{value}");
+                    }
+                    crate::dom::ContentItem::Counters(name, separator) => {
+                        let mut values = counters.iter().filter(|c| c.0 == *name).peekable();
+                        if values.peek().is_none() {
+                            text.push('0');
+                        }
+                        for (i, counter) in values.enumerate() {
+                            if i > 0 {
+                                text.push_str(separator);
+                            }
+                            let _ = write!(text, "{}", counter.1);
+                        }
+                    }
+                }
+            }
+            text
+        }
+        _ => return None,
+    };
+
+    Some((text, pseudo_text_style(decls, element, root_font_size)))
+}
+
+/// Text style for a pseudo-element of `element`: its inherited text style
+/// with any `color` and `font-size` from `decls` applied.
+fn pseudo_text_style(
+    decls: &[&Declaration],
+    element: &crate::dom::ComputedStyle,
+    root_font_size: f32,
+) -> crate::dom::TextComputedStyle {
+    let mut style = crate::dom::TextComputedStyle::from_computed(element);
+    for decl in decls {
+        match (decl.name, &decl.value) {
+            (crate::dom::PropertyName::Color, crate::dom::StyleValue::Color(r, g, b, a)) => {
+                style.color = (*r, *g, *b, *a);
+            }
+            (crate::dom::PropertyName::FontSize, value) => match value {
+                crate::dom::StyleValue::LengthPx(n) | crate::dom::StyleValue::Number(n) => style.font_size = *n,
+                crate::dom::StyleValue::Em(n) => style.font_size = n * element.font_size,
+                crate::dom::StyleValue::Rem(n) => style.font_size = n * root_font_size,
+                _ => {}
+            },
+            _ => {}
         }
     }
+    style
 }
 
 pub fn append_stylesheet(css: &str, stylesheet: &mut StyleSheet) {
     let mut input = cssparser::ParserInput::new(css);
     let mut parser = cssparser::Parser::new(&mut input);
-    parse_rules_list(&mut parser, stylesheet);
+    parse_rules_list(&mut parser, stylesheet, &mut Vec::new());
     stylesheet.sort_rules();
 }
 
-fn parse_rules_list<'i, 't>(parser: &mut Parser<'i, 't>, stylesheet: &mut StyleSheet) {
+#[cfg(test)]
+thread_local! {
+    static STYLESHEET_PARSES: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
+
+/// How many stylesheet texts this thread has parsed, so tests can check that
+/// a restyle parses no style text again.
+#[cfg(test)]
+pub(crate) fn stylesheet_parse_count() -> u64 {
+    STYLESHEET_PARSES.with(|count| count.get())
+}
+
+fn parse_rules_list<'i, 't>(parser: &mut Parser<'i, 't>, stylesheet: &mut StyleSheet, errors: &mut Vec<CssError>) {
+    #[cfg(test)]
+    STYLESHEET_PARSES.with(|count| count.set(count.get() + 1));
     while !parser.is_exhausted() {
-        match parse_rule(parser) {
+        parser.skip_whitespace();
+        let start = parser.state();
+        if let Ok(Token::AtKeyword(name)) = parser.next() {
+            if name.eq_ignore_ascii_case("keyframes") || name.eq_ignore_ascii_case("-webkit-keyframes") {
+                parse_keyframes(parser, stylesheet, errors);
+            } else {
+                // Other at-rules (`@media`, `@import`, ...) are skipped whole.
+                errors.push(CssError::at(start.source_location(), format!("unsupported at-rule `@{name}`")));
+                while !matches!(parser.next(), Ok(Token::CurlyBracketBlock) | Ok(Token::Semicolon) | Err(_)) {}
+            }
+            continue;
+        }
+        parser.reset(&start);
+        match parse_rule(parser, errors) {
             Ok(Some(rule)) => stylesheet.add_rule(rule),
             Ok(None) => {}
             Err(_) => {
+                errors.push(CssError::at(start.source_location(), "malformed rule".to_string()));
                 let _ = parser.parse_until_before(cssparser::Delimiter::CurlyBracketBlock, |p| {
                     while p.next().is_ok() {}
                     Ok::<(), cssparser::ParseError<()>>(())
@@ -1060,11 +2815,15 @@ fn parse_rules_list<'i, 't>(parser: &mut Parser<'i, 't>, stylesheet: &mut StyleS
 
 fn parse_rule<'i, 't>(
     parser: &mut Parser<'i, 't>,
+    errors: &mut Vec<CssError>,
 ) -> Result<Option<StyleRule>, cssparser::ParseError<'i, ()>> {
+    let location = parser.current_source_location();
     // 1. Collect raw selector text
     let mut raw_selectors = String::new();
+    let mut has_block = false;
     while let Ok(token) = parser.next_including_whitespace() {
         if matches!(token, Token::CurlyBracketBlock) {
+            has_block = true;
             break;
         }
         match token {
@@ -1077,6 +2836,23 @@ fn parse_rule<'i, 't>(
             Token::WhiteSpace(_) => raw_selectors.push(' '),
             Token::Comma => raw_selectors.push(','),
             Token::Colon => raw_selectors.push(':'),
+            // Functional pseudo-classes keep a single identifier or string
+            // argument (`:lang(en)`); anything longer is dropped, leaving
+            // `name()`.
+            Token::Function(name) => {
+                raw_selectors.push_str(name);
+                raw_selectors.push('(');
+                let arg = parser.parse_nested_block(|p| {
+                    let arg = match p.next()? {
+                        Token::Ident(s) | Token::QuotedString(s) => s.to_string(),
+                        _ => String::new(),
+                    };
+                    p.expect_exhausted()?;
+                    Ok::<_, cssparser::ParseError<()>>(arg)
+                });
+                raw_selectors.push_str(arg.as_deref().unwrap_or(""));
+                raw_selectors.push(')');
+            }
             _ => {}
         }
     }
@@ -1084,72 +2860,273 @@ fn parse_rule<'i, 't>(
     if raw_selectors.is_empty() {
         return Ok(None);
     }
+    // A prelude running to the end of the sheet has no block to parse.
+    if !has_block {
+        errors.push(CssError::at(location, "expected `{` after selector".to_string()));
+        return Ok(None);
+    }
 
     // 2. Parse the raw selector string into an AST
     let selectors = parse_selector_list(&raw_selectors);
     if selectors.is_empty() {
+        errors.push(CssError::at(location, format!("invalid selector `{}`", raw_selectors.trim())));
         return Ok(None);
     }
 
     // 3. Parse declarations (inside `{...}`)
+    match parse_declaration_block(parser, errors) {
+        Some(declarations) => Ok(Some(StyleRule {
+            selectors,
+            declarations,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Parse the `{...}` block the parser has just stepped over into declarations.
+/// Declarations that cannot apply are skipped and reported to `errors`.
+fn parse_declaration_block<'i, 't>(parser: &mut Parser<'i, 't>, errors: &mut Vec<CssError>) -> Option<Vec<Declaration>> {
     let mut declarations = Vec::new();
     let result = parser.parse_nested_block(|p| {
         while !p.is_exhausted() {
-            if let Ok(ident) = p.expect_ident() {
-                let name = ident.as_ref().to_owned();
-                let _ = p.expect_colon();
-
-                let mut value = String::new();
-                while let Ok(token) = p.next() {
-                    if matches!(token, Token::Semicolon) {
-                        break;
-                    }
-                    match token {
-                        Token::Ident(n) => value.push_str(n),
-                        Token::Number { value: v, .. } => value.push_str(&v.to_string()),
-                        Token::Dimension { value: v, unit, .. } => {
-                            value.push_str(&v.to_string());
-                            value.push_str(unit.as_ref());
-                        }
-                        Token::QuotedString(s) => value.push_str(s),
-                        _ => {}
+            p.skip_whitespace();
+            let start = p.state();
+            let location = start.source_location();
+            let name = match p.expect_ident() {
+                Ok(ident) => ident.as_ref().to_owned(),
+                Err(_) => {
+                    p.reset(&start);
+                    if !matches!(p.next(), Ok(Token::Semicolon)) {
+                        errors.push(CssError::at(location, "expected a property name".to_string()));
+                        collect_value_text(p);
                     }
+                    continue;
                 }
+            };
+            if p.expect_colon().is_err() {
+                errors.push(CssError::at(location, format!("expected `:` after `{name}`")));
+                collect_value_text(p);
+                continue;
+            }
+            let value = collect_value_text(p);
+            if value.trim().is_empty() {
+                errors.push(CssError::at(location, format!("empty value for `{name}`")));
+            } else if !expand_declaration(&name, value.trim(), &mut declarations) {
+                errors.push(CssError::at(location, format!("unknown property `{name}`")));
+            }
+        }
+        Ok::<(), cssparser::ParseError<()>>(())
+    });
+    result.ok().map(|()| declarations)
+}
 
-                // Expand shorthand properties
-                let value_trimmed = value.trim();
-                let name_str = name; // string_cache interning input string
-                if name_str == "margin" || name_str == "padding" {
-                    expand_margin_padding_shorthand(name_str.as_str(), value_trimmed, &mut declarations);
-                } else if name_str == "background" {
-                    if let Some(p) = crate::dom::PropertyName::from_str("background-color") {
-                        declarations.push(Declaration {
-                            name: p,
-                            value: parse_style_value(&value),
-                        });
-                    }
-                } else {
-                    if let Some(p) = crate::dom::PropertyName::from_str(&name_str) {
-                        declarations.push(Declaration {
-                            name: p,
-                            value: parse_style_value(&value),
-                        });
+/// Parse the rest of an `@keyframes` rule (after the at-keyword) into
+/// `stylesheet.keyframes`. Steps with an invalid selector are dropped; a
+/// rule without a name or block is skipped whole.
+fn parse_keyframes<'i, 't>(parser: &mut Parser<'i, 't>, stylesheet: &mut StyleSheet, errors: &mut Vec<CssError>) {
+    let name = match parser.next() {
+        Ok(Token::Ident(n)) | Ok(Token::QuotedString(n)) => n.as_ref().to_owned(),
+        // A nameless rule's block is skipped by the next read.
+        Ok(Token::CurlyBracketBlock) | Err(_) => return,
+        _ => String::new(),
+    };
+    // Skip to the block (or the end of a malformed rule).
+    loop {
+        match parser.next() {
+            Ok(Token::CurlyBracketBlock) => break,
+            Ok(Token::Semicolon) | Err(_) => return,
+            Ok(_) => {}
+        }
+    }
+    let mut steps = Vec::new();
+    let _ = parser.parse_nested_block(|p| {
+        while !p.is_exhausted() {
+            let location = p.current_source_location();
+            let mut offsets = Vec::new();
+            let mut valid = true;
+            loop {
+                match p.next() {
+                    Ok(Token::CurlyBracketBlock) => break,
+                    Ok(Token::Percentage { unit_value, .. }) if (0.0..=1.0).contains(unit_value) => {
+                        offsets.push(*unit_value)
                     }
+                    Ok(Token::Ident(i)) if i.eq_ignore_ascii_case("from") => offsets.push(0.0),
+                    Ok(Token::Ident(i)) if i.eq_ignore_ascii_case("to") => offsets.push(1.0),
+                    Ok(Token::Comma) => {}
+                    Ok(_) => valid = false,
+                    Err(_) => return Ok::<(), cssparser::ParseError<()>>(()),
                 }
-            } else {
-                let _ = p.next();
+            }
+            if !valid {
+                errors.push(CssError::at(location, "invalid keyframe selector".to_string()));
+            }
+            if let Some(declarations) = parse_declaration_block(p, errors)
+                && valid
+            {
+                steps.extend(offsets.into_iter().map(|offset| Keyframe { offset, declarations: declarations.clone() }));
             }
         }
-        Ok::<(), cssparser::ParseError<()>>(())
+        Ok(())
     });
+    if name.is_empty() || name == "none" {
+        return;
+    }
+    steps.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+    stylesheet.keyframes.insert(name, std::rc::Rc::new(steps));
+}
 
-    if result.is_ok() {
-        Ok(Some(StyleRule {
-            selectors,
-            declarations,
-        }))
-    } else {
-        Ok(None)
+// ---------------------------------------------------------------------------
+// Keyframe sampling
+// ---------------------------------------------------------------------------
+
+/// Values of the `@keyframes` block `name` at `progress` (clamped to 0..=1),
+/// one declaration per property the block mentions, in first-mention order.
+/// Empty if `sheet` has no such block.
+///
+/// Each property interpolates between the nearest steps on either side that
+/// declare it; before its first or after its last step it holds that step's
+/// value (there is no underlying computed value to blend with). Lengths,
+/// percentages and numbers of the same unit, colors and transforms blend
+/// linearly; anything else switches halfway. No easing is applied. Hosts
+/// feed the result back in through a restyle, e.g. as inline declarations.
+pub fn sample_animation(sheet: &StyleSheet, name: &str, progress: f32) -> Vec<Declaration> {
+    let Some(steps) = sheet.keyframes.get(name) else {
+        return Vec::new();
+    };
+    let progress = progress.clamp(0.0, 1.0);
+    let mut properties: Vec<crate::dom::PropertyName> = Vec::new();
+    for decl in steps.iter().flat_map(|step| &step.declarations) {
+        if !properties.contains(&decl.name) {
+            properties.push(decl.name);
+        }
+    }
+
+    fn value_at(step: &Keyframe, property: crate::dom::PropertyName) -> Option<(f32, &crate::dom::StyleValue)> {
+        step.declarations.iter().rev().find(|d| d.name == property).map(|d| (step.offset, &d.value))
+    }
+    properties
+        .into_iter()
+        .filter_map(|property| {
+            let before = steps.iter().rev().filter(|s| s.offset <= progress).find_map(|s| value_at(s, property));
+            let after = steps.iter().filter(|s| s.offset >= progress).find_map(|s| value_at(s, property));
+            let value = match (before, after) {
+                (Some((a, from)), Some((b, to))) if b > a => interpolate(from, to, (progress - a) / (b - a)),
+                (Some((_, v)), _) | (None, Some((_, v))) => v.clone(),
+                (None, None) => return None,
+            };
+            Some(Declaration { name: property, value })
+        })
+        .collect()
+}
+
+/// Values for the properties transitioning from `old` to `new` after
+/// `elapsed` seconds, using `new`'s `transition` list. Hosts snapshot an
+/// element's `ComputedStyle` before a restyle and call this each tick with
+/// the time since the restyle, applying the result as overrides (e.g.
+/// inline declarations); when a property's duration has passed it is left
+/// out and the plain computed value shows.
+///
+/// Only properties that blend are transitioned: box lengths (sizes,
+/// margins, paddings, insets, gaps), colors, `font-size`, spacing,
+/// `opacity` and `transform`. A later entry for the same property wins, as
+/// does `all`. Timing is linear; nothing is retargeted mid-flight, so a
+/// host restarting a transition passes the current overrides as `old`.
+pub fn transition_values(
+    old: &crate::dom::ComputedStyle,
+    new: &crate::dom::ComputedStyle,
+    elapsed: f32,
+) -> Vec<Declaration> {
+    let mut out: Vec<Declaration> = Vec::new();
+    for property in crate::dom::PropertyName::ALL {
+        let Some(spec) = new
+            .transitions
+            .iter()
+            .rev()
+            .find(|spec| spec.property.is_none_or(|p| p == property))
+        else {
+            continue;
+        };
+        if elapsed >= spec.duration {
+            continue;
+        }
+        let (Some(from), Some(to)) = (animatable_value(old, property), animatable_value(new, property)) else {
+            continue;
+        };
+        if from != to {
+            out.push(Declaration { name: property, value: interpolate(&from, &to, elapsed.max(0.0) / spec.duration) });
+        }
+    }
+    out
+}
+
+/// `property`'s computed value on `style` as a `StyleValue`, for the
+/// properties `transition_values` can blend.
+fn animatable_value(style: &crate::dom::ComputedStyle, property: crate::dom::PropertyName) -> Option<crate::dom::StyleValue> {
+    use crate::dom::StyleValue as V;
+    let color = |c: Option<(u8, u8, u8, u8)>| {
+        let (r, g, b, a) = c.unwrap_or((0, 0, 0, 0));
+        V::Color(r, g, b, a)
+    };
+    Some(match property.to_index() {
+        2 => style.width.clone(),
+        3 => style.height.clone(),
+        i @ 4..=7 => style.margin[i - 4].clone(),
+        i @ 8..=11 => style.padding[i - 8].clone(),
+        16 => color(style.bg_color),
+        17 => color(style.border_color),
+        18 => color(Some(style.color)),
+        19 => V::LengthPx(style.font_size),
+        30 => style.row_gap.clone(),
+        31 => style.column_gap.clone(),
+        32 => style.min_width.clone(),
+        33 => style.max_width.clone(),
+        34 => style.min_height.clone(),
+        35 => style.max_height.clone(),
+        i @ 37..=40 => style.inset[i - 37].clone(),
+        43 => color(style.outline_color),
+        53 => V::Transform(style.transform.unwrap_or(crate::dom::Transform::IDENTITY)),
+        57 => V::Number(style.opacity),
+        61 => V::LengthPx(style.letter_spacing),
+        62 => V::LengthPx(style.word_spacing),
+        _ => return None,
+    })
+}
+
+/// `from` blended toward `to` by `t` in 0..=1.
+fn interpolate(from: &crate::dom::StyleValue, to: &crate::dom::StyleValue, t: f32) -> crate::dom::StyleValue {
+    use crate::dom::StyleValue as V;
+    let lerp = |a: f32, b: f32| a + (b - a) * t;
+    // A unitless zero (`width: 0`) blends as a zero of the other side's unit.
+    let zero_like = |v: &V, other: &V| match (v, other) {
+        (V::Number(n), V::LengthPx(_)) if *n == 0.0 => V::LengthPx(0.0),
+        (V::Number(n), V::Percent(_)) if *n == 0.0 => V::Percent(0.0),
+        (V::Number(n), V::Em(_)) if *n == 0.0 => V::Em(0.0),
+        (V::Number(n), V::Rem(_)) if *n == 0.0 => V::Rem(0.0),
+        (V::Number(n), V::ViewportWidth(_)) if *n == 0.0 => V::ViewportWidth(0.0),
+        (V::Number(n), V::ViewportHeight(_)) if *n == 0.0 => V::ViewportHeight(0.0),
+        _ => v.clone(),
+    };
+    let (from, to) = (&zero_like(from, to), &zero_like(to, from));
+    match (from, to) {
+        (V::LengthPx(a), V::LengthPx(b)) => V::LengthPx(lerp(*a, *b)),
+        (V::Percent(a), V::Percent(b)) => V::Percent(lerp(*a, *b)),
+        (V::ViewportWidth(a), V::ViewportWidth(b)) => V::ViewportWidth(lerp(*a, *b)),
+        (V::ViewportHeight(a), V::ViewportHeight(b)) => V::ViewportHeight(lerp(*a, *b)),
+        (V::Em(a), V::Em(b)) => V::Em(lerp(*a, *b)),
+        (V::Rem(a), V::Rem(b)) => V::Rem(lerp(*a, *b)),
+        (V::Number(a), V::Number(b)) => V::Number(lerp(*a, *b)),
+        (V::Color(r1, g1, b1, a1), V::Color(r2, g2, b2, a2)) => {
+            let channel = |a: u8, b: u8| lerp(a as f32, b as f32).round() as u8;
+            V::Color(channel(*r1, *r2), channel(*g1, *g2), channel(*b1, *b2), channel(*a1, *a2))
+        }
+        (V::Transform(a), V::Transform(b)) => V::Transform(crate::dom::Transform {
+            translate_x: lerp(a.translate_x, b.translate_x),
+            translate_y: lerp(a.translate_y, b.translate_y),
+            scale_x: lerp(a.scale_x, b.scale_x),
+            scale_y: lerp(a.scale_y, b.scale_y),
+        }),
+        _ if t < 0.5 => from.clone(),
+        _ => to.clone(),
     }
 }
 
@@ -1169,53 +3146,13 @@ impl<'i> DeclarationParser<'i> for InlineStyleParser {
         input: &mut Parser<'i, 't>,
         _start: &ParserState,
     ) -> Result<Vec<Declaration>, cssparser::ParseError<'i, ()>> {
-        let mut value = String::new();
-        while let Ok(token) = input.next() {
-            match token {
-                Token::Ident(n) => value.push_str(n),
-                Token::Number { value: v, .. } => value.push_str(&v.to_string()),
-                Token::Dimension { value: v, unit, .. } => {
-                    value.push_str(&v.to_string());
-                    value.push_str(unit.as_ref());
-                }
-                Token::Percentage { unit_value, .. } => {
-                    value.push_str(&(unit_value * 100.0).to_string());
-                    value.push('%');
-                }
-                Token::Hash(s) | Token::IDHash(s) => {
-                    value.push('#');
-                    value.push_str(s);
-                }
-                Token::QuotedString(s) => value.push_str(s),
-                Token::WhiteSpace(_) => value.push(' '),
-                Token::Comma => value.push(','),
-                Token::Delim(c) => value.push(*c),
-                _ => {}
-            }
-        }
-
-        let name_str = name.as_ref();
-        let value_trimmed = value.trim();
+        let value = collect_value_text(input);
         let mut declarations = Vec::new();
-
-        if name_str == "margin" || name_str == "padding" {
-            expand_margin_padding_shorthand(name_str, value_trimmed, &mut declarations);
-        } else if name_str == "background" {
-            if let Some(p) = crate::dom::PropertyName::from_str("background-color") {
-                declarations.push(Declaration { name: p, value: parse_style_value(value_trimmed) });
-            }
-        } else {
-            if let Some(prop_name) = crate::dom::PropertyName::from_str(name_str) {
-                declarations.push(Declaration {
-                    name: prop_name,
-                    value: parse_style_value(value_trimmed),
-                });
-            } else {
-                return Err(cssparser::ParseError {
-                    kind: cssparser::ParseErrorKind::Custom(()),
-                    location: input.state().source_location(),
-                });
-            }
+        if !expand_declaration(name.as_ref(), value.trim(), &mut declarations) {
+            return Err(cssparser::ParseError {
+                kind: cssparser::ParseErrorKind::Custom(()),
+                location: input.state().source_location(),
+            });
         }
 
         Ok(declarations)
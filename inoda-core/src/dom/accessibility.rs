@@ -0,0 +1,237 @@
+//! Simplified accessibility tree for embedders that bridge to platform
+//! assistive technology APIs.
+//!
+//! Roles come from the `role` attribute or the element's implicit HTML
+//! semantics, names from `aria-labelledby`, `aria-label`, `alt`, descendant
+//! text (for roles named by their content) and `title`, in that order. This
+//! is a small subset of the ARIA and HTML-AAM mapping specs: label elements,
+//! states such as `aria-checked`, and CSS-generated text are not considered.
+
+use super::{DisplayKeyword, Document, Node, NodeId};
+
+/// One node of [`Document::accessibility_tree`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AxNode {
+    /// The DOM node this was built from; the root of the tree is the
+    /// document root and text leaves are their text nodes.
+    pub node_id: NodeId,
+    /// An ARIA role name such as `button` or `heading`; `document` for the
+    /// root and `text` for text leaves.
+    pub role: String,
+    /// Accessible name, whitespace collapsed. Text leaves carry their text.
+    pub name: Option<String>,
+    /// Current value of form controls and ranges (`value`, `aria-valuetext`,
+    /// `aria-valuenow`).
+    pub value: Option<String>,
+    /// Heading level, from `aria-level` or the `h1`-`h6` tag.
+    pub level: Option<u32>,
+    pub children: Vec<AxNode>,
+}
+
+/// Elements that are never rendered and so have no accessible presence.
+const NON_RENDERED_TAGS: &[&str] = &["head", "script", "style", "template", "title", "meta", "link", "noscript"];
+
+/// Roles whose accessible name is computed from their descendant text.
+const NAME_FROM_CONTENT: &[&str] = &[
+    "button", "link", "heading", "cell", "columnheader", "rowheader", "listitem", "menuitem", "option", "tab",
+    "checkbox", "radio", "switch", "treeitem", "tooltip",
+];
+
+fn collapse_whitespace(text: &str) -> Option<String> {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!collapsed.is_empty()).then_some(collapsed)
+}
+
+impl Document {
+    /// Builds the accessibility tree of the attached document.
+    ///
+    /// Elements that are `display: none` (as of the last cascade), carry
+    /// `hidden` or `aria-hidden="true"`, or are never rendered (`script`,
+    /// `style`, `head`, ...) are left out with their subtrees. Elements with
+    /// no semantics (`div`, `span`, `role="presentation"`, ...) and no name
+    /// are flattened: their children join the parent. Non-blank text nodes
+    /// become `text` leaves.
+    pub fn accessibility_tree(&self) -> AxNode {
+        struct Frame {
+            node: Option<AxNode>,
+            children: Vec<AxNode>,
+            next: Option<NodeId>,
+        }
+        let root = AxNode {
+            node_id: self.root_id,
+            role: "document".to_string(),
+            name: self.title.clone(),
+            value: None,
+            level: None,
+            children: Vec::new(),
+        };
+        let mut frames = vec![Frame { node: Some(root), children: Vec::new(), next: self.first_child_of(self.root_id) }];
+        loop {
+            let top = frames.last_mut().expect("the root frame is popped last");
+            let Some(child) = top.next else {
+                let frame = frames.pop().expect("non-empty");
+                let built = match frame.node {
+                    Some(mut node) => {
+                        node.children = frame.children;
+                        vec![node]
+                    }
+                    None => frame.children,
+                };
+                match frames.last_mut() {
+                    Some(parent) => parent.children.extend(built),
+                    None => return built.into_iter().next().expect("the root frame has a node"),
+                }
+                continue;
+            };
+            top.next = self.next_sibling_of(child);
+            match self.nodes.get(child) {
+                Some(Node::Text(data)) => {
+                    if let Some(text) = collapse_whitespace(&data.text) {
+                        top.children.push(AxNode {
+                            node_id: child,
+                            role: "text".to_string(),
+                            name: Some(text),
+                            value: None,
+                            level: None,
+                            children: Vec::new(),
+                        });
+                    }
+                }
+                Some(Node::Element(_)) if !self.is_accessibility_hidden(child) => {
+                    let node = self.ax_element(child);
+                    frames.push(Frame { node, children: Vec::new(), next: self.first_child_of(child) });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn is_accessibility_hidden(&self, node_id: NodeId) -> bool {
+        let Some(Node::Element(data)) = self.nodes.get(node_id) else {
+            return true;
+        };
+        NON_RENDERED_TAGS.contains(&&*data.tag_name)
+            || data.computed.display == DisplayKeyword::None
+            || data.attributes.iter().any(|(key, _)| &**key == "hidden")
+            || self.get_attribute(node_id, "aria-hidden").is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+    }
+
+    /// The node for an element, or `None` if it has no role worth exposing
+    /// and no name, so its children are hoisted.
+    fn ax_element(&self, node_id: NodeId) -> Option<AxNode> {
+        let role = self.ax_role(node_id);
+        let name = self.ax_name(node_id, &role);
+        if matches!(role.as_str(), "generic" | "presentation" | "none") && name.is_none() {
+            return None;
+        }
+        let attr = |key| self.get_attribute(node_id, key);
+        let value = match role.as_str() {
+            "textbox" | "searchbox" | "combobox" | "slider" | "spinbutton" | "progressbar" | "meter" | "scrollbar" => attr(
+                "aria-valuetext",
+            )
+            .or_else(|| attr("aria-valuenow"))
+            .or_else(|| match self.nodes.get(node_id) {
+                Some(Node::Element(d)) if &*d.tag_name == "textarea" => Some(self.text_content(node_id)),
+                _ => attr("value"),
+            }),
+            _ => None,
+        };
+        let level = (role == "heading")
+            .then(|| {
+                attr("aria-level").and_then(|v| v.trim().parse().ok()).or_else(|| match self.nodes.get(node_id) {
+                    Some(Node::Element(d)) => d.tag_name.strip_prefix('h').and_then(|n| n.parse().ok()),
+                    _ => None,
+                })
+            })
+            .flatten();
+        Some(AxNode { node_id, role, name, value, level, children: Vec::new() })
+    }
+
+    fn ax_role(&self, node_id: NodeId) -> String {
+        if let Some(role) = self.get_attribute(node_id, "role")
+            && let Some(first) = role.split_whitespace().next()
+        {
+            return first.to_ascii_lowercase();
+        }
+        let Some(Node::Element(data)) = self.nodes.get(node_id) else {
+            return "generic".to_string();
+        };
+        let has = |key| self.get_attribute(node_id, key).is_some();
+        let role = match &*data.tag_name {
+            "button" | "summary" => "button",
+            "a" | "area" if has("href") => "link",
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => "heading",
+            "img" if self.get_attribute(node_id, "alt").is_some_and(|alt| alt.is_empty()) => "presentation",
+            "img" => "img",
+            "input" => match self.get_attribute(node_id, "type").map(|t| t.to_ascii_lowercase()).as_deref() {
+                Some("checkbox") => "checkbox",
+                Some("radio") => "radio",
+                Some("button" | "submit" | "reset" | "image") => "button",
+                Some("range") => "slider",
+                Some("number") => "spinbutton",
+                Some("search") => "searchbox",
+                Some("hidden") => "none",
+                _ => "textbox",
+            },
+            "textarea" => "textbox",
+            "select" => "combobox",
+            "option" => "option",
+            "ul" | "ol" | "menu" => "list",
+            "li" => "listitem",
+            "nav" => "navigation",
+            "main" => "main",
+            "header" => "banner",
+            "footer" => "contentinfo",
+            "aside" => "complementary",
+            "form" => "form",
+            "section" if has("aria-label") || has("aria-labelledby") => "region",
+            "article" => "article",
+            "dialog" => "dialog",
+            "table" => "table",
+            "tr" => "row",
+            "td" => "cell",
+            "th" => "columnheader",
+            "p" => "paragraph",
+            "hr" => "separator",
+            "progress" => "progressbar",
+            "meter" => "meter",
+            _ => "generic",
+        };
+        role.to_string()
+    }
+
+    fn ax_name(&self, node_id: NodeId, role: &str) -> Option<String> {
+        let attr = |key| self.get_attribute(node_id, key);
+        if let Some(ids) = attr("aria-labelledby") {
+            let text: Vec<String> = ids
+                .split_whitespace()
+                .filter_map(|id| self.id_map.get(id))
+                .map(|&label| self.text_content(label))
+                .collect();
+            if let Some(name) = collapse_whitespace(&text.join(" ")) {
+                return Some(name);
+            }
+        }
+        if let Some(name) = attr("aria-label").as_deref().and_then(collapse_whitespace) {
+            return Some(name);
+        }
+        let tag_is = |tag: &str| matches!(self.nodes.get(node_id), Some(Node::Element(d)) if &*d.tag_name == tag);
+        if (tag_is("img") || tag_is("area") || tag_is("input"))
+            && let Some(name) = attr("alt").as_deref().and_then(collapse_whitespace)
+        {
+            return Some(name);
+        }
+        if NAME_FROM_CONTENT.contains(&role) {
+            // `<input type="submit" value="Send">` is named by its value.
+            let content = match tag_is("input") {
+                true if role == "button" => attr("value"),
+                true => None,
+                false => Some(self.text_content(node_id)),
+            };
+            if let Some(name) = content.as_deref().and_then(collapse_whitespace) {
+                return Some(name);
+            }
+        }
+        attr("title").or_else(|| attr("placeholder")).as_deref().and_then(collapse_whitespace)
+    }
+}
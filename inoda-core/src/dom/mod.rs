@@ -18,43 +18,114 @@
 
 use generational_arena::{Arena, Index};
 
+mod accessibility;
 mod tags;
 
+pub use accessibility::AxNode;
+
 pub const MAX_ATTRIBUTES: usize = 32;
 pub const MAX_ATTRIBUTE_VALUE_LEN: usize = 16384; // 16 KB per attribute
 /// Maximum number of DOM nodes allowed per document.
 /// Prevents memory exhaustion on malicious or malformed HTML.
 pub const MAX_NODES: usize = 65536;
 
+/// HTML void elements: they never have children and have no end tag.
+static VOID_ELEMENTS: phf::Set<&'static str> = phf::phf_set! {
+    "area", "base", "br", "col", "embed", "hr", "img", "input",
+    "link", "meta", "param", "source", "track", "wbr",
+};
+
+/// Whether `tag_name` names an HTML void element.
+pub fn is_void_tag(tag_name: &str) -> bool {
+    VOID_ELEMENTS.contains(tag_name)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct TextMeasureContext {
     pub node_id: NodeId,
     pub font_size: f32,
+    /// Used line height in px (`layout::resolved_line_height`).
+    pub line_height: f32,
     pub max_intrinsic_width: f32,
     pub min_intrinsic_width: f32,
     /// Last definite width from Taffy's measure callback (cache when unchanged between probes).
     pub last_measure_width: Option<f32>,
     pub last_line_count: f32,
+    /// From `line-clamp`; caps the measured line count.
+    pub max_lines: Option<usize>,
+    /// cosmic-text's unspaced max-content width over the used one (1.0
+    /// without `letter-spacing`, `word-spacing` or backend metrics).
+    /// cosmic-text breaks lines on its own advances, so wrap widths are
+    /// scaled by this to match.
+    pub wrap_scale: f32,
 }
 
 /// A DOM document backed by a generational arena.
 pub struct Document {
     pub nodes: Arena<Node>,
     pub root_id: NodeId,
+    /// Default font size the document starts from and that the document
+    /// element's `rem` resolves against (16px). Change it through
+    /// [`Document::set_root_font_size`] so the next pass restyles.
     pub root_font_size: f32,
+    /// What `rem` resolves to: the document element's computed font size,
+    /// kept by the cascade.
+    pub rem_base: f32,
     /// Persistent CSS parsed actively when `<style>` tags change.
     pub stylesheet: crate::css::StyleSheet,
     /// O(1) lookup map for `getElementById`.
     pub id_map: std::collections::HashMap<String, NodeId>,
+    /// Times [`Document::get_element_by_id`] found an `id_map` entry naming
+    /// an element that no longer has that id, and rebuilt the map. Stays 0 while ids are only changed
+    /// through `Document::set_attribute`.
+    pub id_map_repairs: u32,
     /// Iterative deletion queue used by `remove_node` to avoid recursive stack overflow.
     pub dead_nodes: Vec<NodeId>,
     /// Layout invalidation flag. True if DOM was mutated since the last render.
     pub dirty: bool,
     /// Stylesheet invalidation flag. True if `<style>` tags were added or removed.
     pub styles_dirty: bool,
+    /// Set by the HTML parser when its node limit cut the document short.
+    pub truncated: bool,
+    /// Anonymous Taffy boxes that wrap runs of inline-level children, keyed by
+    /// the block parent that owns them. Rebuilt with the parent's child list.
+    pub anonymous_boxes: std::collections::HashMap<NodeId, Vec<taffy::NodeId>>,
     pub taffy_tree: taffy::TaffyTree<TextMeasureContext>,
+    /// Untruncated lines of `line-clamp`ed text buffers, with the wrap width
+    /// they were clamped at, keyed by text node. Layout puts them back only
+    /// when the text is measured at another width, so clamped text is not
+    /// reshaped on passes where nothing changed.
+    pub clamped_text: std::collections::HashMap<NodeId, (f32, Vec<cosmic_text::BufferLine>)>,
+    /// Called by `set_attribute` after each stored attribute change.
+    pub attribute_observer: Option<AttributeObserver>,
+    /// Scroll positions set by the host through `set_scroll_offset`.
+    pub scroll_offsets: std::collections::HashMap<NodeId, (f32, f32)>,
+    /// Viewport size passed to the last layout; `(0, 0)` before any.
+    pub viewport_size: (f32, f32),
+    /// Scroll position of the viewport itself, in px. The host applies it
+    /// (and writes it back when the user scrolls); JS `scrollIntoView` sets it.
+    pub viewport_scroll: (f32, f32),
+    /// Set by the HTML parser when the document has no doctype or a legacy
+    /// one. Of the quirks, only unitless lengths are implemented (see
+    /// `PropertyName::takes_unitless_length_quirk`).
+    pub quirks_mode: bool,
+    /// Text of the first `<title>` element with whitespace stripped and
+    /// collapsed, taken by the HTML parser. Change it with
+    /// [`Document::set_title`].
+    pub title: Option<String>,
+    /// The first `<meta name="viewport">`, parsed when HTML parsing finishes.
+    /// Layout ignores it; hosts read it to pick the viewport they lay out at.
+    pub viewport_meta: Option<ViewportMeta>,
+    /// The element `:focus` matches and JS `document.activeElement` returns.
+    /// Change it with [`Document::set_focus`] and [`Document::blur`]; it is
+    /// cleared when the element is freed.
+    pub focused: Option<NodeId>,
 }
 
+/// Attribute change callback: `(node, name, old value, new value)`. It runs
+/// while the document is mutably borrowed, so it must not reach back into it.
+pub type AttributeObserver = Box<dyn Fn(NodeId, &str, Option<&str>, Option<&str>)>;
+
 /// A handle into the arena. Generational indices prevent ABA problems.
 pub type NodeId = Index;
 
@@ -146,11 +217,126 @@ pub enum PropertyName {
     Right,
     Bottom,
     Left,
+    OutlineWidth,
+    OutlineStyle,
+    OutlineColor,
+    WordBreak,
+    OverflowWrap,
+    LineClamp,
+    Content,
+    OverflowX,
+    OverflowY,
+    AlignContent,
+    JustifyItems,
+    FontStyle,
+    Transform,
+    ContentVisibility,
+    PointerEvents,
+    Direction,
+    Opacity,
+    ListStyleType,
+    Cursor,
+    TextTransform,
+    LetterSpacing,
+    WordSpacing,
+    Float,
+    TabSize,
+    ObjectFit,
+    AnimationName,
+    AnimationDuration,
+    Transition,
+    CounterReset,
+    CounterIncrement,
+    WhiteSpace,
+    BoxShadow,
+    Order,
+    CaretColor,
+    BackgroundImage,
 }
 
-pub const NUM_PROPERTIES: usize = 41;
+pub const NUM_PROPERTIES: usize = 76;
 
 impl PropertyName {
+    /// Every property, ordered so that `ALL[p.to_index()] == p`.
+    pub const ALL: [PropertyName; NUM_PROPERTIES] = [
+        PropertyName::Display,
+        PropertyName::FlexDirection,
+        PropertyName::Width,
+        PropertyName::Height,
+        PropertyName::MarginTop,
+        PropertyName::MarginRight,
+        PropertyName::MarginBottom,
+        PropertyName::MarginLeft,
+        PropertyName::PaddingTop,
+        PropertyName::PaddingRight,
+        PropertyName::PaddingBottom,
+        PropertyName::PaddingLeft,
+        PropertyName::BorderTopWidth,
+        PropertyName::BorderRightWidth,
+        PropertyName::BorderBottomWidth,
+        PropertyName::BorderLeftWidth,
+        PropertyName::BackgroundColor,
+        PropertyName::BorderColor,
+        PropertyName::Color,
+        PropertyName::FontSize,
+        PropertyName::FontFamily,
+        PropertyName::FontWeight,
+        PropertyName::LineHeight,
+        PropertyName::TextAlign,
+        PropertyName::Visibility,
+        PropertyName::AlignItems,
+        PropertyName::JustifyContent,
+        PropertyName::FlexWrap,
+        PropertyName::FlexGrow,
+        PropertyName::FlexShrink,
+        PropertyName::RowGap,
+        PropertyName::ColumnGap,
+        PropertyName::MinWidth,
+        PropertyName::MaxWidth,
+        PropertyName::MinHeight,
+        PropertyName::MaxHeight,
+        PropertyName::Position,
+        PropertyName::Top,
+        PropertyName::Right,
+        PropertyName::Bottom,
+        PropertyName::Left,
+        PropertyName::OutlineWidth,
+        PropertyName::OutlineStyle,
+        PropertyName::OutlineColor,
+        PropertyName::WordBreak,
+        PropertyName::OverflowWrap,
+        PropertyName::LineClamp,
+        PropertyName::Content,
+        PropertyName::OverflowX,
+        PropertyName::OverflowY,
+        PropertyName::AlignContent,
+        PropertyName::JustifyItems,
+        PropertyName::FontStyle,
+        PropertyName::Transform,
+        PropertyName::ContentVisibility,
+        PropertyName::PointerEvents,
+        PropertyName::Direction,
+        PropertyName::Opacity,
+        PropertyName::ListStyleType,
+        PropertyName::Cursor,
+        PropertyName::TextTransform,
+        PropertyName::LetterSpacing,
+        PropertyName::WordSpacing,
+        PropertyName::Float,
+        PropertyName::TabSize,
+        PropertyName::ObjectFit,
+        PropertyName::AnimationName,
+        PropertyName::AnimationDuration,
+        PropertyName::Transition,
+        PropertyName::CounterReset,
+        PropertyName::CounterIncrement,
+        PropertyName::WhiteSpace,
+        PropertyName::BoxShadow,
+        PropertyName::Order,
+        PropertyName::CaretColor,
+        PropertyName::BackgroundImage,
+    ];
+
     pub fn to_index(self) -> usize {
         match self {
             PropertyName::Display => 0,
@@ -194,6 +380,41 @@ impl PropertyName {
             PropertyName::Right => 38,
             PropertyName::Bottom => 39,
             PropertyName::Left => 40,
+            PropertyName::OutlineWidth => 41,
+            PropertyName::OutlineStyle => 42,
+            PropertyName::OutlineColor => 43,
+            PropertyName::WordBreak => 44,
+            PropertyName::OverflowWrap => 45,
+            PropertyName::LineClamp => 46,
+            PropertyName::Content => 47,
+            PropertyName::OverflowX => 48,
+            PropertyName::OverflowY => 49,
+            PropertyName::AlignContent => 50,
+            PropertyName::JustifyItems => 51,
+            PropertyName::FontStyle => 52,
+            PropertyName::Transform => 53,
+            PropertyName::ContentVisibility => 54,
+            PropertyName::PointerEvents => 55,
+            PropertyName::Direction => 56,
+            PropertyName::Opacity => 57,
+            PropertyName::ListStyleType => 58,
+            PropertyName::Cursor => 59,
+            PropertyName::TextTransform => 60,
+            PropertyName::LetterSpacing => 61,
+            PropertyName::WordSpacing => 62,
+            PropertyName::Float => 63,
+            PropertyName::TabSize => 64,
+            PropertyName::ObjectFit => 65,
+            PropertyName::AnimationName => 66,
+            PropertyName::AnimationDuration => 67,
+            PropertyName::Transition => 68,
+            PropertyName::CounterReset => 69,
+            PropertyName::CounterIncrement => 70,
+            PropertyName::WhiteSpace => 71,
+            PropertyName::BoxShadow => 72,
+            PropertyName::Order => 73,
+            PropertyName::CaretColor => 74,
+            PropertyName::BackgroundImage => 75,
         }
     }
 
@@ -240,21 +461,105 @@ impl PropertyName {
             "right" => PropertyName::Right,
             "bottom" => PropertyName::Bottom,
             "left" => PropertyName::Left,
+            "outline-width" => PropertyName::OutlineWidth,
+            "outline-style" => PropertyName::OutlineStyle,
+            "outline-color" => PropertyName::OutlineColor,
+            "word-break" => PropertyName::WordBreak,
+            "overflow-wrap" | "word-wrap" => PropertyName::OverflowWrap,
+            "line-clamp" | "-webkit-line-clamp" | "max-lines" => PropertyName::LineClamp,
+            "content" => PropertyName::Content,
+            "overflow-x" => PropertyName::OverflowX,
+            "overflow-y" => PropertyName::OverflowY,
+            "align-content" => PropertyName::AlignContent,
+            "justify-items" => PropertyName::JustifyItems,
+            "font-style" => PropertyName::FontStyle,
+            "transform" => PropertyName::Transform,
+            "content-visibility" => PropertyName::ContentVisibility,
+            "pointer-events" => PropertyName::PointerEvents,
+            "direction" => PropertyName::Direction,
+            "opacity" => PropertyName::Opacity,
+            "list-style-type" => PropertyName::ListStyleType,
+            "cursor" => PropertyName::Cursor,
+            "text-transform" => PropertyName::TextTransform,
+            "letter-spacing" => PropertyName::LetterSpacing,
+            "word-spacing" => PropertyName::WordSpacing,
+            "float" => PropertyName::Float,
+            "tab-size" => PropertyName::TabSize,
+            "object-fit" => PropertyName::ObjectFit,
+            "animation-name" => PropertyName::AnimationName,
+            "animation-duration" => PropertyName::AnimationDuration,
+            "transition" => PropertyName::Transition,
+            "counter-reset" => PropertyName::CounterReset,
+            "counter-increment" => PropertyName::CounterIncrement,
+            "white-space" => PropertyName::WhiteSpace,
+            "box-shadow" => PropertyName::BoxShadow,
+            "order" => PropertyName::Order,
+            "caret-color" => PropertyName::CaretColor,
+            "background-image" => PropertyName::BackgroundImage,
             _ => return None,
         })
     }
 
     /// Returns true if this property is CSS-inheritable.
     ///
-    /// Only properties with corresponding `ComputedStyle` fields that are actually
-    /// applied during the cascade are listed here. Unimplemented properties
-    /// (FontFamily, FontWeight, LineHeight, TextAlign, Visibility) are parsed
-    /// and stored but silently discarded — marking them inheritable would waste
-    /// cascade time propagating values that are never applied.
+    /// Must stay in sync with `ComputedStyle::inherit_from`, which copies the
+    /// backing fields.
     pub fn is_inheritable(&self) -> bool {
         matches!(
             self,
-            PropertyName::Color | PropertyName::FontSize
+            PropertyName::Color
+                | PropertyName::FontFamily
+                | PropertyName::FontSize
+                | PropertyName::FontWeight
+                | PropertyName::FontStyle
+                | PropertyName::LineHeight
+                | PropertyName::WordBreak
+                | PropertyName::OverflowWrap
+                | PropertyName::PointerEvents
+                | PropertyName::Direction
+                | PropertyName::Visibility
+                | PropertyName::ListStyleType
+                | PropertyName::Cursor
+                | PropertyName::TextTransform
+                | PropertyName::LetterSpacing
+                | PropertyName::WordSpacing
+                | PropertyName::TabSize
+                | PropertyName::WhiteSpace
+                | PropertyName::CaretColor
+                | PropertyName::TextAlign
+        )
+    }
+
+    /// Properties where quirks mode reads a bare number as px (`width: 100`),
+    /// as in the HTML spec's unitless length quirk.
+    pub fn takes_unitless_length_quirk(self) -> bool {
+        matches!(
+            self,
+            PropertyName::Width
+                | PropertyName::Height
+                | PropertyName::MinWidth
+                | PropertyName::MaxWidth
+                | PropertyName::MinHeight
+                | PropertyName::MaxHeight
+                | PropertyName::MarginTop
+                | PropertyName::MarginRight
+                | PropertyName::MarginBottom
+                | PropertyName::MarginLeft
+                | PropertyName::PaddingTop
+                | PropertyName::PaddingRight
+                | PropertyName::PaddingBottom
+                | PropertyName::PaddingLeft
+                | PropertyName::BorderTopWidth
+                | PropertyName::BorderRightWidth
+                | PropertyName::BorderBottomWidth
+                | PropertyName::BorderLeftWidth
+                | PropertyName::Top
+                | PropertyName::Right
+                | PropertyName::Bottom
+                | PropertyName::Left
+                | PropertyName::FontSize
+                | PropertyName::LetterSpacing
+                | PropertyName::WordSpacing
         )
     }
 
@@ -303,6 +608,41 @@ impl PropertyName {
             PropertyName::Right => "right",
             PropertyName::Bottom => "bottom",
             PropertyName::Left => "left",
+            PropertyName::OutlineWidth => "outline-width",
+            PropertyName::OutlineStyle => "outline-style",
+            PropertyName::OutlineColor => "outline-color",
+            PropertyName::WordBreak => "word-break",
+            PropertyName::OverflowWrap => "overflow-wrap",
+            PropertyName::LineClamp => "line-clamp",
+            PropertyName::Content => "content",
+            PropertyName::OverflowX => "overflow-x",
+            PropertyName::OverflowY => "overflow-y",
+            PropertyName::AlignContent => "align-content",
+            PropertyName::JustifyItems => "justify-items",
+            PropertyName::FontStyle => "font-style",
+            PropertyName::Transform => "transform",
+            PropertyName::ContentVisibility => "content-visibility",
+            PropertyName::PointerEvents => "pointer-events",
+            PropertyName::Direction => "direction",
+            PropertyName::Opacity => "opacity",
+            PropertyName::ListStyleType => "list-style-type",
+            PropertyName::Cursor => "cursor",
+            PropertyName::TextTransform => "text-transform",
+            PropertyName::LetterSpacing => "letter-spacing",
+            PropertyName::WordSpacing => "word-spacing",
+            PropertyName::Float => "float",
+            PropertyName::TabSize => "tab-size",
+            PropertyName::ObjectFit => "object-fit",
+            PropertyName::AnimationName => "animation-name",
+            PropertyName::AnimationDuration => "animation-duration",
+            PropertyName::Transition => "transition",
+            PropertyName::CounterReset => "counter-reset",
+            PropertyName::CounterIncrement => "counter-increment",
+            PropertyName::WhiteSpace => "white-space",
+            PropertyName::BoxShadow => "box-shadow",
+            PropertyName::Order => "order",
+            PropertyName::CaretColor => "caret-color",
+            PropertyName::BackgroundImage => "background-image",
         }
     }
 }
@@ -327,6 +667,26 @@ pub struct ElementData {
     pub layout_dirty: bool,
     /// Set true when attributes or classes mutate, demanding a CSS cascade recompute.
     pub styles_dirty: bool,
+    /// Generated `::before` / `::after` text nodes. They live in the arena with
+    /// `parent` set to this element but are not linked into the child list, so
+    /// only layout, rendering and hit testing see them (via `layout_children`).
+    pub pseudo_before: Option<NodeId>,
+    pub pseudo_after: Option<NodeId>,
+    /// Generated list marker (`1. `, `• `) for `li` children of `ul`/`ol`,
+    /// held the same way as `pseudo_before` and laid out ahead of it.
+    pub pseudo_marker: Option<NodeId>,
+    /// Set when `::before` / `::after` content uses `counter()`, so the
+    /// cascade re-resolves it on every pass as counters move.
+    pub counter_content: bool,
+    /// While the element is hidden through `Document::set_hidden`, the
+    /// cascaded `display` it returns to; `computed.display` is `none` meanwhile.
+    pub hidden_display: Option<DisplayKeyword>,
+    /// Text style from matched `::first-letter` rules, handed by the cascade
+    /// to the first text in this element's subtree.
+    pub first_letter: Option<Box<TextComputedStyle>>,
+    /// Position of the `id` entry in `attributes`, kept in sync by
+    /// `set_attributes` / `set_attribute`.
+    id_index: Option<usize>,
 }
 
 impl ElementData {
@@ -346,8 +706,59 @@ impl ElementData {
             js_handles: 0,
             layout_dirty: false,
             styles_dirty: true,
+            pseudo_before: None,
+            pseudo_after: None,
+            pseudo_marker: None,
+            counter_content: false,
+            hidden_display: None,
+            first_letter: None,
+            id_index: None,
+        }
+    }
+
+    /// The element's `id` attribute, in O(1) while `attributes` is mutated
+    /// through `set_attributes` / `set_attribute`. If the list was edited
+    /// directly and the cached position no longer holds `id`, the list is
+    /// scanned instead.
+    pub fn id(&self) -> Option<&str> {
+        match self.id_index.and_then(|i| self.attributes.get(i)).filter(|(k, _)| k == "id") {
+            Some((_, v)) => Some(v.as_str()),
+            None => self.attributes.iter().find(|(k, _)| k == "id").map(|(_, v)| v.as_str()),
         }
     }
+
+    /// Replaces the attribute list and re-caches the `id` position.
+    pub fn set_attributes(&mut self, attributes: Vec<(String, String)>) {
+        self.id_index = attributes.iter().position(|(k, _)| k == "id");
+        self.attributes = attributes;
+    }
+
+    /// Sets or adds one attribute, keeping the `id` cache current. New keys
+    /// are dropped once `MAX_ATTRIBUTES` is reached; returns whether the
+    /// value was stored.
+    pub fn set_attribute(&mut self, key: &str, value: String) -> bool {
+        if let Some((_, v)) = self.attributes.iter_mut().find(|(k, _)| k == key) {
+            *v = value;
+            return true;
+        }
+        if self.attributes.len() >= MAX_ATTRIBUTES {
+            return false;
+        }
+        if key == "id" {
+            self.id_index = Some(self.attributes.len());
+        }
+        self.attributes.push((key.to_string(), value));
+        true
+    }
+
+    /// Removes one attribute, keeping the `id` cache current, and returns
+    /// its value.
+    pub fn remove_attribute(&mut self, key: &str) -> Option<String> {
+        let index = self.attributes.iter().position(|(k, _)| k == key)?;
+        let (_, value) = self.attributes.remove(index);
+        self.id_index = self.attributes.iter().position(|(k, _)| k == "id");
+        Some(value)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -356,10 +767,10 @@ pub struct TextData {
     pub parent: Option<NodeId>,
     pub prev_sibling: Option<NodeId>,
     pub next_sibling: Option<NodeId>,
-    /// Lightweight computed style for text nodes — only carries the two
-    /// inheritable properties that layout and rendering actually read
-    /// (`font_size`, `color`).  Avoids allocating the full 36-field
-    /// `ComputedStyle` struct for every text node in the document.
+    /// Lightweight computed style for text nodes — only carries the
+    /// inheritable properties that layout and rendering actually read.
+    /// Avoids allocating the full `ComputedStyle` struct for every text
+    /// node in the document.
     pub computed: TextComputedStyle,
     pub taffy_node: Option<taffy::NodeId>,
     pub js_handles: usize,
@@ -367,6 +778,9 @@ pub struct TextData {
     pub layout_dirty: bool,
     /// Triggers inheritable CSS recompute
     pub styles_dirty: bool,
+    /// Style for the first letter of this text, set by the cascade when it is
+    /// the first text inside an element with `::first-letter` rules.
+    pub first_letter: Option<Box<TextComputedStyle>>,
 }
 
 impl TextData {
@@ -381,6 +795,7 @@ impl TextData {
             js_handles: 0,
             layout_dirty: false,
             styles_dirty: true,
+            first_letter: None,
         }
     }
 }
@@ -393,6 +808,24 @@ pub struct RootData {
     pub js_handles: usize,
 }
 
+/// A `width` or `height` from a viewport `<meta>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewportLength { DeviceWidth, DeviceHeight, Px(f32) }
+
+/// The `content` of `<meta name="viewport">`, for the host to size its
+/// layout viewport and zoom. Fields the page did not set, or set to an
+/// unusable value, are `None`. Lengths are clamped to 1-10000 px and scales
+/// to 0.1-10, as in the CSS Device Adaptation spec.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ViewportMeta {
+    pub width: Option<ViewportLength>,
+    pub height: Option<ViewportLength>,
+    pub initial_scale: Option<f32>,
+    pub minimum_scale: Option<f32>,
+    pub maximum_scale: Option<f32>,
+    pub user_scalable: Option<bool>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum StyleValue {
     Keyword(string_cache::DefaultAtom),
@@ -406,14 +839,235 @@ pub enum StyleValue {
     Color(u8, u8, u8, u8),
     Auto,
     None,
+    /// A quoted string literal, stored unescaped (e.g. for `content`).
+    String(String),
+    /// A parsed `transform` function list.
+    Transform(Transform),
+    /// A `font-family` list in priority order.
+    FontFamily(std::sync::Arc<[FontFamilyName]>),
+    /// `min()`, `max()` or `clamp()` over length arguments (which may nest);
+    /// resolved to px by layout.
+    Math(MathFunction, std::sync::Arc<[StyleValue]>),
+    /// A `transition` list; empty for `none`.
+    Transitions(std::sync::Arc<[TransitionSpec]>),
+    /// A `content` list mixing strings and counter references.
+    Content(std::sync::Arc<[ContentItem]>),
+    /// `counter-reset` / `counter-increment` name and value pairs; empty for `none`.
+    Counters(std::sync::Arc<[(String, i32)]>),
+    /// A `box-shadow` list, topmost first; empty for `none`.
+    BoxShadows(std::sync::Arc<[BoxShadow]>),
+    /// The URL of a `url(...)` value, unquoted and unresolved.
+    Url(String),
+}
+
+/// One `box-shadow` entry, with lengths in px. `color` is `None` when
+/// omitted, which paints in the element's `color`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxShadow {
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub blur: f32,
+    pub spread: f32,
+    pub color: Option<(u8, u8, u8, u8)>,
+    pub inset: bool,
+}
+
+/// One item of a `content` list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentItem {
+    Text(String),
+    /// `counter(name)`: the innermost value of the counter.
+    Counter(String),
+    /// `counters(name, separator)`: every nested value, outermost first.
+    Counters(String, String),
+}
+
+/// One `transition` entry: `property` is `None` for `all`; `duration` is in
+/// seconds. Timing functions and delays are not kept (timing is linear).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransitionSpec {
+    pub property: Option<PropertyName>,
+    pub duration: f32,
+}
+
+/// CSS comparison functions. `Clamp` always has exactly three arguments
+/// (minimum, preferred, maximum); `Min` and `Max` have at least one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathFunction { Min, Max, Clamp }
+
+/// One entry of a `font-family` list: a named family or a generic one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FontFamilyName { Named(String), Serif, SansSerif, Monospace, Cursive, Fantasy }
+
+/// 2D scale-then-translate transform: a point maps to
+/// `(scale_x * x + translate_x, scale_y * y + translate_y)`.
+///
+/// On `ComputedStyle` it is the element's `transform` relative to its
+/// transform origin (the border-box centre). `render` hands backends the same
+/// type resolved to absolute coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translate_x: f32,
+    pub translate_y: f32,
+    pub scale_x: f32,
+    pub scale_y: f32,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform { translate_x: 0.0, translate_y: 0.0, scale_x: 1.0, scale_y: 1.0 };
+
+    /// `self` applied after `inner`: maps `p` to `self(inner(p))`.
+    pub fn then(&self, inner: &Transform) -> Transform {
+        Transform {
+            translate_x: self.scale_x * inner.translate_x + self.translate_x,
+            translate_y: self.scale_y * inner.translate_y + self.translate_y,
+            scale_x: self.scale_x * inner.scale_x,
+            scale_y: self.scale_y * inner.scale_y,
+        }
+    }
 }
 
 impl Eq for StyleValue {}
 
+/// Serializes to CSS text: `10px`, `50%`, `2em`, `#ff0000` (or `rgba(...)` when
+/// translucent), `auto`, quoted strings with `"`/`\` escaped, `url("...")`.
+impl std::fmt::Display for StyleValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StyleValue::Keyword(k) => f.write_str(k),
+            StyleValue::LengthPx(n) => write!(f, "{}px", n),
+            StyleValue::Percent(n) => write!(f, "{}%", n),
+            StyleValue::ViewportWidth(n) => write!(f, "{}vw", n),
+            StyleValue::ViewportHeight(n) => write!(f, "{}vh", n),
+            StyleValue::Em(n) => write!(f, "{}em", n),
+            StyleValue::Rem(n) => write!(f, "{}rem", n),
+            StyleValue::Number(n) => write!(f, "{}", n),
+            StyleValue::Color(r, g, b, a) => write_css_color(f, (*r, *g, *b, *a)),
+            StyleValue::Auto => f.write_str("auto"),
+            StyleValue::None => f.write_str("none"),
+            StyleValue::String(s) => write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+            StyleValue::Url(url) => write!(f, "url({})", StyleValue::String(url.clone())),
+            StyleValue::Transform(t) => write!(
+                f,
+                "translate({}px,{}px) scale({},{})",
+                t.translate_x, t.translate_y, t.scale_x, t.scale_y
+            ),
+            StyleValue::FontFamily(families) => {
+                for (i, family) in families.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    match family {
+                        FontFamilyName::Named(name) => {
+                            write!(f, "\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))?
+                        }
+                        FontFamilyName::Serif => f.write_str("serif")?,
+                        FontFamilyName::SansSerif => f.write_str("sans-serif")?,
+                        FontFamilyName::Monospace => f.write_str("monospace")?,
+                        FontFamilyName::Cursive => f.write_str("cursive")?,
+                        FontFamilyName::Fantasy => f.write_str("fantasy")?,
+                    }
+                }
+                Ok(())
+            }
+            StyleValue::Math(function, args) => {
+                f.write_str(match function {
+                    MathFunction::Min => "min(",
+                    MathFunction::Max => "max(",
+                    MathFunction::Clamp => "clamp(",
+                })?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                f.write_str(")")
+            }
+            StyleValue::Transitions(specs) if specs.is_empty() => f.write_str("none"),
+            StyleValue::Transitions(specs) => {
+                for (i, spec) in specs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    let property = spec.property.map_or("all", |p| p.as_str());
+                    write!(f, "{} {}s", property, spec.duration)?;
+                }
+                Ok(())
+            }
+            StyleValue::Content(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    match item {
+                        ContentItem::Text(text) => write!(f, "{}", StyleValue::String(text.clone()))?,
+                        ContentItem::Counter(name) => write!(f, "counter({name})")?,
+                        ContentItem::Counters(name, separator) => {
+                            write!(f, "counters({name}, {})", StyleValue::String(separator.clone()))?
+                        }
+                    }
+                }
+                Ok(())
+            }
+            StyleValue::Counters(counters) if counters.is_empty() => f.write_str("none"),
+            StyleValue::Counters(counters) => {
+                for (i, (name, value)) in counters.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{name} {value}")?;
+                }
+                Ok(())
+            }
+            StyleValue::BoxShadows(shadows) if shadows.is_empty() => f.write_str("none"),
+            StyleValue::BoxShadows(shadows) => {
+                for (i, shadow) in shadows.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    if shadow.inset {
+                        f.write_str("inset ")?;
+                    }
+                    write!(
+                        f,
+                        "{}px {}px {}px {}px",
+                        shadow.offset_x, shadow.offset_y, shadow.blur, shadow.spread
+                    )?;
+                    if let Some(color) = shadow.color {
+                        f.write_str(" ")?;
+                        write_css_color(f, color)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl StyleValue {
+    /// CSS text for this value; see the `Display` impl.
+    pub fn to_css_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// `#rrggbb` for opaque colors, `rgba(r,g,b,alpha)` otherwise.
+pub(crate) fn write_css_color(f: &mut std::fmt::Formatter<'_>, (r, g, b, a): (u8, u8, u8, u8)) -> std::fmt::Result {
+    if a == 255 {
+        write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+    } else {
+        write!(f, "rgba({},{},{},{})", r, g, b, a as f32 / 255.0)
+    }
+}
 
 
+
+/// `display`. `Contents` elements generate no box: layout, painting and
+/// hit testing treat their children as children of the nearest ancestor
+/// that has one (`Document::box_children`, `Document::box_parent`).
 #[derive(Debug, Clone, PartialEq)]
-pub enum DisplayKeyword { Block, Inline, InlineBlock, Flex, Grid, None, ListItem }
+pub enum DisplayKeyword { Block, Inline, InlineBlock, Flex, Grid, None, ListItem, Contents }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum FlexDirectionKeyword { Row, Column }
@@ -430,6 +1084,83 @@ pub enum FlexWrapKeyword { Wrap, WrapReverse, NoWrap }
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PositionKeyword { Static, Relative, Absolute }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutlineStyleKeyword { None, Solid, Dashed, Dotted, Double }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WordBreakKeyword { Normal, BreakAll, KeepAll, BreakWord }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowWrapKeyword { Normal, BreakWord, Anywhere }
+
+/// `FirstLetter` has no node of its own: its style rides on the text node
+/// it applies to (`TextData::first_letter`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PseudoElement { Before, After, FirstLetter, Marker }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowKeyword { Visible, Hidden, Clip, Scroll, Auto }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FontStyleKeyword { Normal, Italic, Oblique }
+
+/// `content-visibility`. `Hidden` skips layout and paint of the element's
+/// contents; `Auto` is laid out and painted like `Visible`, and lets hosts
+/// skip off-screen contents via `layout::contents_skipped`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentVisibilityKeyword { Visible, Auto, Hidden }
+
+/// `pointer-events`. `None` makes the element transparent to hit testing;
+/// being inherited, its descendants are too unless they set `auto` again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerEventsKeyword { Auto, None }
+
+/// `direction`: the inline base direction. Text lines start at the left edge
+/// for `Ltr` and at the right edge for `Rtl`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DirectionKeyword { Ltr, Rtl }
+
+/// `visibility`. Hidden boxes still take up space but paint nothing and are
+/// not hit-test targets. `Collapse` removes a flex item from layout as if by
+/// `display: none` and is treated as `Hidden` elsewhere. Inherited, so a
+/// descendant can become visible again inside a hidden ancestor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisibilityKeyword { Visible, Hidden, Collapse }
+
+/// `list-style-type`: the marker generated for `li` children of `ul`/`ol`.
+/// Inherited; `ul` defaults to `Disc` and `ol` to `Decimal`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListStyleTypeKeyword { Disc, Circle, Decimal, None }
+
+/// `text-transform`. Applied to the shaped copy of the text only;
+/// `TextData::text` keeps the source casing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextTransformKeyword { None, Uppercase, Lowercase, Capitalize }
+
+/// `float`. Approximated in layout by pulling the element to the start or
+/// end of its line; text does not flow around it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FloatKeyword { None, Left, Right }
+
+/// `text-align` for the lines of a text node. `Start` and `End` follow
+/// `direction`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlignKeyword { Start, End, Left, Right, Center, Justify }
+
+/// `white-space`: whether spaces and newlines collapse and whether lines wrap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WhiteSpaceKeyword { Normal, Nowrap, Pre, PreWrap, PreLine }
+
+/// `object-fit` for `<img>`, sized against the `width`/`height` attributes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectFitKeyword { Fill, Contain, Cover, None, ScaleDown }
+
+/// Computed `line-height`. A unitless number stays relative to the font size
+/// of whatever inherits it; lengths (`px`, `em`, `rem`, `%`) are resolved to
+/// px against the declaring element's font size, as in CSS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineHeight { Normal, Number(f32), Px(f32) }
+
 /// Pre-calculated native CSS properties to eliminate O(N) tuple lookups during Layout and Rendering loops.
 #[derive(Debug, Clone, PartialEq)]
 pub struct ComputedStyle {
@@ -454,11 +1185,82 @@ pub struct ComputedStyle {
     pub border_color: Option<(u8, u8, u8, u8)>,
     pub font_size: f32,
     pub color: (u8, u8, u8, u8),
+    /// Numeric weight, 1..=1000 (`normal` is 400, `bold` 700).
+    pub font_weight: u16,
+    pub font_style: FontStyleKeyword,
+    /// `font-family` list in priority order; empty means the default family.
+    pub font_family: std::sync::Arc<[FontFamilyName]>,
+    pub line_height: LineHeight,
     pub flex_grow: f32,
     pub flex_shrink: f32,
     pub position: PositionKeyword,
     /// Top, Right, Bottom, Left inset values.
     pub inset: [StyleValue; 4],
+    /// Painted outside the border box; never takes part in layout.
+    pub outline_width: StyleValue,
+    pub outline_style: OutlineStyleKeyword,
+    /// `None` means `currentColor`.
+    pub outline_color: Option<(u8, u8, u8, u8)>,
+    pub word_break: WordBreakKeyword,
+    pub overflow_wrap: OverflowWrapKeyword,
+    /// Maximum number of text lines laid out for direct text children;
+    /// `None` means unclamped.
+    pub line_clamp: Option<u32>,
+    /// X, Y.
+    pub overflow: [OverflowKeyword; 2],
+    /// `None` is `normal`.
+    pub align_content: Option<JustifyContentKeyword>,
+    /// `None` is `normal`.
+    pub justify_items: Option<AlignItemsKeyword>,
+    /// Paint-only; `None` is `transform: none`.
+    pub transform: Option<Transform>,
+    pub content_visibility: ContentVisibilityKeyword,
+    pub pointer_events: PointerEventsKeyword,
+    pub direction: DirectionKeyword,
+    pub visibility: VisibilityKeyword,
+    /// 0.0..=1.0. Not inherited: renderers multiply it down the subtree.
+    pub opacity: f32,
+    pub list_style_type: ListStyleTypeKeyword,
+    /// The `cursor` keyword as written (`auto`, `pointer`, `text`, ...);
+    /// only hosts read it, through `layout::cursor_at`.
+    pub cursor: string_cache::DefaultAtom,
+    pub text_transform: TextTransformKeyword,
+    /// Extra advance after every glyph, in px (`normal` is 0). Lengths are
+    /// resolved against the declaring element's font size, as for `line-height`.
+    pub letter_spacing: f32,
+    /// Extra advance after every space, in px, on top of `letter_spacing`.
+    pub word_spacing: f32,
+    pub float: FloatKeyword,
+    /// Spaces each tab expands to before shaping (`tab-size`, default 8).
+    pub tab_size: u32,
+    pub white_space: WhiteSpaceKeyword,
+    pub text_align: TextAlignKeyword,
+    pub object_fit: ObjectFitKeyword,
+    /// `@keyframes` block to run, `None` for `none`. Hosts drive it with
+    /// `css::sample_animation`; the cascade never applies keyframes itself.
+    pub animation_name: Option<std::sync::Arc<str>>,
+    /// `animation-duration` in seconds.
+    pub animation_duration: f32,
+    /// `transition` entries; read by `css::transition_values`.
+    pub transitions: std::sync::Arc<[TransitionSpec]>,
+    /// Inherited language from the nearest `lang` attribute, as for
+    /// `Document::lang`. Not a CSS property, so no `PropertyName` sets it.
+    pub lang: Option<std::sync::Arc<str>>,
+    /// Counters created on this element, with their initial values.
+    pub counter_reset: std::sync::Arc<[(String, i32)]>,
+    /// Counters bumped on this element, after any reset.
+    pub counter_increment: std::sync::Arc<[(String, i32)]>,
+    /// `box-shadow` entries, topmost first; empty for `none`.
+    pub box_shadow: std::sync::Arc<[BoxShadow]>,
+    /// `order`: flex and grid items are laid out by ascending order, ties
+    /// in document order.
+    pub order: i32,
+    /// `caret-color`; `None` is `auto`, which uses `color`. Hosts draw the
+    /// caret, at `layout::caret_rect`.
+    pub caret_color: Option<(u8, u8, u8, u8)>,
+    /// The `background-image` URL as written, unresolved; `None` is `none`.
+    /// Nothing fetches or paints it yet.
+    pub background_image: Option<std::sync::Arc<str>>,
 }
 
 impl Eq for ComputedStyle {}
@@ -501,22 +1303,207 @@ impl Default for ComputedStyle {
             border_color: None,
             font_size: 16.0,
             color: (0, 0, 0, 255),
+            font_weight: 400,
+            font_style: FontStyleKeyword::Normal,
+            font_family: std::sync::Arc::from([]),
+            line_height: LineHeight::Normal,
             flex_grow: 0.0,
             flex_shrink: 1.0,
             position: PositionKeyword::Static,
             inset: [StyleValue::Auto, StyleValue::Auto, StyleValue::Auto, StyleValue::Auto],
+            outline_width: StyleValue::LengthPx(3.0), // `medium`
+            outline_style: OutlineStyleKeyword::None,
+            outline_color: None,
+            word_break: WordBreakKeyword::Normal,
+            overflow_wrap: OverflowWrapKeyword::Normal,
+            line_clamp: None,
+            overflow: [OverflowKeyword::Visible, OverflowKeyword::Visible],
+            align_content: None,
+            justify_items: None,
+            transform: None,
+            content_visibility: ContentVisibilityKeyword::Visible,
+            pointer_events: PointerEventsKeyword::Auto,
+            direction: DirectionKeyword::Ltr,
+            visibility: VisibilityKeyword::Visible,
+            opacity: 1.0,
+            list_style_type: ListStyleTypeKeyword::Disc,
+            cursor: string_cache::DefaultAtom::from("auto"),
+            text_transform: TextTransformKeyword::None,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            float: FloatKeyword::None,
+            tab_size: 8,
+            white_space: WhiteSpaceKeyword::Normal,
+            text_align: TextAlignKeyword::Start,
+            object_fit: ObjectFitKeyword::Fill,
+            animation_name: None,
+            animation_duration: 0.0,
+            transitions: std::sync::Arc::from([]),
+            lang: None,
+            counter_reset: std::sync::Arc::from([]),
+            counter_increment: std::sync::Arc::from([]),
+            box_shadow: std::sync::Arc::from([]),
+            order: 0,
+            caret_color: None,
+            background_image: None,
+        }
+    }
+}
+
+impl ComputedStyle {
+    /// Copy every inherited property from the parent's resolved style.
+    /// Must stay in sync with `inherited_eq` and `PropertyName::is_inheritable`.
+    pub fn inherit_from(&mut self, parent: &ComputedStyle) {
+        self.font_size = parent.font_size;
+        self.color = parent.color;
+        self.font_weight = parent.font_weight;
+        self.font_style = parent.font_style;
+        self.font_family = parent.font_family.clone();
+        self.line_height = parent.line_height;
+        self.word_break = parent.word_break;
+        self.overflow_wrap = parent.overflow_wrap;
+        self.pointer_events = parent.pointer_events;
+        self.direction = parent.direction;
+        self.visibility = parent.visibility;
+        self.list_style_type = parent.list_style_type;
+        self.cursor = parent.cursor.clone();
+        self.text_transform = parent.text_transform;
+        self.letter_spacing = parent.letter_spacing;
+        self.word_spacing = parent.word_spacing;
+        self.tab_size = parent.tab_size;
+        self.white_space = parent.white_space;
+        self.text_align = parent.text_align;
+        self.lang = parent.lang.clone();
+        self.caret_color = parent.caret_color;
+    }
+
+    /// Copy the field backing the property at `index` (as returned by
+    /// `PropertyName::to_index`) from `from`. Used to resolve the CSS-wide
+    /// keywords; properties without a `ComputedStyle` field are ignored.
+    pub fn copy_indexed_property(&mut self, index: usize, from: &ComputedStyle) {
+        match index {
+            0 => self.display = from.display.clone(),
+            1 => self.flex_direction = from.flex_direction.clone(),
+            2 => self.width = from.width.clone(),
+            3 => self.height = from.height.clone(),
+            4..=7 => self.margin[index - 4] = from.margin[index - 4].clone(),
+            8..=11 => self.padding[index - 8] = from.padding[index - 8].clone(),
+            12..=15 => self.border_width[index - 12] = from.border_width[index - 12].clone(),
+            16 => self.bg_color = from.bg_color,
+            17 => self.border_color = from.border_color,
+            18 => self.color = from.color,
+            19 => self.font_size = from.font_size,
+            20 => self.font_family = from.font_family.clone(),
+            21 => self.font_weight = from.font_weight,
+            22 => self.line_height = from.line_height,
+            23 => self.text_align = from.text_align,
+            24 => self.visibility = from.visibility,
+            25 => self.align_items = from.align_items.clone(),
+            26 => self.justify_content = from.justify_content.clone(),
+            27 => self.flex_wrap = from.flex_wrap.clone(),
+            28 => self.flex_grow = from.flex_grow,
+            29 => self.flex_shrink = from.flex_shrink,
+            30 => self.row_gap = from.row_gap.clone(),
+            31 => self.column_gap = from.column_gap.clone(),
+            32 => self.min_width = from.min_width.clone(),
+            33 => self.max_width = from.max_width.clone(),
+            34 => self.min_height = from.min_height.clone(),
+            35 => self.max_height = from.max_height.clone(),
+            36 => self.position = from.position,
+            37..=40 => self.inset[index - 37] = from.inset[index - 37].clone(),
+            41 => self.outline_width = from.outline_width.clone(),
+            42 => self.outline_style = from.outline_style,
+            43 => self.outline_color = from.outline_color,
+            44 => self.word_break = from.word_break,
+            45 => self.overflow_wrap = from.overflow_wrap,
+            46 => self.line_clamp = from.line_clamp,
+            48 | 49 => self.overflow[index - 48] = from.overflow[index - 48],
+            50 => self.align_content = from.align_content.clone(),
+            51 => self.justify_items = from.justify_items.clone(),
+            52 => self.font_style = from.font_style,
+            53 => self.transform = from.transform,
+            54 => self.content_visibility = from.content_visibility,
+            55 => self.pointer_events = from.pointer_events,
+            56 => self.direction = from.direction,
+            57 => self.opacity = from.opacity,
+            58 => self.list_style_type = from.list_style_type,
+            59 => self.cursor = from.cursor.clone(),
+            60 => self.text_transform = from.text_transform,
+            61 => self.letter_spacing = from.letter_spacing,
+            62 => self.word_spacing = from.word_spacing,
+            63 => self.float = from.float,
+            64 => self.tab_size = from.tab_size,
+            65 => self.object_fit = from.object_fit,
+            66 => self.animation_name = from.animation_name.clone(),
+            67 => self.animation_duration = from.animation_duration,
+            68 => self.transitions = from.transitions.clone(),
+            69 => self.counter_reset = from.counter_reset.clone(),
+            70 => self.counter_increment = from.counter_increment.clone(),
+            71 => self.white_space = from.white_space,
+            72 => self.box_shadow = from.box_shadow.clone(),
+            73 => self.order = from.order,
+            74 => self.caret_color = from.caret_color,
+            75 => self.background_image = from.background_image.clone(),
+            _ => {}
         }
     }
+
+    /// True if both styles agree on every inherited property, i.e. children
+    /// inheriting from either would resolve identically.
+    ///
+    /// Also compares `line_clamp`: it does not inherit to elements, but text
+    /// children copy it via `TextComputedStyle::from_computed`.
+    pub fn inherited_eq(&self, other: &ComputedStyle) -> bool {
+        self.font_size == other.font_size
+            && self.color == other.color
+            && self.font_weight == other.font_weight
+            && self.font_style == other.font_style
+            && self.font_family == other.font_family
+            && self.line_height == other.line_height
+            && self.word_break == other.word_break
+            && self.overflow_wrap == other.overflow_wrap
+            && self.pointer_events == other.pointer_events
+            && self.direction == other.direction
+            && self.visibility == other.visibility
+            && self.list_style_type == other.list_style_type
+            && self.cursor == other.cursor
+            && self.text_transform == other.text_transform
+            && self.letter_spacing == other.letter_spacing
+            && self.word_spacing == other.word_spacing
+            && self.tab_size == other.tab_size
+            && self.white_space == other.white_space
+            && self.text_align == other.text_align
+            && self.lang == other.lang
+            && self.line_clamp == other.line_clamp
+            && self.caret_color == other.caret_color
+    }
 }
 
-/// Lightweight computed style for text nodes.  Text nodes only carry the two
-/// inheritable properties that layout and rendering actually read (`font_size`
-/// and `color`), avoiding the 36-field `ComputedStyle` overhead for every text
-/// node in the document.
+/// Lightweight computed style for text nodes.  Text nodes only carry the
+/// inheritable properties that layout and rendering actually read, avoiding
+/// the full `ComputedStyle` overhead for every text node in the document.
 #[derive(Debug, Clone, PartialEq)]
 pub struct TextComputedStyle {
     pub font_size: f32,
     pub color: (u8, u8, u8, u8),
+    pub font_weight: u16,
+    pub font_style: FontStyleKeyword,
+    pub font_family: std::sync::Arc<[FontFamilyName]>,
+    pub line_height: LineHeight,
+    pub word_break: WordBreakKeyword,
+    pub overflow_wrap: OverflowWrapKeyword,
+    pub direction: DirectionKeyword,
+    pub visibility: VisibilityKeyword,
+    pub text_transform: TextTransformKeyword,
+    pub letter_spacing: f32,
+    pub word_spacing: f32,
+    pub tab_size: u32,
+    pub white_space: WhiteSpaceKeyword,
+    pub text_align: TextAlignKeyword,
+    pub lang: Option<std::sync::Arc<str>>,
+    /// Copied from the parent element's `line-clamp`.
+    pub line_clamp: Option<u32>,
+    pub caret_color: Option<(u8, u8, u8, u8)>,
 }
 
 impl Eq for TextComputedStyle {}
@@ -526,6 +1513,23 @@ impl Default for TextComputedStyle {
         TextComputedStyle {
             font_size: 16.0,
             color: (0, 0, 0, 255),
+            font_weight: 400,
+            font_style: FontStyleKeyword::Normal,
+            font_family: std::sync::Arc::from([]),
+            line_height: LineHeight::Normal,
+            word_break: WordBreakKeyword::Normal,
+            overflow_wrap: OverflowWrapKeyword::Normal,
+            direction: DirectionKeyword::Ltr,
+            visibility: VisibilityKeyword::Visible,
+            text_transform: TextTransformKeyword::None,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            tab_size: 8,
+            white_space: WhiteSpaceKeyword::Normal,
+            text_align: TextAlignKeyword::Start,
+            lang: None,
+            line_clamp: None,
+            caret_color: None,
         }
     }
 }
@@ -537,6 +1541,23 @@ impl TextComputedStyle {
         TextComputedStyle {
             font_size: src.font_size,
             color: src.color,
+            font_weight: src.font_weight,
+            font_style: src.font_style,
+            font_family: src.font_family.clone(),
+            line_height: src.line_height,
+            word_break: src.word_break,
+            overflow_wrap: src.overflow_wrap,
+            direction: src.direction,
+            visibility: src.visibility,
+            text_transform: src.text_transform,
+            letter_spacing: src.letter_spacing,
+            word_spacing: src.word_spacing,
+            tab_size: src.tab_size,
+            white_space: src.white_space,
+            text_align: src.text_align,
+            lang: src.lang.clone(),
+            line_clamp: src.line_clamp,
+            caret_color: src.caret_color,
         }
     }
 }
@@ -554,12 +1575,25 @@ impl Default for Document {
             nodes: arena,
             root_id,
             root_font_size: 16.0,
+            rem_base: 16.0,
             stylesheet: crate::css::StyleSheet::default(),
             id_map: std::collections::HashMap::new(),
             dead_nodes: Vec::new(),
             dirty: true,
             styles_dirty: true,
+            truncated: false,
+            anonymous_boxes: std::collections::HashMap::new(),
             taffy_tree: taffy::TaffyTree::new(),
+            clamped_text: std::collections::HashMap::new(),
+            attribute_observer: None,
+            scroll_offsets: std::collections::HashMap::new(),
+            viewport_size: (0.0, 0.0),
+            viewport_scroll: (0.0, 0.0),
+            quirks_mode: false,
+            title: None,
+            viewport_meta: None,
+            focused: None,
+            id_map_repairs: 0,
         }
     }
 }
@@ -573,13 +1607,25 @@ impl Document {
         self.dirty = true;
         let id = self.nodes.insert(node);
         if let Some(Node::Element(data)) = self.nodes.get(id) {
-            if let Some((_, id_val)) = data.attributes.iter().find(|(k, _)| &**k == "id") {
-                self.id_map.insert(id_val.clone(), id);
+            if let Some(id_val) = data.id() {
+                self.id_map.insert(id_val.to_string(), id);
             }
         }
         id
     }
 
+    /// Add a detached element with no attributes, ready to be placed with
+    /// `append_child` or `insert_before`. `tag` is lowercased.
+    pub fn create_element(&mut self, tag: &str) -> NodeId {
+        let tag = tag.to_ascii_lowercase();
+        self.add_node(Node::Element(ElementData::new(LocalName::new(&tag))))
+    }
+
+    /// Add a detached text node.
+    pub fn create_text(&mut self, text: &str) -> NodeId {
+        self.add_node(Node::Text(TextData::new(text.to_string())))
+    }
+
     pub fn remove_node(&mut self, id: NodeId) -> Option<Node> {
         self.dirty = true;
         
@@ -594,18 +1640,34 @@ impl Document {
         node_copy
     }
 
-    /// Recursively wipes a node and its descendants from the arena.
+    /// Wipes a node and its descendants from the arena.
     /// Internal use only, assumes node is already detached from the root-connected tree.
+    ///
+    /// Runs in three passes so no link is read after anything has been freed:
+    /// collect the subtree in pre-order, purge `id_map` entries pointing into
+    /// it (an entry for the same id on another node is kept), then remove every node (and its Taffy node) from the arena.
     fn wipe_node_recursive(&mut self, id: NodeId) {
-        let mut to_wipe = vec![id];
-        while let Some(current_id) = to_wipe.pop() {
-            let mut child = self.first_child_of(current_id);
-            while let Some(c) = child {
-                to_wipe.push(c);
-                child = self.next_sibling_of(c);
+        let subtree = self.collect_subtree(id);
+
+        for &node_id in &subtree {
+            if let Some(Node::Element(data)) = self.nodes.get(node_id)
+                && let Some(id_val) = data.id()
+                && self.id_map.get(id_val) == Some(&node_id)
+            {
+                self.id_map.remove(id_val);
             }
+        }
 
-            if let Some(node) = self.nodes.remove(current_id) {
+        for node_id in subtree {
+            self.scroll_offsets.remove(&node_id);
+            self.clamped_text.remove(&node_id);
+            if self.focused == Some(node_id) {
+                self.focused = None;
+            }
+            for anonymous in self.anonymous_boxes.remove(&node_id).unwrap_or_default() {
+                let _ = self.taffy_tree.remove(anonymous);
+            }
+            if let Some(node) = self.nodes.remove(node_id) {
                 // Clean up the corresponding Taffy node to prevent unbounded leak
                 let taffy_id = match &node {
                     Node::Element(d) => d.taffy_node,
@@ -615,16 +1677,25 @@ impl Document {
                 if let Some(tid) = taffy_id {
                     let _ = self.taffy_tree.remove(tid);
                 }
+            }
+        }
+    }
 
-                if let Node::Element(data) = &node {
-                    if let Some((_, id_val)) = data.attributes.iter().find(|(k, _)| &**k == "id") {
-                        if self.id_map.get(id_val) == Some(&current_id) {
-                            self.id_map.remove(id_val);
-                        }
-                    }
-                }
+    /// Pre-order list of `id` and everything beneath it, including
+    /// `::before`/`::after` and list marker text nodes.
+    fn collect_subtree(&self, id: NodeId) -> Vec<NodeId> {
+        let mut out = Vec::new();
+        let mut stack = vec![id];
+        while let Some(current_id) = stack.pop() {
+            if !self.nodes.contains(current_id) {
+                continue;
             }
+            out.push(current_id);
+            let start = stack.len();
+            stack.extend(self.layout_children(current_id));
+            stack[start..].reverse();
         }
+        out
     }
 
     /// Checks if a detached tree can be safely deleted.
@@ -657,20 +1728,34 @@ impl Document {
     }
 
     /// Invoked by the GC bridge when a JS NodeHandle is dropped.
-    pub fn try_cleanup_node(&mut self, id: NodeId) {
-        if let Some(node) = self.nodes.get_mut(id) {
-            let handles = match node {
-                Node::Element(d) => &mut d.js_handles,
-                Node::Text(d) => &mut d.js_handles,
-                Node::Root(d) => &mut d.js_handles,
-            };
-            if *handles > 0 {
-                *handles -= 1;
-            }
-            if *handles == 0 {
-                self.dead_nodes.push(id);
-            }
+    ///
+    /// Returns false (and does nothing) when the key is stale: the arena's
+    /// generation check rejects ids whose slot has been reused, and a node
+    /// whose handle count is already zero has been finalized once too often.
+    /// Neither case may decrement a live node or queue it for the sweep.
+    ///
+    /// Otherwise the count of the node `id` names is decremented, even when
+    /// its element id is mapped to another node (a detached duplicate): the
+    /// sweep frees only `id`'s detached tree, and `wipe_node_recursive` only
+    /// drops `id_map` entries that point into that tree, so the mapped node
+    /// and its entry are untouched.
+    pub fn try_cleanup_node(&mut self, id: NodeId) -> bool {
+        let Some(node) = self.nodes.get_mut(id) else {
+            return false;
+        };
+        let handles = match node {
+            Node::Element(d) => &mut d.js_handles,
+            Node::Text(d) => &mut d.js_handles,
+            Node::Root(d) => &mut d.js_handles,
+        };
+        if *handles == 0 {
+            return false;
+        }
+        *handles -= 1;
+        if *handles == 0 {
+            self.dead_nodes.push(id);
         }
+        true
     }
 
     /// Performs a batched sweep of potential dead nodes. 
@@ -681,12 +1766,9 @@ impl Document {
 
         for id in potential {
             if !self.nodes.contains(id) { continue; }
-            
+
             // Find the "detached root" of this node branch
-            let mut curr = id;
-            while let Some(parent) = self.parent_of(curr) {
-                curr = parent;
-            }
+            let curr = self.ancestors(id).last().unwrap_or(id);
 
             if curr != self.root_id {
                 detached_roots.insert(curr);
@@ -700,6 +1782,69 @@ impl Document {
         }
     }
 
+    /// Ids of the attached nodes for which `pred` holds, in document order.
+    ///
+    /// The list is a snapshot taken before the caller changes anything, so
+    /// it is the way to mutate "every node that matches": removing or moving
+    /// nodes while walking the tree or the arena can skip or revisit nodes.
+    /// Entries may go stale as the caller works through them (removing an
+    /// element frees its unreferenced descendants), so check
+    /// `nodes.contains(id)` before acting on each one.
+    pub fn collect_ids_matching(&self, pred: impl FnMut(NodeId, &Node) -> bool) -> Vec<NodeId> {
+        self.collect_descendants_matching(self.root_id, pred)
+    }
+
+    /// [`Document::collect_ids_matching`] scoped to the descendants of
+    /// `node_id`, which is not itself included.
+    pub fn collect_descendants_matching(
+        &self,
+        node_id: NodeId,
+        mut pred: impl FnMut(NodeId, &Node) -> bool,
+    ) -> Vec<NodeId> {
+        self.descendants(node_id)
+            .filter(|&id| self.nodes.get(id).is_some_and(|node| pred(id, node)))
+            .collect()
+    }
+
+    /// The element with `id`, like DOM `getElementById`; O(1) on a hit.
+    ///
+    /// The `id_map` entry is used after checking that the node still carries
+    /// that id. If it does not, or the id has no entry (the id was written
+    /// straight into `ElementData`, bypassing [`Document::set_attribute`]),
+    /// the attached tree is scanned once and `id_map` rebuilt from it. When
+    /// the rebuild changes the map, `id_map_repairs` is incremented.
+    pub fn get_element_by_id(&mut self, id: &str) -> Option<NodeId> {
+        fn element_id(node: &Node) -> Option<&str> {
+            match node {
+                Node::Element(data) => data.id(),
+                _ => None,
+            }
+        }
+        if let Some(&node_id) = self.id_map.get(id)
+            && self.nodes.get(node_id).and_then(element_id) == Some(id)
+        {
+            return Some(node_id);
+        }
+
+        // Keep entries that are still right (including detached elements,
+        // which `set_attribute` also maps), then add the first attached
+        // element in document order for every id left unmapped.
+        let mut rebuilt = self.id_map.clone();
+        rebuilt.retain(|key, node_id| self.nodes.get(*node_id).and_then(element_id) == Some(key.as_str()));
+        for node_id in self.descendants(self.root_id) {
+            if let Some(found) = self.nodes.get(node_id).and_then(element_id)
+                && !rebuilt.contains_key(found)
+            {
+                rebuilt.insert(found.to_string(), node_id);
+            }
+        }
+        if rebuilt != self.id_map {
+            self.id_map = rebuilt;
+            self.id_map_repairs += 1;
+        }
+        self.id_map.get(id).copied()
+    }
+
     /// Rebuilds the internal stylesheet by collecting all currently attached `<style>` tags.
     pub fn rebuild_styles(&mut self) {
         if !self.styles_dirty {
@@ -707,46 +1852,11 @@ impl Document {
         }
 
         let mut all_css = String::new();
-        let mut stack = vec![self.root_id];
-
-        while let Some(id) = stack.pop() {
-            if let Some(node) = self.nodes.get(id) {
-                match node {
-                    Node::Element(data) => {
-                        if &*data.tag_name == "style" {
-                            // Collect inner text from children
-                            let mut child_id = data.first_child;
-                            while let Some(c) = child_id {
-                                if let Some(Node::Text(text_data)) = self.nodes.get(c) {
-                                    all_css.push_str(&text_data.text);
-                                }
-                                child_id = self.next_sibling_of(c);
-                            }
-                        }
-
-                        // Traverse children (reverse for stack)
-                        let mut children = Vec::new();
-                        let mut child_id = data.first_child;
-                        while let Some(c) = child_id {
-                            children.push(c);
-                            child_id = self.next_sibling_of(c);
-                        }
-                        for c in children.into_iter().rev() {
-                            stack.push(c);
-                        }
-                    }
-                    Node::Root(data) => {
-                        let mut children = Vec::new();
-                        let mut child_id = data.first_child;
-                        while let Some(c) = child_id {
-                            children.push(c);
-                            child_id = self.next_sibling_of(c);
-                        }
-                        for c in children.into_iter().rev() {
-                            stack.push(c);
-                        }
-                    }
-                    Node::Text(_) => {}
+        let styles = self.collect_ids_matching(|_, node| matches!(node, Node::Element(d) if &*d.tag_name == "style"));
+        for style in styles {
+            for child in std::iter::successors(self.first_child_of(style), |&c| self.next_sibling_of(c)) {
+                if let Some(Node::Text(text_data)) = self.nodes.get(child) {
+                    all_css.push_str(&text_data.text);
                 }
             }
         }
@@ -755,19 +1865,53 @@ impl Document {
         self.styles_dirty = false;
     }
 
-    pub fn append_child(&mut self, parent_id: NodeId, child_id: NodeId) {
+    /// Whether `node_id` is an element that may not have children
+    /// (`<img>`, `<br>`, `<input>`, ...).
+    pub fn is_void_element(&self, node_id: NodeId) -> bool {
+        matches!(self.nodes.get(node_id), Some(Node::Element(data)) if is_void_tag(&data.tag_name))
+    }
+
+    /// Appends `child_id` as the last child of `parent_id`, detaching it from
+    /// any previous parent. Returns `false` without changing the tree if the
+    /// parent is missing, a text node, or a void element, or if the append
+    /// would create a cycle.
+    pub fn append_child(&mut self, parent_id: NodeId, child_id: NodeId) -> bool {
+        self.insert_before(parent_id, child_id, None)
+    }
+
+    /// Inserts `child_id` into `parent_id` just before `reference`, or last
+    /// when `reference` is `None`, like DOM `insertBefore`. The child is
+    /// detached from any previous parent first, so it may already be a child
+    /// of `parent_id`. Returns `false` without changing the tree if the
+    /// parent cannot have children, `reference` is not one of its children,
+    /// or the insert would create a cycle.
+    pub fn insert_before(&mut self, parent_id: NodeId, child_id: NodeId, reference: Option<NodeId>) -> bool {
+        let parent_accepts_children = match self.nodes.get(parent_id) {
+            Some(Node::Element(data)) => !is_void_tag(&data.tag_name),
+            Some(Node::Root(_)) => true,
+            _ => false,
+        };
+        if !parent_accepts_children || !self.nodes.contains(child_id) {
+            return false;
+        }
+        if reference.is_some_and(|r| self.parent_of(r) != Some(parent_id)) {
+            return false;
+        }
         // Cycle check: Ensure child_id is not an ancestor of parent_id
         let mut curr = Some(parent_id);
         while let Some(pid) = curr {
             if pid == child_id {
-                return; // Cycle detected, abort append
+                return false; // Cycle detected, abort insert
             }
             curr = self.parent_of(pid);
         }
+        if reference == Some(child_id) {
+            return true;
+        }
 
         self.dirty = true;
-        
-        // If we are appending a <style> tag, mark styles as dirty
+
+        // If we are inserting a <style> tag, mark styles as dirty
         if let Some(Node::Element(data)) = self.nodes.get(child_id) {
             if &*data.tag_name == "style" {
                 self.styles_dirty = true;
@@ -775,39 +1919,152 @@ impl Document {
         }
 
         if let Some(old_parent) = self.parent_of(child_id) {
-            self.remove_child(old_parent, child_id);
+            self.detach_child(old_parent, child_id);
         }
 
-        let old_last_child = match self.nodes.get_mut(parent_id) {
+        // Neighbours are read after detaching, since the child may have been one.
+        let prev = match reference {
+            Some(r) => self.prev_sibling_of(r),
+            None => self.last_child_of(parent_id),
+        };
+        match self.nodes.get_mut(parent_id) {
             Some(Node::Element(data)) => {
-                let last = data.last_child;
                 if data.first_child.is_none() {
+                    // No longer `:empty`.
+                    data.styles_dirty = true;
+                }
+                if prev.is_none() {
                     data.first_child = Some(child_id);
                 }
-                data.last_child = Some(child_id);
-                last
+                if reference.is_none() {
+                    data.last_child = Some(child_id);
+                }
             }
             Some(Node::Root(root)) => {
-                let last = root.last_child;
-                if root.first_child.is_none() {
+                if prev.is_none() {
                     root.first_child = Some(child_id);
                 }
-                root.last_child = Some(child_id);
-                last
+                if reference.is_none() {
+                    root.last_child = Some(child_id);
+                }
             }
-            _ => return,
+            _ => unreachable!("parent checked above"),
+        }
+
+        if let Some(p) = prev {
+            self.set_next_sibling(p, Some(child_id));
+        }
+        if let Some(r) = reference {
+            self.set_prev_sibling(r, Some(child_id));
+        }
+        self.set_prev_sibling(child_id, prev);
+        self.set_next_sibling(child_id, reference);
+        self.set_parent(child_id, Some(parent_id));
+        true
+    }
+
+    /// Appends `children` to `parent_id` in order, like DOM `append(...nodes)`,
+    /// linking the whole batch in one pass. The ancestor chain is walked once
+    /// for the cycle check rather than once per child.
+    ///
+    /// Returns false without mutating anything if the parent cannot have
+    /// children or any child is missing or an inclusive ancestor of the
+    /// parent. A node listed twice ends up at its last position.
+    pub fn append_children(&mut self, parent_id: NodeId, children: &[NodeId]) -> bool {
+        let parent_accepts_children = match self.nodes.get(parent_id) {
+            Some(Node::Element(data)) => !is_void_tag(&data.tag_name),
+            Some(Node::Root(_)) => true,
+            _ => false,
+        };
+        if !parent_accepts_children {
+            return false;
+        }
+        let ancestors: std::collections::HashSet<NodeId> =
+            std::iter::successors(Some(parent_id), |&id| self.parent_of(id)).collect();
+        if children.iter().any(|c| !self.nodes.contains(*c) || ancestors.contains(c)) {
+            return false;
+        }
+        let mut seen = std::collections::HashSet::with_capacity(children.len());
+        let mut batch: Vec<NodeId> = children.iter().rev().copied().filter(|c| seen.insert(*c)).collect();
+        batch.reverse();
+        let (Some(&first), Some(&last)) = (batch.first(), batch.last()) else {
+            return true;
         };
 
+        self.dirty = true;
+        for &child_id in &batch {
+            if let Some(Node::Element(data)) = self.nodes.get(child_id)
+                && &*data.tag_name == "style"
+            {
+                self.styles_dirty = true;
+            }
+            if let Some(old_parent) = self.parent_of(child_id) {
+                self.detach_child(old_parent, child_id);
+            }
+        }
+
+        // Read after detaching: some of the batch may have been the parent's own children.
+        let old_last_child = match self.nodes.get_mut(parent_id) {
+            Some(Node::Element(data)) => {
+                if data.first_child.is_none() {
+                    // No longer `:empty`.
+                    data.styles_dirty = true;
+                }
+                data.first_child.get_or_insert(first);
+                data.last_child.replace(last)
+            }
+            Some(Node::Root(root)) => {
+                root.first_child.get_or_insert(first);
+                root.last_child.replace(last)
+            }
+            _ => unreachable!("parent checked above"),
+        };
         if let Some(old_last) = old_last_child {
-            self.set_next_sibling(old_last, Some(child_id));
+            self.set_next_sibling(old_last, Some(first));
+        }
+        for (i, &child_id) in batch.iter().enumerate() {
+            let prev = if i == 0 { old_last_child } else { Some(batch[i - 1]) };
+            self.set_prev_sibling(child_id, prev);
+            self.set_next_sibling(child_id, batch.get(i + 1).copied());
+            self.set_parent(child_id, Some(parent_id));
         }
+        true
+    }
 
-        self.set_prev_sibling(child_id, old_last_child);
-        self.set_next_sibling(child_id, None);
-        self.set_parent(child_id, Some(parent_id));
+    /// Puts `new_child` where `old_child` is among `parent_id`'s children,
+    /// like DOM `replaceChild`. `new_child` is detached from any previous
+    /// parent first; `old_child` is detached but not freed, so it can be
+    /// reused. Returns `false` without changing the tree if `old_child` is
+    /// not a child of `parent_id` or `new_child` cannot go there (see
+    /// [`Document::insert_before`]).
+    pub fn replace_child(&mut self, parent_id: NodeId, new_child: NodeId, old_child: NodeId) -> bool {
+        if self.parent_of(old_child) != Some(parent_id) {
+            return false;
+        }
+        if new_child == old_child {
+            return true;
+        }
+        if !self.insert_before(parent_id, new_child, Some(old_child)) {
+            return false;
+        }
+        self.detach_child(parent_id, old_child);
+        true
     }
 
     pub fn remove_child(&mut self, parent_id: NodeId, child_id: NodeId) {
+        self.detach_child(parent_id, child_id);
+
+        // Attempt to wipe the detached subtree if no JS handles reference it.
+        // This prevents memory leaks for parser-created nodes removed via removeChild
+        // that were never held by JS (and thus never trigger FinalizationRegistry).
+        if child_id != self.root_id && self.can_wipe_detached_tree(child_id) {
+            self.wipe_node_recursive(child_id);
+        }
+    }
+
+    /// Unlinks `child_id` from `parent_id` without freeing it, so it can be
+    /// re-inserted elsewhere.
+    fn detach_child(&mut self, parent_id: NodeId, child_id: NodeId) {
         self.dirty = true;
         
         // If we are removing a <style> tag, mark styles as dirty
@@ -829,6 +2086,10 @@ impl Document {
                     if data.last_child == Some(child_id) {
                         data.last_child = prev;
                     }
+                    if data.first_child.is_none() {
+                        // Now `:empty`.
+                        data.styles_dirty = true;
+                    }
                 }
                 Node::Root(root) => {
                     if root.first_child == Some(child_id) {
@@ -852,27 +2113,450 @@ impl Document {
         self.set_parent(child_id, None);
         self.set_prev_sibling(child_id, None);
         self.set_next_sibling(child_id, None);
+    }
 
-        // Attempt to wipe the detached subtree if no JS handles reference it.
-        // This prevents memory leaks for parser-created nodes removed via removeChild
-        // that were never held by JS (and thus never trigger FinalizationRegistry).
-        if child_id != self.root_id && self.can_wipe_detached_tree(child_id) {
-            self.wipe_node_recursive(child_id);
+    /// Removes every child of `node_id` via [`Document::remove_child`]:
+    /// subtrees without JS handles are freed (purging their `id_map` entries)
+    /// and the rest are left detached for `collect_garbage`. Generated
+    /// `::before`/`::after` nodes are not children and are kept. The
+    /// children are snapshotted with
+    /// [`Document::collect_descendants_matching`] before the first removal.
+    pub fn clear_children(&mut self, node_id: NodeId) {
+        let children = self.collect_descendants_matching(node_id, |id, _| self.parent_of(id) == Some(node_id));
+        for child in children {
+            self.remove_child(node_id, child);
         }
     }
 
-    pub fn is_attached_to_root(&self, node_id: NodeId) -> bool {
-        let mut current_id = node_id;
-        while current_id != self.root_id {
-            if let Some(parent_id) = self.parent_of(current_id) {
-                current_id = parent_id;
-            } else {
-                return false;
+    /// Concatenated text of `node_id`'s descendant text nodes in document
+    /// order (or its own text for a text node), like the DOM `textContent`
+    /// getter. Generated `::before`/`::after` text is not included.
+    pub fn text_content(&self, node_id: NodeId) -> String {
+        let mut out = String::new();
+        let mut stack = vec![node_id];
+        while let Some(id) = stack.pop() {
+            if let Some(Node::Text(data)) = self.nodes.get(id) {
+                out.push_str(&data.text);
+                continue;
+            }
+            let start = stack.len();
+            stack.extend(std::iter::successors(self.first_child_of(id), |&c| self.next_sibling_of(c)));
+            stack[start..].reverse();
+        }
+        out
+    }
+
+    /// Set `title`, and replace the children of the first `<title>` element,
+    /// if there is one, with the new text.
+    pub fn set_title(&mut self, title: &str) {
+        self.title = Some(title.to_string());
+        if let Some(element) = self.first_title_element() {
+            self.set_text_content(element, title);
+        }
+    }
+
+    /// Moves focus to the element `node_id`, marking the previously focused
+    /// element and the new one for restyle so `:focus` rules follow on the
+    /// next cascade. Returns `false`, leaving focus unchanged, if `node_id`
+    /// is not an element.
+    pub fn set_focus(&mut self, node_id: NodeId) -> bool {
+        if !matches!(self.nodes.get(node_id), Some(Node::Element(_))) {
+            return false;
+        }
+        if self.focused != Some(node_id) {
+            self.blur();
+            self.focused = Some(node_id);
+            self.mark_focus_dirty(node_id);
+        }
+        true
+    }
+
+    /// Clears focus, marking the element that had it for restyle.
+    pub fn blur(&mut self) {
+        if let Some(old) = self.focused.take() {
+            self.mark_focus_dirty(old);
+        }
+    }
+
+    /// The element Tab moves focus to from `from` (`None`: from the start of
+    /// the document), wrapping around after the last one. `None` if nothing
+    /// is focusable.
+    ///
+    /// Focusable elements are `a[href]`, `button`, `input` (except
+    /// `type="hidden"`), `select`, `textarea` and anything with a `tabindex`,
+    /// unless it is a `disabled` form control or it or an ancestor is
+    /// `display: none` as of the last cascade. Elements
+    /// with a positive `tabindex` come first, in ascending order, then those
+    /// with `tabindex="0"` or none in document order; a negative `tabindex`
+    /// leaves an element out of the sequence. If `from` is not in the
+    /// sequence, the next element after it in document order is returned.
+    pub fn next_focusable(&self, from: Option<NodeId>) -> Option<NodeId> {
+        let mut positive = Vec::new();
+        let mut natural = Vec::new();
+        let mut from_position = None;
+        for id in self.collect_ids_matching(|id, _| Some(id) == from || self.tab_index(id).is_some()) {
+            if Some(id) == from {
+                from_position = Some(natural.len());
+            }
+            match self.tab_index(id) {
+                Some(index) if index > 0 => positive.push((index, id)),
+                Some(0) => natural.push(id),
+                _ => {}
+            }
+        }
+        // Stable, so equal tabindex values keep document order.
+        positive.sort_by_key(|&(index, _)| index);
+        let sequence: Vec<NodeId> = positive.into_iter().map(|(_, id)| id).chain(natural.iter().copied()).collect();
+        let first = *sequence.first()?;
+        let Some(from) = from else { return Some(first) };
+        let next = match sequence.iter().position(|&id| id == from) {
+            Some(i) => sequence.get(i + 1),
+            None => from_position.and_then(|natural_index| natural.get(natural_index)),
+        };
+        Some(next.copied().unwrap_or(first))
+    }
+
+    /// Effective `tabindex` of a focusable element, or `None` if it cannot
+    /// take focus.
+    fn tab_index(&self, node_id: NodeId) -> Option<i32> {
+        let Some(Node::Element(data)) = self.nodes.get(node_id) else {
+            return None;
+        };
+        let attr = |key: &str| data.attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        let form_control = matches!(&*data.tag_name, "button" | "input" | "select" | "textarea");
+        if form_control && attr("disabled").is_some() {
+            return None;
+        }
+        // `display` is not inherited, so a hidden container has to be found
+        // by walking up.
+        let hidden = |id| matches!(self.nodes.get(id), Some(Node::Element(d)) if d.computed.display == DisplayKeyword::None);
+        if hidden(node_id) || self.ancestors(node_id).any(hidden) {
+            return None;
+        }
+        if let Some(index) = attr("tabindex").and_then(|v| v.trim().parse().ok()) {
+            return Some(index);
+        }
+        let focusable = match &*data.tag_name {
+            "a" => attr("href").is_some(),
+            "input" => !attr("type").is_some_and(|t| t.eq_ignore_ascii_case("hidden")),
+            "button" | "select" | "textarea" => true,
+            _ => false,
+        };
+        focusable.then_some(0)
+    }
+
+    fn mark_focus_dirty(&mut self, node_id: NodeId) {
+        if let Some(Node::Element(data)) = self.nodes.get_mut(node_id) {
+            data.styles_dirty = true;
+            self.dirty = true;
+        }
+    }
+
+    /// The first `<title>` element in document order.
+    pub(crate) fn first_title_element(&self) -> Option<NodeId> {
+        self.descendants(self.root_id)
+            .find(|&id| matches!(self.nodes.get(id), Some(Node::Element(d)) if &*d.tag_name == "title"))
+    }
+
+    /// Replaces the children of `node_id` with a single text node holding
+    /// `text` (none if `text` is empty), like the DOM `textContent` setter.
+    /// On a text node it replaces the node's own text.
+    pub fn set_text_content(&mut self, node_id: NodeId, text: &str) {
+        match self.nodes.get_mut(node_id) {
+            Some(Node::Text(data)) => {
+                data.text = text.to_string();
+                data.layout_dirty = true;
+                if matches!(data.parent.and_then(|p| self.nodes.get(p)), Some(Node::Element(e)) if &*e.tag_name == "style") {
+                    self.styles_dirty = true;
+                }
+                self.dirty = true;
+                return;
+            }
+            Some(Node::Element(data)) => {
+                if &*data.tag_name == "style" {
+                    self.styles_dirty = true;
+                }
+            }
+            Some(Node::Root(_)) => {}
+            None => return,
+        }
+        self.clear_children(node_id);
+        if !text.is_empty() {
+            let text_id = self.add_node(Node::Text(TextData::new(text.to_string())));
+            if !self.append_child(node_id, text_id) {
+                // Void elements take no children.
+                self.nodes.remove(text_id);
+            }
+        }
+    }
+
+    /// Serialized markup of `node_id` and its subtree, like the DOM
+    /// `outerHTML` getter. A text node yields its escaped text and the root
+    /// yields its children. Void elements get no end tag and the text of
+    /// `script`/`style` is written unescaped.
+    pub fn outer_html(&self, node_id: NodeId) -> String {
+        enum Step<'a> {
+            Node(NodeId),
+            End(&'a str),
+        }
+        let mut out = String::new();
+        let mut stack = vec![Step::Node(node_id)];
+        while let Some(step) = stack.pop() {
+            let id = match step {
+                Step::End(tag) => {
+                    out.push_str("</");
+                    out.push_str(tag);
+                    out.push('>');
+                    continue;
+                }
+                Step::Node(id) => id,
+            };
+            match self.nodes.get(id) {
+                Some(Node::Text(data)) => {
+                    let raw = matches!(data.parent.and_then(|p| self.nodes.get(p)),
+                        Some(Node::Element(e)) if matches!(&*e.tag_name, "script" | "style"));
+                    if raw {
+                        out.push_str(&data.text);
+                    } else {
+                        escape_html(&data.text, false, &mut out);
+                    }
+                    continue;
+                }
+                Some(Node::Element(data)) => {
+                    out.push('<');
+                    out.push_str(&data.tag_name);
+                    for (key, value) in &data.attributes {
+                        out.push(' ');
+                        out.push_str(key);
+                        out.push_str("=\"");
+                        escape_html(value, true, &mut out);
+                        out.push('"');
+                    }
+                    out.push('>');
+                    if is_void_tag(&data.tag_name) {
+                        continue;
+                    }
+                    stack.push(Step::End(&data.tag_name));
+                }
+                Some(Node::Root(_)) => {}
+                None => continue,
+            }
+            let start = stack.len();
+            stack.extend(
+                std::iter::successors(self.first_child_of(id), |&c| self.next_sibling_of(c)).map(Step::Node),
+            );
+            stack[start..].reverse();
+        }
+        out
+    }
+
+    /// Rendered text of `node_id`, approximating the DOM `innerText` getter:
+    /// whitespace runs collapse to one space, block-level boundaries and
+    /// `<br>` become `\n`, and `display: none`, `script` and `style` subtrees
+    /// are skipped. Block-ness is read from the computed `display`, so the
+    /// result reflects the last `compute_styles` pass.
+    pub fn inner_text(&self, node_id: NodeId) -> String {
+        enum Step {
+            Node(NodeId),
+            BlockEnd,
+        }
+        let mut out = String::new();
+        let (mut pending_break, mut pending_space) = (false, false);
+        let mut stack = vec![Step::Node(node_id)];
+        while let Some(step) = stack.pop() {
+            let id = match step {
+                Step::BlockEnd => {
+                    pending_break = true;
+                    continue;
+                }
+                Step::Node(id) => id,
+            };
+            match self.nodes.get(id) {
+                Some(Node::Text(data)) => {
+                    for ch in data.text.chars() {
+                        if ch.is_whitespace() {
+                            pending_space = true;
+                            continue;
+                        }
+                        if pending_break && !out.is_empty() && !out.ends_with('\n') {
+                            out.push('\n');
+                        } else if pending_space && !out.is_empty() && !out.ends_with('\n') && !pending_break {
+                            out.push(' ');
+                        }
+                        (pending_break, pending_space) = (false, false);
+                        out.push(ch);
+                    }
+                    continue;
+                }
+                Some(Node::Element(data)) => {
+                    if data.computed.display == DisplayKeyword::None
+                        || matches!(&*data.tag_name, "script" | "style")
+                    {
+                        continue;
+                    }
+                    if &*data.tag_name == "br" {
+                        out.push('\n');
+                        (pending_break, pending_space) = (false, false);
+                        continue;
+                    }
+                    if !matches!(data.computed.display, DisplayKeyword::Inline | DisplayKeyword::InlineBlock) {
+                        pending_break = true;
+                        stack.push(Step::BlockEnd);
+                    }
+                }
+                Some(Node::Root(_)) => {}
+                None => continue,
+            }
+            let start = stack.len();
+            stack.extend(
+                std::iter::successors(self.first_child_of(id), |&c| self.next_sibling_of(c)).map(Step::Node),
+            );
+            stack[start..].reverse();
+        }
+        out
+    }
+
+    /// The value of attribute `key` on `node_id`, like the DOM `getAttribute`.
+    /// `class` reads `classes` and `style` is serialized from the parsed
+    /// inline declarations.
+    pub fn get_attribute(&self, node_id: NodeId, key: &str) -> Option<String> {
+        let Some(Node::Element(data)) = self.nodes.get(node_id) else {
+            return None;
+        };
+        match key {
+            "class" => (!data.classes.is_empty()).then(|| data.classes.clone()),
+            "style" => {
+                let inline = data.cached_inline_styles.as_ref()?;
+                let css = inline
+                    .iter()
+                    .map(|(name, val)| format!("{}:{}", name.as_str(), val))
+                    .collect::<Vec<_>>()
+                    .join(";");
+                (!css.is_empty()).then_some(css)
+            }
+            _ => data.attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone()),
+        }
+    }
+
+    /// Sets attribute `key` on `node_id`, like the DOM `setAttribute`. Values
+    /// are truncated to `MAX_ATTRIBUTE_VALUE_LEN` on a char boundary; `class`
+    /// and `style` also update `classes` / `cached_inline_styles`, and `id`
+    /// keeps `id_map` current. Notifies `attribute_observer` when the value
+    /// was stored, and returns whether it was.
+    pub fn set_attribute(&mut self, node_id: NodeId, key: &str, mut value: String) -> bool {
+        if value.len() > MAX_ATTRIBUTE_VALUE_LEN {
+            let mut cap = MAX_ATTRIBUTE_VALUE_LEN;
+            while !value.is_char_boundary(cap) {
+                cap -= 1;
+            }
+            value.truncate(cap);
+        }
+        let old = match self.attribute_observer {
+            Some(_) => self.get_attribute(node_id, key),
+            None => None,
+        };
+        let Some(Node::Element(data)) = self.nodes.get_mut(node_id) else {
+            return false;
+        };
+        let old_id = data.id().map(str::to_string);
+        let stored = match key {
+            "class" => {
+                data.classes = value.clone();
+                data.set_attribute(key, value.clone());
+                true
+            }
+            "style" => {
+                let decls = crate::css::parse_inline_declarations(&value);
+                data.cached_inline_styles = Some(decls.into_iter().map(|d| (d.name, d.value)).collect());
+                data.set_attribute(key, value.clone());
+                true
+            }
+            _ => data.set_attribute(key, value.clone()),
+        };
+        data.styles_dirty = true;
+        self.dirty = true;
+        if !stored {
+            return false;
+        }
+        if key == "id" {
+            if let Some(oid) = old_id
+                && self.id_map.get(&oid) == Some(&node_id)
+            {
+                self.id_map.remove(&oid);
+            }
+            self.id_map.insert(value.clone(), node_id);
+        }
+        if let Some(observer) = &self.attribute_observer {
+            let new = self.get_attribute(node_id, key);
+            observer(node_id, key, old.as_deref(), new.as_deref());
+        }
+        true
+    }
+
+    /// Removes attribute `key` from `node_id`, like the DOM `removeAttribute`.
+    /// `class` and `style` also clear `classes` / `cached_inline_styles`, and
+    /// `id` drops the `id_map` entry if it points here. Notifies
+    /// `attribute_observer` and returns true if the attribute was present.
+    pub fn remove_attribute(&mut self, node_id: NodeId, key: &str) -> bool {
+        let Some(Node::Element(data)) = self.nodes.get_mut(node_id) else {
+            return false;
+        };
+        let Some(old) = data.remove_attribute(key) else {
+            return false;
+        };
+        match key {
+            "class" => data.classes.clear(),
+            "style" => data.cached_inline_styles = None,
+            "id" if self.id_map.get(&old) == Some(&node_id) => {
+                self.id_map.remove(&old);
             }
+            _ => {}
+        }
+        data.styles_dirty = true;
+        self.dirty = true;
+        if let Some(observer) = &self.attribute_observer {
+            observer(node_id, key, Some(&old), None);
         }
         true
     }
 
+    /// Scrolls `node_id` so its content is drawn `(x, y)` px up and to the
+    /// left. Negative values are treated as 0; `(0, 0)` forgets the entry.
+    /// Only takes effect on scroll containers (`overflow` other than
+    /// `visible`), and is clamped to their scrollable range when read.
+    pub fn set_scroll_offset(&mut self, node_id: NodeId, x: f32, y: f32) {
+        let offset = (x.max(0.0), y.max(0.0));
+        if offset == (0.0, 0.0) {
+            self.scroll_offsets.remove(&node_id);
+        } else {
+            self.scroll_offsets.insert(node_id, offset);
+        }
+    }
+
+    /// The effective scroll offset of `node_id`: the stored offset on each
+    /// axis whose `overflow` is not `visible`, clamped to the last layout's
+    /// scroll width/height. `(0, 0)` for anything else.
+    pub fn scroll_offset(&self, node_id: NodeId) -> (f32, f32) {
+        let Some(&(x, y)) = self.scroll_offsets.get(&node_id) else {
+            return (0.0, 0.0);
+        };
+        let Some(Node::Element(data)) = self.nodes.get(node_id) else {
+            return (0.0, 0.0);
+        };
+        let Some(layout) = data.taffy_node.and_then(|t| self.taffy_tree.layout(t).ok()) else {
+            return (0.0, 0.0);
+        };
+        let scrolls = |k: OverflowKeyword| k != OverflowKeyword::Visible;
+        (
+            if scrolls(data.computed.overflow[0]) { x.min(layout.scroll_width()) } else { 0.0 },
+            if scrolls(data.computed.overflow[1]) { y.min(layout.scroll_height()) } else { 0.0 },
+        )
+    }
+
+    pub fn is_attached_to_root(&self, node_id: NodeId) -> bool {
+        node_id == self.root_id || self.ancestors(node_id).any(|a| a == self.root_id)
+    }
+
     fn set_parent(&mut self, node_id: NodeId, parent: Option<NodeId>) {
         if let Some(node) = self.nodes.get_mut(node_id) {
             match node {
@@ -883,6 +2567,162 @@ impl Document {
         }
     }
 
+    /// Children in layout order: the list marker, the generated `::before`
+    /// node, the DOM children, then the generated `::after` node.
+    pub fn layout_children(&self, node_id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let (marker, before, after) = match self.nodes.get(node_id) {
+            Some(Node::Element(data)) => (data.pseudo_marker, data.pseudo_before, data.pseudo_after),
+            _ => (None, None, None),
+        };
+        marker
+            .into_iter()
+            .chain(before)
+            .chain(std::iter::successors(self.first_child_of(node_id), move |&c| {
+                self.next_sibling_of(c)
+            }))
+            .chain(after)
+    }
+
+    /// `layout_children` with every `display: contents` element replaced by
+    /// its own children, recursively: the nodes whose boxes are laid out in
+    /// `node_id`'s box. Allocates only when a `contents` child is present.
+    pub fn box_children(&self, node_id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let mut current = self.layout_children(node_id);
+        let mut outer = Vec::new();
+        std::iter::from_fn(move || loop {
+            match current.next() {
+                Some(child) if self.is_display_contents(child) => {
+                    outer.push(std::mem::replace(&mut current, self.layout_children(child)));
+                }
+                Some(child) => return Some(child),
+                None => current = outer.pop()?,
+            }
+        })
+    }
+
+    /// The nearest ancestor of `node_id` that generates a box, skipping
+    /// `display: contents` elements. Inverse of `box_children`.
+    pub fn box_parent(&self, node_id: NodeId) -> Option<NodeId> {
+        self.ancestors(node_id).find(|&id| !self.is_display_contents(id))
+    }
+
+    /// True for an element with `display: contents`.
+    pub fn is_display_contents(&self, node_id: NodeId) -> bool {
+        matches!(self.nodes.get(node_id), Some(Node::Element(d)) if d.computed.display == DisplayKeyword::Contents)
+    }
+
+    /// Set the default root font size that unstyled text and `rem` start
+    /// from, and restyle and relay out everything on the next pass.
+    pub fn set_root_font_size(&mut self, px: f32) {
+        if self.root_font_size != px {
+            self.root_font_size = px;
+            self.styles_dirty = true;
+            self.dirty = true;
+        }
+    }
+
+    /// Hide an element as if by `display: none`, or show it again, without a
+    /// restyle: the next `compute_layout` only redoes the element's ancestors
+    /// and the nodes it shared a line with. The cascaded `display` is kept
+    /// while hidden (later restyles update it), so showing restores it.
+    /// Returns false if `node_id` is not an element or is already in that state.
+    pub fn set_hidden(&mut self, node_id: NodeId, hidden: bool) -> bool {
+        let Some(Node::Element(data)) = self.nodes.get_mut(node_id) else {
+            return false;
+        };
+        if hidden == data.hidden_display.is_some() {
+            return false;
+        }
+        if hidden {
+            data.hidden_display = Some(std::mem::replace(&mut data.computed.display, DisplayKeyword::None));
+        } else if let Some(display) = data.hidden_display.take() {
+            data.computed.display = display;
+        }
+        data.layout_dirty = true;
+        // Inline runs are regrouped around the element.
+        self.dirty = true;
+        true
+    }
+
+    /// Invalidate `node_id`'s layout for the next `compute_layout`, for
+    /// changes the engine cannot see (e.g. a host-side font swap). Taffy drops
+    /// the cached layout of the node and its ancestors; a text node is also
+    /// reshaped. Style and DOM changes need no call: only nodes whose Taffy
+    /// style or children change are invalidated.
+    pub fn mark_layout_dirty(&mut self, node_id: NodeId) {
+        let taffy_node = match self.nodes.get_mut(node_id) {
+            Some(Node::Element(data)) => {
+                data.layout_dirty = true;
+                data.taffy_node
+            }
+            Some(Node::Text(data)) => {
+                data.layout_dirty = true;
+                data.taffy_node
+            }
+            Some(Node::Root(data)) => data.taffy_node,
+            None => None,
+        };
+        if let Some(taffy_node) = taffy_node {
+            let _ = self.taffy_tree.mark_dirty(taffy_node);
+        }
+    }
+
+    /// Create, update or remove the generated text node for one of an
+    /// element's pseudo-elements. `content` is the resolved string and text
+    /// style, or `None` when no rule with string `content` matches.
+    /// `FirstLetter` generates no node and is ignored.
+    pub fn set_pseudo_element(
+        &mut self,
+        element_id: NodeId,
+        which: PseudoElement,
+        content: Option<(String, TextComputedStyle)>,
+    ) {
+        let existing = match self.nodes.get(element_id) {
+            Some(Node::Element(data)) => match which {
+                PseudoElement::Before => data.pseudo_before,
+                PseudoElement::After => data.pseudo_after,
+                PseudoElement::Marker => data.pseudo_marker,
+                PseudoElement::FirstLetter => return,
+            },
+            _ => return,
+        };
+
+        let slot = match (existing, content) {
+            (Some(id), Some((text, style))) => {
+                if let Some(Node::Text(data)) = self.nodes.get_mut(id)
+                    && (data.text != text || data.computed != style)
+                {
+                    data.text = text;
+                    data.computed = style;
+                    data.layout_dirty = true;
+                }
+                return;
+            }
+            (None, Some((text, style))) => {
+                let mut data = TextData::new(text);
+                data.parent = Some(element_id);
+                data.computed = style;
+                data.styles_dirty = false;
+                Some(self.add_node(Node::Text(data)))
+            }
+            (Some(id), None) => {
+                self.dirty = true;
+                self.wipe_node_recursive(id);
+                None
+            }
+            (None, None) => return,
+        };
+
+        if let Some(Node::Element(data)) = self.nodes.get_mut(element_id) {
+            match which {
+                PseudoElement::Before => data.pseudo_before = slot,
+                PseudoElement::After => data.pseudo_after = slot,
+                PseudoElement::Marker => data.pseudo_marker = slot,
+                PseudoElement::FirstLetter => {}
+            }
+        }
+    }
+
     /// Get the parent of a node via O(1) in-node lookup.
     pub fn parent_of(&self, node_id: NodeId) -> Option<NodeId> {
         match self.nodes.get(node_id)? {
@@ -892,6 +2732,116 @@ impl Document {
         }
     }
 
+    /// Iterate the ancestors of a node, nearest first, ending at the root
+    /// (or at the top of a detached subtree). The node itself is not yielded.
+    pub fn ancestors(&self, node_id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent_of(node_id), move |&p| self.parent_of(p))
+    }
+
+    /// The language of a node: the `lang` attribute of the node or its
+    /// nearest ancestor that has one. `None` when no ancestor sets it or the
+    /// nearest value is empty (unknown language).
+    pub fn lang(&self, node_id: NodeId) -> Option<&str> {
+        std::iter::once(node_id)
+            .chain(self.ancestors(node_id))
+            .find_map(|id| match self.nodes.get(id) {
+                Some(Node::Element(data)) => data.attributes.iter().find(|(k, _)| k == "lang").map(|(_, v)| v.as_str()),
+                _ => None,
+            })
+            .filter(|lang| !lang.is_empty())
+    }
+
+    /// Iterate the descendants of a node in document (pre-order) order by
+    /// following the tree links; nothing is allocated. The node itself and
+    /// generated pseudo-element nodes are not yielded.
+    pub fn descendants(&self, node_id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.first_child_of(node_id), move |&current| {
+            if let Some(child) = self.first_child_of(current) {
+                return Some(child);
+            }
+            let mut cur = current;
+            loop {
+                if let Some(next) = self.next_sibling_of(cur) {
+                    return Some(next);
+                }
+                cur = self.parent_of(cur)?;
+                if cur == node_id {
+                    return None;
+                }
+            }
+        })
+    }
+
+    /// Number of ancestors above a node; the root (and any detached subtree
+    /// top) has depth 0.
+    pub fn depth(&self, node_id: NodeId) -> usize {
+        self.ancestors(node_id).count()
+    }
+
+    /// Checks the intrusive tree links of every node in the arena, attached
+    /// or not: each child's `parent` points back, `prev`/`next` siblings are
+    /// symmetric, `first_child`/`last_child` bound the sibling chain, no node
+    /// is linked twice, and no parent chain loops. Generated pseudo-element
+    /// nodes are only required to point at an element that owns them.
+    /// Read-only and linear in the arena size; meant for tests of mutation
+    /// code.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut linked = std::collections::HashSet::new();
+        for (id, node) in self.nodes.iter() {
+            let (first, last) = match node {
+                Node::Element(d) => (d.first_child, d.last_child),
+                Node::Root(d) => (d.first_child, d.last_child),
+                Node::Text(_) => (None, None),
+            };
+            if first.is_some() != last.is_some() {
+                return Err(format!("{id:?}: first_child is {first:?} but last_child is {last:?}"));
+            }
+            let mut prev = None;
+            let mut current = first;
+            while let Some(child) = current {
+                if !self.nodes.contains(child) {
+                    return Err(format!("{id:?}: child {child:?} is not in the arena"));
+                }
+                if !linked.insert(child) {
+                    return Err(format!("{child:?} is linked more than once (cycle in the children of {id:?}?)"));
+                }
+                if self.parent_of(child) != Some(id) {
+                    return Err(format!("{child:?} is a child of {id:?} but its parent is {:?}", self.parent_of(child)));
+                }
+                if self.prev_sibling_of(child) != prev {
+                    return Err(format!(
+                        "{child:?} follows {prev:?} under {id:?} but its prev_sibling is {:?}",
+                        self.prev_sibling_of(child)
+                    ));
+                }
+                prev = Some(child);
+                current = self.next_sibling_of(child);
+            }
+            if prev != last {
+                return Err(format!("{id:?}: sibling chain ends at {prev:?} but last_child is {last:?}"));
+            }
+        }
+        for (id, _) in self.nodes.iter() {
+            let Some(parent) = self.parent_of(id) else {
+                if self.prev_sibling_of(id).is_some() || self.next_sibling_of(id).is_some() {
+                    return Err(format!("{id:?} has siblings but no parent"));
+                }
+                continue;
+            };
+            if !linked.contains(&id) {
+                let owns = matches!(self.nodes.get(parent), Some(Node::Element(d))
+                    if [d.pseudo_before, d.pseudo_after, d.pseudo_marker].contains(&Some(id)));
+                if !owns {
+                    return Err(format!("{id:?} names {parent:?} as parent but is not among its children"));
+                }
+            }
+            if self.ancestors(id).take(self.nodes.len() + 1).count() > self.nodes.len() {
+                return Err(format!("{id:?}: parent chain has a cycle"));
+            }
+        }
+        Ok(())
+    }
+
     /// Get the first child of a node via O(1) in-node lookup.
     pub fn first_child_of(&self, node_id: NodeId) -> Option<NodeId> {
         match self.nodes.get(node_id)? {
@@ -966,14 +2916,33 @@ impl Document {
                 if px >= abs_x && px <= abs_x + layout.size.width &&
                    py >= abs_y && py <= abs_y + layout.size.height {
 
-                    hit = Some(node_id);
+                    // Text nodes have no `pointer-events` of their own; they
+                    // take it from the element that contains them.
+                    let pointer_events = match self.nodes.get(node_id) {
+                        Some(Node::Element(d)) => d.computed.pointer_events,
+                        Some(Node::Text(t)) => match t.parent.and_then(|p| self.nodes.get(p)) {
+                            Some(Node::Element(d)) => d.computed.pointer_events,
+                            _ => PointerEventsKeyword::Auto,
+                        },
+                        _ => PointerEventsKeyword::Auto,
+                    };
+                    let visibility = match self.nodes.get(node_id) {
+                        Some(Node::Element(d)) => d.computed.visibility,
+                        Some(Node::Text(t)) => t.computed.visibility,
+                        _ => VisibilityKeyword::Visible,
+                    };
+                    if pointer_events == PointerEventsKeyword::Auto && visibility == VisibilityKeyword::Visible {
+                        hit = Some(node_id);
+                    }
+                    if matches!(self.nodes.get(node_id), Some(Node::Element(d))
+                        if d.computed.content_visibility == ContentVisibilityKeyword::Hidden)
+                    {
+                        continue;
+                    }
 
                     children_buf.clear();
-                    let mut child_id = self.first_child_of(node_id);
-                    while let Some(c) = child_id {
-                        children_buf.push(c);
-                        child_id = self.next_sibling_of(c);
-                    }
+                    children_buf.extend(self.box_children(node_id));
+                    let (scroll_x, scroll_y) = self.scroll_offset(node_id);
 
                     for c in &children_buf {
                         let c_taffy = match self.nodes.get(*c) {
@@ -982,7 +2951,8 @@ impl Document {
                             _ => None,
                         };
                         if let Some(t) = c_taffy {
-                            stack.push((*c, t, abs_x, abs_y));
+                            let (ax, ay) = crate::layout::anonymous_box_offset(&self.taffy_tree, taffy_id, t);
+                            stack.push((*c, t, abs_x + ax - scroll_x, abs_y + ay - scroll_y));
                         }
                     }
                 }
@@ -992,3 +2962,17 @@ impl Document {
         hit
     }
 }
+
+/// Appends `text` to `out` with `&`, `<` and `>` escaped, plus `"` when
+/// `in_attribute` is set.
+fn escape_html(text: &str, in_attribute: bool, out: &mut String) {
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if in_attribute => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+}
@@ -10,9 +10,17 @@
 //! `Document` in a single pass. Implicit tag auto-closing walks up the
 //! ancestor chain to find the matching tag before block-level boundaries.
 //!
+//! `parse_html_bytes` decodes non-UTF-8 input with `encoding_rs` first, picking
+//! the charset from a BOM, the caller's declaration or a `<meta>` prescan.
+//!
+//! `HtmlParser` accepts input incrementally (`feed` / `finish`); `parse_html`
+//! is the one-shot wrapper around it.
+//!
 //! Content inside `<script>` and `<style>` tags is treated as raw text.
 //! CSS text from `<style>` elements is parsed immediately into
-//! `document.stylesheet` via `css::append_stylesheet()`.
+//! `document.stylesheet` via `css::append_stylesheet()`. The first `<title>`
+//! element's text is copied into `document.title` when parsing finishes, and
+//! the first `<meta name="viewport">` is parsed into `document.viewport_meta`.
 //!
 //! Byte slices from `html5gum` tokens are validated as UTF-8 via
 //! `std::str::from_utf8()` (zero-allocation for tag names). Attribute
@@ -24,149 +32,463 @@ use crate::dom::{Document, ElementData, Node, TextData};
 use html5gum::{Token, Tokenizer};
 
 pub fn parse_html(html: &str) -> Document {
-    let mut doc = Document::default();
-    let mut current_parent = doc.root_id;
-    let mut inside_raw_tag: Option<crate::dom::LocalName> = None;
-    let mut current_style_text = String::new();
+    let mut parser = HtmlParser::new();
+    parser.text.push_str(html);
+    parser.finish()
+}
+
+/// Like [`parse_html`], but stops creating nodes once the document holds
+/// `max_nodes` elements and text nodes. The rest of the input is ignored and
+/// `Document::truncated` is set, so embedders can cap memory on hostile input.
+pub fn parse_html_with_limit(html: &str, max_nodes: usize) -> Document {
+    let mut parser = HtmlParser::with_node_limit(max_nodes);
+    parser.text.push_str(html);
+    parser.finish()
+}
+
+/// Parse HTML from raw bytes in an unknown or declared character encoding.
+///
+/// The encoding is chosen the way browsers do it, in priority order: a byte
+/// order mark, then `declared_charset` (e.g. from a `Content-Type` header),
+/// then a `<meta charset>` / `<meta http-equiv content="...; charset=...">`
+/// found in the first 1024 bytes, and finally UTF-8. Labels are resolved with
+/// `encoding_rs`, so `latin1`, `iso-8859-1` etc. all map to windows-1252.
+pub fn parse_html_bytes(bytes: &[u8], declared_charset: Option<&str>) -> Document {
+    let encoding = declared_charset
+        .and_then(|label| encoding_rs::Encoding::for_label(label.trim().as_bytes()))
+        .or_else(|| prescan_meta_charset(bytes))
+        .unwrap_or(encoding_rs::UTF_8);
+    // `decode` lets a BOM override the chosen encoding and strips it.
+    let (text, _, _) = encoding.decode(bytes);
+    parse_html(&text)
+}
+
+/// Parse the `content` of a viewport `<meta>`: `key=value` pairs separated
+/// by commas, semicolons or whitespace, keys and keywords case-insensitive.
+/// Unknown keys and unparseable values are skipped.
+pub fn parse_viewport_content(content: &str) -> crate::dom::ViewportMeta {
+    let mut meta = crate::dom::ViewportMeta::default();
+    // Spaces around `=` are allowed, so drop them before splitting pairs.
+    let mut normalized = String::with_capacity(content.len());
+    for c in content.chars().map(|c| c.to_ascii_lowercase()) {
+        if c == '=' {
+            normalized.truncate(normalized.trim_end().len());
+        } else if c.is_ascii_whitespace() && normalized.ends_with('=') {
+            continue;
+        }
+        normalized.push(c);
+    }
+    let number = |value: &str| {
+        // A leading number is used, as in `initial-scale=1.0abc`.
+        let end = value
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+            .unwrap_or(value.len());
+        value[..end].parse::<f32>().ok().filter(|n| n.is_finite())
+    };
+    let length = |value: &str| match value {
+        "device-width" => Some(crate::dom::ViewportLength::DeviceWidth),
+        "device-height" => Some(crate::dom::ViewportLength::DeviceHeight),
+        _ => number(value).filter(|&n| n > 0.0).map(|n| crate::dom::ViewportLength::Px(n.clamp(1.0, 10000.0))),
+    };
+    let scale = |value: &str| match value {
+        "yes" => Some(1.0),
+        "device-width" | "device-height" => Some(10.0),
+        _ => number(value).filter(|&n| n > 0.0).map(|n| n.clamp(0.1, 10.0)),
+    };
+    for pair in normalized.split(|c: char| c == ',' || c == ';' || c.is_ascii_whitespace()) {
+        let Some((key, value)) = pair.split_once('=') else { continue };
+        match key {
+            "width" => meta.width = length(value).or(meta.width),
+            "height" => meta.height = length(value).or(meta.height),
+            "initial-scale" => meta.initial_scale = scale(value).or(meta.initial_scale),
+            "minimum-scale" => meta.minimum_scale = scale(value).or(meta.minimum_scale),
+            "maximum-scale" => meta.maximum_scale = scale(value).or(meta.maximum_scale),
+            "user-scalable" => {
+                meta.user_scalable = match value {
+                    "yes" => Some(true),
+                    "no" => Some(false),
+                    _ => number(value).map(|n| n.abs() >= 1.0).or(meta.user_scalable),
+                }
+            }
+            _ => {}
+        }
+    }
+    meta
+}
+
+/// Look for a charset declaration in a `<meta>` tag near the start of the input.
+fn prescan_meta_charset(bytes: &[u8]) -> Option<&'static encoding_rs::Encoding> {
+    let head = &bytes[..bytes.len().min(1024)];
+    let mut pos = 0;
+    while let Some(offset) = find_ascii_ci(&head[pos..], b"<meta") {
+        let tag_start = pos + offset + 5;
+        let tag_end = head[tag_start..]
+            .iter()
+            .position(|&b| b == b'>')
+            .map_or(head.len(), |end| tag_start + end);
+        let tag = &head[tag_start..tag_end];
+        if let Some(at) = find_ascii_ci(tag, b"charset") {
+            let rest = &tag[at + 7..];
+            let rest = rest.trim_ascii_start();
+            if let Some(rest) = rest.strip_prefix(b"=") {
+                let rest = rest.trim_ascii_start();
+                let rest = rest.strip_prefix(b"\"").or_else(|| rest.strip_prefix(b"'")).unwrap_or(rest);
+                let len = rest
+                    .iter()
+                    .position(|&b| matches!(b, b'"' | b'\'' | b';' | b'/' | b'>') || b.is_ascii_whitespace())
+                    .unwrap_or(rest.len());
+                if let Some(encoding) = encoding_rs::Encoding::for_label(&rest[..len]) {
+                    // A meta declaration can't describe a UTF-16 byte stream the
+                    // prescan was able to read as ASCII; the spec maps it to UTF-8.
+                    return Some(if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+                        encoding_rs::UTF_8
+                    } else if encoding == encoding_rs::X_USER_DEFINED {
+                        encoding_rs::WINDOWS_1252
+                    } else {
+                        encoding
+                    });
+                }
+            }
+        }
+        pos = tag_end;
+    }
+    None
+}
+
+fn find_ascii_ci(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w.eq_ignore_ascii_case(needle))
+}
+
+/// Incremental HTML parser for hosts that receive the document in pieces.
+///
+/// `feed` accepts raw bytes; a multi-byte UTF-8 sequence split across chunks is
+/// held back until completed, and invalid sequences become U+FFFD. Decoded
+/// text is tokenized up to the last point where the tokenizer is back in
+/// plain data (after a tag's `>` outside quotes, or after a comment's `-->`),
+/// so the `Document` grows while input arrives; the unterminated tail waits
+/// for the next chunk. `finish` flushes the remainder.
+///
+/// Adjacent text is merged into one node, so the result does not depend on
+/// how the input was chunked.
+pub struct HtmlParser {
+    doc: Document,
+    current_parent: crate::dom::NodeId,
+    inside_raw_tag: Option<crate::dom::LocalName>,
+    current_style_text: String,
+    /// Bytes of an incomplete UTF-8 sequence at the end of the last chunk.
+    undecoded: Vec<u8>,
+    /// Decoded input not yet handed to the tokenizer.
+    text: String,
+    /// Maximum number of element and text nodes (the root is not counted).
+    max_nodes: usize,
+    /// Set once a doctype or the first element has fixed `quirks_mode`.
+    mode_decided: bool,
+}
+
+impl Default for HtmlParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HtmlParser {
+    pub fn new() -> Self {
+        let doc = Document::default();
+        HtmlParser {
+            current_parent: doc.root_id,
+            doc,
+            inside_raw_tag: None,
+            current_style_text: String::new(),
+            undecoded: Vec::new(),
+            text: String::new(),
+            max_nodes: usize::MAX,
+            mode_decided: false,
+        }
+    }
+
+    /// A parser that stops adding nodes after `max_nodes`; see [`parse_html_with_limit`].
+    pub fn with_node_limit(max_nodes: usize) -> Self {
+        HtmlParser { max_nodes, ..Self::new() }
+    }
+
+    /// Append a chunk of input bytes and parse as much of it as is complete.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        if self.doc.truncated {
+            return;
+        }
+        self.undecoded.extend_from_slice(chunk);
+        let mut start = 0;
+        while start < self.undecoded.len() {
+            match std::str::from_utf8(&self.undecoded[start..]) {
+                Ok(s) => {
+                    self.text.push_str(s);
+                    start = self.undecoded.len();
+                }
+                Err(e) => {
+                    let valid = start + e.valid_up_to();
+                    // The prefix up to `valid_up_to` is UTF-8 by definition.
+                    self.text
+                        .push_str(std::str::from_utf8(&self.undecoded[start..valid]).unwrap_or(""));
+                    match e.error_len() {
+                        Some(len) => {
+                            self.text.push('\u{FFFD}');
+                            start = valid + len;
+                        }
+                        // Incomplete sequence at the end: wait for more bytes.
+                        None => {
+                            start = valid;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.undecoded.drain(..start);
 
-    for token in Tokenizer::new(html).infallible() {
+        let cut = safe_tokenize_end(&self.text);
+        if cut > 0 {
+            let ready: String = self.text.drain(..cut).collect();
+            self.tokenize(&ready);
+        }
+    }
+
+    /// Parse any remaining input and return the finished `Document`.
+    pub fn finish(mut self) -> Document {
+        if !self.undecoded.is_empty() {
+            self.undecoded.clear();
+            self.text.push('\u{FFFD}');
+        }
+        let rest = std::mem::take(&mut self.text);
+        self.tokenize(&rest);
+        self.doc.title = self.doc.first_title_element().map(|title| {
+            self.doc.text_content(title).split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+        });
+        let viewport_meta = self
+            .doc
+            .descendants(self.doc.root_id)
+            .find(|&id| {
+                matches!(self.doc.nodes.get(id), Some(Node::Element(d)) if &*d.tag_name == "meta")
+                    && self.doc.get_attribute(id, "name").is_some_and(|name| name.trim().eq_ignore_ascii_case("viewport"))
+            })
+            .map(|meta| parse_viewport_content(&self.doc.get_attribute(meta, "content").unwrap_or_default()));
+        self.doc.viewport_meta = viewport_meta;
+        self.doc.dirty = true;
+        self.doc
+    }
+
+    fn tokenize(&mut self, input: &str) {
+        for token in Tokenizer::new(input).infallible() {
+            self.process_token(token);
+        }
+    }
+
+    /// False (and marks the document truncated) once the node limit is reached.
+    fn has_room(&mut self) -> bool {
+        // The arena always holds the root in addition to parsed nodes.
+        if self.doc.nodes.len() > self.max_nodes {
+            self.doc.truncated = true;
+        }
+        !self.doc.truncated
+    }
+
+    fn process_token(&mut self, token: Token) {
+        if self.doc.truncated {
+            return;
+        }
         match token {
+            Token::Doctype(doctype) if !self.mode_decided => {
+                self.mode_decided = true;
+                self.doc.quirks_mode = doctype_is_quirky(&doctype);
+            }
             Token::StartTag(tag) => {
+                if !self.mode_decided {
+                    // No doctype before the first element.
+                    self.mode_decided = true;
+                    self.doc.quirks_mode = true;
+                }
+                if !self.has_room() {
+                    return;
+                }
                 // Zero-allocation UTF-8 validation for tag names (html5gum emits valid UTF-8)
                 let tag_name_str = std::str::from_utf8(&tag.name).unwrap_or("");
                 let tag_name = crate::dom::LocalName::new(tag_name_str);
 
                 if &*tag_name == "script" || &*tag_name == "style" {
-                    inside_raw_tag = Some(tag_name.clone());
+                    self.inside_raw_tag = Some(tag_name.clone());
                     if &*tag_name == "style" {
-                        current_style_text.clear();
+                        self.current_style_text.clear();
                     }
                 }
 
-                let mut attributes = Vec::new();
-                let mut classes = String::new();
-                let mut cached_inline_styles = None;
-
+                let node_id = self.doc.add_node(Node::Element(ElementData::new(tag_name.clone())));
                 for (key, value) in tag.attributes {
-                    if attributes.len() >= crate::dom::MAX_ATTRIBUTES {
-                        break;
-                    }
                     // Zero-allocation UTF-8 validation for attribute keys
-                    let k_str = std::str::from_utf8(&key).unwrap_or("").to_string();
+                    let k_str = std::str::from_utf8(&key).unwrap_or("");
                     if k_str.is_empty() {
                         continue;
                     }
-                    // Attribute values must be owned; validate UTF-8 without lossy replacement
-                    let mut v_str = std::str::from_utf8(&value).unwrap_or("").to_string();
-
-                    // Safe truncation: avoid splitting multi-byte UTF-8 characters
-                    if v_str.len() > crate::dom::MAX_ATTRIBUTE_VALUE_LEN {
-                        let mut cap = crate::dom::MAX_ATTRIBUTE_VALUE_LEN;
-                        while !v_str.is_char_boundary(cap) {
-                            cap -= 1;
-                        }
-                        v_str.truncate(cap);
-                    }
-
-                    if k_str == "class" {
-                        classes = v_str.to_string();
-                    } else if k_str == "style" {
-                        let decls = crate::css::parse_inline_declarations(&v_str);
-                        cached_inline_styles = Some(decls.into_iter().map(|d| (d.name, d.value)).collect());
-                    } else if k_str == "id" {
-                        attributes.push((k_str, v_str));
-                    } else {
-                        attributes.push((k_str, v_str));
-                    }
+                    // Attribute values must be owned; validate UTF-8 without lossy replacement.
+                    // `set_attribute` truncates over-long values and enforces `MAX_ATTRIBUTES`.
+                    let v_str = std::str::from_utf8(&value).unwrap_or("").to_string();
+                    self.doc.set_attribute(node_id, k_str, v_str);
                 }
 
-                let mut data = ElementData::new(tag_name.clone());
-                data.attributes = attributes;
-                data.classes = classes;
-                data.cached_inline_styles = cached_inline_styles;
-
-                let node = Node::Element(data);
-                // add_node handles id_map insertion internally
-                let node_id = doc.add_node(node);
-
-                doc.append_child(current_parent, node_id);
-
-                let is_void = matches!(
-                    &*tag_name,
-                    "area"
-                    | "base"
-                    | "br"
-                    | "col"
-                    | "embed"
-                    | "hr"
-                    | "img"
-                    | "input"
-                    | "link"
-                    | "meta"
-                    | "param"
-                    | "source"
-                    | "track"
-                    | "wbr"
-                );
+                self.doc.append_child(self.current_parent, node_id);
+
+                let is_void = crate::dom::is_void_tag(&tag_name);
 
                 if !is_void && !tag.self_closing {
-                    current_parent = node_id;
+                    self.current_parent = node_id;
                 }
             }
             Token::EndTag(tag) => {
                 let tag_name_str = std::str::from_utf8(&tag.name).unwrap_or("");
                 let tag_name = crate::dom::LocalName::new(tag_name_str);
 
-                if let Some(ref raw) = inside_raw_tag {
+                if let Some(ref raw) = self.inside_raw_tag {
                     if &**raw == &*tag_name {
-                        inside_raw_tag = None;
-                        if &*tag_name == "style" && !current_style_text.is_empty() {
-                            crate::css::append_stylesheet(&current_style_text, &mut doc.stylesheet);
-                            current_style_text.clear();
+                        self.inside_raw_tag = None;
+                        // The raw element is the current parent; close it.
+                        self.current_parent = self.doc.parent_of(self.current_parent).unwrap_or(self.doc.root_id);
+                        if &*tag_name == "style" && !self.current_style_text.is_empty() {
+                            crate::css::append_stylesheet(&self.current_style_text, &mut self.doc.stylesheet);
+                            self.current_style_text.clear();
                         }
                     } else {
                         // Skip EndTags inside Rawtext tags unless they match
-                        continue;
                     }
                 } else {
                     // Walk up to find matching tag
-                    let mut p = Some(current_parent);
+                    let mut p = Some(self.current_parent);
                     while let Some(pid) = p {
-                        if let Some(Node::Element(data)) = doc.nodes.get(pid) {
+                        if let Some(Node::Element(data)) = self.doc.nodes.get(pid) {
                             if data.tag_name == tag_name {
-                                current_parent = doc.parent_of(pid).unwrap_or(doc.root_id);
+                                self.current_parent = self.doc.parent_of(pid).unwrap_or(self.doc.root_id);
                                 break;
                             }
                         }
-                        p = doc.parent_of(pid);
+                        p = self.doc.parent_of(pid);
                     }
                 }
             }
             Token::String(s) => {
                 let text = std::str::from_utf8(&s).unwrap_or("").to_string();
                 if text.is_empty() {
-                    continue;
+                    return;
                 }
 
-                if let Some(ref raw) = inside_raw_tag {
+                if let Some(ref raw) = self.inside_raw_tag {
                     if &**raw == "style" {
-                        current_style_text.push_str(&text);
-                        continue;
+                        self.current_style_text.push_str(&text);
+                        return;
                     }
                     if &**raw == "script" {
                         // Scripts are ignored for now but we consume their content
-                        continue;
+                        return;
                     }
                 }
 
+                // Merge with a preceding text sibling so a run of text split
+                // across tokens (or fed chunks) still yields one node.
+                if let Some(last) = self.doc.last_child_of(self.current_parent)
+                    && let Some(Node::Text(data)) = self.doc.nodes.get_mut(last)
+                {
+                    data.text.push_str(&text);
+                    return;
+                }
+
+                if !self.has_room() {
+                    return;
+                }
                 let node = Node::Text(TextData::new(text));
-                let node_id = doc.add_node(node);
-                doc.append_child(current_parent, node_id);
+                let node_id = self.doc.add_node(node);
+                self.doc.append_child(self.current_parent, node_id);
             }
             _ => {}
         }
     }
+}
+
+/// Whether `doctype` puts the document in quirks mode: the force-quirks flag,
+/// a name other than `html`, or one of the common legacy public identifiers
+/// from the HTML spec's list (HTML 2 and 3.2, vendor DTDs, and HTML 4
+/// Transitional/Frameset without a system identifier). Limited-quirks mode
+/// is treated as standards mode.
+fn doctype_is_quirky(doctype: &html5gum::Doctype) -> bool {
+    const LEGACY_PREFIXES: [&str; 12] = [
+        "+//silmaril//dtd html pro",
+        "-//advasoft ltd//dtd html",
+        "-//as//dtd html",
+        "-//ietf//dtd html",
+        "-//metrius//dtd metrius presentational//",
+        "-//microsoft//dtd internet explorer",
+        "-//netscape comm. corp.//dtd",
+        "-//o'reilly and associates//dtd html",
+        "-//softquad",
+        "-//w3c//dtd html 3",
+        "-//w3c//dtd html 4.0 ",
+        "-//w3o//dtd w3 html",
+    ];
+    let name = String::from_utf8_lossy(&doctype.name);
+    if doctype.force_quirks || !name.eq_ignore_ascii_case("html") {
+        return true;
+    }
+    let Some(public) = &doctype.public_identifier else {
+        return false;
+    };
+    let public = String::from_utf8_lossy(public).to_ascii_lowercase();
+    LEGACY_PREFIXES.iter().any(|p| public.starts_with(p))
+        || (doctype.system_identifier.is_none()
+            && (public.starts_with("-//w3c//dtd html 4.01 frameset//")
+                || public.starts_with("-//w3c//dtd html 4.01 transitional//")))
+}
 
-    doc.dirty = true;
-    doc
+/// Byte offset just past the last position in `text` where the tokenizer is
+/// in the data state: after a tag's closing `>` (ignoring `>` inside quoted
+/// attribute values) or after a comment's `-->`. Returns 0 if there is none.
+fn safe_tokenize_end(text: &str) -> usize {
+    enum State {
+        Data,
+        Tag,
+        Quoted(u8),
+        Comment,
+    }
+    let bytes = text.as_bytes();
+    let mut state = State::Data;
+    let mut cut = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match state {
+            State::Data => {
+                if bytes[i..].starts_with(b"<!--") {
+                    state = State::Comment;
+                    i += 3;
+                } else if bytes[i] == b'<' {
+                    state = State::Tag;
+                }
+            }
+            State::Tag => match bytes[i] {
+                q @ (b'"' | b'\'') => state = State::Quoted(q),
+                b'>' => {
+                    state = State::Data;
+                    cut = i + 1;
+                }
+                _ => {}
+            },
+            State::Quoted(q) => {
+                if bytes[i] == q {
+                    state = State::Tag;
+                }
+            }
+            State::Comment => {
+                if bytes[i..].starts_with(b"-->") {
+                    state = State::Data;
+                    i += 2;
+                    cut = i + 1;
+                }
+            }
+        }
+        i += 1;
+    }
+    cut
 }
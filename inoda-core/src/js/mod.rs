@@ -1,18 +1,46 @@
 //! JavaScript execution module.
 //!
 //! Embeds QuickJS via `rquickjs`. Exposes a subset of the Web API:
-//! - `console.log`, `console.warn`, `console.error` (print to stdout)
+//! - `console.log`, `console.warn`, `console.error` (any number of arguments, formatted
+//!   space-separated like browsers; print to stdout unless the host installs a sink with
+//!   `JsEngine::set_console_sink`)
 //! - `document.getElementById`, `document.querySelector` (return native `NodeHandle` objects)
-//! - `document.createElement`, `document.appendChild` (mutate the arena DOM)
+//! - `document.title` (`Document::title`, `""` without a `<title>`; setting it calls `Document::set_title`)
+//! - `document.activeElement` (`Document::focused`, or `null` when nothing has focus)
+//! - `document.createElement`, `document.appendChild` (mutate the arena DOM; `appendChild`
+//!   returns the child and throws `TypeError` for void-element parents such as `<img>` and for cycles)
 //! - `element.addEventListener` (registers callbacks; dispatched via `JsEngine::dispatch_event`)
 //! - `setTimeout`, `setInterval` (cooperative timer queue via `pump()`)
 //!
+//! Hosts can pass structured data in with `JsEngine::set_global_json` and
+//! invoke a global function with JSON arguments via `JsEngine::call_function`.
+//!
 //! DOM handles are exposed to JavaScript as native `NodeHandle` class instances
 //! wrapping a `generational_arena::Index`. Methods include:
 //! - `handle.tagName` (lazy lookup in arena, no redundant string storage)
-//! - `handle.getAttribute(key)` (checks `classes` and `cached_inline_styles` for class/style)
-//! - `handle.setAttribute(key, value)` (truncates at `MAX_ATTRIBUTE_VALUE_LEN` with UTF-8 safety)
+//! - `handle.getAttribute(key)` (`Document::get_attribute`; class/style read their dedicated fields)
+//! - `handle.setAttribute(key, value)` (`Document::set_attribute`; truncates at `MAX_ATTRIBUTE_VALUE_LEN` with UTF-8 safety)
+//! - `handle.removeAttribute(key)` (`Document::remove_attribute`)
 //! - `handle.removeChild(child)`
+//! - `handle.appendChild(child)` / `insertBefore(node, reference)` / `replaceChild(node, old)`
+//!   (`Document::append_child` / `insert_before` / `replace_child`; return the appended,
+//!   inserted or replaced node and throw `TypeError` where the `Document` call fails)
+//! - `handle.append(...nodes)` (appends a batch in one pass; throws `TypeError` like `appendChild`)
+//! - `handle.isConnected` (true while the node is reachable from the document root)
+//! - `handle.contains(other)` (true if `other` is the handle's node or a descendant; false for `null`)
+//! - `handle.focus()` / `handle.blur()` (`Document::set_focus` / `Document::blur`; `:focus` follows on the next cascade)
+//! - `handle.textContent` (descendant text; setting it replaces all children with one text node)
+//! - `handle.outerHTML` / `handle.innerText` (`Document::outer_html` / `Document::inner_text`; read-only)
+//! - `handle.matches(selector)` (full cascade selector matcher; parsed selectors are cached)
+//! - `handle.closest(selector)` (nearest inclusive ancestor matching the selector, or null)
+//! - `handle.querySelector(selector)` / `querySelectorAll` / `getElementsByTagName` /
+//!   `getElementsByClassName` (pre-order walk of the handle's subtree only; lists are arrays)
+//! - `handle.dataset` (camelCase view of `data-*` attributes: `dataset.userId` <-> `data-user-id`;
+//!   `delete` removes the attribute)
+//! - `handle.getBoundingClientRect()` (`x`/`y`/`width`/`height`/`top`/`left`/`right`/`bottom`
+//!   from the layout stored in the document; all zero if the node has not been laid out)
+//! - `handle.scrollIntoView(options)` (sets `Document::viewport_scroll` via
+//!   `layout::scroll_offset_to_reveal`; `false` or `{ block: 'start'|'center'|'end'|'nearest' }`)
 //!
 //! Each `NodeHandle` carries a `__nodeKey` property: a two-element JS array
 //! `[u32 index, u64 generation]`. JavaScript object identity (`===`) is enforced
@@ -28,6 +56,9 @@
 //! listeners (minimal propagation covering the most common event pattern).
 //!
 //! The Document is held behind `Rc<RefCell<Document>>` for single-threaded access.
+//! Geometry reads do not trigger layout: the host refreshes it by running
+//! `layout::compute_layout` on `engine.document` (e.g. once per frame after
+//! scripts and timers), and scripts see the result of the most recent run.
 //! All JS operations are synchronous and serialized through this lock.
 //!
 //! Initialization is fallible: use [`JsEngine::try_new`] and handle [`JsEngineError`].
@@ -68,10 +99,116 @@ impl std::fmt::Display for JsEngineError {
 
 impl std::error::Error for JsEngineError {}
 
+/// Which `console` method produced a line handed to a [`ConsoleSink`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleLevel {
+    Log,
+    Warn,
+    Error,
+}
+
+/// Host callback receiving each formatted `console.*` line.
+pub type ConsoleSink = Box<dyn FnMut(ConsoleLevel, &str)>;
+
 fn js_try<T>(r: rquickjs::Result<T>, ctx: &'static str) -> Result<T, JsEngineError> {
     r.map_err(|e| JsEngineError::WebApiInit(format!("{ctx}: {e:?}")))
 }
 
+// ---------------------------------------------------------------------------
+// console formatting
+// ---------------------------------------------------------------------------
+
+/// Joins `console.*` arguments with spaces, as browsers do. Top-level strings
+/// print bare; arrays and plain objects are expanded one level deep.
+fn format_console_args(args: &[rquickjs::Value<'_>]) -> String {
+    let mut out = String::new();
+    for (i, value) in args.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        match value.as_string() {
+            Some(s) => out.push_str(&s.to_string().unwrap_or_default()),
+            None => format_console_value(&mut out, value, 0),
+        }
+    }
+    out
+}
+
+/// Hands `line` to the host sink, or prints it when none is set. A sink that
+/// itself logs through the engine re-enters here; that line goes to stdout.
+fn write_console(sink: &RefCell<Option<ConsoleSink>>, level: ConsoleLevel, line: &str) {
+    if let Ok(mut sink) = sink.try_borrow_mut()
+        && let Some(sink) = sink.as_mut()
+    {
+        sink(level, line);
+        return;
+    }
+    let method = match level {
+        ConsoleLevel::Log => "log",
+        ConsoleLevel::Warn => "warn",
+        ConsoleLevel::Error => "error",
+    };
+    println!("[JS console.{method}] {line}");
+}
+
+fn format_console_value(out: &mut String, value: &rquickjs::Value<'_>, depth: u32) {
+    use rquickjs::Type;
+    match value.type_of() {
+        Type::String => {
+            out.push('\'');
+            out.push_str(&value.as_string().and_then(|s| s.to_string().ok()).unwrap_or_default());
+            out.push('\'');
+        }
+        Type::Array if depth > 0 => out.push_str("[Array]"),
+        Type::Array => {
+            out.push('[');
+            if let Some(arr) = value.as_array() {
+                for (i, item) in arr.iter::<rquickjs::Value>().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    match item {
+                        Ok(item) => format_console_value(out, &item, depth + 1),
+                        Err(_) => out.push('?'),
+                    }
+                }
+            }
+            out.push(']');
+        }
+        Type::Function | Type::Constructor => out.push_str("[Function]"),
+        Type::Symbol => {
+            let description = value
+                .as_symbol()
+                .and_then(|symbol| symbol.description().ok())
+                .and_then(|d| d.as_string().and_then(|s| s.to_string().ok()));
+            out.push_str("Symbol(");
+            out.push_str(description.as_deref().unwrap_or(""));
+            out.push(')');
+        }
+        Type::Object if depth > 0 => out.push_str("[Object]"),
+        Type::Object => {
+            out.push('{');
+            if let Some(obj) = value.as_object() {
+                for (i, prop) in obj.props::<String, rquickjs::Value>().flatten().enumerate() {
+                    out.push_str(if i > 0 { ", " } else { " " });
+                    out.push_str(&prop.0);
+                    out.push_str(": ");
+                    format_console_value(out, &prop.1, depth + 1);
+                }
+                if out.ends_with(|c| c != '{') {
+                    out.push(' ');
+                }
+            }
+            out.push('}');
+        }
+        // Numbers, booleans, null, undefined, errors, promises: JS `String(v)`.
+        _ => match value.get::<rquickjs::Coerced<String>>() {
+            Ok(s) => out.push_str(&s.0),
+            Err(_) => out.push_str(value.type_name()),
+        },
+    }
+}
+
 // ---------------------------------------------------------------------------
 // NodeHandle: an opaque JS class wrapping a generational_arena::Index.
 // ---------------------------------------------------------------------------
@@ -126,6 +263,47 @@ unsafe impl<'js> rquickjs::JsLifetime<'js> for NodeHandle {
     type Changed<'to> = NodeHandle;
 }
 
+// ---------------------------------------------------------------------------
+// Selector cache
+// ---------------------------------------------------------------------------
+
+/// Maximum number of distinct selector strings kept parsed. Scripts that build
+/// selectors dynamically would otherwise grow the cache without bound.
+const MAX_CACHED_SELECTORS: usize = 64;
+
+/// Compiled selector lists for `matches` and `closest`, keyed by source text.
+#[derive(Default)]
+struct SelectorCache {
+    entries: std::collections::HashMap<String, Rc<crate::css::Matcher>>,
+}
+
+impl SelectorCache {
+    fn get(&mut self, selector: &str) -> Rc<crate::css::Matcher> {
+        if let Some(parsed) = self.entries.get(selector) {
+            return parsed.clone();
+        }
+        if self.entries.len() >= MAX_CACHED_SELECTORS {
+            self.entries.clear();
+        }
+        let parsed = Rc::new(crate::css::Matcher::compile(selector));
+        self.entries.insert(selector.to_string(), parsed.clone());
+        parsed
+    }
+}
+
+/// Wraps element ids in fresh `NodeHandle`s, counting each toward its
+/// node's `js_handles` as every handle handed to JS must.
+fn handles_for(doc: &mut crate::dom::Document, ids: Vec<crate::dom::NodeId>) -> Vec<NodeHandle> {
+    ids.into_iter()
+        .map(|id| {
+            if let Some(crate::dom::Node::Element(d)) = doc.nodes.get_mut(id) {
+                d.js_handles += 1;
+            }
+            NodeHandle::from_node_id(id)
+        })
+        .collect()
+}
+
 // ---------------------------------------------------------------------------
 // Timer queue
 // ---------------------------------------------------------------------------
@@ -185,6 +363,8 @@ pub struct JsEngine {
     pump_ticks: Rc<Cell<u32>>,
     /// Track start time of the current JS execution block to prevent infinite loops.
     last_start_time: Rc<Cell<Option<Instant>>>,
+    /// Receives console output instead of stdout when set.
+    console_sink: Rc<RefCell<Option<ConsoleSink>>>,
 }
 
 impl JsEngine {
@@ -249,6 +429,7 @@ impl JsEngine {
             active_timers: Rc::new(RefCell::new(std::collections::HashSet::new())),
             pump_ticks: Rc::new(Cell::new(0)),
             last_start_time,
+            console_sink: Rc::new(RefCell::new(None)),
         };
 
         engine.init_web_api()?;
@@ -261,6 +442,7 @@ impl JsEngine {
         let timer_id_counter = self.next_timer_id.clone();
         let pending_timers = self.pending_timers.clone();
         let active_timers = self.active_timers.clone();
+        let console_sink = self.console_sink.clone();
 
         self.context.with(|ctx| -> Result<(), JsEngineError> {
             let globals = ctx.globals();
@@ -294,204 +476,298 @@ impl JsEngine {
                 "proto _tagNameRaw",
             )?;
 
-            // Item 4: getAttribute now checks class/style dedicated fields
-            let get_attr_func = js_try(
+            let text_content_func = js_try(
                 rquickjs::Function::new(ctx.clone(), {
                     let doc_ref = doc_ref.clone();
-                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>,
-                          attr: String|
-                          -> Option<String> {
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>| -> String {
+                        doc_ref.borrow().text_content(this.borrow().to_node_id())
+                    }
+                }),
+                "Function _textContentRaw",
+            )?;
+            js_try(proto.set("_textContentRaw", text_content_func), "proto _textContentRaw")?;
+
+            let set_text_content_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>, text: String| {
+                        doc_ref.borrow_mut().set_text_content(this.borrow().to_node_id(), &text);
+                    }
+                }),
+                "Function _setTextContentRaw",
+            )?;
+            js_try(proto.set("_setTextContentRaw", set_text_content_func), "proto _setTextContentRaw")?;
+
+            let outer_html_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>| -> String {
+                        doc_ref.borrow().outer_html(this.borrow().to_node_id())
+                    }
+                }),
+                "Function _outerHtmlRaw",
+            )?;
+            js_try(proto.set("_outerHtmlRaw", outer_html_func), "proto _outerHtmlRaw")?;
+
+            let inner_text_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>| -> String {
+                        doc_ref.borrow().inner_text(this.borrow().to_node_id())
+                    }
+                }),
+                "Function _innerTextRaw",
+            )?;
+            js_try(proto.set("_innerTextRaw", inner_text_func), "proto _innerTextRaw")?;
+
+            let is_connected_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>| -> bool {
                         let doc = doc_ref.borrow();
                         let node_id = this.borrow().to_node_id();
-                        if let Some(crate::dom::Node::Element(data)) = doc.nodes.get(node_id) {
-                            // Check dedicated class field first
-                            if attr == "class" {
-                                if !data.classes.is_empty() {
-                                    return Some(data.classes.clone());
-                                }
-                                return None;
-                            }
-                            // Check dedicated style field
-                            if attr == "style" {
-                                if let Some(inline) = &data.cached_inline_styles {
-                                    let reconstructed: String = inline
-                                        .iter()
-                                        .map(|(name, val)| {
-                                            let val_str = match val {
-                                                crate::dom::StyleValue::LengthPx(n) => {
-                                                    format!("{}px", n)
-                                                }
-                                                crate::dom::StyleValue::Percent(n) => {
-                                                    format!("{}%", n)
-                                                }
-                                                crate::dom::StyleValue::Keyword(k) => {
-                                                    k.to_string()
-                                                }
-                                                crate::dom::StyleValue::Color(r, g, b, a) => {
-                                                    if *a == 255 {
-                                                        format!("#{:02x}{:02x}{:02x}", r, g, b)
-                                                    } else {
-                                                        format!(
-                                                            "rgba({},{},{},{})",
-                                                            r,
-                                                            g,
-                                                            b,
-                                                            *a as f32 / 255.0
-                                                        )
-                                                    }
-                                                }
-                                                crate::dom::StyleValue::Auto => "auto".to_string(),
-                                                crate::dom::StyleValue::Number(n) => n.to_string(),
-                                                crate::dom::StyleValue::Em(n) => format!("{}em", n),
-                                                crate::dom::StyleValue::Rem(n) => {
-                                                    format!("{}rem", n)
-                                                }
-                                                crate::dom::StyleValue::ViewportWidth(n) => {
-                                                    format!("{}vw", n)
-                                                }
-                                                crate::dom::StyleValue::ViewportHeight(n) => {
-                                                    format!("{}vh", n)
-                                                }
-                                                crate::dom::StyleValue::None => "none".to_string(),
-                                            };
-                                            format!(
-                                                "{}:{}",
-                                                crate::dom::PropertyName::as_str(*name),
-                                                val_str
-                                            )
-                                        })
-                                        .collect::<Vec<_>>()
-                                        .join(";");
-                                    if !reconstructed.is_empty() {
-                                        return Some(reconstructed);
-                                    }
-                                }
-                                return None;
-                            }
-                            // Fall through to attributes vector for all other keys
-                            for (k, v) in &data.attributes {
-                                if k == &attr {
-                                    return Some(v.clone());
-                                }
-                            }
-                        }
-                        None
+                        doc.nodes.contains(node_id) && doc.is_attached_to_root(node_id)
                     }
                 }),
-                "Function getAttribute",
+                "Function _isConnectedRaw",
             )?;
             js_try(
-                proto.set("getAttribute", get_attr_func),
-                "proto getAttribute",
+                proto.set("_isConnectedRaw", is_connected_func),
+                "proto _isConnectedRaw",
             )?;
 
-            let set_attr_func = js_try(
+            // contains(other): `other` is this node or one of its descendants.
+            // `null` and nodes from detached or freed subtrees report false.
+            let contains_func = js_try(
                 rquickjs::Function::new(ctx.clone(), {
                     let doc_ref = doc_ref.clone();
                     move |This(this): This<rquickjs::Class<'_, NodeHandle>>,
-                          key: String,
-                          mut value: String| {
-                        // Item 3: Safe UTF-8 truncation — avoid splitting multi-byte characters
-                        if value.len() > crate::dom::MAX_ATTRIBUTE_VALUE_LEN {
-                            let mut cap = crate::dom::MAX_ATTRIBUTE_VALUE_LEN;
-                            while !value.is_char_boundary(cap) {
-                                cap -= 1;
-                            }
-                            value.truncate(cap);
+                          other: Option<rquickjs::Class<'_, NodeHandle>>|
+                          -> bool {
+                        let Some(other) = other else { return false };
+                        let doc = doc_ref.borrow();
+                        let node_id = this.borrow().to_node_id();
+                        let other_id = other.borrow().to_node_id();
+                        doc.nodes.contains(node_id)
+                            && (other_id == node_id || doc.ancestors(other_id).any(|id| id == node_id))
+                    }
+                }),
+                "Function contains",
+            )?;
+            js_try(proto.set("contains", contains_func), "proto contains")?;
+
+            // focus() / blur(): `Document::set_focus` / `Document::blur`. blur()
+            // only clears focus when this element has it.
+            let focus_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>| {
+                        let node_id = this.borrow().to_node_id();
+                        doc_ref.borrow_mut().set_focus(node_id);
+                    }
+                }),
+                "Function focus",
+            )?;
+            js_try(proto.set("focus", focus_func), "proto focus")?;
+
+            let blur_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>| {
+                        let node_id = this.borrow().to_node_id();
+                        let mut doc = doc_ref.borrow_mut();
+                        if doc.focused == Some(node_id) {
+                            doc.blur();
                         }
+                    }
+                }),
+                "Function blur",
+            )?;
+            js_try(proto.set("blur", blur_func), "proto blur")?;
+
+            // Compiled selector lists shared by `matches` and `closest`, keyed by source text.
+            let selector_cache: Rc<RefCell<SelectorCache>> = Rc::new(RefCell::new(SelectorCache::default()));
+
+            let matches_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    let selector_cache = selector_cache.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>, selector: String| -> bool {
+                        let matcher = selector_cache.borrow_mut().get(&selector);
+                        let doc = doc_ref.borrow();
+                        matcher.matches(this.borrow().to_node_id(), &doc)
+                    }
+                }),
+                "Function matches",
+            )?;
+            js_try(proto.set("matches", matches_func), "proto matches")?;
+
+            let closest_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    let selector_cache = selector_cache.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>,
+                          selector: String|
+                          -> Option<NodeHandle> {
+                        let matcher = selector_cache.borrow_mut().get(&selector);
                         let mut doc = doc_ref.borrow_mut();
                         let node_id = this.borrow().to_node_id();
-                        let mut old_id = None;
-                        let mut is_class = false;
-                        let mut is_style = false;
-
-                        if let Some(crate::dom::Node::Element(data)) = doc.nodes.get(node_id) {
-                            if key == "class" {
-                                is_class = true;
-                            } else if key == "style" {
-                                is_style = true;
-                            } else if key == "id" {
-                                for (k, v) in &data.attributes {
-                                    if k == "id" {
-                                        old_id = Some(v.clone());
-                                        break;
-                                    }
-                                }
-                            }
+                        let found = std::iter::once(node_id)
+                            .chain(doc.ancestors(node_id))
+                            .find(|&id| matcher.matches(id, &doc))?;
+                        if let Some(crate::dom::Node::Element(d)) = doc.nodes.get_mut(found) {
+                            d.js_handles += 1;
                         }
+                        Some(NodeHandle::from_node_id(found))
+                    }
+                }),
+                "Function _closestRaw",
+            )?;
+            js_try(proto.set("_closestRaw", closest_func), "proto _closestRaw")?;
 
-                        if is_class {
-                            if let Some(crate::dom::Node::Element(data)) =
-                                doc.nodes.get_mut(node_id)
-                            {
-                                data.classes = value.clone();
-
-                                // Also update attributes vector for consistency with getAttribute
-                                let mut found = false;
-                                for (k, v) in data.attributes.iter_mut() {
-                                    if k == "class" {
-                                        *v = value.clone();
-                                        found = true;
-                                        break;
-                                    }
-                                }
-                                if !found {
-                                    data.attributes
-                                        .push(("class".to_string(), value.clone()));
-                                }
-                            }
-                        } else if is_style {
-                            let decls = crate::css::parse_inline_declarations(&value);
-                            if let Some(crate::dom::Node::Element(data)) =
-                                doc.nodes.get_mut(node_id)
-                            {
-                                data.cached_inline_styles =
-                                    Some(decls.into_iter().map(|d| (d.name, d.value)).collect());
-
-                                // Also update attributes vector
-                                let mut found = false;
-                                for (k, v) in data.attributes.iter_mut() {
-                                    if k == "style" {
-                                        *v = value.clone();
-                                        found = true;
-                                        break;
-                                    }
-                                }
-                                if !found {
-                                    data.attributes
-                                        .push(("style".to_string(), value.clone()));
-                                }
-                            }
-                        } else {
-                            if key == "id" {
-                                if let Some(oid) = old_id {
-                                    doc.id_map.remove(&oid);
-                                }
-                                doc.id_map.insert(value.clone(), node_id);
-                            }
+            // Scoped queries: pre-order walks of `this`'s subtree only.
+            let scoped_query_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    let selector_cache = selector_cache.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>,
+                          selector: String|
+                          -> Option<NodeHandle> {
+                        let matcher = selector_cache.borrow_mut().get(&selector);
+                        let mut doc = doc_ref.borrow_mut();
+                        let found = doc
+                            .descendants(this.borrow().to_node_id())
+                            .find(|&id| matcher.matches(id, &doc))?;
+                        handles_for(&mut doc, vec![found]).pop()
+                    }
+                }),
+                "Function _querySelectorRaw",
+            )?;
+            js_try(proto.set("_querySelectorRaw", scoped_query_func), "proto _querySelectorRaw")?;
 
-                            if let Some(crate::dom::Node::Element(data)) =
-                                doc.nodes.get_mut(node_id)
-                            {
-                                let mut found = false;
-                                for (k, v) in data.attributes.iter_mut() {
-                                    if k == &key {
-                                        *v = value.clone();
-                                        found = true;
-                                        break;
-                                    }
-                                }
-                                if !found && data.attributes.len() < crate::dom::MAX_ATTRIBUTES {
-                                    data.attributes.push((key, value));
-                                }
-                            }
+            let scoped_query_all_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    let selector_cache = selector_cache.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>, selector: String| -> Vec<NodeHandle> {
+                        let matcher = selector_cache.borrow_mut().get(&selector);
+                        let mut doc = doc_ref.borrow_mut();
+                        let found: Vec<_> = doc
+                            .descendants(this.borrow().to_node_id())
+                            .filter(|&id| matcher.matches(id, &doc))
+                            .collect();
+                        handles_for(&mut doc, found)
+                    }
+                }),
+                "Function _querySelectorAllRaw",
+            )?;
+            js_try(proto.set("_querySelectorAllRaw", scoped_query_all_func), "proto _querySelectorAllRaw")?;
+
+            let by_tag_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>, tag: String| -> Vec<NodeHandle> {
+                        let tag = tag.to_ascii_lowercase();
+                        let mut doc = doc_ref.borrow_mut();
+                        let found = doc.collect_descendants_matching(this.borrow().to_node_id(), |_, node| {
+                            matches!(node, crate::dom::Node::Element(d) if tag == "*" || *d.tag_name == *tag)
+                        });
+                        handles_for(&mut doc, found)
+                    }
+                }),
+                "Function _getElementsByTagNameRaw",
+            )?;
+            js_try(proto.set("_getElementsByTagNameRaw", by_tag_func), "proto _getElementsByTagNameRaw")?;
+
+            let by_class_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>, names: String| -> Vec<NodeHandle> {
+                        let mut doc = doc_ref.borrow_mut();
+                        // Like the DOM, an empty list of names matches nothing.
+                        if names.split_whitespace().next().is_none() {
+                            return Vec::new();
                         }
+                        let found = doc.collect_descendants_matching(this.borrow().to_node_id(), |_, node| {
+                            matches!(node, crate::dom::Node::Element(d) if names
+                                .split_whitespace()
+                                .all(|name| d.classes.split_whitespace().any(|c| c == name)))
+                        });
+                        handles_for(&mut doc, found)
+                    }
+                }),
+                "Function _getElementsByClassNameRaw",
+            )?;
+            js_try(proto.set("_getElementsByClassNameRaw", by_class_func), "proto _getElementsByClassNameRaw")?;
 
-                        if let Some(crate::dom::Node::Element(data)) = doc.nodes.get_mut(node_id) {
-                            data.styles_dirty = true;
+            let bounding_rect_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>| -> Vec<f64> {
+                        let doc = doc_ref.borrow();
+                        match crate::layout::absolute_rect(&doc, this.borrow().to_node_id()) {
+                            Some(r) => vec![r.x as f64, r.y as f64, r.width as f64, r.height as f64],
+                            None => vec![0.0; 4],
+                        }
+                    }
+                }),
+                "Function _boundingRectRaw",
+            )?;
+            js_try(proto.set("_boundingRectRaw", bounding_rect_func), "proto _boundingRectRaw")?;
+
+            let scroll_into_view_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>, block: String| {
+                        let mut doc = doc_ref.borrow_mut();
+                        let block = match block.as_str() {
+                            "center" => crate::layout::ScrollAlign::Center,
+                            "end" => crate::layout::ScrollAlign::End,
+                            "nearest" => crate::layout::ScrollAlign::Nearest,
+                            _ => crate::layout::ScrollAlign::Start,
+                        };
+                        let viewport = crate::layout::AbsoluteRect {
+                            x: doc.viewport_scroll.0,
+                            y: doc.viewport_scroll.1,
+                            width: doc.viewport_size.0,
+                            height: doc.viewport_size.1,
+                        };
+                        let target = this.borrow().to_node_id();
+                        if let Some(offset) = crate::layout::scroll_offset_to_reveal(&doc, target, viewport, block) {
+                            doc.viewport_scroll = offset;
                         }
+                    }
+                }),
+                "Function _scrollIntoViewRaw",
+            )?;
+            js_try(proto.set("_scrollIntoViewRaw", scroll_into_view_func), "proto _scrollIntoViewRaw")?;
 
-                        doc.dirty = true;
+            // getAttribute/setAttribute delegate to Document so parser and JS share one path
+            let get_attr_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>,
+                          attr: String|
+                          -> Option<String> {
+                        let node_id = this.borrow().to_node_id();
+                        doc_ref.borrow().get_attribute(node_id, &attr)
+                    }
+                }),
+                "Function getAttribute",
+            )?;
+            js_try(
+                proto.set("getAttribute", get_attr_func),
+                "proto getAttribute",
+            )?;
+
+            let set_attr_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>,
+                          key: String,
+                          value: String| {
+                        let node_id = this.borrow().to_node_id();
+                        doc_ref.borrow_mut().set_attribute(node_id, &key, value);
                     }
                 }),
                 "Function setAttribute",
@@ -501,6 +777,21 @@ impl JsEngine {
                 "proto setAttribute",
             )?;
 
+            let remove_attr_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |This(this): This<rquickjs::Class<'_, NodeHandle>>, key: String| {
+                        let node_id = this.borrow().to_node_id();
+                        doc_ref.borrow_mut().remove_attribute(node_id, &key);
+                    }
+                }),
+                "Function removeAttribute",
+            )?;
+            js_try(
+                proto.set("removeAttribute", remove_attr_func),
+                "proto removeAttribute",
+            )?;
+
             let remove_child_func = js_try(
                 rquickjs::Function::new(ctx.clone(), {
                     let doc_ref = doc_ref.clone();
@@ -519,6 +810,79 @@ impl JsEngine {
                 "proto removeChild",
             )?;
 
+            // append(...nodes): links the whole batch via Document::append_children
+            let append_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |ctx: rquickjs::Ctx<'_>,
+                          This(this): This<rquickjs::Class<'_, NodeHandle>>,
+                          nodes: rquickjs::function::Rest<rquickjs::Class<'_, NodeHandle>>|
+                          -> rquickjs::Result<()> {
+                        let parent_id = this.borrow().to_node_id();
+                        let children: Vec<NodeId> = nodes.0.iter().map(|n| n.borrow().to_node_id()).collect();
+                        if doc_ref.borrow_mut().append_children(parent_id, &children) {
+                            Ok(())
+                        } else {
+                            Err(rquickjs::Exception::throw_type(
+                                &ctx,
+                                "append: parent cannot contain these children",
+                            ))
+                        }
+                    }
+                }),
+                "Function append",
+            )?;
+            js_try(proto.set("append", append_func), "proto append")?;
+
+            let insert_before_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |ctx: rquickjs::Ctx<'_>,
+                          This(this): This<rquickjs::Class<'_, NodeHandle>>,
+                          node: rquickjs::Class<'_, NodeHandle>,
+                          reference: Option<rquickjs::Class<'_, NodeHandle>>|
+                          -> rquickjs::Result<()> {
+                        let parent_id = this.borrow().to_node_id();
+                        let child_id = node.borrow().to_node_id();
+                        let reference_id = reference.map(|r| r.borrow().to_node_id());
+                        if doc_ref.borrow_mut().insert_before(parent_id, child_id, reference_id) {
+                            Ok(())
+                        } else {
+                            Err(rquickjs::Exception::throw_type(
+                                &ctx,
+                                "insertBefore: cannot insert this node before the reference node",
+                            ))
+                        }
+                    }
+                }),
+                "Function _insertBeforeRaw",
+            )?;
+            js_try(proto.set("_insertBeforeRaw", insert_before_func), "proto _insertBeforeRaw")?;
+
+            let replace_child_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |ctx: rquickjs::Ctx<'_>,
+                          This(this): This<rquickjs::Class<'_, NodeHandle>>,
+                          node: rquickjs::Class<'_, NodeHandle>,
+                          old: rquickjs::Class<'_, NodeHandle>|
+                          -> rquickjs::Result<()> {
+                        let parent_id = this.borrow().to_node_id();
+                        let (new_id, old_id) = (node.borrow().to_node_id(), old.borrow().to_node_id());
+                        if doc_ref.borrow_mut().replace_child(parent_id, new_id, old_id) {
+                            Ok(())
+                        } else {
+                            Err(rquickjs::Exception::throw_type(
+                                &ctx,
+                                "replaceChild: cannot replace this child",
+                            ))
+                        }
+                    }
+                }),
+                "Function _replaceChildRaw",
+            )?;
+            js_try(proto.set("_replaceChildRaw", replace_child_func), "proto _replaceChildRaw")?;
+
             let parent_node_func = js_try(
                 rquickjs::Function::new(ctx.clone(), {
                     let doc_ref = doc_ref.clone();
@@ -601,24 +965,33 @@ impl JsEngine {
             let console_obj = js_try(rquickjs::Object::new(ctx.clone()), "console Object::new")?;
 
             let log_func = js_try(
-                rquickjs::Function::new(ctx.clone(), |msg: String| {
-                    println!("[JS console.log] {}", msg);
+                rquickjs::Function::new(ctx.clone(), {
+                    let console_sink = console_sink.clone();
+                    move |args: rquickjs::function::Rest<rquickjs::Value>| {
+                        write_console(&console_sink, ConsoleLevel::Log, &format_console_args(&args.0));
+                    }
                 }),
                 "console.log",
             )?;
             js_try(console_obj.set("log", log_func), "console set log")?;
 
             let warn_func = js_try(
-                rquickjs::Function::new(ctx.clone(), |msg: String| {
-                    println!("[JS console.warn] {}", msg);
+                rquickjs::Function::new(ctx.clone(), {
+                    let console_sink = console_sink.clone();
+                    move |args: rquickjs::function::Rest<rquickjs::Value>| {
+                        write_console(&console_sink, ConsoleLevel::Warn, &format_console_args(&args.0));
+                    }
                 }),
                 "console.warn",
             )?;
             js_try(console_obj.set("warn", warn_func), "console set warn")?;
 
             let error_func = js_try(
-                rquickjs::Function::new(ctx.clone(), |msg: String| {
-                    println!("[JS console.error] {}", msg);
+                rquickjs::Function::new(ctx.clone(), {
+                    let console_sink = console_sink.clone();
+                    move |args: rquickjs::function::Rest<rquickjs::Value>| {
+                        write_console(&console_sink, ConsoleLevel::Error, &format_console_args(&args.0));
+                    }
                 }),
                 "console.error",
             )?;
@@ -636,7 +1009,7 @@ impl JsEngine {
                     let doc_ref = doc_ref.clone();
                     move |id: String| -> Option<NodeHandle> {
                         let mut doc = doc_ref.borrow_mut();
-                        if let Some(&node_id) = doc.id_map.get(&id) {
+                        if let Some(node_id) = doc.get_element_by_id(&id) {
                             if let Some(crate::dom::Node::Element(data)) =
                                 doc.nodes.get_mut(node_id)
                             {
@@ -668,10 +1041,7 @@ impl JsEngine {
                                 let class_name = &selector[1..];
                                 data.classes.split_whitespace().any(|c| c == class_name)
                             } else if selector.starts_with('#') {
-                                let id_name = &selector[1..];
-                                data.attributes
-                                    .iter()
-                                    .any(|(k, v)| &**k == "id" && v == id_name)
+                                data.id() == Some(&selector[1..])
                             } else {
                                 &*data.tag_name == selector
                             }
@@ -709,7 +1079,7 @@ impl JsEngine {
 
                         if selector.starts_with('#') {
                             let id_name = &selector[1..];
-                            if let Some(&node_id) = doc.id_map.get(id_name) {
+                            if let Some(node_id) = doc.get_element_by_id(id_name) {
                                 if let Some(node) = doc.nodes.get_mut(node_id) {
                                     match node {
                                         crate::dom::Node::Element(d) => d.js_handles += 1,
@@ -745,6 +1115,40 @@ impl JsEngine {
 
             // addEventListener is implemented via JS polyfill on the Prototype now
 
+            let title_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move || -> String { doc_ref.borrow().title.clone().unwrap_or_default() }
+                }),
+                "Function _titleRaw",
+            )?;
+            js_try(document_obj.set("_titleRaw", title_func), "document _titleRaw")?;
+
+            let set_title_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move |title: String| doc_ref.borrow_mut().set_title(&title)
+                }),
+                "Function _setTitleRaw",
+            )?;
+            js_try(document_obj.set("_setTitleRaw", set_title_func), "document _setTitleRaw")?;
+
+            let active_element_func = js_try(
+                rquickjs::Function::new(ctx.clone(), {
+                    let doc_ref = doc_ref.clone();
+                    move || -> Option<NodeHandle> {
+                        let mut doc = doc_ref.borrow_mut();
+                        let focused = doc.focused?;
+                        handles_for(&mut doc, vec![focused]).pop()
+                    }
+                }),
+                "Function _activeElementRaw",
+            )?;
+            js_try(
+                document_obj.set("_activeElementRaw", active_element_func),
+                "document _activeElementRaw",
+            )?;
+
             // createElement: creates an unattached node, returns a NodeHandle JS object
             let create_element_func = js_try(
                 rquickjs::Function::new(ctx.clone(), {
@@ -769,26 +1173,38 @@ impl JsEngine {
                 "document _createElementRaw",
             )?;
 
-            // appendChild: accepts two NodeHandle objects (no string parsing)
+            // _appendChildRaw: accepts two NodeHandle objects (no string parsing).
+            // The JS `appendChild` wrappers return the child object they were given.
             let append_child_func = js_try(
                 rquickjs::Function::new(ctx.clone(), {
                     let doc_ref = doc_ref.clone();
-                    move |parent_cls: rquickjs::Class<'_, NodeHandle>,
-                          child_cls: rquickjs::Class<'_, NodeHandle>| {
+                    move |ctx: rquickjs::Ctx<'_>,
+                          parent_cls: rquickjs::Class<'_, NodeHandle>,
+                          child_cls: rquickjs::Class<'_, NodeHandle>|
+                          -> rquickjs::Result<()> {
                         let parent_id = parent_cls.borrow().to_node_id();
                         let child_id = child_cls.borrow().to_node_id();
                         let mut doc = doc_ref.borrow_mut();
-                        doc.append_child(parent_id, child_id);
+                        if doc.append_child(parent_id, child_id) {
+                            Ok(())
+                        } else {
+                            Err(rquickjs::Exception::throw_type(
+                                &ctx,
+                                "appendChild: parent cannot contain this child",
+                            ))
+                        }
                     }
                 }),
-                "Function appendChild",
+                "Function _appendChildRaw",
             )?;
             js_try(
-                document_obj.set("appendChild", append_child_func),
-                "document appendChild",
+                document_obj.set("_appendChildRaw", append_child_func),
+                "document _appendChildRaw",
             )?;
 
-            // _garbageCollectNodeRaw: invoked natively by JS FinalizationRegistry
+            // _garbageCollectNodeRaw: invoked natively by JS FinalizationRegistry.
+            // Keys may outlive their node (slot reused) or be finalized twice;
+            // `try_cleanup_node` rejects both so a stale key never frees a live node.
             let gc_func = js_try(
                 rquickjs::Function::new(ctx.clone(), {
                     let doc_ref = doc_ref.clone();
@@ -851,6 +1267,17 @@ impl JsEngine {
                     document.createElement = function(tag) {
                         return this._wrapNode(this._createElementRaw(tag));
                     };
+                    document.appendChild = function(parent, child) {
+                        this._appendChildRaw(parent, child);
+                        return child;
+                    };
+                    Object.defineProperty(document, "title", {
+                        get() { return this._titleRaw(); },
+                        set(value) { this._setTitleRaw(String(value)); },
+                    });
+                    Object.defineProperty(document, "activeElement", {
+                        get() { return this._wrapNode(this._activeElementRaw()); },
+                    });
                     document.addEventListener = function(eventType, cb) {
                         this.__listeners = this.__listeners || {};
                         this.__listeners[eventType] = this.__listeners[eventType] || [];
@@ -883,6 +1310,81 @@ impl JsEngine {
                         Object.defineProperty(proto, "firstChild", { get() { return document._wrapNode(this._firstChildRaw()); } });
                         Object.defineProperty(proto, "nextSibling", { get() { return document._wrapNode(this._nextSiblingRaw()); } });
                         Object.defineProperty(proto, "tagName", { get() { return this._tagNameRaw(); } });
+                        Object.defineProperty(proto, "isConnected", { get() { return this._isConnectedRaw(); } });
+                        Object.defineProperty(proto, "outerHTML", { get() { return this._outerHtmlRaw(); } });
+                        Object.defineProperty(proto, "innerText", { get() { return this._innerTextRaw(); } });
+                        Object.defineProperty(proto, "textContent", {
+                            get() { return this._textContentRaw(); },
+                            set(value) { this._setTextContentRaw(value == null ? "" : String(value)); },
+                        });
+                        // `dataset` maps camelCase keys to `data-*` attributes using the
+                        // HTML naming rules: `fooBar` <-> `data-foo-bar`.
+                        const dataAttrName = (key) =>
+                            "data-" + key.replace(/[A-Z]/g, (c) => "-" + c.toLowerCase());
+                        Object.defineProperty(proto, "dataset", { get() {
+                            if (this.__dataset) return this.__dataset;
+                            const el = this;
+                            this.__dataset = new Proxy({}, {
+                                get(_, key) {
+                                    if (typeof key !== "string") return undefined;
+                                    const value = el.getAttribute(dataAttrName(key));
+                                    return value == null ? undefined : value;
+                                },
+                                set(_, key, value) {
+                                    if (typeof key !== "string") return false;
+                                    if (/-[a-z]/.test(key)) {
+                                        throw new SyntaxError("Invalid dataset key: " + key);
+                                    }
+                                    el.setAttribute(dataAttrName(key), String(value));
+                                    return true;
+                                },
+                                has(_, key) {
+                                    return typeof key === "string" && el.getAttribute(dataAttrName(key)) != null;
+                                },
+                                deleteProperty(_, key) {
+                                    if (typeof key === "string") el.removeAttribute(dataAttrName(key));
+                                    return true;
+                                },
+                            });
+                            return this.__dataset;
+                        } });
+                        proto.getBoundingClientRect = function() {
+                            const [x, y, width, height] = this._boundingRectRaw();
+                            return { x, y, width, height, top: y, left: x, right: x + width, bottom: y + height };
+                        };
+                        proto.scrollIntoView = function(options) {
+                            let block = "start";
+                            if (options === false) block = "end";
+                            else if (options && typeof options === "object" && options.block !== undefined) block = String(options.block);
+                            this._scrollIntoViewRaw(block);
+                        };
+                        proto.appendChild = function(child) {
+                            document._appendChildRaw(this, child);
+                            return child;
+                        };
+                        proto.insertBefore = function(node, reference) {
+                            this._insertBeforeRaw(node, reference == null ? null : reference);
+                            return node;
+                        };
+                        proto.replaceChild = function(node, old) {
+                            this._replaceChildRaw(node, old);
+                            return old;
+                        };
+                        proto.closest = function(selector) {
+                            return document._wrapNode(this._closestRaw(selector));
+                        };
+                        proto.querySelector = function(selector) {
+                            return document._wrapNode(this._querySelectorRaw(String(selector)));
+                        };
+                        proto.querySelectorAll = function(selector) {
+                            return this._querySelectorAllRaw(String(selector)).map((n) => document._wrapNode(n));
+                        };
+                        proto.getElementsByTagName = function(tag) {
+                            return this._getElementsByTagNameRaw(String(tag)).map((n) => document._wrapNode(n));
+                        };
+                        proto.getElementsByClassName = function(names) {
+                            return this._getElementsByClassNameRaw(String(names)).map((n) => document._wrapNode(n));
+                        };
                         proto.addEventListener = function(eventType, cb) {
                             this.__listeners = this.__listeners || {};
                             this.__listeners[eventType] = this.__listeners[eventType] || [];
@@ -1119,4 +1621,52 @@ impl JsEngine {
         self.last_start_time.set(None);
         res
     }
+
+    /// Route `console.log`/`warn`/`error` output to `sink` instead of stdout,
+    /// e.g. to collect script logs in tests or show them in a host UI.
+    /// `None` restores printing to stdout.
+    pub fn set_console_sink(&self, sink: Option<ConsoleSink>) {
+        *self.console_sink.borrow_mut() = sink;
+    }
+
+    /// Parse `json` in the engine and assign the result to the global `name`,
+    /// so scripts can read structured input without it being spliced into code.
+    pub fn set_global_json(&self, name: &str, json: &str) -> Result<(), JsEngineError> {
+        self.context.with(|ctx| {
+            let value = ctx
+                .json_parse(json)
+                .map_err(|e| JsEngineError::ScriptEval(format!("JSON.parse: {e:?}")))?;
+            ctx.globals()
+                .set(name, value)
+                .map_err(|e| JsEngineError::ScriptEval(format!("set global {name}: {e:?}")))
+        })
+    }
+
+    /// Call the global function `name` with the elements of the JSON array
+    /// `args_json` as arguments and return its result as JSON text. A result
+    /// with no JSON form (`undefined`, a function) is returned as `"null"`.
+    pub fn call_function(&self, name: &str, args_json: &str) -> Result<String, JsEngineError> {
+        self.last_start_time.set(Some(Instant::now()));
+        let res = self.context.with(|ctx| {
+            let err = |what: &str, e: rquickjs::Error| JsEngineError::ScriptEval(format!("{what}: {e:?}"));
+            let func: rquickjs::Function = ctx
+                .globals()
+                .get(name)
+                .map_err(|e| err(&format!("global function {name}"), e))?;
+            let args: Vec<rquickjs::Value> = ctx
+                .json_parse(args_json)
+                .and_then(|v| v.get())
+                .map_err(|e| err("arguments must be a JSON array", e))?;
+            let result: rquickjs::Value = func
+                .call((rquickjs::function::Rest(args),))
+                .map_err(|e| err(name, e))?;
+            let json = ctx.json_stringify(result).map_err(|e| err("JSON.stringify", e))?;
+            Ok(match json {
+                Some(s) => s.to_string().map_err(|e| err("JSON.stringify", e))?,
+                None => "null".to_string(),
+            })
+        });
+        self.last_start_time.set(None);
+        res
+    }
 }
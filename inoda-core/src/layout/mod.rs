@@ -14,32 +14,64 @@
 //! All traversals (`prepare_text_buffers`, `build_taffy_node`, `finalize_text_measurements`)
 //! are iterative and stack-based to avoid stack overflow on deep DOM trees.
 //!
+//! [`relayout`] re-runs only the solver and the final reshaping pass on the
+//! existing tree, for viewport resizes where the DOM and styles are unchanged.
+//!
 //! `build_taffy_node` performs work conditionally:
 //! - **Structural Updates**: Taffy node children are only updated via
-//!   `set_children` if a node is new or the `document.dirty` flag is set.
+//!   `set_children` if a node is new or the `document.dirty` flag is set,
+//!   and the child list differs.
+//! - **Styles**: `set_style` is skipped when the Taffy style is unchanged, so
+//!   Taffy only invalidates changed nodes and their ancestors
+//!   ([`update_layout_tree`] stops after this step).
 //! - **Text Measurement**: Intrinsic width calculation and shaping are
 //!   only re-run if a node is new or its `layout_dirty` flag is set.
 //!
+//! Whitespace-only text that would collapse away (between blocks, or inside
+//! flex and grid containers) is left out of the Taffy tree and not shaped.
+//!
+//! `content-visibility: hidden` elements are laid out as if empty: their
+//! descendants are not shaped or given Taffy nodes (existing ones are detached
+//! until the element is shown again). `auto` lays out normally;
+//! [`contents_skipped`] reports whether its contents are off-screen.
+//!
+//! [`cursor_at`] reports the computed `cursor` under a point, on top of
+//! `Document::hit_test`.
+//!
+//! `<br>` ends the current line; a `<br>` that does not follow text on the
+//! same line (e.g. the second of two) occupies one empty line of height.
+//!
 //! `<img>` elements use intrinsic sizing from HTML `width`/`height` attributes
 //! and Taffy's `aspect_ratio` property.
 //!
 //! Supported dimension units: px, %, vw, vh, em, rem, auto.
-//! `rem` resolves against `Document.root_font_size`; `em` resolves against the
+//! `rem` resolves against `Document.rem_base`; `em` resolves against the
 //! element's own `font_size`. Supported display modes: flex, grid, block, none.
-//! Note: inline and inline-block are normalized to block.
+//! Inline-level boxes (text, `display: inline`/`inline-block`, and phrasing
+//! tags such as `span` by default) are laid out as blocks themselves, but a run
+//! of them under a block container is wrapped in an anonymous Taffy flex row
+//! with wrapping so the run flows horizontally. A run that is a single text
+//! node needs no wrapper. Anonymous boxes are owned by
+//! `Document::anonymous_boxes`; use [`anonymous_box_offset`] when converting a
+//! child's Taffy location into its DOM parent's coordinate space.
+//! Floats join these rows too, pulled to the row's start (`left`) or end
+//! (`right`); text does not wrap around them.
 //! Box model properties mapped: margin-*, padding-*, border-*-width.
+//! `overflow-x`/`overflow-y` map to Taffy `overflow` (`auto` is treated as `scroll`).
+//! A unitless `0` is accepted wherever a length is.
 
 use std::collections::HashMap;
 
-use cosmic_text::{Attrs, Buffer, FontSystem, Metrics, Shaping, Wrap};
+use cosmic_text::{Align, Attrs, AttrsOwned, Buffer, BufferLine, Family, FontSystem, Metrics, Shaping, Style as FontStyle, Weight, Wrap};
 use taffy::{
-    TaffyTree,
     prelude::*,
     style::{Dimension, Style},
 };
 
 /// Width tolerance for treating two Taffy measure probes as identical.
 const MEASURE_WIDTH_EPSILON: f32 = 1e-3;
+/// Upper bound on solver passes spent settling percentage `min()`/`max()`/`clamp()` sizes.
+const MAX_MATH_PASSES: usize = 3;
 
 // TextMeasureContext moved to crate::dom
 
@@ -50,90 +82,202 @@ pub fn compute_layout(
     viewport_height: f32,
     font_system: &mut FontSystem,
     buffer_cache: &mut HashMap<crate::dom::NodeId, Buffer>,
+) -> taffy::NodeId {
+    layout_document(document, viewport_width, viewport_height, font_system, buffer_cache, None)
+}
+
+/// [`compute_layout`], but sizing text with `metrics.measure_text` wherever
+/// the backend answers, so boxes match what the backend will raster.
+///
+/// cosmic-text still shapes and breaks lines: each hard line's width is
+/// replaced by the backend's and glyph advances are scaled to match, and
+/// wrap widths are scaled by the same ratio, so breaks are approximate when
+/// the backend's proportions differ from cosmic-text's. Under
+/// `line-height: normal` the backend's height becomes the line height.
+/// Glyph positions handed to `draw_glyphs` are still cosmic-text's.
+///
+/// Measurements are cached with each text node until it changes; use one
+/// entry point consistently for a document.
+pub fn compute_layout_with_metrics(
+    document: &mut crate::dom::Document,
+    viewport_width: f32,
+    viewport_height: f32,
+    font_system: &mut FontSystem,
+    buffer_cache: &mut HashMap<crate::dom::NodeId, Buffer>,
+    metrics: &dyn crate::render::RendererBackend,
+) -> taffy::NodeId {
+    layout_document(document, viewport_width, viewport_height, font_system, buffer_cache, Some(metrics))
+}
+
+fn layout_document(
+    document: &mut crate::dom::Document,
+    viewport_width: f32,
+    viewport_height: f32,
+    font_system: &mut FontSystem,
+    buffer_cache: &mut HashMap<crate::dom::NodeId, Buffer>,
+    metrics: Option<&dyn crate::render::RendererBackend>,
+) -> taffy::NodeId {
+    let root_taffy_node =
+        sync_taffy_tree(document, viewport_width, viewport_height, font_system, buffer_cache, metrics);
+    solve(document, root_taffy_node, viewport_width, viewport_height, font_system, buffer_cache);
+    root_taffy_node
+}
+
+/// Brings the Taffy tree in line with the DOM without solving it: the first
+/// half of [`compute_layout`]. Taffy nodes persist across calls on each arena
+/// node (`taffy_node`), and a node's style and children are only written
+/// when they changed, so Taffy marks just the changed nodes and their
+/// ancestors dirty and every other subtree keeps its cached layout. Query
+/// `document.taffy_tree.dirty(node)` to see what the next solve will redo.
+pub fn update_layout_tree(
+    document: &mut crate::dom::Document,
+    viewport_width: f32,
+    viewport_height: f32,
+    font_system: &mut FontSystem,
+    buffer_cache: &mut HashMap<crate::dom::NodeId, Buffer>,
+) -> taffy::NodeId {
+    sync_taffy_tree(document, viewport_width, viewport_height, font_system, buffer_cache, None)
+}
+
+fn sync_taffy_tree(
+    document: &mut crate::dom::Document,
+    viewport_width: f32,
+    viewport_height: f32,
+    font_system: &mut FontSystem,
+    buffer_cache: &mut HashMap<crate::dom::NodeId, Buffer>,
+    metrics: Option<&dyn crate::render::RendererBackend>,
 ) -> taffy::NodeId {
     // Evict cached text buffers for nodes that have been removed from the DOM
     buffer_cache.retain(|node_id, _| document.nodes.contains(*node_id));
 
     prepare_text_buffers(document, document.root_id, font_system, buffer_cache);
 
-    let root_font_size = document.root_font_size;
     let mut scratchpad = Vec::new();
-    let root_taffy_node = build_taffy_node(
+    build_taffy_node(
         document,
         document.root_id,
         viewport_width,
         viewport_height,
-        root_font_size,
         buffer_cache,
+        metrics,
         &mut scratchpad,
-    );
+    )
+}
 
+/// Re-runs the solver for a new viewport size on the Taffy tree left by the
+/// last [`compute_layout`], reusing its node styles and cached text buffers:
+/// no restyle, tree rebuild, or initial shaping pass. Text is only rewrapped
+/// (and reshaped) where the new widths require it.
+///
+/// Intended for window resizes when nothing else changed. Styles that depend
+/// on the viewport (`vw`/`vh`) keep the values resolved at the old size, and
+/// DOM or style changes are not picked up; call [`compute_layout`] instead in
+/// those cases. Returns `None` if the document has never been laid out.
+pub fn relayout(
+    document: &mut crate::dom::Document,
+    viewport_width: f32,
+    viewport_height: f32,
+    font_system: &mut FontSystem,
+    buffer_cache: &mut HashMap<crate::dom::NodeId, Buffer>,
+) -> Option<taffy::NodeId> {
+    let root_taffy_node = match document.nodes.get(document.root_id) {
+        Some(crate::dom::Node::Root(r)) => r.taffy_node?,
+        _ => return None,
+    };
+    solve(document, root_taffy_node, viewport_width, viewport_height, font_system, buffer_cache);
+    Some(root_taffy_node)
+}
+
+/// Runs Taffy with the text measure function, then reshapes text buffers at
+/// their final widths.
+fn solve(
+    document: &mut crate::dom::Document,
+    root_taffy_node: taffy::NodeId,
+    viewport_width: f32,
+    viewport_height: f32,
+    font_system: &mut FontSystem,
+    buffer_cache: &mut HashMap<crate::dom::NodeId, Buffer>,
+) {
+    document.viewport_size = (viewport_width, viewport_height);
     let available_space = Size {
         width: AvailableSpace::Definite(viewport_width),
         height: AvailableSpace::Definite(viewport_height),
     };
 
-    // Taffy 0.9's compute_layout_with_measure takes FnMut, so we can capture
-    // &mut font_system and &mut buffer_cache directly — they are disjoint from
-    // &mut document.taffy_tree. No UnsafeCell needed.
-    document.taffy_tree.compute_layout_with_measure(
-        root_taffy_node,
-        available_space,
-        |_known_dimensions,
-         available_space,
-         _node_id,
-         context: Option<&mut crate::dom::TextMeasureContext>,
-         _style| {
-            let Some(ctx) = context else {
-                return taffy::geometry::Size::ZERO;
-            };
+    // Sizes using `min()`/`max()`/`clamp()` with percentages are resolved
+    // against the parents' boxes from the previous pass; each pass can settle
+    // one more level of nesting.
+    let mut passes = 0;
+    loop {
+        // Taffy 0.9's compute_layout_with_measure takes FnMut, so we can capture
+        // &mut font_system and &mut buffer_cache directly — they are disjoint from
+        // &mut document.taffy_tree. No UnsafeCell needed.
+        let clamped_text = &mut document.clamped_text;
+        document.taffy_tree.compute_layout_with_measure(
+            root_taffy_node,
+            available_space,
+            |_known_dimensions,
+             available_space,
+             _node_id,
+             context: Option<&mut crate::dom::TextMeasureContext>,
+             _style| {
+                let Some(ctx) = context else {
+                    return taffy::geometry::Size::ZERO;
+                };
 
-            let width_constraint = match available_space.width {
-                AvailableSpace::Definite(w) if w.is_finite() && w > 0.0 => w,
-                AvailableSpace::MinContent => ctx.min_intrinsic_width.max(1.0),
-                AvailableSpace::MaxContent => ctx.max_intrinsic_width.max(1.0),
-                _ => viewport_width.max(1.0),
-            };
+                let width_constraint = match available_space.width {
+                    AvailableSpace::Definite(w) if w.is_finite() && w > 0.0 => w,
+                    AvailableSpace::MinContent => ctx.min_intrinsic_width.max(1.0),
+                    AvailableSpace::MaxContent => ctx.max_intrinsic_width.max(1.0),
+                    _ => viewport_width.max(1.0),
+                };
 
-            if let Some(buffer) = buffer_cache.get_mut(&ctx.node_id) {
-                let line_height = (ctx.font_size * 1.2).max(1.0);
-                if let Some(last_w) = ctx.last_measure_width {
-                    if (last_w - width_constraint).abs() <= MEASURE_WIDTH_EPSILON {
-                        let width = ctx.max_intrinsic_width.min(width_constraint);
-                        let height =
-                            (ctx.last_line_count * line_height).max(line_height);
-                        return taffy::geometry::Size { width, height };
+                if let Some(buffer) = buffer_cache.get_mut(&ctx.node_id) {
+                    let line_height = ctx.line_height;
+                    if let Some(last_w) = ctx.last_measure_width {
+                        if (last_w - width_constraint).abs() <= MEASURE_WIDTH_EPSILON {
+                            let width = ctx.max_intrinsic_width.min(width_constraint);
+                            let height =
+                                (ctx.last_line_count * line_height).max(line_height);
+                            return taffy::geometry::Size { width, height };
+                        }
                     }
-                }
 
-                buffer.set_size(font_system, Some(width_constraint), None);
-                let line_count = buffer.layout_runs().count() as f32;
-                ctx.last_measure_width = Some(width_constraint);
-                ctx.last_line_count = line_count;
+                    unclamp(buffer, clamped_text, ctx.node_id);
+                    buffer.set_size(font_system, Some(wrap_width(ctx, width_constraint)), None);
+                    let mut line_count = buffer.layout_runs().count();
+                    if let Some(max_lines) = ctx.max_lines {
+                        line_count = line_count.min(max_lines);
+                    }
+                    let line_count = line_count as f32;
+                    ctx.last_measure_width = Some(width_constraint);
+                    ctx.last_line_count = line_count;
 
-                let width = ctx.max_intrinsic_width.min(width_constraint);
-                let height = (line_count * line_height).max(line_height);
+                    let width = ctx.max_intrinsic_width.min(width_constraint);
+                    let height = (line_count * line_height).max(line_height);
 
-                taffy::geometry::Size { width, height }
-            } else {
-                // Fallback to minimal approximation if buffer is missing
-                let width = ctx.max_intrinsic_width.min(width_constraint);
-                let height = (ctx.font_size * 1.2).max(1.0);
-                taffy::geometry::Size { width, height }
-            }
-        },
-    )
-    .unwrap();
+                    taffy::geometry::Size { width, height }
+                } else {
+                    // Fallback to minimal approximation if buffer is missing
+                    let width = ctx.max_intrinsic_width.min(width_constraint);
+                    taffy::geometry::Size { width, height: ctx.line_height }
+                }
+            },
+        )
+        .unwrap();
+        passes += 1;
+        if passes == MAX_MATH_PASSES || !resolve_math_sizes(document, viewport_width, viewport_height) {
+            break;
+        }
+    }
 
     // We walk the tree one last time to enforce exact text heights for empty nodes
     finalize_text_measurements(
-        &document.taffy_tree,
+        document,
         root_taffy_node,
         font_system,
         buffer_cache,
     );
-
-    root_taffy_node
 }
 
 /// Iterative pre-pass: creates `Buffer` objects for all text nodes in the DOM.
@@ -143,6 +287,7 @@ fn prepare_text_buffers(
     font_system: &mut FontSystem,
     buffer_cache: &mut HashMap<crate::dom::NodeId, Buffer>,
 ) {
+    let mut families = FontFamilyCache::default();
     let mut stack = vec![root_id];
     while let Some(node_id) = stack.pop() {
         if let Some(crate::dom::Node::Text(data)) = document.nodes.get_mut(node_id) {
@@ -151,14 +296,34 @@ fn prepare_text_buffers(
                 // Don't clear layout_dirty here — build_taffy_node still needs to
                 // read it to decide whether to recalculate intrinsic widths.
             }
+            // A new buffer holds the full text, so an earlier clamp is stale.
+            if !buffer_cache.contains_key(&node_id) {
+                document.clamped_text.remove(&node_id);
+            }
 
             let font_size = data.computed.font_size;
-            let line_height = (font_size * 1.2).max(1.0);
+            let line_height = resolved_line_height(data.computed.line_height, font_size);
+            let wrap = wrap_mode(&data.computed);
 
             buffer_cache.entry(node_id).or_insert_with(|| {
                 let mut b = Buffer::new(font_system, Metrics::new(font_size, line_height));
-                b.set_wrap(font_system, Wrap::WordOrGlyph);
-                b.set_text(font_system, &data.text, Attrs::new(), Shaping::Advanced);
+                b.set_wrap(font_system, wrap);
+                let attrs = text_attrs(&data.computed, font_system, &mut families);
+                let text = transformed_text(&data.text, data.computed.text_transform);
+                let text = expand_tabs(collapse_white_space(text, data.computed.white_space), data.computed.tab_size);
+                match &data.first_letter {
+                    Some(letter) => {
+                        let (red, green, blue, alpha) = letter.color;
+                        let letter_height = resolved_line_height(letter.line_height, letter.font_size);
+                        let letter_attrs = text_attrs(letter, font_system, &mut families)
+                            .metrics(Metrics::new(letter.font_size, letter_height))
+                            .color(cosmic_text::Color::rgba(red, green, blue, alpha));
+                        let (head, tail) = text.split_at(first_letter_end(&text));
+                        b.set_rich_text(font_system, [(head, letter_attrs), (tail, attrs)], attrs, Shaping::Advanced);
+                    }
+                    None => b.set_text(font_system, &text, attrs, Shaping::Advanced),
+                }
+                set_line_align(&mut b, text_align(&data.computed));
 
                 // Shape ONCE in pre-pass to resolve intrinsic widths
                 b.set_size(font_system, Some(f32::INFINITY), Some(f32::INFINITY));
@@ -167,17 +332,375 @@ fn prepare_text_buffers(
             });
         }
 
-        // Push children in reverse order so first child is processed first
-        let mut children = Vec::new();
-        let mut child_id = document.first_child_of(node_id);
-        while let Some(c) = child_id {
-            children.push(c);
-            child_id = document.next_sibling_of(c);
+        if contents_hidden(document, node_id) {
+            continue;
         }
+        // Push children in reverse order so first child is processed first
+        let children: Vec<_> = document.layout_children(node_id).collect();
         for c in children.into_iter().rev() {
-            stack.push(c);
+            if is_collapsible_whitespace(document, c) {
+                buffer_cache.remove(&c);
+            } else {
+                stack.push(c);
+            }
+        }
+    }
+}
+
+/// Whether `node_id` is a whitespace-only text node that takes no part in
+/// layout, so it gets neither a text buffer nor a Taffy node: under a flex or
+/// grid container, or in block flow unless inline-level content sits on both
+/// sides of it (looking past the edges of inline parents). Whitespace kept by
+/// `white-space: pre`, `pre-wrap` or `pre-line` always counts.
+fn is_collapsible_whitespace(document: &crate::dom::Document, node_id: crate::dom::NodeId) -> bool {
+    let Some(crate::dom::Node::Text(data)) = document.nodes.get(node_id) else {
+        return false;
+    };
+    if !data.text.chars().all(char::is_whitespace)
+        || matches!(
+            data.computed.white_space,
+            crate::dom::WhiteSpaceKeyword::Pre | crate::dom::WhiteSpaceKeyword::PreWrap | crate::dom::WhiteSpaceKeyword::PreLine
+        )
+    {
+        return false;
+    }
+    if !document.box_parent(node_id).is_none_or(|parent| establishes_inline_runs(document, parent)) {
+        return true;
+    }
+    !(has_inline_neighbor(document, node_id, false) && has_inline_neighbor(document, node_id, true))
+}
+
+/// Whether the nearest rendered sibling of `node_id` on one side is
+/// inline-level, climbing out of inline and `display: contents` parents that
+/// have none on that side.
+fn has_inline_neighbor(document: &crate::dom::Document, node_id: crate::dom::NodeId, forward: bool) -> bool {
+    let mut current = node_id;
+    loop {
+        let mut sibling = current;
+        loop {
+            let next = if forward { document.next_sibling_of(sibling) } else { document.prev_sibling_of(sibling) };
+            let Some(next) = next else { break };
+            sibling = next;
+            match document.nodes.get(sibling) {
+                Some(crate::dom::Node::Text(_)) => return true,
+                Some(crate::dom::Node::Element(d)) if d.computed.display != crate::dom::DisplayKeyword::None => {
+                    return is_inline_level(&d.computed);
+                }
+                _ => {}
+            }
+        }
+        match document.parent_of(current).map(|parent| (parent, document.nodes.get(parent))) {
+            Some((parent, Some(crate::dom::Node::Element(d))))
+                if is_inline_level(&d.computed) || d.computed.display == crate::dom::DisplayKeyword::Contents =>
+            {
+                current = parent
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// `document.box_children(node_id)` without collapsible whitespace: the
+/// Taffy children of `node_id`'s box, including those hoisted out of
+/// `display: contents` children.
+fn flow_children(
+    document: &crate::dom::Document,
+    node_id: crate::dom::NodeId,
+) -> impl Iterator<Item = crate::dom::NodeId> + '_ {
+    document.box_children(node_id).filter(move |&c| !is_collapsible_whitespace(document, c))
+}
+
+/// The width cosmic-text should break lines at for a box `width` wide,
+/// leaving room for letter and word spacing and for backend metrics that
+/// differ from cosmic-text's. The small slack keeps text
+/// measured at exactly its spaced max-content width on one line.
+fn wrap_width(ctx: &crate::dom::TextMeasureContext, width: f32) -> f32 {
+    if ctx.wrap_scale != 1.0 { width * ctx.wrap_scale + 0.01 } else { width }
+}
+
+/// Extra advance that `letter-spacing` and `word-spacing` add after `glyph`.
+/// `text` is the glyph's layout run text.
+fn glyph_spacing(style: &crate::dom::TextComputedStyle, text: &str, glyph: &cosmic_text::LayoutGlyph) -> f32 {
+    let is_space = style.word_spacing != 0.0
+        && text.get(glyph.start..glyph.end).is_some_and(|s| s.contains([' ', '\u{a0}']));
+    style.letter_spacing + if is_space { style.word_spacing } else { 0.0 }
+}
+
+/// `run`'s glyphs moved right by the spacing accumulated before each one, or
+/// `None` when the style adds no spacing and `run.glyphs` can be drawn as is.
+pub(crate) fn spaced_glyphs(
+    run: &cosmic_text::LayoutRun,
+    style: &crate::dom::TextComputedStyle,
+) -> Option<Vec<cosmic_text::LayoutGlyph>> {
+    if style.letter_spacing == 0.0 && style.word_spacing == 0.0 {
+        return None;
+    }
+    let mut shift = 0.0;
+    let glyphs = run
+        .glyphs
+        .iter()
+        .map(|glyph| {
+            let mut spaced = glyph.clone();
+            spaced.x += shift;
+            shift += glyph_spacing(style, run.text, glyph);
+            spaced
+        })
+        .collect();
+    Some(glyphs)
+}
+
+/// The text to shape for `text-transform`. `capitalize` uppercases the first
+/// letter or digit of each whitespace-separated word. The source text is never
+/// modified; case mapping may change its length, so glyph offsets index the
+/// buffer's line text rather than `TextData::text`.
+fn transformed_text(text: &str, transform: crate::dom::TextTransformKeyword) -> std::borrow::Cow<'_, str> {
+    match transform {
+        crate::dom::TextTransformKeyword::None => std::borrow::Cow::Borrowed(text),
+        crate::dom::TextTransformKeyword::Uppercase => std::borrow::Cow::Owned(text.to_uppercase()),
+        crate::dom::TextTransformKeyword::Lowercase => std::borrow::Cow::Owned(text.to_lowercase()),
+        crate::dom::TextTransformKeyword::Capitalize => {
+            let mut out = String::with_capacity(text.len());
+            let mut word_start = true;
+            for c in text.chars() {
+                if word_start && c.is_alphanumeric() {
+                    out.extend(c.to_uppercase());
+                    word_start = false;
+                } else {
+                    out.push(c);
+                    word_start |= c.is_whitespace();
+                }
+            }
+            std::borrow::Cow::Owned(out)
+        }
+    }
+}
+
+/// Applies `white-space` to the text to shape. `normal` and `nowrap`
+/// collapse every run of spaces, tabs and newlines to one space; `pre-line`
+/// does the same to spaces and tabs, keeps newlines and drops the spaces
+/// around them; `pre` and `pre-wrap` keep everything. Collapsing works within
+/// one text node, so spaces at line edges and across inline boundaries are
+/// kept.
+fn collapse_white_space(
+    text: std::borrow::Cow<'_, str>,
+    white_space: crate::dom::WhiteSpaceKeyword,
+) -> std::borrow::Cow<'_, str> {
+    fn collapse_into(text: &str, newlines: bool, out: &mut String) {
+        let mut in_space = false;
+        for c in text.chars() {
+            if matches!(c, ' ' | '\t' | '\r' | '\x0c') || (newlines && c == '\n') {
+                if !in_space {
+                    out.push(' ');
+                }
+                in_space = true;
+            } else {
+                out.push(c);
+                in_space = false;
+            }
+        }
+    }
+
+    if matches!(white_space, crate::dom::WhiteSpaceKeyword::Pre | crate::dom::WhiteSpaceKeyword::PreWrap) {
+        return text;
+    }
+    let mut out = String::with_capacity(text.len());
+    match white_space {
+        crate::dom::WhiteSpaceKeyword::Pre | crate::dom::WhiteSpaceKeyword::PreWrap => {}
+        crate::dom::WhiteSpaceKeyword::Normal | crate::dom::WhiteSpaceKeyword::Nowrap => {
+            collapse_into(&text, true, &mut out)
+        }
+        crate::dom::WhiteSpaceKeyword::PreLine => {
+            let lines: Vec<&str> = text.split('\n').collect();
+            let last = lines.len() - 1;
+            for (i, line) in lines.into_iter().enumerate() {
+                let mut line = line;
+                if i > 0 {
+                    line = line.trim_start_matches([' ', '\t']);
+                    out.push('\n');
+                }
+                if i < last {
+                    line = line.trim_end_matches([' ', '\t']);
+                }
+                collapse_into(line, false, &mut out);
+            }
         }
     }
+    if out == *text { text } else { std::borrow::Cow::Owned(out) }
+}
+
+/// Replaces each tab with `tab_size` spaces. Only tabs that survive
+/// `collapse_white_space` (under `pre` and `pre-wrap`) remain to expand.
+fn expand_tabs(text: std::borrow::Cow<'_, str>, tab_size: u32) -> std::borrow::Cow<'_, str> {
+    if !text.contains('\t') {
+        return text;
+    }
+    std::borrow::Cow::Owned(text.replace('\t', &" ".repeat(tab_size as usize)))
+}
+
+/// Byte length of the `::first-letter` span at the start of `text`: leading
+/// white space and punctuation plus the character after them.
+fn first_letter_end(text: &str) -> usize {
+    let mut chars = text.char_indices().skip_while(|&(_, c)| !c.is_alphanumeric());
+    chars.next().map_or(text.len(), |(i, c)| i + c.len_utf8())
+}
+
+/// True for an element with `content-visibility: hidden`: its descendants get
+/// no text buffers or Taffy nodes, and it lays out as if empty.
+fn contents_hidden(document: &crate::dom::Document, node_id: crate::dom::NodeId) -> bool {
+    matches!(
+        document.nodes.get(node_id),
+        Some(crate::dom::Node::Element(d))
+            if d.computed.content_visibility == crate::dom::ContentVisibilityKeyword::Hidden
+    )
+}
+
+/// Whether the contents of `node_id` can be skipped: always for
+/// `content-visibility: hidden`, and for `auto` when the element's last
+/// layout box lies entirely outside `viewport` (document coordinates).
+/// Without a viewport `auto` contents are never skipped.
+pub fn contents_skipped(
+    document: &crate::dom::Document,
+    node_id: crate::dom::NodeId,
+    viewport: Option<AbsoluteRect>,
+) -> bool {
+    let Some(crate::dom::Node::Element(d)) = document.nodes.get(node_id) else {
+        return false;
+    };
+    match (d.computed.content_visibility, viewport) {
+        (crate::dom::ContentVisibilityKeyword::Hidden, _) => true,
+        (crate::dom::ContentVisibilityKeyword::Auto, Some(v)) => absolute_rect(document, node_id).is_some_and(|r| {
+            r.x >= v.x + v.width || r.x + r.width <= v.x || r.y >= v.y + v.height || r.y + r.height <= v.y
+        }),
+        _ => false,
+    }
+}
+
+/// The computed `cursor` of the element under `(x, y)` (document
+/// coordinates, as for `Document::hit_test`), so hosts can pick the OS
+/// pointer. A text node reports its parent element's cursor. `None` when
+/// nothing but the root is hit.
+pub fn cursor_at(document: &crate::dom::Document, x: f32, y: f32) -> Option<String> {
+    let hit = document.hit_test(x, y)?;
+    let element = match document.nodes.get(hit)? {
+        crate::dom::Node::Text(t) => t.parent?,
+        _ => hit,
+    };
+    match document.nodes.get(element)? {
+        crate::dom::Node::Element(d) => Some(d.computed.cursor.to_string()),
+        _ => None,
+    }
+}
+
+/// Horizontal alignment for a text buffer from `text-align`, with `start`
+/// and `end` taken from `direction`. Without an explicit alignment
+/// cosmic-text aligns each paragraph by its first strong character, so
+/// Hebrew text in an `ltr` element would hug the right edge.
+fn text_align(style: &crate::dom::TextComputedStyle) -> Align {
+    use crate::dom::{DirectionKeyword, TextAlignKeyword};
+    match (style.text_align, style.direction) {
+        (TextAlignKeyword::Left, _)
+        | (TextAlignKeyword::Start, DirectionKeyword::Ltr)
+        | (TextAlignKeyword::End, DirectionKeyword::Rtl) => Align::Left,
+        (TextAlignKeyword::Right, _)
+        | (TextAlignKeyword::Start, DirectionKeyword::Rtl)
+        | (TextAlignKeyword::End, DirectionKeyword::Ltr) => Align::Right,
+        (TextAlignKeyword::Center, _) => Align::Center,
+        (TextAlignKeyword::Justify, _) => Align::Justified,
+    }
+}
+
+fn set_line_align(buffer: &mut Buffer, align: Align) {
+    for line in buffer.lines.iter_mut() {
+        line.set_align(Some(align));
+    }
+}
+
+/// Used line height in px for text at `font_size`: the single source for
+/// buffer metrics (and so rendered line positions), measured heights, and
+/// `<br>` line boxes. `normal` is `1.2 * font_size`; never below 1px.
+pub fn resolved_line_height(line_height: crate::dom::LineHeight, font_size: f32) -> f32 {
+    let px = match line_height {
+        crate::dom::LineHeight::Normal => font_size * 1.2,
+        crate::dom::LineHeight::Number(n) => font_size * n,
+        crate::dom::LineHeight::Px(px) => px,
+    };
+    px.max(1.0)
+}
+
+/// Font attributes for shaping a text node: family, weight and slant.
+/// cosmic-text 0.12's `Attrs` carries no language, so `lang` does not reach
+/// shaping; rustybuzz infers the script of each run from its text.
+fn text_attrs<'a>(
+    style: &'a crate::dom::TextComputedStyle,
+    font_system: &FontSystem,
+    families: &mut FontFamilyCache,
+) -> Attrs<'a> {
+    Attrs::new()
+        .family(families.resolve(&style.font_family, font_system))
+        .weight(Weight(style.font_weight))
+        .style(match style.font_style {
+            crate::dom::FontStyleKeyword::Normal => FontStyle::Normal,
+            crate::dom::FontStyleKeyword::Italic => FontStyle::Italic,
+            crate::dom::FontStyleKeyword::Oblique => FontStyle::Oblique,
+        })
+}
+
+/// `font-family` lists resolved during one pass over the text nodes, keyed by
+/// the list: the index of the family used, or `None` for the sans-serif
+/// fallback. Nodes share their inherited list, so the face database is
+/// walked once per distinct list rather than once per node.
+#[derive(Default)]
+struct FontFamilyCache {
+    resolved: HashMap<std::sync::Arc<[crate::dom::FontFamilyName]>, Option<usize>>,
+}
+
+impl FontFamilyCache {
+    /// The first family in a `font-family` list that can be used: a generic
+    /// family, or a named one installed in `font_system`. Falls back to
+    /// sans-serif when nothing in the list matches.
+    fn resolve<'a>(
+        &mut self,
+        families: &'a std::sync::Arc<[crate::dom::FontFamilyName]>,
+        font_system: &FontSystem,
+    ) -> Family<'a> {
+        use crate::dom::FontFamilyName;
+        let index = *self.resolved.entry(families.clone()).or_insert_with(|| {
+            families.iter().position(|family| match family {
+                FontFamilyName::Named(name) => font_system
+                    .db()
+                    .faces()
+                    .any(|face| face.families.iter().any(|(n, _)| n.eq_ignore_ascii_case(name))),
+                _ => true,
+            })
+        });
+        match index.map(|i| &families[i]) {
+            Some(FontFamilyName::Named(name)) => Family::Name(name),
+            Some(FontFamilyName::Serif) => Family::Serif,
+            Some(FontFamilyName::Monospace) => Family::Monospace,
+            Some(FontFamilyName::Cursive) => Family::Cursive,
+            Some(FontFamilyName::Fantasy) => Family::Fantasy,
+            Some(FontFamilyName::SansSerif) | None => Family::SansSerif,
+        }
+    }
+}
+
+/// Maps `word-break` / `overflow-wrap` to a cosmic-text wrap mode.
+/// `keep-all` suppresses wrapping entirely; `break-all` breaks between any
+/// glyphs; `break-word`/`anywhere` break inside words only when a word would
+/// otherwise overflow; `normal` breaks at word boundaries only.
+fn wrap_mode(style: &crate::dom::TextComputedStyle) -> Wrap {
+    if matches!(style.white_space, crate::dom::WhiteSpaceKeyword::Nowrap | crate::dom::WhiteSpaceKeyword::Pre) {
+        return Wrap::None;
+    }
+    match (style.word_break, style.overflow_wrap) {
+        (crate::dom::WordBreakKeyword::KeepAll, _) => Wrap::None,
+        (crate::dom::WordBreakKeyword::BreakAll, _) => Wrap::Glyph,
+        (crate::dom::WordBreakKeyword::BreakWord, _)
+        | (_, crate::dom::OverflowWrapKeyword::BreakWord | crate::dom::OverflowWrapKeyword::Anywhere) => {
+            Wrap::WordOrGlyph
+        }
+        _ => Wrap::Word,
+    }
 }
 
 /// Iterative post-pass: reshapes text buffers at their final resolved widths.
@@ -186,33 +709,49 @@ fn prepare_text_buffers(
 /// unnecessary reshaping. Only reshapes if the width differs beyond MEASURE_WIDTH_EPSILON.
 /// This replaces the previous `layout_dirty` flag check, which was already cleared by
 /// `prepare_text_buffers` before this function runs.
+///
+/// Buffers with `max_lines` are truncated to that many lines, with an
+/// ellipsis appended to the last retained line. The full lines are kept in
+/// `document.clamped_text`, so a buffer already clamped at its final width is
+/// left alone.
 fn finalize_text_measurements(
-    tree: &TaffyTree<crate::dom::TextMeasureContext>,
+    document: &mut crate::dom::Document,
     root_taffy_node: taffy::NodeId,
     font_system: &mut FontSystem,
     buffer_cache: &mut HashMap<crate::dom::NodeId, Buffer>,
 ) {
     const MEASURE_WIDTH_EPSILON: f32 = 0.5;
 
+    let tree = &document.taffy_tree;
+    let clamped_text = &mut document.clamped_text;
     let mut stack = vec![root_taffy_node];
     while let Some(taffy_node) = stack.pop() {
         if let Some(ctx) = tree.get_node_context(taffy_node) {
             if let Ok(layout) = tree.layout(taffy_node) {
                 let resolved_width = layout.size.width;
+                let width = wrap_width(ctx, resolved_width).max(1.0);
 
-                // Only reshape if width differs from last measure by more than epsilon
-                let needs_reshape = ctx.last_measure_width
-                    .map(|last_w| (last_w - resolved_width).abs() > MEASURE_WIDTH_EPSILON)
-                    .unwrap_or(true);
+                // Only reshape if width differs from last measure by more than
+                // epsilon. Clamp-styled text is re-clamped unless it was
+                // clamped at this width and not measured since.
+                let needs_reshape = match (ctx.max_lines, clamped_text.get(&ctx.node_id)) {
+                    (Some(_), Some(&(clamped_at, _))) => (clamped_at - width).abs() > MEASURE_WIDTH_EPSILON,
+                    (Some(_), None) => true,
+                    (None, _) => ctx.last_measure_width
+                        .map(|last_w| (last_w - resolved_width).abs() > MEASURE_WIDTH_EPSILON)
+                        .unwrap_or(true),
+                };
 
                 if needs_reshape {
                     if let Some(buffer) = buffer_cache.get_mut(&ctx.node_id) {
-                        buffer.set_size(
-                            font_system,
-                            Some(resolved_width.max(1.0)),
-                            Some(f32::INFINITY),
-                        );
+                        unclamp(buffer, clamped_text, ctx.node_id);
+                        buffer.set_size(font_system, Some(width), Some(f32::INFINITY));
                         buffer.shape_until_scroll(font_system, false);
+                        if let Some(max_lines) = ctx.max_lines
+                            && let Some(full) = clamp_lines(buffer, font_system, max_lines)
+                        {
+                            clamped_text.insert(ctx.node_id, (width, full));
+                        }
                     }
                 }
             }
@@ -227,6 +766,99 @@ fn finalize_text_measurements(
     }
 }
 
+/// Puts the full lines of a buffer clamped by [`clamp_lines`] back. They are
+/// laid out at the width it was clamped at, which is still the buffer's.
+fn unclamp(
+    buffer: &mut Buffer,
+    clamped_text: &mut HashMap<crate::dom::NodeId, (f32, Vec<BufferLine>)>,
+    node_id: crate::dom::NodeId,
+) {
+    if let Some((_, lines)) = clamped_text.remove(&node_id) {
+        buffer.lines = lines;
+        buffer.set_redraw(true);
+    }
+}
+
+/// Truncates a shaped buffer to `max_lines` layout runs, ending the last
+/// retained line with an ellipsis. Returns the buffer's full lines if it cut
+/// anything.
+///
+/// Paragraphs before the last retained one are kept as they are, shaping
+/// included. The last one is cut in place, so its attribute spans (a
+/// `::first-letter`, say) survive, and the ellipsis takes the attributes of
+/// the text before it. The cut point comes from the glyph positions of the
+/// last retained line: it keeps the glyphs that end at least an ellipsis
+/// width before the wrap width, so only that paragraph is reshaped, once.
+/// Only if the result still spills onto another line (kerning against the
+/// ellipsis) are further glyphs dropped, one reshape each.
+fn clamp_lines(buffer: &mut Buffer, font_system: &mut FontSystem, max_lines: usize) -> Option<Vec<BufferLine>> {
+    let last_run = buffer.layout_runs().nth(max_lines.saturating_sub(1))?;
+    buffer.layout_runs().nth(max_lines)?;
+
+    let line_i = last_run.line_i;
+    let last_glyph = last_run.glyphs.iter().map(|g| g.start).max().unwrap_or(0);
+    let ellipsis_width = {
+        let attrs = buffer.lines[line_i].attrs_list().get_span(last_glyph);
+        let mut probe = Buffer::new(font_system, buffer.metrics());
+        probe.set_size(font_system, None, None);
+        probe.set_text(font_system, "\u{2026}", attrs, Shaping::Advanced);
+        probe.shape_until_scroll(font_system, false);
+        probe.layout_runs().next().map_or(0.0, |run| run.line_w)
+    };
+
+    // Glyphs of the last run that leave room for the ellipsis. Positions are
+    // measured from the line's start edge, so alignment and direction do not
+    // matter.
+    let room = buffer.size().0.unwrap_or(f32::INFINITY) - ellipsis_width;
+    let line_start = last_run.glyphs.iter().map(|g| g.x).fold(f32::INFINITY, f32::min);
+    let line_end = last_run.glyphs.iter().map(|g| g.x + g.w).fold(f32::NEG_INFINITY, f32::max);
+    let mut kept: Vec<(usize, usize)> = last_run
+        .glyphs
+        .iter()
+        .filter(|g| {
+            let extent = if last_run.rtl { line_end - g.x } else { g.x + g.w - line_start };
+            extent <= room
+        })
+        .map(|g| (g.start, g.end))
+        .collect();
+    kept.sort_unstable();
+    kept.dedup();
+    let start = last_run.glyphs.iter().map(|g| g.start).min().unwrap_or(0);
+
+    let full = std::mem::take(&mut buffer.lines);
+    let source = &full[line_i];
+    loop {
+        // Glyphs are kept as a logical prefix of the line, ending at the
+        // first glyph that does not fit.
+        let end = kept
+            .iter()
+            .scan(start, |expected, &(s, e)| (s == *expected).then(|| { *expected = e; e }))
+            .last()
+            .unwrap_or(start);
+        let cut = start + source.text()[start..end].trim_end().len();
+        let mut attrs_list = source.attrs_list().clone();
+        attrs_list.split_off(cut);
+        let ellipsis_attrs = AttrsOwned::new(attrs_list.get_span(cut.saturating_sub(1)));
+        attrs_list.add_span(cut..cut + '\u{2026}'.len_utf8(), ellipsis_attrs.as_attrs());
+        let mut line = BufferLine::new(
+            format!("{}\u{2026}", &source.text()[..cut]),
+            source.ending(),
+            attrs_list,
+            Shaping::Advanced,
+        );
+        line.set_align(source.align());
+        buffer.lines = full[..line_i].to_vec();
+        buffer.lines.push(line);
+        buffer.set_redraw(true);
+        buffer.shape_until_scroll(font_system, false);
+        if buffer.layout_runs().count() <= max_lines || end == start {
+            break;
+        }
+        kept.retain(|&(_, e)| e < end);
+    }
+    Some(full)
+}
+
 /// Iterative DOM walk that builds Taffy nodes bottom-up.
 ///
 /// Uses a two-phase approach:
@@ -239,16 +871,21 @@ fn build_taffy_node(
     root_id: crate::dom::NodeId,
     vw: f32,
     vh: f32,
-    root_font_size: f32,
-    buffer_cache: &HashMap<crate::dom::NodeId, Buffer>,
+    buffer_cache: &mut HashMap<crate::dom::NodeId, Buffer>,
+    metrics: Option<&dyn crate::render::RendererBackend>,
     scratchpad: &mut Vec<taffy::NodeId>,
 ) -> taffy::NodeId {
+    let root_font_size = document.rem_base;
     // Phase 1: Collect DOM nodes in post-order (children before parents).
     // Stack entries: (node_id, visited). When visited=false, we push the node
     // again with visited=true, then push its children with visited=false.
     // When visited=true, we add the node to the post-order list.
     let mut dfs_stack = vec![(root_id, false)];
     let mut post_order = Vec::new();
+    // Set when a node joins or leaves the Taffy tree without the DOM
+    // changing (whitespace changing significance), so child lists are
+    // rewritten as if `document.dirty` were set.
+    let mut relink = false;
 
     while let Some((nid, visited)) = dfs_stack.pop() {
         if visited {
@@ -260,15 +897,20 @@ fn build_taffy_node(
 
         // Push children in reverse order so first child ends up on top
         let is_text = matches!(document.nodes.get(nid), Some(crate::dom::Node::Text(_)));
-        if !is_text {
-            let mut children = Vec::new();
-            let mut child_id = document.first_child_of(nid);
-            while let Some(c) = child_id {
-                children.push(c);
-                child_id = document.next_sibling_of(c);
-            }
+        if !is_text && !contents_hidden(document, nid) {
+            let children: Vec<_> = document.layout_children(nid).collect();
             for c in children.into_iter().rev() {
-                dfs_stack.push((c, false));
+                if !is_collapsible_whitespace(document, c) {
+                    dfs_stack.push((c, false));
+                } else if let Some(crate::dom::Node::Text(d)) = document.nodes.get_mut(c)
+                    && let Some(t) = d.taffy_node.take()
+                {
+                    // Whitespace that stopped counting (a neighbour became
+                    // block-level) leaves the tree; `relink` below rebuilds
+                    // the parent's child list.
+                    let _ = document.taffy_tree.remove(t);
+                    relink = true;
+                }
             }
         }
     }
@@ -277,8 +919,25 @@ fn build_taffy_node(
     // their Taffy nodes already created and stored on the arena node.
     // We use a secondary scratchpad to collect child Taffy node IDs for set_children.
     let mut child_taffy_buf: Vec<taffy::NodeId> = Vec::new();
+    // Each collected child's `order`, parallel to `child_taffy_buf`.
+    let mut child_order_buf: Vec<i32> = Vec::new();
 
     for node_id in post_order {
+        // `display: contents` elements get no box; their children were
+        // collected into the nearest ancestor's by `flow_children`.
+        if document.is_display_contents(node_id) {
+            for anonymous in document.anonymous_boxes.remove(&node_id).unwrap_or_default() {
+                let _ = document.taffy_tree.remove(anonymous);
+            }
+            if let Some(crate::dom::Node::Element(d)) = document.nodes.get_mut(node_id)
+                && let Some(t) = d.taffy_node.take()
+            {
+                let _ = document.taffy_tree.remove(t);
+                relink = true;
+            }
+            continue;
+        }
+
         // 1. Get or create the Taffy node and determine if this is a text node.
         let (t_node, is_text, is_new_taffy_node) = {
             let entry = document.nodes.get(node_id).expect("Invalid node_id");
@@ -293,6 +952,7 @@ fn build_taffy_node(
             t
         } else {
             let t = document.taffy_tree.new_leaf(Style::DEFAULT).unwrap();
+            relink = true;
             if let Some(node) = document.nodes.get_mut(node_id) {
                 match node {
                     crate::dom::Node::Element(d) => d.taffy_node = Some(t),
@@ -308,13 +968,16 @@ fn build_taffy_node(
         // the full ComputedStyle.  We branch early so text nodes skip the 36-field
         // style resolution that doesn't apply to leaf measure nodes.
         let font_size;
+        let line_height;
         let mut style = Style::default();
     
         if is_text {
-            // Text nodes: only need font_size for the measure closure context.
-            font_size = match document.nodes.get(node_id) {
-                Some(crate::dom::Node::Text(d)) => d.computed.font_size,
-                _ => 16.0,
+            // Text nodes: only need font metrics for the measure closure context.
+            (font_size, line_height) = match document.nodes.get(node_id) {
+                Some(crate::dom::Node::Text(d)) => {
+                    (d.computed.font_size, resolved_line_height(d.computed.line_height, d.computed.font_size))
+                }
+                _ => (16.0, resolved_line_height(crate::dom::LineHeight::Normal, 16.0)),
             };
             // Text nodes are leaf measure nodes — Style::DEFAULT is sufficient.
         } else {
@@ -325,6 +988,7 @@ fn build_taffy_node(
             };
     
             font_size = computed.font_size;
+            line_height = resolved_line_height(computed.line_height, font_size);
 
         style.display = match computed.display {
             crate::dom::DisplayKeyword::Flex => taffy::style::Display::Flex,
@@ -332,6 +996,16 @@ fn build_taffy_node(
             crate::dom::DisplayKeyword::None => taffy::style::Display::None,
             _ => taffy::style::Display::Block,
         };
+        // `visibility: collapse` takes a flex item out of layout; anywhere
+        // else it is painted and hit-tested like `hidden`.
+        if computed.visibility == crate::dom::VisibilityKeyword::Collapse
+            && matches!(
+                document.box_parent(node_id).and_then(|p| document.nodes.get(p)),
+                Some(crate::dom::Node::Element(parent)) if parent.computed.display == crate::dom::DisplayKeyword::Flex
+            )
+        {
+            style.display = taffy::style::Display::None;
+        }
         style.flex_direction = match computed.flex_direction {
             crate::dom::FlexDirectionKeyword::Column => taffy::style::FlexDirection::Column,
             _ => taffy::style::FlexDirection::Row,
@@ -351,6 +1025,34 @@ fn build_taffy_node(
             crate::dom::JustifyContentKeyword::SpaceEvenly => taffy::style::JustifyContent::SpaceEvenly,
             _ => taffy::style::JustifyContent::FlexStart,
         });
+        style.align_content = computed.align_content.as_ref().map(|k| match k {
+            crate::dom::JustifyContentKeyword::FlexEnd => taffy::style::AlignContent::FlexEnd,
+            crate::dom::JustifyContentKeyword::Center => taffy::style::AlignContent::Center,
+            crate::dom::JustifyContentKeyword::SpaceBetween => taffy::style::AlignContent::SpaceBetween,
+            crate::dom::JustifyContentKeyword::SpaceAround => taffy::style::AlignContent::SpaceAround,
+            crate::dom::JustifyContentKeyword::SpaceEvenly => taffy::style::AlignContent::SpaceEvenly,
+            crate::dom::JustifyContentKeyword::FlexStart => taffy::style::AlignContent::FlexStart,
+        });
+        style.justify_items = computed.justify_items.as_ref().map(|k| match k {
+            crate::dom::AlignItemsKeyword::FlexEnd => taffy::style::AlignItems::FlexEnd,
+            crate::dom::AlignItemsKeyword::Center => taffy::style::AlignItems::Center,
+            crate::dom::AlignItemsKeyword::Baseline => taffy::style::AlignItems::Baseline,
+            crate::dom::AlignItemsKeyword::FlexStart => taffy::style::AlignItems::FlexStart,
+            crate::dom::AlignItemsKeyword::Stretch => taffy::style::AlignItems::Stretch,
+        });
+        // Taffy has no `auto`; both `auto` and `scroll` make a scroll container.
+        let overflow = |k: crate::dom::OverflowKeyword| match k {
+            crate::dom::OverflowKeyword::Visible => taffy::style::Overflow::Visible,
+            crate::dom::OverflowKeyword::Clip => taffy::style::Overflow::Clip,
+            crate::dom::OverflowKeyword::Hidden => taffy::style::Overflow::Hidden,
+            crate::dom::OverflowKeyword::Scroll | crate::dom::OverflowKeyword::Auto => {
+                taffy::style::Overflow::Scroll
+            }
+        };
+        style.overflow = taffy::geometry::Point {
+            x: overflow(computed.overflow[0]),
+            y: overflow(computed.overflow[1]),
+        };
         style.flex_wrap = match computed.flex_wrap {
             crate::dom::FlexWrapKeyword::Wrap => taffy::style::FlexWrap::Wrap,
             crate::dom::FlexWrapKeyword::WrapReverse => taffy::style::FlexWrap::WrapReverse,
@@ -407,6 +1109,12 @@ fn build_taffy_node(
         if let Some(dim) = parse_length_percentage_auto(&computed.margin[3], vw, vh, font_size, root_font_size) {
             style.margin.left = dim;
         }
+        // Floats are items of their parent's anonymous line (see
+        // `wrap_inline_runs`); the right float nearest the end edge takes the
+        // line's free space as its left margin, pushing the group right.
+        if computed.float == crate::dom::FloatKeyword::Right && leads_right_floats(document, node_id) {
+            style.margin.left = LengthPercentageAuto::auto();
+        }
 
         if let Some(dim) = parse_length_percentage(&computed.padding[0], vw, vh, font_size, root_font_size) {
             style.padding.top = dim;
@@ -459,71 +1167,110 @@ fn build_taffy_node(
         }
 
         let mut aspect_ratio = None;
-        if let Some(crate::dom::Node::Element(d)) = document.nodes.get(node_id) {
-            if &*d.tag_name == "img" {
-                let mut img_w = None;
-                let mut img_h = None;
-                for (k, v) in &d.attributes {
-                    if k == "width" {
-                        if let Ok(w) = v.parse::<f32>() { img_w = Some(w); }
-                    } else if k == "height" {
-                        if let Ok(h) = v.parse::<f32>() { img_h = Some(h); }
-                    }
-                }
-                if let (Some(w), Some(h)) = (img_w, img_h) {
-                    if h > 0.0 {
-                        aspect_ratio = Some(w / h);
-                    }
-                    if style.size.width == Dimension::auto() {
-                        style.size.width = Dimension::length(w);
-                    }
-                    if style.size.height == Dimension::auto() {
-                        style.size.height = Dimension::length(h);
-                    }
-                }
+        if let Some(crate::dom::Node::Element(d)) = document.nodes.get(node_id)
+            && &*d.tag_name == "img"
+            && let Some((w, h)) = image_attribute_size(d)
+        {
+            if h > 0.0 {
+                aspect_ratio = Some(w / h);
+            }
+            if style.size.width == Dimension::auto() {
+                style.size.width = Dimension::length(w);
+            }
+            if style.size.height == Dimension::auto() {
+                style.size.height = Dimension::length(h);
             }
         }
         style.aspect_ratio = aspect_ratio;
+
+        // Text runs are block-level leaves here, so the text on either side of
+        // a `<br>` already lands on separate lines. The `<br>` itself only needs
+        // height when it doesn't end a line of text (`a<br><br>b`, or a leading
+        // `<br>`), where it stands for an empty line.
+        if matches!(document.nodes.get(node_id), Some(crate::dom::Node::Element(d)) if &*d.tag_name == "br") {
+            let ends_text_line = match document.prev_sibling_of(node_id).and_then(|p| document.nodes.get(p)) {
+                Some(crate::dom::Node::Text(t)) => !t.text.trim().is_empty(),
+                Some(crate::dom::Node::Element(d)) => &*d.tag_name != "br" && is_inline_level(&d.computed),
+                _ => false,
+            };
+            let height = if ends_text_line { 0.0 } else { line_height };
+            style.size.height = Dimension::length(height);
+            // Inside an inline run (a wrapping flex row) a full-width item
+            // forces whatever follows onto the next line.
+            style.size.width = Dimension::percent(1.0);
+            style.flex_shrink = 0.0;
+        }
         } // end of element else block
     
-        document.taffy_tree.set_style(t_node, style).unwrap();
+        // `set_style` dirties the node and its ancestors, so unchanged styles
+        // are left alone to keep Taffy's caches.
+        if !document.taffy_tree.style(t_node).is_ok_and(|current| *current == style) {
+            document.taffy_tree.set_style(t_node, style).unwrap();
+        }
     
         // is_text specific shaping:
         if is_text {
             // Read AND clear layout_dirty here (deferred from prepare_text_buffers).
             // This ensures intrinsic widths are recalculated for dirty text nodes.
-            let needs_measure = is_new_taffy_node || match document.nodes.get_mut(node_id) {
-                Some(crate::dom::Node::Text(t)) => {
-                    let dirty = t.layout_dirty;
-                    t.layout_dirty = false;
-                    dirty
-                }
+            // It is cleared for new Taffy nodes too, or the next pass would
+            // rebuild their buffers again.
+            let was_dirty = match document.nodes.get_mut(node_id) {
+                Some(crate::dom::Node::Text(t)) => std::mem::replace(&mut t.layout_dirty, false),
                 _ => false,
             };
+            let needs_measure = is_new_taffy_node || was_dirty;
 
             if needs_measure {
+                let max_lines = match document.nodes.get(node_id) {
+                    Some(crate::dom::Node::Text(d)) => d.computed.line_clamp.map(|n| n as usize),
+                    _ => None,
+                };
                 let mut max_intrinsic_width: f32 = 0.0;
                 let mut min_intrinsic_width: f32 = 0.0;
+                let mut unspaced_width: f32 = 0.0;
+                let mut measured_line_height = None;
 
+                if let Some(buffer) = buffer_cache.get_mut(&node_id) {
+                    unclamp(buffer, &mut document.clamped_text, node_id);
+                }
                 if let Some(buffer) = buffer_cache.get(&node_id) {
                     if let Some(crate::dom::Node::Text(text_node)) = document.nodes.get(node_id) {
                         for run in buffer.layout_runs() {
-                            max_intrinsic_width = max_intrinsic_width.max(run.line_w);
-
+                            // The backend's width for this line, with glyph
+                            // advances scaled to add up to it.
+                            let (line_w, scale) = match metrics.and_then(|m| m.measure_text(run.text, font_size)) {
+                                Some((w, h)) => {
+                                    measured_line_height = Some(h);
+                                    (w, if run.line_w > 0.0 { w / run.line_w } else { 1.0 })
+                                }
+                                None => (run.line_w, 1.0),
+                            };
                             let mut current_word_width = 0.0;
+                            let mut spacing = 0.0;
                             for glyph in run.glyphs {
-                                let is_whitespace = text_node.text.get(glyph.start..glyph.end)
+                                let extra = glyph_spacing(&text_node.computed, run.text, glyph);
+                                spacing += extra;
+                                let is_whitespace = run.text.get(glyph.start..glyph.end)
                                     .map(|s| s.chars().any(|c| c.is_whitespace()))
                                     .unwrap_or(false);
                                 if is_whitespace {
                                     min_intrinsic_width = min_intrinsic_width.max(current_word_width);
                                     current_word_width = 0.0;
                                 } else {
-                                    current_word_width += glyph.w;
+                                    current_word_width += glyph.w * scale + extra;
                                 }
                             }
+                            max_intrinsic_width = max_intrinsic_width.max(line_w + spacing);
+                            unspaced_width = unspaced_width.max(run.line_w);
                             min_intrinsic_width = min_intrinsic_width.max(current_word_width);
                         }
+                        // Unwrappable text cannot shrink below its full line width.
+                        if wrap_mode(&text_node.computed) == Wrap::None {
+                            min_intrinsic_width = max_intrinsic_width;
+                        }
+                        if text_node.computed.line_height != crate::dom::LineHeight::Normal {
+                            measured_line_height = None;
+                        }
                     }
                 }
                 document
@@ -531,10 +1278,13 @@ fn build_taffy_node(
                     .set_node_context(t_node, Some(crate::dom::TextMeasureContext {
                         node_id,
                         font_size,
+                        line_height: measured_line_height.unwrap_or(line_height),
                         max_intrinsic_width,
                         min_intrinsic_width,
                         last_measure_width: None,
                         last_line_count: 0.0,
+                        max_lines,
+                        wrap_scale: if max_intrinsic_width > 0.0 { unspaced_width / max_intrinsic_width } else { 1.0 },
                     }))
                     .unwrap();
             }
@@ -543,27 +1293,49 @@ fn build_taffy_node(
         if !is_text {
             // Collect child Taffy node IDs — children are already processed (post-order).
             child_taffy_buf.clear();
-            let mut child_id = document.first_child_of(node_id);
-            while let Some(c) = child_id {
-                let child_taffy = match document.nodes.get(c) {
-                    Some(crate::dom::Node::Element(d)) => d.taffy_node,
-                    Some(crate::dom::Node::Text(d)) => d.taffy_node,
-                    Some(crate::dom::Node::Root(d)) => d.taffy_node,
-                    None => None,
+            child_order_buf.clear();
+            let children = if contents_hidden(document, node_id) { None } else { Some(flow_children(document, node_id)) };
+            for c in children.into_iter().flatten() {
+                let (child_taffy, order) = match document.nodes.get(c) {
+                    Some(crate::dom::Node::Element(d)) => (d.taffy_node, d.computed.order),
+                    Some(crate::dom::Node::Text(d)) => (d.taffy_node, 0),
+                    Some(crate::dom::Node::Root(d)) => (d.taffy_node, 0),
+                    None => (None, 0),
                 };
                 if let Some(ct) = child_taffy {
                     child_taffy_buf.push(ct);
+                    child_order_buf.push(order);
                 }
-                child_id = document.next_sibling_of(c);
             }
 
             // Only update children if the node is new or the document is structurally dirty.
             // This avoids violent allocator thrashing inside Taffy's edge arrays on every frame.
-            if is_new_taffy_node || document.dirty {
-                document
-                    .taffy_tree
-                    .set_children(t_node, &child_taffy_buf)
-                    .unwrap();
+            if is_new_taffy_node || document.dirty || relink {
+                for anonymous in document.anonymous_boxes.remove(&node_id).unwrap_or_default() {
+                    let _ = document.taffy_tree.remove(anonymous);
+                }
+                let children = if establishes_inline_runs(document, node_id) {
+                    wrap_inline_runs(document, node_id, &child_taffy_buf)
+                } else if child_order_buf.iter().any(|&order| order != 0) {
+                    // Flex and grid items are placed in `order`-modified
+                    // document order; the sort is stable, so ties keep theirs.
+                    let mut items: Vec<_> = child_order_buf.iter().copied().zip(child_taffy_buf.iter().copied()).collect();
+                    items.sort_by_key(|&(order, _)| order);
+                    items.into_iter().map(|(_, child)| child).collect()
+                } else {
+                    child_taffy_buf.clone()
+                };
+                // Rebuilt anonymous boxes always differ, but a list that
+                // merely survived a structural change elsewhere is kept.
+                if document.taffy_tree.children(t_node).ok().as_deref() != Some(&children[..]) {
+                    document.taffy_tree.set_children(t_node, &children).unwrap();
+                    // `set_children` orphans the old children, including any
+                    // that moved into a new anonymous row; re-parent them.
+                    for &row in document.anonymous_boxes.get(&node_id).into_iter().flatten() {
+                        let items = document.taffy_tree.children(row).unwrap();
+                        document.taffy_tree.set_children(row, &items).unwrap();
+                    }
+                }
             }
         }
 
@@ -574,6 +1346,411 @@ fn build_taffy_node(
     *scratchpad.last().unwrap()
 }
 
+/// An `<img>`'s intrinsic size from its HTML `width`/`height` attributes, if
+/// both parse as numbers.
+pub(crate) fn image_attribute_size(data: &crate::dom::ElementData) -> Option<(f32, f32)> {
+    let mut img_w = None;
+    let mut img_h = None;
+    for (k, v) in &data.attributes {
+        if k == "width" {
+            if let Ok(w) = v.parse::<f32>() { img_w = Some(w); }
+        } else if k == "height" {
+            if let Ok(h) = v.parse::<f32>() { img_h = Some(h); }
+        }
+    }
+    img_w.zip(img_h)
+}
+
+fn is_inline_level(computed: &crate::dom::ComputedStyle) -> bool {
+    matches!(
+        computed.display,
+        crate::dom::DisplayKeyword::Inline | crate::dom::DisplayKeyword::InlineBlock
+    )
+}
+
+/// The effective `float`: absolutely positioned boxes do not float.
+fn float_of(computed: &crate::dom::ComputedStyle) -> crate::dom::FloatKeyword {
+    if computed.position == crate::dom::PositionKeyword::Absolute {
+        crate::dom::FloatKeyword::None
+    } else {
+        computed.float
+    }
+}
+
+/// True if the element goes into an anonymous line with its inline siblings.
+fn joins_inline_run(computed: &crate::dom::ComputedStyle) -> bool {
+    is_inline_level(computed) || float_of(computed) != crate::dom::FloatKeyword::None
+}
+
+/// True if `node_id` floats right in block flow and no later sibling in the
+/// same inline run does, making it the first of the right floats on its line.
+fn leads_right_floats(document: &crate::dom::Document, node_id: crate::dom::NodeId) -> bool {
+    match document.nodes.get(node_id) {
+        Some(crate::dom::Node::Element(d)) if float_of(&d.computed) == crate::dom::FloatKeyword::Right => {}
+        _ => return false,
+    }
+    if !document.box_parent(node_id).is_some_and(|p| establishes_inline_runs(document, p)) {
+        return false;
+    }
+    let mut next = document.next_sibling_of(node_id);
+    while let Some(id) = next {
+        if let Some(crate::dom::Node::Element(d)) = document.nodes.get(id) {
+            if float_of(&d.computed) == crate::dom::FloatKeyword::Right {
+                return false;
+            }
+            if !joins_inline_run(&d.computed) {
+                break;
+            }
+        }
+        next = document.next_sibling_of(id);
+    }
+    true
+}
+
+/// True if `node_id` lays its children out in block flow, where runs of
+/// inline-level children need an anonymous line container. Flex and grid
+/// containers treat every child as an item instead.
+fn establishes_inline_runs(document: &crate::dom::Document, node_id: crate::dom::NodeId) -> bool {
+    match document.nodes.get(node_id) {
+        Some(crate::dom::Node::Root(_)) => true,
+        Some(crate::dom::Node::Element(d)) => !matches!(
+            d.computed.display,
+            crate::dom::DisplayKeyword::Flex | crate::dom::DisplayKeyword::Grid
+        ),
+        _ => false,
+    }
+}
+
+/// Groups consecutive inline-level and floated children into anonymous
+/// wrapping flex rows and returns the parent's new Taffy child list.
+/// Within a row, left floats move to the start and right floats to the end
+/// in reverse order, so the first right float sits at the end edge.
+/// `child_taffy` is parallel to `flow_children(document, node_id)`.
+fn wrap_inline_runs(
+    document: &mut crate::dom::Document,
+    node_id: crate::dom::NodeId,
+    child_taffy: &[taffy::NodeId],
+) -> Vec<taffy::NodeId> {
+    let inline: Vec<bool> = flow_children(document, node_id)
+        .map(|c| match document.nodes.get(c) {
+            Some(crate::dom::Node::Text(_)) => true,
+            Some(crate::dom::Node::Element(d)) => joins_inline_run(&d.computed),
+            _ => false,
+        })
+        .collect();
+    let floats: Vec<crate::dom::FloatKeyword> = flow_children(document, node_id)
+        .map(|c| match document.nodes.get(c) {
+            Some(crate::dom::Node::Element(d)) => float_of(&d.computed),
+            _ => crate::dom::FloatKeyword::None,
+        })
+        .collect();
+    let is_text: Vec<bool> = flow_children(document, node_id)
+        .map(|c| matches!(document.nodes.get(c), Some(crate::dom::Node::Text(_))))
+        .collect();
+
+    let anonymous_style = Style {
+        display: taffy::style::Display::Flex,
+        flex_direction: taffy::style::FlexDirection::Row,
+        flex_wrap: taffy::style::FlexWrap::Wrap,
+        align_items: Some(taffy::style::AlignItems::FlexStart),
+        ..Style::default()
+    };
+
+    let mut children = Vec::with_capacity(child_taffy.len());
+    let mut anonymous = Vec::new();
+    let mut i = 0;
+    while i < child_taffy.len() {
+        if !inline[i] {
+            children.push(child_taffy[i]);
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < child_taffy.len() && inline[i] {
+            i += 1;
+        }
+        // A lone text node already fills the line as a block leaf.
+        if i - start == 1 && is_text[start] {
+            children.push(child_taffy[start]);
+            continue;
+        }
+        let run = start..i;
+        let lefts = run.clone().filter(|&j| floats[j] == crate::dom::FloatKeyword::Left);
+        let flow = run.clone().filter(|&j| floats[j] == crate::dom::FloatKeyword::None);
+        let rights = run.rev().filter(|&j| floats[j] == crate::dom::FloatKeyword::Right);
+        let items: Vec<_> = lefts.chain(flow).chain(rights).map(|j| child_taffy[j]).collect();
+        let row = document
+            .taffy_tree
+            .new_with_children(anonymous_style.clone(), &items)
+            .unwrap();
+        anonymous.push(row);
+        children.push(row);
+    }
+    if !anonymous.is_empty() {
+        document.anonymous_boxes.insert(node_id, anonymous);
+    }
+    children
+}
+
+/// Offset of `child`'s Taffy location relative to its DOM parent's box. This is
+/// zero unless `child` sits inside one of the parent's anonymous inline rows,
+/// in which case it is that row's location.
+pub fn anonymous_box_offset<T>(
+    tree: &taffy::TaffyTree<T>,
+    parent: taffy::NodeId,
+    child: taffy::NodeId,
+) -> (f32, f32) {
+    match tree.parent(child) {
+        Some(p) if p != parent => tree
+            .layout(p)
+            .map(|l| (l.location.x, l.location.y))
+            .unwrap_or((0.0, 0.0)),
+        _ => (0.0, 0.0),
+    }
+}
+
+/// A node's border box in absolute document coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AbsoluteRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Border box of `node_id` from the document's last [`compute_layout`], found
+/// by summing Taffy locations (and anonymous row offsets) up to the root.
+/// Returns `None` if the node has no layout, e.g. it has `display: contents`
+/// or was created or detached since layout last ran.
+pub fn absolute_rect(document: &crate::dom::Document, node_id: crate::dom::NodeId) -> Option<AbsoluteRect> {
+    let taffy_of = |id| match document.nodes.get(id)? {
+        crate::dom::Node::Element(d) => d.taffy_node,
+        crate::dom::Node::Text(d) => d.taffy_node,
+        crate::dom::Node::Root(d) => d.taffy_node,
+    };
+    let tree = &document.taffy_tree;
+    let (mut node, mut taffy_node) = (node_id, taffy_of(node_id)?);
+    let size = tree.layout(taffy_node).ok()?.size;
+    let (mut x, mut y) = (0.0, 0.0);
+    loop {
+        let location = tree.layout(taffy_node).ok()?.location;
+        x += location.x;
+        y += location.y;
+        let Some(parent) = document.box_parent(node) else { break };
+        let parent_taffy = taffy_of(parent)?;
+        // Stale nodes (e.g. under a `content-visibility: hidden` ancestor)
+        // are no longer attached to their parent's box.
+        let attached = tree.parent(taffy_node).is_some_and(|p| p == parent_taffy || tree.parent(p) == Some(parent_taffy));
+        if !attached {
+            return None;
+        }
+        let (ax, ay) = anonymous_box_offset(tree, parent_taffy, taffy_node);
+        x += ax;
+        y += ay;
+        (node, taffy_node) = (parent, parent_taffy);
+    }
+    (node == document.root_id).then_some(AbsoluteRect { x, y, width: size.width, height: size.height })
+}
+
+/// Where a text caret before character `char_index` of text node `node_id`
+/// is drawn: a 1px-wide rect spanning its line box, in absolute document
+/// coordinates like [`absolute_rect`]. An index at or past the end of a line
+/// puts the caret after its last glyph. Indices count the characters of the
+/// shaped text, after `white-space` collapsing and `text-transform`, with
+/// one for each line break between paragraphs. Returns `None` if the node is
+/// not laid out text; the host draws the caret in the node's `caret_color`.
+pub fn caret_rect(
+    document: &crate::dom::Document,
+    buffer_cache: &HashMap<crate::dom::NodeId, Buffer>,
+    node_id: crate::dom::NodeId,
+    char_index: usize,
+) -> Option<AbsoluteRect> {
+    let Some(crate::dom::Node::Text(data)) = document.nodes.get(node_id) else {
+        return None;
+    };
+    let buffer = buffer_cache.get(&node_id)?;
+    let origin = absolute_rect(document, node_id)?;
+
+    // Find the paragraph holding the index and the byte offset within it.
+    let (mut line_i, mut byte) = (buffer.lines.len().saturating_sub(1), usize::MAX);
+    let mut remaining = char_index;
+    for (i, line) in buffer.lines.iter().enumerate() {
+        let count = line.text().chars().count();
+        if remaining <= count {
+            line_i = i;
+            byte = line.text().char_indices().nth(remaining).map_or(line.text().len(), |(b, _)| b);
+            break;
+        }
+        remaining -= count + 1;
+    }
+
+    let mut caret = None;
+    for run in buffer.layout_runs().filter(|run| run.line_i == line_i) {
+        let spaced = spaced_glyphs(&run, &data.computed);
+        let glyphs = spaced.as_deref().unwrap_or(run.glyphs);
+        let hit = glyphs.iter().find(|g| g.start <= byte && byte < g.end);
+        let x = match hit {
+            Some(g) if g.level.is_rtl() => g.x + g.w,
+            Some(g) => g.x,
+            // Past every glyph so far: after the end of this line, unless a
+            // later line of the paragraph holds the byte.
+            None => glyphs.last().map_or(0.0, |g| if g.level.is_rtl() { g.x } else { g.x + g.w }),
+        };
+        caret = Some(AbsoluteRect { x: origin.x + x, y: origin.y + run.line_top, width: 1.0, height: run.line_height });
+        if hit.is_some() {
+            break;
+        }
+    }
+    caret
+}
+
+/// Where `scroll_offset_to_reveal` puts the target along an axis, as the
+/// `block` / `inline` options of `scrollIntoView`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollAlign {
+    Start,
+    Center,
+    End,
+    /// Leave the scroll position alone if the target is already fully
+    /// visible, otherwise align whichever edge is closer.
+    Nearest,
+}
+
+/// Viewport scroll position that brings `target` into view: `block` places
+/// it vertically and the horizontal position is [`ScrollAlign::Nearest`].
+/// `viewport` is the visible area in document coordinates, i.e. the current
+/// scroll position and the viewport size. The result is clamped to the
+/// document's scrollable range. Returns `None` when `target` has no layout,
+/// as for [`absolute_rect`]. Scroll containers between the root and the
+/// target are not scrolled.
+pub fn scroll_offset_to_reveal(
+    document: &crate::dom::Document,
+    target: crate::dom::NodeId,
+    viewport: AbsoluteRect,
+    block: ScrollAlign,
+) -> Option<(f32, f32)> {
+    fn align(start: f32, len: f32, view_start: f32, view_len: f32, mode: ScrollAlign, max: f32) -> f32 {
+        let offset = match mode {
+            ScrollAlign::Start => start,
+            ScrollAlign::Center => start + (len - view_len) / 2.0,
+            ScrollAlign::End => start + len - view_len,
+            ScrollAlign::Nearest if start >= view_start && start + len <= view_start + view_len => view_start,
+            ScrollAlign::Nearest if start < view_start || len > view_len => start,
+            ScrollAlign::Nearest => start + len - view_len,
+        };
+        offset.min(max).max(0.0)
+    }
+
+    let rect = absolute_rect(document, target)?;
+    let root = match document.nodes.get(document.root_id)? {
+        crate::dom::Node::Root(d) => d.taffy_node,
+        _ => None,
+    };
+    let page = root.and_then(|t| document.taffy_tree.layout(t).ok());
+    let (max_x, max_y) = page.map_or((0.0, 0.0), |l| {
+        (l.size.width + l.scroll_width() - viewport.width, l.size.height + l.scroll_height() - viewport.height)
+    });
+    Some((
+        align(rect.x, rect.width, viewport.x, viewport.width, ScrollAlign::Nearest, max_x),
+        align(rect.y, rect.height, viewport.y, viewport.height, block, max_y),
+    ))
+}
+
+/// Resolves a `min()`/`max()`/`clamp()` value to px. Percentages resolve
+/// against `basis`; without one, a value containing them yields `None`.
+fn resolve_math(
+    function: crate::dom::MathFunction,
+    args: &[crate::dom::StyleValue],
+    basis: Option<f32>,
+    vw: f32,
+    vh: f32,
+    font_size: f32,
+    root_font_size: f32,
+) -> Option<f32> {
+    let mut px = args.iter().map(|arg| match arg {
+        crate::dom::StyleValue::LengthPx(n) => Some(*n),
+        crate::dom::StyleValue::Percent(p) => basis.map(|b| p / 100.0 * b),
+        crate::dom::StyleValue::ViewportWidth(n) => Some(n / 100.0 * vw),
+        crate::dom::StyleValue::ViewportHeight(n) => Some(n / 100.0 * vh),
+        crate::dom::StyleValue::Em(n) => Some(n * font_size),
+        crate::dom::StyleValue::Rem(n) => Some(n * root_font_size),
+        crate::dom::StyleValue::Math(f, a) => resolve_math(*f, a, basis, vw, vh, font_size, root_font_size),
+        _ => None,
+    });
+    match function {
+        crate::dom::MathFunction::Min => px.try_fold(f32::INFINITY, |acc, v| Some(acc.min(v?))),
+        crate::dom::MathFunction::Max => px.try_fold(f32::NEG_INFINITY, |acc, v| Some(acc.max(v?))),
+        // `clamp(MIN, VAL, MAX)` is `max(MIN, min(VAL, MAX))`: MIN wins a conflict.
+        crate::dom::MathFunction::Clamp => {
+            let (min, val, max) = (px.next()??, px.next()??, px.next()??);
+            Some(val.min(max).max(min))
+        }
+    }
+}
+
+/// True if a comparison function has a percentage argument at any depth.
+fn math_has_percent(args: &[crate::dom::StyleValue]) -> bool {
+    args.iter().any(|arg| match arg {
+        crate::dom::StyleValue::Percent(_) => true,
+        crate::dom::StyleValue::Math(_, a) => math_has_percent(a),
+        _ => false,
+    })
+}
+
+/// After a solve, re-resolves width/height (and their min/max) declared as
+/// `min()`/`max()`/`clamp()` with percentages against the parent's content
+/// box, which Taffy cannot do itself. Returns true if any style changed and
+/// another pass is needed.
+fn resolve_math_sizes(document: &mut crate::dom::Document, vw: f32, vh: f32) -> bool {
+    let root_font_size = document.rem_base;
+    let mut updates = Vec::new();
+    for (_, node) in document.nodes.iter() {
+        let crate::dom::Node::Element(d) = node else { continue };
+        let c = &d.computed;
+        let sizes = [&c.width, &c.height, &c.min_width, &c.min_height, &c.max_width, &c.max_height];
+        if !sizes.iter().any(|v| matches!(v, crate::dom::StyleValue::Math(_, a) if math_has_percent(a))) {
+            continue;
+        }
+        let tree = &document.taffy_tree;
+        let Some(t_node) = d.taffy_node else { continue };
+        let (Some(parent), Ok(mut style)) = (tree.parent(t_node), tree.style(t_node).cloned()) else {
+            continue;
+        };
+        let Ok(p) = tree.layout(parent) else { continue };
+        let basis_w = p.size.width - p.padding.left - p.padding.right - p.border.left - p.border.right;
+        let basis_h = p.size.height - p.padding.top - p.padding.bottom - p.border.top - p.border.bottom;
+
+        let targets = [
+            &mut style.size.width,
+            &mut style.size.height,
+            &mut style.min_size.width,
+            &mut style.min_size.height,
+            &mut style.max_size.width,
+            &mut style.max_size.height,
+        ];
+        let mut changed = false;
+        for (i, (value, target)) in sizes.into_iter().zip(targets).enumerate() {
+            let crate::dom::StyleValue::Math(f, args) = value else { continue };
+            let basis = if i % 2 == 0 { basis_w } else { basis_h };
+            if let Some(px) = resolve_math(*f, args, Some(basis.max(0.0)), vw, vh, c.font_size, root_font_size) {
+                let dim = Dimension::length(px);
+                if *target != dim {
+                    *target = dim;
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            updates.push((t_node, style));
+        }
+    }
+    let changed = !updates.is_empty();
+    for (t_node, style) in updates {
+        document.taffy_tree.set_style(t_node, style).unwrap();
+    }
+    changed
+}
+
 #[inline]
 fn parse_dimension(
     val: &crate::dom::StyleValue,
@@ -585,11 +1762,16 @@ fn parse_dimension(
     match val {
         crate::dom::StyleValue::Auto => Some(Dimension::auto()),
         crate::dom::StyleValue::LengthPx(num) => Some(Dimension::length(*num)),
+        // A unitless zero is a valid length (`inset: 0`, `margin: 0`).
+        crate::dom::StyleValue::Number(num) if *num == 0.0 => Some(Dimension::length(0.0)),
         crate::dom::StyleValue::Percent(p) => Some(Dimension::percent(*p / 100.0)),
         crate::dom::StyleValue::ViewportWidth(num) => Some(Dimension::length((num / 100.0) * vw)),
         crate::dom::StyleValue::ViewportHeight(num) => Some(Dimension::length((num / 100.0) * vh)),
         crate::dom::StyleValue::Em(num) => Some(Dimension::length(num * font_size)),
         crate::dom::StyleValue::Rem(num) => Some(Dimension::length(num * root_font_size)),
+        crate::dom::StyleValue::Math(f, args) => {
+            resolve_math(*f, args, None, vw, vh, font_size, root_font_size).map(Dimension::length)
+        }
         _ => None,
     }
 }
@@ -607,6 +1789,9 @@ fn parse_length_percentage_auto(
         crate::dom::StyleValue::LengthPx(num) => {
             Some(taffy::style::LengthPercentageAuto::length(*num))
         }
+        crate::dom::StyleValue::Number(num) if *num == 0.0 => {
+            Some(taffy::style::LengthPercentageAuto::length(0.0))
+        }
         crate::dom::StyleValue::Percent(p) => {
             Some(taffy::style::LengthPercentageAuto::percent(*p / 100.0))
         }
@@ -622,6 +1807,8 @@ fn parse_length_percentage_auto(
         crate::dom::StyleValue::Rem(num) => {
             Some(taffy::style::LengthPercentageAuto::length(num * root_font_size))
         }
+        crate::dom::StyleValue::Math(f, args) => resolve_math(*f, args, None, vw, vh, font_size, root_font_size)
+            .map(taffy::style::LengthPercentageAuto::length),
         _ => None,
     }
 }
@@ -636,6 +1823,7 @@ fn parse_length_percentage(
 ) -> Option<taffy::style::LengthPercentage> {
     match val {
         crate::dom::StyleValue::LengthPx(num) => Some(taffy::style::LengthPercentage::length(*num)),
+        crate::dom::StyleValue::Number(num) if *num == 0.0 => Some(taffy::style::LengthPercentage::length(0.0)),
         crate::dom::StyleValue::Percent(p) => {
             Some(taffy::style::LengthPercentage::percent(*p / 100.0))
         }
@@ -651,6 +1839,8 @@ fn parse_length_percentage(
         crate::dom::StyleValue::Rem(num) => {
             Some(taffy::style::LengthPercentage::length(num * root_font_size))
         }
+        crate::dom::StyleValue::Math(f, args) => resolve_math(*f, args, None, vw, vh, font_size, root_font_size)
+            .map(taffy::style::LengthPercentage::length),
         _ => None,
     }
 }
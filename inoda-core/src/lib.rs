@@ -189,6 +189,2307 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_gc_hook_ignores_stale_keys() {
+        let mut doc = html::parse_html("<html><body><p id=\"t\">x</p></body></html>");
+        let nid = *doc.id_map.get("t").expect("id t");
+
+        // Reuse a freed slot: the old key must not reach the new occupant.
+        let stale = doc.add_node(dom::Node::Text(dom::TextData::new("old".to_string())));
+        doc.nodes.remove(stale);
+        let reused = doc.add_node(dom::Node::Text(dom::TextData::new("new".to_string())));
+        assert_eq!(stale.into_raw_parts().0, reused.into_raw_parts().0);
+        if let Some(dom::Node::Text(t)) = doc.nodes.get_mut(reused) {
+            t.js_handles = 1;
+        }
+
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let _ = engine
+            .execute_script("var __inoda_hold = document.getElementById('t');")
+            .unwrap();
+
+        let (idx, generation) = nid.into_raw_parts();
+        let (stale_idx, stale_gen) = stale.into_raw_parts();
+        let _ = engine
+            .execute_script(&format!(
+                "document._garbageCollectNodeRaw([{}, {}]); document._garbageCollectNodeRaw([{}, {}]);",
+                idx,
+                generation + 1,
+                stale_idx,
+                stale_gen
+            ))
+            .unwrap();
+
+        let mut doc = engine.document.borrow_mut();
+        match doc.nodes.get(nid) {
+            Some(dom::Node::Element(e)) => assert_eq!(e.js_handles, 1, "wrong generation must be ignored"),
+            _ => panic!("expected element"),
+        }
+        match doc.nodes.get(reused) {
+            Some(dom::Node::Text(t)) => assert_eq!(t.js_handles, 1, "reused slot must be untouched"),
+            _ => panic!("expected text"),
+        }
+        assert!(doc.dead_nodes.is_empty());
+
+        // Double finalization: the second call is rejected and queues nothing.
+        assert!(doc.try_cleanup_node(reused));
+        assert!(!doc.try_cleanup_node(reused));
+        assert_eq!(doc.dead_nodes.len(), 1);
+        drop(doc);
+
+        // A detached element sharing an id with the mapped one is freed once
+        // JS lets go of it, and the mapped element keeps its entry.
+        let mut doc = html::parse_html("<div id='box'><p id='t'>y</p></div><p id='t'>x</p>");
+        let (mapped, wrapper) = (doc.id_map["t"], doc.id_map["box"]);
+        let twin = doc.first_child_of(wrapper).unwrap();
+        assert_ne!(twin, mapped);
+        if let Some(dom::Node::Element(d)) = doc.nodes.get_mut(twin) {
+            d.js_handles = 1;
+        }
+        doc.remove_child(doc.parent_of(wrapper).unwrap(), wrapper);
+        assert!(doc.try_cleanup_node(twin));
+        doc.collect_garbage();
+        assert!(!doc.nodes.contains(twin), "a duplicate-id element must not leak");
+        assert!(!doc.nodes.contains(wrapper));
+        assert_eq!(doc.id_map.get("t"), Some(&mapped));
+        assert!(doc.nodes.contains(mapped));
+    }
+
+    #[test]
+    fn test_ancestors_and_depth() {
+        let doc = html::parse_html("<div id='a'><section id='b'><p id='c'><span id='d'>x</span></p></section></div>");
+        let id = |k: &str| *doc.id_map.get(k).unwrap();
+        let chain: Vec<_> = doc.ancestors(id("d")).collect();
+        assert_eq!(chain.last(), Some(&doc.root_id), "chain must end at the root");
+        assert_eq!(&chain[..3], &[id("c"), id("b"), id("a")]);
+        assert_eq!(doc.depth(id("d")), chain.len());
+        assert_eq!(doc.depth(doc.root_id), 0);
+        assert_eq!(doc.ancestors(doc.root_id).count(), 0);
+    }
+
+    #[test]
+    fn test_accessibility_tree() {
+        let mut doc = html::parse_html(
+            "<head><title>Page</title></head><body><div><h2>Intro <span>text</span></h2>\
+             <button aria-label='Close'>x</button><a href='/a'>Home</a><a>plain</a></div>\
+             <p hidden>gone</p><p class='none'>gone</p><nav aria-hidden='true'>gone</nav>\
+             <span id='lbl'>Search</span><input aria-labelledby='lbl' value='cats'>\
+             <input type='submit' value='Go'><img alt=''><img alt='Logo'>\
+             <div role='presentation' aria-label='Group'><h5 aria-level='3'>Deep</h5></div></body>",
+        );
+        let sheet = css::parse_stylesheet(".none { display: none }");
+        css::compute_styles(&mut doc, &sheet);
+        let tree = doc.accessibility_tree();
+        assert_eq!((tree.role.as_str(), tree.name.as_deref()), ("document", Some("Page")));
+
+        fn flatten(node: &dom::AxNode, out: &mut Vec<(String, Option<String>)>) {
+            out.push((node.role.clone(), node.name.clone()));
+            node.children.iter().for_each(|c| flatten(c, out));
+        }
+        let mut nodes = Vec::new();
+        tree.children.iter().for_each(|c| flatten(c, &mut nodes));
+        let got: Vec<(&str, Option<&str>)> = nodes.iter().map(|(r, n)| (r.as_str(), n.as_deref())).collect();
+        assert_eq!(
+            got,
+            [
+                ("heading", Some("Intro text")),
+                ("text", Some("Intro")),
+                ("text", Some("text")),
+                ("button", Some("Close")),
+                ("text", Some("x")),
+                ("link", Some("Home")),
+                ("text", Some("Home")),
+                ("text", Some("plain")),
+                ("text", Some("Search")),
+                ("textbox", Some("Search")),
+                ("button", Some("Go")),
+                ("img", Some("Logo")),
+                ("presentation", Some("Group")),
+                ("heading", Some("Deep")),
+                ("text", Some("Deep")),
+            ]
+        );
+        assert_eq!(tree.children[0].level, Some(2));
+        let textbox = tree.children.iter().find(|n| n.role == "textbox").unwrap();
+        assert_eq!(textbox.value.as_deref(), Some("cats"));
+        let group = tree.children.iter().find(|n| n.role == "presentation").unwrap();
+        assert_eq!(group.children[0].level, Some(3), "aria-level overrides the tag");
+    }
+
+    #[test]
+    fn test_collect_ids_matching_bulk_remove() {
+        let mut doc = html::parse_html(
+            "<div id='a' class='x'><p id='b' class='x'></p><p id='keep1'></p></div>\
+             <p id='c' class='x y'></p><p id='d' class='x'></p><p id='keep2' class='xx'></p>",
+        );
+        let has_x = |_: dom::NodeId, node: &dom::Node| {
+            matches!(node, dom::Node::Element(d) if d.classes.split_whitespace().any(|c| c == "x"))
+        };
+        for (css, class) in [("p { color: red }", ""), ("p { color: blue }", "x")] {
+            let style = doc.create_element("style");
+            doc.set_attribute(style, "class", class.to_string());
+            doc.set_text_content(style, css);
+            doc.append_child(doc.root_id, style);
+        }
+        let targets = doc.collect_ids_matching(has_x);
+        assert_eq!(targets.len(), 5, "a, b (inside a), c, d and a style");
+        let detached: Vec<_> = ["keep1"].iter().map(|id| doc.id_map[*id]).collect();
+        for id in targets {
+            if let Some(parent) = doc.parent_of(id).filter(|_| doc.nodes.contains(id)) {
+                doc.remove_child(parent, id);
+            }
+        }
+
+        assert!(doc.collect_ids_matching(has_x).is_empty());
+        let remaining: Vec<_> = doc.collect_ids_matching(|_, node| matches!(node, dom::Node::Element(_)));
+        assert_eq!(remaining.len(), 2, "#keep2 and the first <style>");
+        assert_eq!(remaining[0], doc.id_map["keep2"]);
+        assert!(!doc.id_map.contains_key("keep1") && !doc.id_map.contains_key("a"));
+        assert!(!doc.nodes.contains(detached[0]), "unreferenced descendants of removed nodes are freed");
+        assert!(doc.validate().is_ok());
+
+        doc.rebuild_styles();
+        let mut doc2 = html::parse_html("<p id='p'></p>");
+        css::compute_styles(&mut doc2, &doc.stylesheet);
+        let Some(dom::Node::Element(p)) = doc2.nodes.get(doc2.id_map["p"]) else { panic!("expected element") };
+        assert_eq!(p.computed.color, (255, 0, 0, 255), "only the remaining <style> is rebuilt");
+    }
+
+    #[test]
+    fn test_raw_text_end_tag_closes_element() {
+        let doc = html::parse_html(
+            "<div id='d'><style>p { color: red }</style><p id='p'></p><script>x</script><i id='i'></i></div><b id='b'></b>",
+        );
+        let d = doc.id_map["d"];
+        assert_eq!(doc.parent_of(doc.id_map["p"]), Some(d), "</style> closes the style element");
+        assert_eq!(doc.parent_of(doc.id_map["i"]), Some(d), "</script> closes the script element");
+        assert_eq!(doc.parent_of(doc.id_map["b"]), doc.parent_of(d));
+        assert_eq!(doc.stylesheet.next_rule_index, 1, "the style text is still collected");
+        assert!(doc.validate().is_ok());
+    }
+
+    #[test]
+    fn test_is_connected() {
+        let doc = html::parse_html("<html><body></body></html>");
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let before = engine
+            .execute_script("var el = document.createElement('div'); el.isConnected")
+            .unwrap();
+        assert_eq!(before, "false");
+        let after = engine
+            .execute_script("document.appendChild(document.querySelector('body'), el); el.isConnected")
+            .unwrap();
+        assert_eq!(after, "true");
+    }
+
+    #[test]
+    fn test_document_title() {
+        let doc = html::parse_html("<html><head><title> Hi\n  there </title><title>Second</title></head><body></body></html>");
+        assert_eq!(doc.title.as_deref(), Some("Hi there"));
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        assert_eq!(engine.execute_script("document.title").unwrap(), "Hi there");
+        assert_eq!(engine.execute_script("document.title = 'New'; document.title").unwrap(), "New");
+        let doc = engine.document.borrow();
+        assert_eq!(doc.title.as_deref(), Some("New"));
+        let title = doc.first_title_element().unwrap();
+        assert_eq!(doc.text_content(title), "New", "the title element's text follows");
+
+        let doc = html::parse_html("<p>no title</p>");
+        assert_eq!(doc.title, None);
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        assert_eq!(engine.execute_script("document.title").unwrap(), "");
+        assert_eq!(engine.execute_script("document.title = 42; document.title").unwrap(), "42");
+    }
+
+    #[test]
+    fn test_viewport_meta() {
+        let doc = html::parse_html(
+            "<html><head><meta charset='utf-8'><meta name='Viewport' content='width=device-width, initial-scale=1'>\
+             <meta name='viewport' content='width=10'></head></html>",
+        );
+        let meta = doc.viewport_meta.expect("viewport meta");
+        assert_eq!(meta.width, Some(dom::ViewportLength::DeviceWidth));
+        assert_eq!(meta.initial_scale, Some(1.0));
+        assert_eq!((meta.height, meta.maximum_scale, meta.user_scalable), (None, None, None));
+
+        let meta = html::parse_viewport_content("width = 320; initial-scale=0.01 maximum-scale=2.5,user-scalable=no, bogus=1, height=x");
+        assert_eq!(meta.width, Some(dom::ViewportLength::Px(320.0)));
+        assert_eq!(meta.initial_scale, Some(0.1), "clamped to the minimum scale");
+        assert_eq!(meta.maximum_scale, Some(2.5));
+        assert_eq!(meta.user_scalable, Some(false));
+        assert_eq!(meta.height, None, "unparseable values are dropped");
+        assert_eq!(html::parse_html("<p>plain</p>").viewport_meta, None);
+    }
+
+    #[test]
+    fn test_child_insertion_returns_nodes() {
+        let doc = html::parse_html("<div id='list'><p id='first'></p></div>");
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let result = engine
+            .execute_script(
+                "var list = document.getElementById('list'); \
+                 var first = document.getElementById('first'); \
+                 var span = document.createElement('span'); \
+                 var tags = [list.appendChild(document.createElement('em')).tagName, \
+                             list.appendChild(span) === span, \
+                             document.appendChild(list, span) === span]; \
+                 var b = document.createElement('b'); \
+                 tags.push(list.insertBefore(b, first) === b, list.firstChild === b); \
+                 var i = document.createElement('i'); \
+                 tags.push(list.replaceChild(i, first) === first, first.parentNode === null, i.nextSibling.tagName); \
+                 var threw = false; try { list.replaceChild(b, first); } catch (e) { threw = e instanceof TypeError; } \
+                 tags.push(threw); \
+                 tags.join()",
+            )
+            .unwrap();
+        assert_eq!(result, "em,true,true,true,true,true,true,em,true");
+        assert!(engine.document.borrow().validate().is_ok());
+    }
+
+    #[test]
+    fn test_node_contains() {
+        let doc = html::parse_html(
+            "<div id='box'><section><p id='inner'></p></section></div><div id='other'><span id='cousin'></span></div>",
+        );
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let result = engine
+            .execute_script(
+                "var box = document.getElementById('box'); \
+                 var inner = document.getElementById('inner'); \
+                 var cousin = document.getElementById('cousin'); \
+                 var loose = document.createElement('div'); \
+                 [box.contains(inner), box.contains(box), box.contains(cousin), inner.contains(box), \
+                  box.contains(null), loose.contains(loose), box.contains(loose)].join()",
+            )
+            .unwrap();
+        assert_eq!(result, "true,true,false,false,false,true,false");
+    }
+
+    #[test]
+    fn test_focus_pseudo_class() {
+        let doc = html::parse_html("<input id='a'><input id='b'>");
+        let sheet = css::parse_stylesheet(":focus { outline-color: blue } input { outline-color: red }");
+        let outline = |doc: &dom::Document, id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d.computed.outline_color,
+            _ => panic!("expected element"),
+        };
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        css::compute_styles(&mut engine.document.borrow_mut(), &sheet);
+        assert_eq!(outline(&engine.document.borrow(), "a"), Some((255, 0, 0, 255)));
+
+        let result = engine
+            .execute_script(
+                "var before = document.activeElement; var a = document.getElementById('a'); a.focus(); \
+                 [before, document.activeElement === a].join()",
+            )
+            .unwrap();
+        assert_eq!(result, ",true");
+        css::compute_styles(&mut engine.document.borrow_mut(), &sheet);
+        assert_eq!(outline(&engine.document.borrow(), "a"), Some((0, 0, 255, 255)), ":focus outranks the tag rule");
+        assert_eq!(outline(&engine.document.borrow(), "b"), Some((255, 0, 0, 255)));
+
+        engine.execute_script("document.getElementById('b').blur(); document.getElementById('b').focus()").unwrap();
+        css::compute_styles(&mut engine.document.borrow_mut(), &sheet);
+        assert_eq!(outline(&engine.document.borrow(), "a"), Some((255, 0, 0, 255)), "moving focus restyles the old element");
+        assert_eq!(outline(&engine.document.borrow(), "b"), Some((0, 0, 255, 255)));
+
+        let mut doc = html::parse_html("<div id='d'><input id='i'></div>");
+        let (d, i) = (doc.id_map["d"], doc.id_map["i"]);
+        assert!(doc.set_focus(i));
+        let parent = doc.parent_of(d).unwrap();
+        doc.remove_child(parent, d);
+        assert_eq!(doc.focused, None, "a freed element loses focus");
+        let text = doc.create_text("t");
+        assert!(!doc.set_focus(text), "only elements take focus");
+    }
+
+    #[test]
+    fn test_tab_order() {
+        let mut doc = html::parse_html(
+            "<a id='link' href='#'>l</a><a id='plain'>p</a><input id='i1'><div id='t2' tabindex='2'></div>\
+             <button id='b'>b</button><span id='t1' tabindex='1'></span><input id='neg' tabindex='-1'>\
+             <div id='t1b' tabindex='1'></div><input id='hid' type='hidden'><button id='dis' disabled></button>\
+             <textarea id='gone' class='none'></textarea><p id='after'></p><select id='s'></select>\
+             <div class='none'><button id='inside'>x</button></div><div id='dd' tabindex='0' disabled></div>",
+        );
+        css::compute_styles(&mut doc, &css::parse_stylesheet(".none { display: none }"));
+        let mut order = Vec::new();
+        let mut current = None;
+        for _ in 0..9 {
+            current = doc.next_focusable(current);
+            let id = current.unwrap();
+            order.push(doc.id_map.iter().find(|&(_, &v)| v == id).unwrap().0.as_str());
+        }
+        assert_eq!(
+            order,
+            ["t1", "t1b", "t2", "link", "i1", "b", "s", "dd", "t1"],
+            "positive tabindex first, then document order, wrapping; hidden containers hide their controls"
+        );
+        assert_eq!(doc.next_focusable(Some(doc.id_map["neg"])), Some(doc.id_map["s"]), "from outside the sequence");
+        assert_eq!(doc.next_focusable(Some(doc.id_map["after"])), Some(doc.id_map["s"]));
+
+        let doc = html::parse_html("<p>nothing here</p>");
+        assert_eq!(doc.next_focusable(None), None);
+    }
+
+    #[test]
+    fn test_get_element_by_id_repairs_id_map() {
+        let mut doc = html::parse_html("<div id='a'></div><p id='b'></p><span id='c'></span><em></em>");
+        let (a, b, c) = (doc.id_map["a"], doc.id_map["b"], doc.id_map["c"]);
+        assert_eq!(doc.get_element_by_id("a"), Some(a));
+        assert_eq!(doc.get_element_by_id("missing"), None);
+        assert_eq!(doc.id_map_repairs, 0, "a miss that finds nothing leaves the map alone");
+
+        // An id pushed straight into the attributes has no entry yet: the
+        // miss scans and maps it.
+        let plain = doc
+            .descendants(doc.root_id)
+            .find(|&n| matches!(doc.nodes.get(n), Some(dom::Node::Element(d)) if &*d.tag_name == "em"))
+            .expect("em element");
+        let Some(dom::Node::Element(data)) = doc.nodes.get_mut(plain) else { panic!("expected element") };
+        data.attributes.push(("id".into(), "x".into()));
+        assert_eq!(doc.get_element_by_id("x"), Some(plain));
+        assert_eq!(doc.id_map.get("x"), Some(&plain));
+        assert_eq!(doc.id_map_repairs, 1);
+        doc.id_map.remove("x");
+        assert_eq!(doc.get_element_by_id("x"), Some(plain), "a removed entry is found again");
+        assert_eq!(doc.id_map_repairs, 2);
+
+        // Swap two entries, drop one, and add one for no element.
+        doc.id_map.insert("a".into(), b);
+        doc.id_map.insert("b".into(), a);
+        doc.id_map.remove("c");
+        doc.id_map.insert("ghost".into(), c);
+        assert_eq!(doc.get_element_by_id("a"), Some(a));
+        assert_eq!(doc.id_map_repairs, 3);
+        assert_eq!((doc.id_map["b"], doc.id_map["c"]), (b, c), "the whole map is repaired in one scan");
+        assert!(!doc.id_map.contains_key("ghost"));
+        assert_eq!(doc.get_element_by_id("b"), Some(b));
+        assert_eq!(doc.id_map_repairs, 3);
+
+        // An id changed straight in the element, bypassing `set_attribute`:
+        // looking up the old id finds the mismatch and maps the new one.
+        let Some(dom::Node::Element(data)) = doc.nodes.get_mut(c) else { panic!("expected element") };
+        data.set_attribute("id", "renamed".to_string());
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let result = engine
+            .execute_script("[document.getElementById('c'), document.getElementById('renamed').tagName].join()")
+            .unwrap();
+        assert_eq!(result.to_ascii_lowercase(), ",span");
+        let doc = engine.document.borrow();
+        assert_eq!(doc.id_map_repairs, 4);
+        assert_eq!(doc.id_map.get("renamed"), Some(&c));
+        assert!(!doc.id_map.contains_key("c"));
+    }
+
+    #[test]
+    fn test_node_matches() {
+        let doc = html::parse_html("<div id='b' class='box'></div>");
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let result = engine
+            .execute_script(
+                "var el = document.getElementById('b'); [el.matches('.box'), el.matches('*'), el.matches('#missing')].join()",
+            )
+            .unwrap();
+        assert_eq!(result, "true,true,false");
+    }
+
+    #[test]
+    fn test_node_closest() {
+        let doc = html::parse_html(
+            "<div id='o' class='outer'><div class='mid'><div id='i' class='inner'></div></div></div>",
+        );
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let result = engine
+            .execute_script(
+                "var inner = document.getElementById('i'); \
+                 [inner.closest('.outer') === document.getElementById('o'), \
+                  inner.closest('.inner') === inner, \
+                  inner.closest('.missing') === null].join()",
+            )
+            .unwrap();
+        assert_eq!(result, "true,true,true");
+    }
+
+    #[test]
+    fn test_scoped_queries() {
+        let doc = html::parse_html(
+            "<div id='a'><p class='x y'>1</p><section><p id='deep' class='x'>2</p></section></div>\
+             <div id='b'><p class='x'>3</p></div>",
+        );
+        assert_eq!(doc.descendants(doc.id_map["a"]).count(), 5, "three elements and two text nodes");
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let result = engine
+            .execute_script(
+                "var a = document.getElementById('a'); \
+                 [a.getElementsByTagName('p').map(p => p.textContent).join(''), \
+                  a.getElementsByTagName('*').length, \
+                  a.getElementsByClassName('x').length, a.getElementsByClassName('y x').length, \
+                  a.querySelectorAll('section > .x').length, \
+                  a.querySelector('section .x') === document.getElementById('deep'), \
+                  a.querySelector('div') === null, \
+                  document.getElementById('b').querySelectorAll('p').map(p => p.textContent).join('')].join()",
+            )
+            .unwrap();
+        assert_eq!(result, "12,3,2,1,1,true,true,3");
+    }
+
+    #[test]
+    fn test_js_json_globals_and_calls() {
+        let engine = js::JsEngine::try_new(dom::Document::new()).expect("try_new");
+        engine.set_global_json("data", r#"{"n":3}"#).unwrap();
+        assert_eq!(engine.execute_script("data.n").unwrap(), "3");
+
+        engine
+            .execute_script("function scale(point, k) { return { x: point.x * k, tag: 'p' + data.n }; }")
+            .unwrap();
+        assert_eq!(engine.call_function("scale", r#"[{"x":2}, 5]"#).unwrap(), r#"{"x":10,"tag":"p3"}"#);
+        assert!(engine.set_global_json("bad", "{").is_err());
+        assert!(engine.call_function("missing", "[]").is_err());
+    }
+
+    #[test]
+    fn test_js_bounding_client_rect() {
+        let mut doc = html::parse_html(
+            "<div style='margin-left: 10px; margin-top: 5px'>\
+               <div id='box' style='position: absolute; left: 30px; top: 20px; width: 50px; height: 40px'></div>\
+             </div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let fresh = engine
+            .execute_script("var r = document.getElementById('box').getBoundingClientRect(); [r.width, r.height].join()")
+            .unwrap();
+        assert_eq!(fresh, "0,0", "no layout has run yet");
+
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut engine.document.borrow_mut(), 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let rect = engine
+            .execute_script(
+                "var r = document.getElementById('box').getBoundingClientRect(); \
+                 [r.x, r.y, r.width, r.height, r.left, r.top, r.right, r.bottom].join()",
+            )
+            .unwrap();
+        assert_eq!(rect, "40,25,50,40,40,25,90,65");
+    }
+
+    #[test]
+    fn test_caret_rect_and_color() {
+        let mut doc = html::parse_html(
+            "<div id='w' style='caret-color: #00ff00'><p id='p' style='padding-left: 7px'>hello</p>\
+             <p id='q' style='caret-color: auto'>x</p></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let text = doc.first_child_of(doc.id_map["p"]).unwrap();
+        let origin = layout::absolute_rect(&doc, text).unwrap();
+        let caret = |i| layout::caret_rect(&doc, &buffer_cache, text, i).unwrap();
+
+        assert_eq!((caret(0).x, caret(0).y, caret(0).width), (origin.x, origin.y, 1.0));
+        assert_eq!(origin.x, 7.0);
+        assert!((caret(0).height - 16.0 * 1.2).abs() < 1e-3, "one normal line high");
+        assert!(caret(1).x > caret(0).x);
+        let end = caret(5);
+        assert!(end.x > caret(4).x && end.x < origin.x + origin.width, "after the last glyph: {end:?}");
+        assert_eq!(caret(99), end, "past the end stays after the last glyph");
+        assert!(layout::caret_rect(&doc, &buffer_cache, doc.id_map["p"], 0).is_none());
+
+        let Some(dom::Node::Text(data)) = doc.nodes.get(text) else { panic!("expected text") };
+        assert_eq!(data.computed.caret_color, Some((0, 255, 0, 255)));
+        let Some(dom::Node::Element(q)) = doc.nodes.get(doc.id_map["q"]) else { panic!("expected element") };
+        assert_eq!(q.computed.caret_color, None);
+    }
+
+    #[test]
+    fn test_text_properties_inherit() {
+        let mut doc = html::parse_html(
+            "<div id='w' style='font-style: italic; font-weight: bold; white-space: pre; letter-spacing: 2px; word-spacing: 3px; \
+             text-transform: uppercase; direction: rtl; cursor: pointer; text-align: center; \
+             width: 300px; margin: 5px; display: flex; background-color: red'>\
+             <div id='c'><p id='p'>hi</p></div></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let element = |id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d.computed.clone(),
+            _ => panic!("expected element"),
+        };
+        let (parent, child) = (element("w"), element("c"));
+
+        assert_eq!(child.font_style, dom::FontStyleKeyword::Italic);
+        assert_eq!(child.font_weight, 700);
+        assert_eq!(child.white_space, dom::WhiteSpaceKeyword::Pre);
+        assert_eq!(child.letter_spacing, 2.0);
+        assert_eq!(child.word_spacing, 3.0);
+        assert_eq!(child.text_transform, dom::TextTransformKeyword::Uppercase);
+        assert_eq!(child.direction, dom::DirectionKeyword::Rtl);
+        assert_eq!(&*child.cursor, "pointer");
+        assert_eq!(child.text_align, dom::TextAlignKeyword::Center);
+        assert!(child.inherited_eq(&parent));
+        assert!(dom::PropertyName::TextAlign.is_inheritable());
+
+        assert_eq!(child.width, dom::StyleValue::Auto);
+        assert_eq!(child.margin[0], dom::StyleValue::LengthPx(0.0));
+        assert_eq!(child.display, dom::DisplayKeyword::Block);
+        assert_eq!(child.bg_color, None);
+        for name in ["width", "margin-top", "display", "background-color"] {
+            assert!(!dom::PropertyName::from_str(name).unwrap().is_inheritable(), "{name}");
+        }
+
+        let text = doc.first_child_of(doc.id_map["p"]).unwrap();
+        let Some(dom::Node::Text(data)) = doc.nodes.get(text) else { panic!("expected text") };
+        assert_eq!(data.computed.text_align, dom::TextAlignKeyword::Center);
+    }
+
+    #[test]
+    fn test_text_align_positions_lines() {
+        let mut doc = html::parse_html(
+            "<div id='w' style='width: 400px'><p id='l'>hi</p><p id='c' style='text-align: center'>hi</p>\
+             <p id='r' style='text-align: right'>hi</p><p id='e' style='direction: rtl; text-align: end'>hi</p></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let start = |id: &str| {
+            let text = doc.first_child_of(doc.id_map[id]).unwrap();
+            layout::caret_rect(&doc, &buffer_cache, text, 0).unwrap().x
+        };
+
+        assert_eq!(start("l"), 0.0);
+        let width = 2.0 * (200.0 - start("c"));
+        assert!(width > 0.0 && width < 50.0, "centred in the 400px line: {width}");
+        assert!((start("r") - (400.0 - width)).abs() < 1.0, "{}", start("r"));
+        assert_eq!(start("e"), 0.0, "end is the left edge in rtl");
+    }
+
+    #[test]
+    fn test_scroll_into_view_offsets() {
+        let mut doc = html::parse_html(
+            "<div style='height: 2000px'></div><div id='t' style='height: 100px'></div>\
+             <div style='height: 1000px'></div><div id='last' style='height: 50px'></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let (t, last) = (doc.id_map["t"], doc.id_map["last"]);
+        let top = layout::AbsoluteRect { x: 0.0, y: 0.0, width: 800.0, height: 600.0 };
+        let reveal = |id, viewport, block| layout::scroll_offset_to_reveal(&doc, id, viewport, block).unwrap();
+        assert_eq!(reveal(t, top, layout::ScrollAlign::Start), (0.0, 2000.0));
+        assert_eq!(reveal(t, top, layout::ScrollAlign::Center), (0.0, 1750.0));
+        assert_eq!(reveal(t, top, layout::ScrollAlign::End), (0.0, 1500.0));
+        assert_eq!(reveal(t, top, layout::ScrollAlign::Nearest), (0.0, 1500.0));
+        // Already visible stays put; the last element cannot scroll past the page end.
+        let scrolled = layout::AbsoluteRect { y: 1700.0, ..top };
+        assert_eq!(reveal(t, scrolled, layout::ScrollAlign::Nearest), (0.0, 1700.0));
+        assert_eq!(reveal(last, top, layout::ScrollAlign::Start), (0.0, 2550.0));
+
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        engine.execute_script("document.getElementById('t').scrollIntoView()").unwrap();
+        assert_eq!(engine.document.borrow().viewport_scroll, (0.0, 2000.0));
+        engine.execute_script("document.getElementById('t').scrollIntoView({ block: 'center' })").unwrap();
+        assert_eq!(engine.document.borrow().viewport_scroll, (0.0, 1750.0));
+        engine.execute_script("document.getElementById('t').scrollIntoView(false)").unwrap();
+        assert_eq!(engine.document.borrow().viewport_scroll, (0.0, 1500.0));
+    }
+
+    #[test]
+    fn test_append_to_void_element_rejected() {
+        let mut doc = html::parse_html("<div><img id='pic'><p>after</p></div>");
+        let img = *doc.id_map.get("pic").expect("id pic");
+        assert!(doc.is_void_element(img));
+        // The parser must not have nested the following <p> inside the <img>.
+        assert!(doc.first_child_of(img).is_none());
+
+        let span = doc.add_node(dom::Node::Element(dom::ElementData::new(dom::LocalName::new("span"))));
+        assert!(!doc.append_child(img, span));
+        assert!(doc.first_child_of(img).is_none() && doc.parent_of(span).is_none());
+
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let result = engine
+            .execute_script(
+                "var img = document.getElementById('pic'); var el = document.createElement('span'); \
+                 var threw = false; try { document.appendChild(img, el); } catch (e) { threw = e instanceof TypeError; } \
+                 [threw, img.firstChild === null].join()",
+            )
+            .unwrap();
+        assert_eq!(result, "true,true");
+    }
+
+    #[test]
+    fn test_clear_children_and_text_content() {
+        let mut doc = html::parse_html("<div id='box'><p id='a'>one</p><span id='b'><em id='c'>two</em></span></div>");
+        let div = *doc.id_map.get("box").unwrap();
+        assert_eq!(doc.text_content(div), "onetwo");
+        let before = doc.nodes.len();
+
+        doc.clear_children(div);
+        assert!(doc.first_child_of(div).is_none());
+        for id in ["a", "b", "c"] {
+            assert!(!doc.id_map.contains_key(id), "{id} should be purged");
+        }
+        assert_eq!(doc.nodes.len(), before - 5, "removed elements and text nodes are freed");
+
+        doc.set_text_content(div, "fresh");
+        let text = doc.first_child_of(div).unwrap();
+        assert!(matches!(doc.nodes.get(text), Some(dom::Node::Text(t)) if t.text == "fresh"));
+        assert_eq!(doc.last_child_of(div), Some(text));
+
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let result = engine
+            .execute_script(
+                "var d = document.getElementById('box'); var before = d.textContent; \
+                 d.textContent = 'from js'; [before, d.textContent, d.firstChild === d.firstChild].join()",
+            )
+            .unwrap();
+        assert_eq!(result, "fresh,from js,true");
+    }
+
+    #[test]
+    fn test_outer_html_and_inner_text() {
+        let mut doc = html::parse_html(
+            "<div id='w'><div>a</div><div>b</div><p class='x' title='1 &lt; \"2\"'>c  <b>d</b><br>e<img src='i.png'></p></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let w = doc.id_map["w"];
+        assert_eq!(doc.text_content(w), "abc  de");
+        assert_eq!(doc.inner_text(w), "a\nb\nc d\ne");
+        assert_eq!(
+            doc.outer_html(w),
+            "<div id=\"w\"><div>a</div><div>b</div><p class=\"x\" title=\"1 &lt; &quot;2&quot;\">c  <b>d</b><br>e<img src=\"i.png\"></p></div>",
+        );
+
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let result = engine
+            .execute_script(
+                "var w = document.getElementById('w'); \
+                 [JSON.stringify(w.firstChild.innerText), w.firstChild.outerHTML].join()",
+            )
+            .unwrap();
+        assert_eq!(result, "\"a\",<div>a</div>");
+    }
+
+    #[test]
+    fn test_node_dataset() {
+        let doc = html::parse_html("<div id='d' data-user-id='42'></div>");
+        let engine = js::JsEngine::try_new(doc).expect("try_new");
+        let result = engine
+            .execute_script(
+                "var d = document.getElementById('d'); \
+                 d.dataset.fooBar = '1'; \
+                 [d.dataset.userId, d.getAttribute('data-foo-bar'), d.dataset.missing === undefined, \
+                  'fooBar' in d.dataset].join()",
+            )
+            .unwrap();
+        assert_eq!(result, "42,1,true,true");
+    }
+
+    /// Records backend calls so tests can assert on what the renderer emitted.
+    #[derive(Default)]
+    struct RecordingBackend {
+        fills: Vec<(f32, f32, f32, f32, render::Color)>,
+        strokes: Vec<(f32, f32, f32, f32, f32, render::Color)>,
+        glyph_runs: Vec<(f32, f32, usize, render::Color)>,
+        transforms: Vec<render::Transform>,
+        clips: Vec<(f32, f32, f32, f32)>,
+        images: Vec<(f32, f32, f32, f32)>,
+        shadows: Vec<(f32, f32, f32, f32, render::Shadow)>,
+        /// Every call in order: "fill", "stroke", "glyphs", "image", "shadow",
+        /// "push", "pop", "clip", "unclip".
+        ops: Vec<&'static str>,
+    }
+
+    impl render::RendererBackend for RecordingBackend {
+        fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: render::Color) {
+            self.fills.push((x, y, w, h, color));
+            self.ops.push("fill");
+        }
+        fn stroke_rect(&mut self, x: f32, y: f32, w: f32, h: f32, line_width: f32, color: render::Color) {
+            self.strokes.push((x, y, w, h, line_width, color));
+            self.ops.push("stroke");
+        }
+        fn draw_glyphs(
+            &mut self,
+            x: f32,
+            y: f32,
+            glyphs: &[cosmic_text::LayoutGlyph],
+            _size: f32,
+            color: render::Color,
+        ) {
+            self.glyph_runs.push((x, y, glyphs.len(), color));
+            self.ops.push("glyphs");
+        }
+        fn draw_image(&mut self, x: f32, y: f32, w: f32, h: f32, _url: &str) {
+            self.images.push((x, y, w, h));
+            self.ops.push("image");
+        }
+        fn draw_shadow(&mut self, x: f32, y: f32, w: f32, h: f32, shadow: &render::Shadow) {
+            self.shadows.push((x, y, w, h, *shadow));
+            self.ops.push("shadow");
+        }
+        fn push_transform(&mut self, transform: &render::Transform) {
+            self.transforms.push(*transform);
+            self.ops.push("push");
+        }
+        fn pop_transform(&mut self) {
+            self.ops.push("pop");
+        }
+        fn push_clip(&mut self, x: f32, y: f32, w: f32, h: f32) {
+            self.clips.push((x, y, w, h));
+            self.ops.push("clip");
+        }
+        fn pop_clip(&mut self) {
+            self.ops.push("unclip");
+        }
+    }
+
+    #[test]
+    fn test_collapsible_whitespace_skips_layout() {
+        let mut doc = html::parse_html(
+            "<div id='w'><div id='a'>a</div> <div>b</div>\
+             <p><span id='s'>a</span> <span id='t'>b</span></p></div>",
+        );
+        let block_space = doc.next_sibling_of(doc.id_map["a"]).unwrap();
+        let inline_space = doc.next_sibling_of(doc.id_map["s"]).unwrap();
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        let mut laid_out = |doc: &mut dom::Document, id| {
+            css::compute_styles(doc, &css::StyleSheet::default());
+            layout::compute_layout(doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+            let Some(dom::Node::Text(data)) = doc.nodes.get(id) else { panic!("expected text") };
+            assert_eq!(data.taffy_node.is_some(), buffer_cache.contains_key(&id));
+            buffer_cache.contains_key(&id)
+        };
+        assert!(!laid_out(&mut doc, block_space), "whitespace between blocks is dropped");
+        assert!(laid_out(&mut doc, inline_space), "whitespace between inlines is kept");
+        let gap = |doc: &dom::Document| {
+            let (s, t) = (layout::absolute_rect(doc, doc.id_map["s"]).unwrap(), layout::absolute_rect(doc, doc.id_map["t"]).unwrap());
+            t.x - (s.x + s.width)
+        };
+        assert!(gap(&doc) > 0.0);
+
+        // A block neighbour makes the space insignificant, and back again.
+        let t = doc.id_map["t"];
+        doc.set_attribute(t, "style", "display: block".into());
+        assert!(!laid_out(&mut doc, inline_space));
+        doc.remove_attribute(t, "style");
+        assert!(laid_out(&mut doc, inline_space));
+        assert!(gap(&doc) > 0.0);
+    }
+
+    #[test]
+    fn test_flex_order_swaps_items() {
+        let mut doc = html::parse_html(
+            "<div id='w' style='display: flex'>\
+             <div id='a' style='order: 2; width: 50px; height: 10px; background-color: #ff0000'></div>\
+             <div id='b' style='order: 1; width: 30px; height: 10px; background-color: #0000ff'></div></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        let mut fills = |doc: &mut dom::Document| {
+            layout::compute_layout(doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+            let w = doc.id_map["w"];
+            let Some(dom::Node::Element(data)) = doc.nodes.get(w) else { panic!() };
+            let mut backend = RecordingBackend::default();
+            render::draw_layout_tree(&mut backend, doc, &doc.taffy_tree, w, data.taffy_node.unwrap(), 0.0, 0.0, &buffer_cache);
+            backend.fills.iter().map(|&(x, _, w, ..)| (x, w)).collect::<Vec<_>>()
+        };
+        // Painted in document order, but `b` is placed first.
+        assert_eq!(fills(&mut doc), [(30.0, 50.0), (0.0, 30.0)]);
+
+        // Dropping `a`'s order moves it back ahead of `b`.
+        doc.set_attribute(doc.id_map["a"], "style", "width: 50px; height: 10px; background-color: #ff0000".into());
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        assert_eq!(fills(&mut doc), [(0.0, 50.0), (50.0, 30.0)]);
+    }
+
+    #[test]
+    fn test_box_shadow_draws_behind_box() {
+        let mut doc = html::parse_html(
+            "<div id='w'><div style='width: 50px; height: 20px; background-color: #ffffff; box-shadow: 2px 2px 4px black'></div>\
+             <div style='width: 10px; height: 10px; color: #ff0000; \
+             box-shadow: 1px 0 red, inset 0 0 3px 1px rgba(0, 0, 255, 0.5), -1px -1px'></div></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let w = doc.id_map["w"];
+        let Some(dom::Node::Element(data)) = doc.nodes.get(w) else { panic!() };
+        let mut backend = RecordingBackend::default();
+        render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, w, data.taffy_node.unwrap(), 0.0, 0.0, &buffer_cache);
+
+        let black = render::Color { r: 0, g: 0, b: 0, a: 255 };
+        let red = render::Color { r: 255, g: 0, b: 0, a: 255 };
+        let shadow = |offset_x, offset_y, blur, spread, color, inset| render::Shadow {
+            offset_x,
+            offset_y,
+            blur,
+            spread,
+            color,
+            inset,
+        };
+        assert_eq!(backend.shadows[0], (0.0, 0.0, 50.0, 20.0, shadow(2.0, 2.0, 4.0, 0.0, black, false)));
+        assert_eq!(backend.ops[..2], ["shadow", "fill"]);
+        // Outer shadows bottommost first (the colorless one uses `color`), then inset.
+        let b: Vec<_> = backend.shadows[1..].iter().map(|&(.., s)| s).collect();
+        assert_eq!(
+            b,
+            [
+                shadow(-1.0, -1.0, 0.0, 0.0, red, false),
+                shadow(1.0, 0.0, 0.0, 0.0, red, false),
+                shadow(0.0, 0.0, 3.0, 1.0, render::Color { r: 0, g: 0, b: 255, a: 127 }, true),
+            ]
+        );
+
+        let value = |css: &str| css::parse_inline_declarations(&format!("box-shadow: {css}")).pop().map(|d| d.value);
+        assert_eq!(value("none"), Some(dom::StyleValue::BoxShadows(std::sync::Arc::from([]))));
+        assert_eq!(value("inset 1px 2px #00ff00").unwrap().to_css_string(), "inset 1px 2px 0px 0px #00ff00");
+        for invalid in ["1px", "1px 2px 3px 4px 5px", "1px 2px -3px", "1px red 2px", "1px 2px bogus"] {
+            assert_eq!(value(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_url_values() {
+        let value = |css: &str| css::parse_inline_declarations(&format!("background-image: {css}")).pop().map(|d| d.value);
+        let url = |u: &str| Some(dom::StyleValue::Url(u.to_string()));
+        assert_eq!(value("url(\"a.png\")"), url("a.png"));
+        assert_eq!(value("url( 'b c.png' )"), url("b c.png"));
+        assert_eq!(value("URL(img/d.png)"), url("img/d.png"));
+        assert_eq!(value("url(\"a.png\")").unwrap().to_string(), "url(\"a.png\")");
+        assert_ne!(value("url(a b)"), url("a b"), "unquoted URLs cannot hold spaces");
+
+        let mut doc = html::parse_html("<div id='a'><p id='p'></p></div><div id='n' style='background-image: url(x.png)'></div>");
+        let sheet = css::parse_stylesheet("div { background-image: url(\"a.png\") } #n { background-image: none }");
+        css::compute_styles(&mut doc, &sheet);
+        let image = |id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d.computed.background_image.as_deref().map(str::to_string),
+            _ => panic!("expected element"),
+        };
+        assert_eq!(image("a").as_deref(), Some("a.png"));
+        assert_eq!(image("p"), None, "background-image is not inherited");
+        assert_eq!(image("n").as_deref(), Some("x.png"), "inline style wins");
+    }
+
+    #[test]
+    fn test_comma_separated_lists() {
+        let value = |css: &str| css::parse_inline_declarations(css).into_iter().map(|d| (d.name, d.value)).collect::<Vec<_>>();
+        let [(_, dom::StyleValue::Transitions(specs))] = &value("transition: width 1s, height 2s")[..] else {
+            panic!("expected a transition list")
+        };
+        let entries: Vec<_> = specs.iter().map(|s| (s.property, s.duration)).collect();
+        assert_eq!(
+            entries,
+            [(Some(dom::PropertyName::Width), 1.0), (Some(dom::PropertyName::Height), 2.0)]
+        );
+        let [(_, dom::StyleValue::FontFamily(families))] = &value("font-family: Arial, 'Noto Sans', serif")[..] else {
+            panic!("expected a font-family list")
+        };
+        assert_eq!(families.len(), 3);
+
+        // Only the first layer's image and the last layer's color are kept.
+        assert_eq!(
+            value("background: url(a.png) no-repeat, url(b.png) center rgb(0, 0, 255)"),
+            [
+                (dom::PropertyName::BackgroundImage, dom::StyleValue::Url("a.png".into())),
+                (dom::PropertyName::BackgroundColor, dom::StyleValue::Color(0, 0, 255, 255)),
+            ]
+        );
+        assert_eq!(
+            value("background: #ff0000"),
+            [
+                (dom::PropertyName::BackgroundImage, dom::StyleValue::None),
+                (dom::PropertyName::BackgroundColor, dom::StyleValue::Color(255, 0, 0, 255)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_first_letter_pseudo_element() {
+        let mut doc = html::parse_html(
+            "<div id='w'><p id='p'><b>\u{201c}Hello</b> world</p><p id='q'>Plain</p></div>",
+        );
+        let sheet = css::parse_stylesheet("#p::first-letter { font-size: 2em; color: #ff0000 }");
+        assert_eq!(css::parse_selector_list("p:first-letter")[0].to_string(), "p::first-letter");
+        css::compute_styles(&mut doc, &sheet);
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        // The style lands on the first text, inside the `<b>`, and nowhere else.
+        let first_text = |doc: &dom::Document, id: &str| {
+            let mut node = doc.id_map[id];
+            while let Some(child) = doc.first_child_of(node) {
+                node = child;
+            }
+            node
+        };
+        let hello = first_text(&doc, "p");
+        let letter_of = |doc: &dom::Document, id| match doc.nodes.get(id) {
+            Some(dom::Node::Text(t)) => t.first_letter.as_ref().map(|s| s.font_size),
+            _ => panic!("expected text"),
+        };
+        assert_eq!(letter_of(&doc, hello), Some(32.0));
+        assert_eq!(letter_of(&doc, first_text(&doc, "q")), None);
+        let world = doc.next_sibling_of(doc.parent_of(hello).unwrap()).unwrap();
+        assert_eq!(letter_of(&doc, world), None);
+
+        // The opening quote and the letter after it are drawn as one larger, red run.
+        let w = doc.id_map["w"];
+        let Some(dom::Node::Element(data)) = doc.nodes.get(w) else { panic!() };
+        let list = render::build_display_list(&doc, &doc.taffy_tree, w, data.taffy_node.unwrap(), 0.0, 0.0, &buffer_cache);
+        let runs: Vec<_> = list
+            .iter()
+            .filter_map(|c| match c {
+                render::DrawCommand::Text { glyphs, size, color, .. } => Some((glyphs.0.len(), *size, color.r)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(runs[..2], [(2, 32.0, 255), (4, 16.0, 0)]);
+        assert!(runs[2..].iter().all(|&(_, size, _)| size == 16.0));
+
+        // Inserting text ahead moves the letter there on the next pass.
+        let p = doc.id_map["p"];
+        let lead = doc.add_node(dom::Node::Text(dom::TextData::new("Lead ".to_string())));
+        assert!(doc.insert_before(p, lead, doc.first_child_of(p)));
+        css::compute_styles(&mut doc, &sheet);
+        assert_eq!((letter_of(&doc, lead), letter_of(&doc, hello)), (Some(32.0), None));
+    }
+
+    #[test]
+    fn test_display_list_matches_backend_calls() {
+        let mut doc = html::parse_html(
+            "<div id='w' style='width: 100px; height: 60px; background-color: #ff0000; border-color: #0000ff; overflow: hidden'>\
+             <p style='margin: 0'>hi</p><img src='a.png' width='10' height='10'></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let w = doc.id_map["w"];
+        let w_taffy = match doc.nodes.get(w) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected element"),
+        };
+
+        let list = render::build_display_list(&doc, &doc.taffy_tree, w, w_taffy, 0.0, 0.0, &buffer_cache);
+        let red = render::Color { r: 255, g: 0, b: 0, a: 255 };
+        let blue = render::Color { r: 0, g: 0, b: 255, a: 255 };
+        assert_eq!(list.len(), 6);
+        assert_eq!(list[0], render::DrawCommand::FillRect { x: 0.0, y: 0.0, w: 100.0, h: 60.0, color: red });
+        assert_eq!(
+            list[1],
+            render::DrawCommand::StrokeRect { x: 0.0, y: 0.0, w: 100.0, h: 60.0, line_width: 1.0, color: blue }
+        );
+        assert_eq!(list[2], render::DrawCommand::PushClip { x: 0.0, y: 0.0, w: 100.0, h: 60.0 });
+        assert!(matches!(&list[3], render::DrawCommand::Text { x: 0.0, glyphs, .. } if glyphs.0.len() == 2));
+        assert!(matches!(&list[4], render::DrawCommand::Image { w: 10.0, h: 10.0, url, .. } if url == "a.png"));
+        assert_eq!(list[5], render::DrawCommand::PopClip);
+
+        // Replaying the list is what `draw_layout_tree` does.
+        let mut drawn = RecordingBackend::default();
+        render::draw_layout_tree(&mut drawn, &doc, &doc.taffy_tree, w, w_taffy, 0.0, 0.0, &buffer_cache);
+        let mut replayed = RecordingBackend::default();
+        render::replay_display_list(&mut replayed, &list);
+        assert_eq!(replayed.ops, ["fill", "stroke", "clip", "glyphs", "image", "unclip"]);
+        assert_eq!((replayed.ops, replayed.glyph_runs), (drawn.ops, drawn.glyph_runs));
+        // An unchanged frame produces an identical list.
+        assert_eq!(list, render::build_display_list(&doc, &doc.taffy_tree, w, w_taffy, 0.0, 0.0, &buffer_cache));
+    }
+
+    #[test]
+    fn test_object_fit_image_rects() {
+        let draw = |fit: &str| {
+            let mut doc = html::parse_html(&format!(
+                "<div id='w'><img src='a.png' width='100' height='50' style='width: 200px; height: 200px; object-fit: {fit}'></div>"
+            ));
+            css::compute_styles(&mut doc, &css::StyleSheet::default());
+            let mut font_system = cosmic_text::FontSystem::new();
+            let mut buffer_cache = std::collections::HashMap::new();
+            layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+            let w = doc.id_map["w"];
+            let w_taffy = match doc.nodes.get(w) {
+                Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+                _ => panic!("expected element"),
+            };
+            let mut backend = RecordingBackend::default();
+            render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, w, w_taffy, 0.0, 0.0, &buffer_cache);
+            backend
+        };
+
+        let fill = draw("fill");
+        assert_eq!(fill.images, [(0.0, 0.0, 200.0, 200.0)]);
+        // A 2:1 image letterboxed into a square box.
+        let contain = draw("contain");
+        assert_eq!(contain.images, [(0.0, 50.0, 200.0, 100.0)]);
+        assert!(contain.clips.is_empty());
+        let cover = draw("cover");
+        assert_eq!(cover.images, [(-100.0, 0.0, 400.0, 200.0)]);
+        assert_eq!(cover.clips, [(0.0, 0.0, 200.0, 200.0)]);
+        assert_eq!(cover.ops, ["clip", "image", "unclip"]);
+        assert_eq!(draw("none").images, [(50.0, 75.0, 100.0, 50.0)]);
+        assert_eq!(draw("scale-down").images, [(50.0, 75.0, 100.0, 50.0)]);
+    }
+
+    #[test]
+    fn test_scroll_offset_shifts_clipped_children() {
+        let mut doc = html::parse_html(
+            "<div id='w'><div id='s' style='overflow: scroll; width: 100px; height: 50px; border-top-width: 2px; border-right-width: 2px; border-bottom-width: 2px; border-left-width: 2px'>\
+               <p id='a' style='height: 40px; background-color: red'></p>\
+               <p id='b' style='height: 40px; background-color: blue'></p>\
+             </div><p style='height: 10px; background-color: green'></p></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let scroller = doc.id_map["s"];
+        let draw = |doc: &dom::Document| {
+            let w = doc.id_map["w"];
+            let w_taffy = match doc.nodes.get(w) {
+                Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+                _ => unreachable!(),
+            };
+            let mut backend = RecordingBackend::default();
+            render::draw_layout_tree(&mut backend, doc, &doc.taffy_tree, w, w_taffy, 0.0, 0.0, &buffer_cache);
+            backend
+        };
+
+        let before = draw(&doc);
+        doc.set_scroll_offset(scroller, 0.0, 25.0);
+        let after = draw(&doc);
+        assert_eq!(after.ops, ["clip", "fill", "fill", "unclip", "fill"]);
+        assert_eq!(after.clips, [(2.0, 2.0, 96.0, 46.0)]);
+        let ys = |b: &RecordingBackend| b.fills.iter().map(|f| f.1).collect::<Vec<_>>();
+        let (ys_before, ys_after) = (ys(&before), ys(&after));
+        // Both children move up by the offset; content after the container does not.
+        assert_eq!(ys_after[..2], [ys_before[0] - 25.0, ys_before[1] - 25.0]);
+        assert_eq!(ys_after[2], ys_before[2]);
+        // Hit testing follows the scrolled content: y = 30 now lands on `b`.
+        assert_eq!(doc.hit_test(50.0, 30.0), Some(doc.id_map["b"]));
+
+        // Clamped to the scrollable range (80px of content in a 46px box).
+        doc.set_scroll_offset(scroller, 0.0, 1000.0);
+        assert_eq!(doc.scroll_offset(scroller), (0.0, 34.0));
+        assert_eq!(ys(&draw(&doc))[0], ys_before[0] - 34.0);
+    }
+
+    #[test]
+    fn test_transform_brackets_subtree_draws() {
+        let mut doc = html::parse_html(
+            "<div id='w'><div id='t'><p style='height: 4px; background-color: blue'></p></div>\
+             <div id='u' style='height: 4px'></div></div>",
+        );
+        let sheet = css::parse_stylesheet(
+            "#t { transform: translate(10px, 5px); background-color: red; } #u { background-color: rgb(0, 128, 0); }",
+        );
+        css::compute_styles(&mut doc, &sheet);
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let taffy_of = |id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected element"),
+        };
+        // Layout ignores the transform.
+        assert_eq!(doc.taffy_tree.layout(taffy_of("t")).unwrap().location.x, 0.0);
+
+        let mut backend = RecordingBackend::default();
+        render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, doc.id_map["w"], taffy_of("w"), 0.0, 0.0, &buffer_cache);
+
+        assert_eq!(backend.ops, ["push", "fill", "fill", "pop", "fill"]);
+        assert_eq!(
+            backend.transforms,
+            [render::Transform { translate_x: 10.0, translate_y: 5.0, scale_x: 1.0, scale_y: 1.0 }]
+        );
+        assert_eq!(backend.fills[2].4, render::Color { r: 0, g: 128, b: 0, a: 255 });
+
+        assert_eq!(
+            css::parse_style_value("translate(10px,5px) scale(2)"),
+            dom::StyleValue::Transform(render::Transform { translate_x: 10.0, translate_y: 5.0, scale_x: 2.0, scale_y: 2.0 })
+        );
+    }
+
+    #[test]
+    fn test_rgba_background_alpha_reaches_backend() {
+        let mut doc = html::parse_html(
+            "<div id='w'><div style='height: 4px; background-color: rgba(255, 0, 0, 0.2)'></div>\
+             <div id='h' style='height: 4px'></div></div>",
+        );
+        css::compute_styles(&mut doc, &css::parse_stylesheet("#h { background-color: #00ff0080; }"));
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let w_taffy = match doc.nodes.get(doc.id_map["w"]) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected element"),
+        };
+        let mut backend = RecordingBackend::default();
+        render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, doc.id_map["w"], w_taffy, 0.0, 0.0, &buffer_cache);
+
+        let colors: Vec<_> = backend.fills.iter().map(|f| f.4).collect();
+        assert_eq!(
+            colors,
+            [render::Color { r: 255, g: 0, b: 0, a: 51 }, render::Color { r: 0, g: 255, b: 0, a: 128 }]
+        );
+    }
+
+    #[test]
+    fn test_layout_and_render_read_typed_computed_values() {
+        // No CSS text anywhere: the typed values written into `ComputedStyle`
+        // are what layout and rendering consume, with nothing to re-parse.
+        let mut doc = html::parse_html("<div id='w'><div id='b'></div></div>");
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        if let Some(dom::Node::Element(d)) = doc.nodes.get_mut(doc.id_map["b"]) {
+            assert!(d.attributes.iter().all(|(k, _)| k != "style"));
+            d.computed.width = dom::StyleValue::LengthPx(123.0);
+            d.computed.height = dom::StyleValue::LengthPx(7.0);
+            d.computed.bg_color = Some((1, 2, 3, 4));
+        }
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let taffy_of = |id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected element"),
+        };
+        let size = doc.taffy_tree.layout(taffy_of("b")).unwrap().size;
+        assert_eq!((size.width, size.height), (123.0, 7.0));
+
+        let mut backend = RecordingBackend::default();
+        render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, doc.id_map["w"], taffy_of("w"), 0.0, 0.0, &buffer_cache);
+        assert_eq!(backend.fills, [(0.0, 0.0, 123.0, 7.0, render::Color { r: 1, g: 2, b: 3, a: 4 })]);
+    }
+
+    #[test]
+    fn test_visibility_inherits_and_opacity_multiplies() {
+        let mut doc = html::parse_html(
+            "<div id='w'>\
+               <div style='visibility: hidden; background-color: red'>hidden text\
+                 <div style='height: 4px; background-color: blue'></div>\
+                 <div style='visibility: visible; height: 4px; background-color: #00ff00'></div>\
+               </div>\
+               <div style='opacity: 0.5'><div style='opacity: 50%; height: 4px; background-color: black'></div></div>\
+             </div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let w_taffy = match doc.nodes.get(doc.id_map["w"]) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected element"),
+        };
+        let mut backend = RecordingBackend::default();
+        render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, doc.id_map["w"], w_taffy, 0.0, 0.0, &buffer_cache);
+
+        assert_eq!(backend.ops, ["fill", "fill"], "hidden parent, its text and hidden child paint nothing");
+        assert_eq!(backend.fills[0].4, render::Color { r: 0, g: 255, b: 0, a: 255 });
+        assert_eq!(backend.fills[1].4, render::Color { r: 0, g: 0, b: 0, a: 64 });
+    }
+
+    #[test]
+    fn test_outline_strokes_outside_layout_box() {
+        let mut doc = html::parse_html(
+            "<div id='o' style='width: 40px; height: 20px; outline: 2px solid red;'></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let div_id = *doc.id_map.get("o").unwrap();
+        let div_taffy = match doc.nodes.get(div_id) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected element"),
+        };
+        let box_layout = *doc.taffy_tree.layout(div_taffy).unwrap();
+
+        let mut backend = RecordingBackend::default();
+        render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, div_id, div_taffy, 0.0, 0.0, &buffer_cache);
+
+        assert_eq!(backend.strokes.len(), 1, "outline should stroke exactly once");
+        let (x, y, w, h, line_width, color) = backend.strokes[0];
+        assert_eq!(line_width, 2.0);
+        assert_eq!(color, render::Color { r: 255, g: 0, b: 0, a: 255 });
+        assert!(x < box_layout.location.x && y < box_layout.location.y);
+        assert!(w > box_layout.size.width && h > box_layout.size.height);
+        assert_eq!(box_layout.size.width, 40.0, "outline must not consume layout space");
+    }
+
+    #[test]
+    fn test_before_pseudo_element_renders_first() {
+        let sheet = css::parse_stylesheet("div::before { content: \"x\"; color: #ff0000; }");
+        let f = LayoutFixture::with_sheet("<div id='p'>abc</div>", &sheet);
+        let doc = &f.doc;
+
+        let div_id = *doc.id_map.get("p").unwrap();
+        let div_taffy = match doc.nodes.get(div_id) {
+            Some(dom::Node::Element(d)) => {
+                assert!(d.pseudo_before.is_some() && d.pseudo_after.is_none());
+                d.taffy_node.unwrap()
+            }
+            _ => panic!("expected element"),
+        };
+        assert_eq!(doc.first_child_of(div_id), doc.last_child_of(div_id), "generated node is not a DOM child");
+
+        let mut backend = RecordingBackend::default();
+        render::draw_layout_tree(&mut backend, doc, &doc.taffy_tree, div_id, div_taffy, 0.0, 0.0, &f.buffer_cache);
+
+        assert_eq!(backend.glyph_runs.len(), 2);
+        let (before_x, before_y, before_glyphs, before_color) = backend.glyph_runs[0];
+        let (real_x, real_y, real_glyphs, _) = backend.glyph_runs[1];
+        assert_eq!((before_glyphs, real_glyphs), (1, 3));
+        assert_eq!(before_color, render::Color { r: 255, g: 0, b: 0, a: 255 });
+        // Both are inline text, so they share a line with ::before first.
+        assert_eq!(before_y, real_y);
+        assert!(before_x < real_x, "::before content should precede the element's own text");
+
+        // Generated text follows a change to the color it inherits.
+        let mut doc = html::parse_html("<section id='s' style='color: #ff0000'><div id='p'>abc</div></section>");
+        let sheet = css::parse_stylesheet("div::before { content: \"x\" }");
+        css::compute_styles(&mut doc, &sheet);
+        let before_color = |doc: &dom::Document| match doc.nodes.get(doc.id_map["p"]) {
+            Some(dom::Node::Element(d)) => match d.pseudo_before.and_then(|b| doc.nodes.get(b)) {
+                Some(dom::Node::Text(t)) => t.computed.color,
+                _ => panic!("expected generated text"),
+            },
+            _ => panic!("expected element"),
+        };
+        assert_eq!(before_color(&doc), (255, 0, 0, 255));
+        let section = doc.id_map["s"];
+        doc.set_attribute(section, "style", "color: #0000ff".to_string());
+        css::compute_styles(&mut doc, &sheet);
+        assert_eq!(before_color(&doc), (0, 0, 255, 255));
+    }
+
+    #[test]
+    fn test_css_counters_in_generated_content() {
+        fn before(doc: &dom::Document, id: &str) -> String {
+            match doc.nodes.get(doc.id_map[id]) {
+                Some(dom::Node::Element(d)) => d.pseudo_before.map(|b| doc.text_content(b)).unwrap_or_default(),
+                _ => panic!("expected element"),
+            }
+        }
+
+        let mut doc = html::parse_html(
+            "<div id='w'><h2 id='a'>A</h2><h3 id='a1'>x</h3><h3 id='a2'>y</h3>\
+             <h2 id='b'>B</h2><h3 id='b1'>z</h3>\
+             <ol id='o'><li id='l1'>p<ol><li id='l11'>q</li><li id='l12'>r</li></ol></li><li id='l2'>s</li></ol></div>",
+        );
+        let sheet = css::parse_stylesheet(
+            "#w { counter-reset: sec }\
+             h2 { counter-increment: sec; counter-reset: sub }\
+             h3 { counter-increment: sub }\
+             h2::before { content: counter(sec) \". \" }\
+             h3::before { content: counter(sec) \".\" counter(sub) \" \" }\
+             ol { counter-reset: item; list-style-type: none }\
+             li { counter-increment: item }\
+             li::before { content: counters(item, \".\") \") \" }",
+        );
+        css::compute_styles(&mut doc, &sheet);
+
+        let got = ["a", "a1", "a2", "b", "b1"].map(|id| before(&doc, id));
+        assert_eq!(got, ["1. ", "1.1 ", "1.2 ", "2. ", "2.1 "]);
+        // Nested lists reset their own instance; `counters()` joins them all.
+        let got = ["l1", "l11", "l12", "l2"].map(|id| before(&doc, id));
+        assert_eq!(got, ["1) ", "1.1) ", "1.2) ", "2) "]);
+
+        // Inserting a heading renumbers the ones after it without restyling them.
+        let h2 = doc.add_node(dom::Node::Element(dom::ElementData::new(dom::LocalName::new("h2"))));
+        let w = doc.id_map["w"];
+        let first = doc.id_map["a"];
+        doc.remove_child(w, first);
+        doc.append_child(w, h2);
+        css::compute_styles(&mut doc, &sheet);
+        let got = ["a1", "a2", "b", "b1"].map(|id| before(&doc, id));
+        assert_eq!(got, ["0.1 ", "0.2 ", "1. ", "1.1 "]);
+    }
+
+    #[test]
+    fn test_list_item_markers() {
+        fn markers(doc: &dom::Document, list: dom::NodeId) -> Vec<Option<String>> {
+            std::iter::successors(doc.first_child_of(list), |&c| doc.next_sibling_of(c))
+                .filter_map(|c| match doc.nodes.get(c) {
+                    Some(dom::Node::Element(d)) => Some(d.pseudo_marker.map(|m| doc.text_content(m).trim_end().to_string())),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        let mut doc = html::parse_html("<ol id='o'><li>a</li><p>x</p><li>b</li><li>c</li></ol><ul id='u'><li>d</li></ul>");
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let (ol, ul) = (doc.id_map["o"], doc.id_map["u"]);
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(markers(&doc, ol), [some("1."), None, some("2."), some("3.")]);
+        assert_eq!(markers(&doc, ul), [some("\u{2022}")]);
+
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let ol_taffy = match doc.nodes.get(ol) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected element"),
+        };
+        let mut backend = RecordingBackend::default();
+        render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, ol, ol_taffy, 0.0, 0.0, &buffer_cache);
+        // Marker, then the item's own text, on the same line.
+        let runs = &backend.glyph_runs;
+        assert_eq!(runs.len(), 7);
+        for (marker, text) in [(runs[0], runs[1]), (runs[3], runs[4]), (runs[5], runs[6])] {
+            assert_eq!(marker.1, text.1);
+            assert!(marker.0 < text.0 && text.2 == 1);
+        }
+
+        // Inserting an item renumbers its siblings; `none` drops the markers.
+        let li = doc.add_node(dom::Node::Element(dom::ElementData::new(dom::LocalName::new("li"))));
+        doc.append_child(ol, li);
+        let sheet = css::parse_stylesheet("ul { list-style: inside none; } ol { list-style-type: circle; }");
+        css::compute_styles(&mut doc, &sheet);
+        assert_eq!(markers(&doc, ol), [some("\u{25e6}"), None, some("\u{25e6}"), some("\u{25e6}"), some("\u{25e6}")]);
+        assert_eq!(markers(&doc, ul), [None]);
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        assert_eq!(markers(&doc, ol).last(), Some(&some("4.")));
+    }
+
+    #[test]
+    fn test_text_transform_shapes_transformed_copy() {
+        let mut doc = html::parse_html(
+            "<p id='u' style='text-transform: uppercase'>abc <b>stra\u{df}e</b></p>\
+             <p id='c' style='text-transform: capitalize'>hello (big) world<span style='text-transform: none'> kept</span></p>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let shaped: Vec<(String, String, usize)> = doc
+            .nodes
+            .iter()
+            .filter_map(|(id, node)| match node {
+                dom::Node::Text(t) => {
+                    let buffer = &buffer_cache[&id];
+                    let glyphs = buffer.layout_runs().map(|r| r.glyphs.len()).sum();
+                    Some((t.text.clone(), buffer.lines[0].text().to_string(), glyphs))
+                }
+                _ => None,
+            })
+            .collect();
+        let find = |source: &str| shaped.iter().find(|(t, _, _)| t == source).expect(source);
+        // `TextData::text` keeps the source casing; only the shaped copy changes.
+        assert_eq!(find("abc "), &("abc ".to_string(), "ABC ".to_string(), 4));
+        assert_eq!(find("stra\u{df}e").1, "STRASSE");
+        assert_eq!(find("hello (big) world").1, "Hello (Big) World");
+        assert_eq!(find(" kept").1, " kept");
+    }
+
+    #[test]
+    fn test_backend_text_metrics_size_text_nodes() {
+        /// Monospace backend: 10px per character, 20px lines.
+        struct FixedMetrics;
+        impl render::RendererBackend for FixedMetrics {
+            fn fill_rect(&mut self, _: f32, _: f32, _: f32, _: f32, _: render::Color) {}
+            fn stroke_rect(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: render::Color) {}
+            fn draw_glyphs(&mut self, _: f32, _: f32, _: &[cosmic_text::LayoutGlyph], _: f32, _: render::Color) {}
+            fn measure_text(&self, text: &str, _size: f32) -> Option<(f32, f32)> {
+                Some((text.chars().count() as f32 * 10.0, 20.0))
+            }
+        }
+
+        let html = "<div style='display: flex; align-items: flex-start'><p>abcd</p><p style='line-height: 30px'>ab</p></div>";
+        let texts = |doc: &dom::Document| {
+            let p = find_node(doc, "p").unwrap();
+            [p, doc.next_sibling_of(p).unwrap()]
+                .map(|p| layout::absolute_rect(doc, doc.first_child_of(p).unwrap()).unwrap())
+        };
+
+        let mut doc = html::parse_html(html);
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout_with_metrics(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache, &FixedMetrics);
+        let [a, b] = texts(&doc);
+        assert_eq!((a.width, a.height), (40.0, 20.0));
+        // An explicit `line-height` wins over the backend's.
+        assert_eq!((b.width, b.height), (20.0, 30.0));
+
+        // The default backend method leaves cosmic-text's metrics in place.
+        let mut doc = html::parse_html(html);
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        layout::compute_layout_with_metrics(
+            &mut doc,
+            800.0,
+            600.0,
+            &mut font_system,
+            &mut std::collections::HashMap::new(),
+            &RecordingBackend::default(),
+        );
+        let plain = texts(&doc);
+        assert!(plain[0].width > 0.0 && plain[0].width != 40.0);
+    }
+
+    #[test]
+    fn test_white_space_modes() {
+        // Shaped lines and wrapped line count of `text` in a `width`px box.
+        let shape = |mode: &str, text: &str, width: f32| {
+            let mut doc = html::parse_html(&format!("<div style='width: {width}px; white-space: {mode}'>{text}</div>"));
+            css::compute_styles(&mut doc, &css::StyleSheet::default());
+            let mut font_system = cosmic_text::FontSystem::new();
+            let mut buffer_cache = std::collections::HashMap::new();
+            layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+            let text = doc.first_child_of(find_node(&doc, "div").unwrap()).unwrap();
+            let buffer = &buffer_cache[&text];
+            let lines: Vec<String> = buffer.lines.iter().map(|l| l.text().to_string()).collect();
+            (lines, buffer.layout_runs().count())
+        };
+
+        let text = "a  b \n  c\td";
+        assert_eq!(shape("normal", text, 500.0).0, ["a b c d"]);
+        assert_eq!(shape("nowrap", text, 500.0).0, ["a b c d"]);
+        assert_eq!(shape("pre-line", text, 500.0).0, ["a b", "c d"]);
+        let preserved = ["a  b ", "  c        d"];
+        assert_eq!(shape("pre", text, 500.0).0, preserved);
+        assert_eq!(shape("pre-wrap", text, 500.0).0, preserved);
+
+        // Only `pre` and `nowrap` keep a long line from wrapping.
+        let words = "aaaa bbbb cccc dddd";
+        for (mode, wraps) in [("normal", true), ("nowrap", false), ("pre", false), ("pre-wrap", true), ("pre-line", true)] {
+            assert_eq!(shape(mode, words, 40.0).1 > 1, wraps, "{mode}");
+        }
+
+        // `<pre>` defaults to `white-space: pre`.
+        let mut doc = html::parse_html("<pre>x  y</pre>");
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let text = doc.first_child_of(find_node(&doc, "pre").unwrap()).unwrap();
+        match doc.nodes.get(text) {
+            Some(dom::Node::Text(t)) => assert_eq!(t.computed.white_space, dom::WhiteSpaceKeyword::Pre),
+            _ => panic!("expected text"),
+        }
+    }
+
+    #[test]
+    fn test_compiled_matcher_reused() {
+        let items: String = (0..100).map(|i| if i % 3 == 0 { "<li class='item'></li>" } else { "<li></li>" }).collect();
+        let doc = html::parse_html(&format!("<ul id='l'>{items}</ul><p class='item'></p>"));
+        let matcher = css::Matcher::compile(".item");
+        let list = doc.id_map["l"];
+        let matched = doc.descendants(list).filter(|&id| matcher.matches(id, &doc)).count();
+        assert_eq!(matched, 34);
+        assert_eq!(doc.descendants(doc.root_id).filter(|&id| matcher.matches(id, &doc)).count(), 35);
+
+        let nested = css::Matcher::compile("ul > .item, p");
+        assert_eq!(nested.selectors().len(), 2);
+        assert_eq!(doc.descendants(doc.root_id).filter(|&id| nested.matches(id, &doc)).count(), 35);
+        assert!(!css::Matcher::compile("").matches(list, &doc));
+    }
+
+    #[test]
+    fn test_stylesheet_errors_keep_valid_rules() {
+        let (sheet, errors) = css::parse_stylesheet_with_errors(
+            "div { color: red; colr: blue; width 10px; height: ; margin: 1px }\n> { color: red }\np { color: blue }",
+        );
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "1:19: unknown property `colr`",
+                "1:31: expected `:` after `width`",
+                "1:43: empty value for `height`",
+                "2:1: invalid selector `>`",
+            ]
+        );
+
+        let mut doc = html::parse_html("<div id='d'></div><p id='p'></p>");
+        css::compute_styles(&mut doc, &sheet);
+        let element = |id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d,
+            _ => panic!("expected element"),
+        };
+        assert_eq!(element("d").computed.color, (255, 0, 0, 255));
+        assert_eq!(element("d").computed.margin[0], dom::StyleValue::LengthPx(1.0));
+        assert_eq!(element("d").computed.width, dom::StyleValue::Auto);
+        assert_eq!(element("p").computed.color, (0, 0, 255, 255));
+        assert!(css::parse_stylesheet_with_errors("a { color: red }").1.is_empty());
+
+        let (sheet, errors) = css::parse_stylesheet_with_errors("@media print { a { color: red } }\np { color: blue }");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "1:1: unsupported at-rule `@media`");
+        assert_eq!(sheet.next_rule_index, 1);
+        assert!(sheet.by_tag.keys().all(|tag| &**tag == "p"));
+    }
+
+    #[test]
+    fn test_parse_selector_round_trips() {
+        let selector = css::parse_selector("div.card>  span").unwrap();
+        assert_eq!(selector.last.parts, [css::SimpleSelector::Tag(dom::LocalName::new("span"))]);
+        let [(css::Combinator::Child, parent)] = &selector.ancestors[..] else { panic!("{:?}", selector.ancestors) };
+        assert_eq!(
+            parent.parts,
+            [css::SimpleSelector::Tag(dom::LocalName::new("div")), css::SimpleSelector::Class("card".into())]
+        );
+        assert_eq!(selector.specificity, (0, 1, 2));
+        assert_eq!(selector.to_string(), "div.card > span");
+        assert_eq!(css::parse_selector(&selector.to_string()).unwrap().to_string(), "div.card > span");
+
+        assert_eq!(css::parse_selector("#a ~ [href]::before").unwrap().to_string(), "#a ~ [href]::before");
+        assert!(css::parse_selector("").is_none());
+        assert!(css::parse_selector("a, b").is_none());
+
+        // Commas nested in parentheses or quotes do not make a list.
+        let not = css::parse_selector("p:not(a, b)").unwrap();
+        assert!(not.ancestors.is_empty(), "spaces inside parentheses do not split the compound");
+        assert_eq!(not.to_string(), "p:not(a, b)");
+        assert_eq!(css::parse_selector("[title=\"a,b\"]").unwrap().last.parts.len(), 1);
+        assert!(css::parse_selector("::before").is_some(), "a bare pseudo-element is a selector");
+        assert!(css::parse_selector("ul > li:lang(en)::after").is_some());
+
+        for malformed in ["div >", "> div", "a > + b", "%%", "div.", "#", "a:", "[href", "[]", "p)", "div::bogus"] {
+            assert!(css::parse_selector(malformed).is_none(), "{malformed:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_attribute_presence_selector() {
+        let doc = html::parse_html(
+            "<div id='empty' data-active=''></div><div id='one' data-active='1'></div><div id='none'></div>\
+             <input id='btn' class='primary' disabled><input id='plain' class='primary'>",
+        );
+        let matches = |selector: &str, id: &str| {
+            css::element_matches(&doc, doc.id_map[id], &css::parse_selector_list(selector))
+        };
+        assert!(matches("[data-active]", "empty"));
+        assert!(matches("[data-active]", "one"));
+        assert!(!matches("[data-active]", "none"));
+        assert!(matches("div[data-active]#one", "one"));
+        for selector in ["input[disabled].primary", ".primary[disabled]", "#btn[disabled]", "[disabled]:not(.x)"] {
+            assert!(matches(selector, "btn"), "{selector}");
+            assert!(!matches(selector, "plain"), "{selector}");
+        }
+        assert_eq!(css::parse_selector_list("input[disabled].primary")[0].to_string(), "input[disabled].primary");
+    }
+
+    #[test]
+    fn test_lang_pseudo_class_and_inheritance() {
+        let mut doc = html::parse_html(
+            "<div lang='en'><section><p id='a'>x</p></section></div>\
+             <div lang='en-GB'><p id='b'>y</p></div>\
+             <div lang='fr'><p id='c'>z</p><p id='d' lang='EN'>w</p></div>\
+             <p id='e'>v</p>",
+        );
+        let sheet = css::parse_stylesheet("p:lang(en) { color: #ff0000 } :lang(fr) { font-weight: 700 }");
+        css::compute_styles(&mut doc, &sheet);
+
+        let style = |id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d.computed.clone(),
+            _ => panic!("expected element"),
+        };
+        let red = (255, 0, 0, 255);
+        assert_eq!(style("a").color, red, "inherited from an ancestor's lang");
+        assert_eq!(style("b").color, red, "subtags match");
+        assert_ne!(style("c").color, red);
+        assert_eq!(style("c").font_weight, 700);
+        assert_eq!(style("d").color, red, "nearest lang wins, case-insensitively");
+        assert_ne!(style("e").color, red, "no lang anywhere");
+
+        assert_eq!(style("a").lang.as_deref(), Some("en"));
+        assert_eq!(doc.lang(doc.id_map["d"]), Some("EN"));
+        let text = doc.first_child_of(doc.id_map["b"]).unwrap();
+        match doc.nodes.get(text) {
+            Some(dom::Node::Text(t)) => assert_eq!(t.computed.lang.as_deref(), Some("en-GB")),
+            _ => panic!("expected text"),
+        }
+    }
+
+    #[test]
+    fn test_incremental_layout_dirties_changed_path_only() {
+        let html = |width: u32| {
+            format!(
+                "<div id='w' style='display: flex; align-items: flex-start'>\
+                 <div id='a' style='width: {width}px'><p id='pa'>x</p></div>\
+                 <div id='b'><p id='pb'>y</p></div></div>"
+            )
+        };
+        let taffy_of = |doc: &dom::Document, id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected element"),
+        };
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        let mut doc = html::parse_html(&html(50));
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let a = doc.id_map["a"];
+        doc.set_attribute(a, "style", "width: 120px".to_string());
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        layout::update_layout_tree(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let dirty = |doc: &dom::Document, id: &str| doc.taffy_tree.dirty(taffy_of(doc, id)).unwrap();
+        assert!(dirty(&doc, "a") && dirty(&doc, "w"), "the changed node and its ancestors");
+        assert!(!dirty(&doc, "pa") && !dirty(&doc, "b") && !dirty(&doc, "pb"));
+
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let mut fresh = html::parse_html(&html(120));
+        css::compute_styles(&mut fresh, &css::StyleSheet::default());
+        layout::compute_layout(&mut fresh, 800.0, 600.0, &mut font_system, &mut std::collections::HashMap::new());
+        for id in ["w", "a", "pa", "b", "pb"] {
+            assert_eq!(
+                layout::absolute_rect(&doc, doc.id_map[id]),
+                layout::absolute_rect(&fresh, fresh.id_map[id]),
+                "{id}"
+            );
+        }
+
+        // Hosts can invalidate a node explicitly.
+        assert!(!dirty(&doc, "b"));
+        doc.mark_layout_dirty(doc.id_map["pb"]);
+        assert!(dirty(&doc, "pb") && dirty(&doc, "b") && !dirty(&doc, "a"));
+    }
+
+    #[test]
+    fn test_set_hidden_toggles_without_restyle() {
+        let mut doc = html::parse_html(
+            "<div id='w' style='display: flex; align-items: flex-start'>\
+             <div id='a' style='width: 40px; height: 30px'>x</div><div id='b' style='width: 20px; height: 10px'></div></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let (a, b) = (doc.id_map["a"], doc.id_map["b"]);
+        let rect = |doc: &dom::Document, id| layout::absolute_rect(doc, id).unwrap();
+        let (shown_a, shown_b) = (rect(&doc, a), rect(&doc, b));
+        assert_eq!(shown_b.x, 40.0);
+
+        assert!(doc.set_hidden(a, true));
+        assert!(!doc.set_hidden(a, true), "already hidden");
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        assert_eq!(rect(&doc, a).width, 0.0);
+        assert_eq!(rect(&doc, b).x, 0.0, "the sibling takes the freed space");
+
+        // A restyle while hidden keeps the element hidden.
+        doc.set_attribute(a, "style", "width: 60px; height: 30px".to_string());
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        assert_eq!(rect(&doc, b).x, 0.0);
+        doc.set_attribute(a, "style", "width: 40px; height: 30px".to_string());
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+
+        assert!(doc.set_hidden(a, false));
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        assert_eq!((rect(&doc, a), rect(&doc, b)), (shown_a, shown_b));
+        match doc.nodes.get(a) {
+            Some(dom::Node::Element(d)) => assert_eq!(d.computed.display, dom::DisplayKeyword::Block),
+            _ => panic!("expected element"),
+        }
+    }
+
+    #[test]
+    fn test_tab_size_expands_tabs() {
+        // Width of the shrink-to-fit text inside `<pre style='{style}'>{text}</pre>`.
+        let measure = |style: &str, text: &str| {
+            let mut doc = html::parse_html(&format!(
+                "<div style='display: flex; align-items: flex-start'><pre style='{style}'>{text}</pre></div>"
+            ));
+            css::compute_styles(&mut doc, &css::StyleSheet::default());
+            let mut font_system = cosmic_text::FontSystem::new();
+            let mut buffer_cache = std::collections::HashMap::new();
+            layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+            let text = doc.first_child_of(find_node(&doc, "pre").unwrap()).unwrap();
+            layout::absolute_rect(&doc, text).unwrap().width
+        };
+        let tab4 = measure("tab-size: 4", "\tx");
+        let spaces4 = measure("", "    x");
+        assert!(tab4 > 0.0 && (tab4 - spaces4).abs() < 0.01, "{tab4} vs {spaces4}");
+        let tab8 = measure("", "\tx");
+        assert!(tab8 > tab4);
+        assert!((tab8 - measure("", "        x")).abs() < 0.01, "tab-size defaults to 8");
+    }
+
+    #[test]
+    fn test_float_pulls_to_container_edge() {
+        let mut doc = html::parse_html(
+            "<div id='w' style='width: 300px'>\
+             <div id='box' style='float: right; width: 50px; height: 10px'></div>\
+             <p id='p'><span id='r1' style='float: right'>one</span>text<span id='l' style='float: left'>L</span>\
+             <span id='r2' style='float: right'>two</span></p></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let rect = |k: &str| layout::absolute_rect(&doc, doc.id_map[k]).unwrap();
+        let right_edge = |k: &str| rect(k).x + rect(k).width;
+        assert_eq!((rect("box").x, rect("box").width), (250.0, 50.0));
+
+        // The first right float sits at the end edge, later ones to its left;
+        // the left float moves ahead of the text.
+        assert!((right_edge("r1") - 300.0).abs() < 0.5, "{:?}", rect("r1"));
+        assert!((right_edge("r2") - rect("r1").x).abs() < 0.5);
+        assert_eq!(rect("l").x, 0.0);
+        let text = doc.first_child_of(doc.id_map["p"]).and_then(|c| doc.next_sibling_of(c)).unwrap();
+        let text_x = layout::absolute_rect(&doc, text).unwrap().x;
+        assert!((text_x - right_edge("l")).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_float_css_wide_keywords() {
+        let mut doc = html::parse_html(
+            "<div id='w' class='r'><span id='inherit'></span><span id='initial' class='r'></span>\
+             <span id='unset' class='r'></span></div>",
+        );
+        let sheet = css::parse_stylesheet(
+            ".r { float: right } #inherit { float: inherit } #initial { float: initial } #unset { float: unset }",
+        );
+        css::compute_styles(&mut doc, &sheet);
+        let float = |id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d.computed.float,
+            _ => panic!("expected element"),
+        };
+        assert_eq!(float("w"), dom::FloatKeyword::Right);
+        assert_eq!(float("inherit"), dom::FloatKeyword::Right);
+        assert_eq!(float("initial"), dom::FloatKeyword::None);
+        assert_eq!(float("unset"), dom::FloatKeyword::None, "float is not inherited, so unset is initial");
+    }
+
+    #[test]
+    fn test_letter_and_word_spacing_widen_text() {
+        // (text width, line count, glyph runs drawn) of a shrink-to-fit span.
+        let measure = |style: &str| {
+            let mut doc = html::parse_html(&format!(
+                "<div style='display: flex; align-items: flex-start'><span style='{style}'>abc def</span></div>"
+            ));
+            css::compute_styles(&mut doc, &css::StyleSheet::default());
+            let mut font_system = cosmic_text::FontSystem::new();
+            let mut buffer_cache = std::collections::HashMap::new();
+            layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+            let span = find_node(&doc, "span").unwrap();
+            let text = doc.first_child_of(span).unwrap();
+            let t_node = match doc.nodes.get(text) {
+                Some(dom::Node::Text(t)) => t.taffy_node.unwrap(),
+                _ => panic!("expected text"),
+            };
+            let width = doc.taffy_tree.layout(t_node).unwrap().size.width;
+            let lines = buffer_cache[&text].layout_runs().count();
+            let span_taffy = match doc.nodes.get(span) {
+                Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+                _ => unreachable!(),
+            };
+            let mut backend = RecordingBackend::default();
+            render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, span, span_taffy, 0.0, 0.0, &buffer_cache);
+            (width, lines, backend.glyph_runs.len())
+        };
+        let (plain, plain_lines, _) = measure("");
+        let (lettered, lettered_lines, runs) = measure("letter-spacing: 4px");
+        let (worded, _, _) = measure("word-spacing: 0.5em");
+        // Seven glyphs, each followed by 4px; one space widened by 8px.
+        assert!((lettered - plain - 28.0).abs() < 0.5, "{plain} -> {lettered}");
+        assert!((worded - plain - 8.0).abs() < 0.5, "{plain} -> {worded}");
+        assert_eq!((plain_lines, lettered_lines, runs), (1, 1, 1));
+    }
+
+    /// A parsed, styled document laid out at 800x600, with the font system
+    /// and buffer cache kept for further passes and further documents.
+    struct LayoutFixture {
+        doc: dom::Document,
+        font_system: cosmic_text::FontSystem,
+        buffer_cache: std::collections::HashMap<dom::NodeId, cosmic_text::Buffer>,
+    }
+
+    impl LayoutFixture {
+        fn new(html_src: &str) -> Self {
+            Self::with_sheet(html_src, &css::StyleSheet::default())
+        }
+
+        fn with_sheet(html_src: &str, sheet: &css::StyleSheet) -> Self {
+            let mut fixture = LayoutFixture {
+                doc: dom::Document::new(),
+                font_system: cosmic_text::FontSystem::new(),
+                buffer_cache: std::collections::HashMap::new(),
+            };
+            fixture.load(html_src, sheet);
+            fixture
+        }
+
+        /// Replaces the document, reusing the font system.
+        fn load(&mut self, html_src: &str, sheet: &css::StyleSheet) {
+            self.doc = html::parse_html(html_src);
+            self.buffer_cache.clear();
+            self.update(sheet);
+        }
+
+        /// Restyles the current document and lays it out again at 800x600.
+        fn update(&mut self, sheet: &css::StyleSheet) {
+            css::compute_styles(&mut self.doc, sheet);
+            self.layout(800.0, 600.0);
+        }
+
+        fn layout(&mut self, width: f32, height: f32) {
+            layout::compute_layout(&mut self.doc, width, height, &mut self.font_system, &mut self.buffer_cache);
+        }
+
+        /// The first text node in the arena.
+        fn text_id(&self) -> dom::NodeId {
+            self.doc
+                .nodes
+                .iter()
+                .find_map(|(id, n)| matches!(n, dom::Node::Text(_)).then_some(id))
+                .expect("text node")
+        }
+
+        fn text_buffer(&self) -> &cosmic_text::Buffer {
+            &self.buffer_cache[&self.text_id()]
+        }
+    }
+
+    fn text_line_count(html_src: &str) -> usize {
+        LayoutFixture::new(html_src).text_buffer().layout_runs().count()
+    }
+
+    #[test]
+    fn test_word_break_keep_all_suppresses_wrapping() {
+        let text = "alpha beta gamma delta epsilon zeta eta theta";
+        let wrapped = text_line_count(&format!("<div style='width: 60px;'>{}</div>", text));
+        let kept = text_line_count(&format!(
+            "<div style='width: 60px; word-break: keep-all;'>{}</div>",
+            text
+        ));
+        assert!(wrapped > 1, "default wrapping should break the line, got {}", wrapped);
+        assert_eq!(kept, 1, "keep-all should keep the text on one overflowing line");
+    }
+
+    #[test]
+    fn test_line_clamp_truncates_to_one_line() {
+        let mut f = LayoutFixture::new("<div style='width: 60px; line-clamp: 1;'>alpha beta gamma delta epsilon zeta</div>");
+        let buffer = f.text_buffer();
+        assert_eq!(buffer.layout_runs().count(), 1);
+        assert!(buffer.lines[0].text().ends_with('\u{2026}'), "clamped line should end in an ellipsis");
+
+        let Some(dom::Node::Text(data)) = f.doc.nodes.get(f.text_id()) else { unreachable!() };
+        let height = f.doc.taffy_tree.layout(data.taffy_node.unwrap()).unwrap().size.height;
+        assert!((height - 16.0 * 1.2).abs() < 1.0, "measured height should cover only the retained line, got {}", height);
+
+        // Breaking anywhere fills the last line, so the cut taken from glyph
+        // positions leaves it nearly full with the ellipsis inside the width.
+        f.load(
+            "<div style='width: 80px; line-clamp: 2; word-break: break-all'>abcdefghijklmnopqrstuvwxyzabcdefghijklmnop</div>",
+            &css::StyleSheet::default(),
+        );
+        let runs: Vec<_> = f.text_buffer().layout_runs().map(|run| (run.text.to_string(), run.line_w)).collect();
+        assert_eq!(runs.len(), 2, "{runs:?}");
+        assert!(runs[1].0.ends_with('\u{2026}'), "{runs:?}");
+        assert!(runs[1].1 <= 80.0 && runs[1].1 > 60.0, "last line should stay nearly full: {runs:?}");
+
+        // A pass with nothing changed keeps the clamped line (its metadata
+        // would be gone had it been rebuilt); a new width clamps again from
+        // the full text.
+        f.load(
+            "<style>p::first-letter { color: #ff0000 }</style><p style='width: 50%; line-clamp: 2'>alpha beta gamma delta epsilon zeta eta theta iota kappa lambda</p>",
+            &css::StyleSheet::default(),
+        );
+        f.layout(300.0, 600.0);
+        let text_id = f.text_id();
+        f.buffer_cache.get_mut(&text_id).unwrap().lines[0].set_metadata(7);
+        f.layout(300.0, 600.0);
+        assert_eq!(f.text_buffer().lines[0].metadata(), Some(7), "unchanged clamped text is not reshaped");
+
+        layout::relayout(&mut f.doc, 200.0, 600.0, &mut f.font_system, &mut f.buffer_cache).unwrap();
+        let buffer = f.text_buffer();
+        let widths: Vec<_> = buffer.layout_runs().map(|run| run.line_w).collect();
+        assert_eq!(widths.len(), 2, "{widths:?}");
+        assert!(widths.iter().all(|&w| w <= 100.0), "{widths:?}");
+        let line = &buffer.lines[0];
+        assert!(line.text().starts_with("alpha") && line.text().ends_with('\u{2026}'), "{:?}", line.text());
+        let red = cosmic_text::Color::rgba(255, 0, 0, 255);
+        assert_eq!(line.attrs_list().get_span(0).color_opt, Some(red), "the first-letter span survives the clamp");
+        assert_ne!(line.attrs_list().get_span(1).color_opt, Some(red));
+    }
+
+    /// Absolute (x, y) of every laid-out node, in document order.
+    fn absolute_positions(doc: &dom::Document) -> Vec<(dom::NodeId, (f32, f32))> {
+        let taffy_of = |id| match doc.nodes.get(id) {
+            Some(dom::Node::Element(d)) => d.taffy_node,
+            Some(dom::Node::Text(d)) => d.taffy_node,
+            Some(dom::Node::Root(d)) => d.taffy_node,
+            None => None,
+        };
+        let mut out = Vec::new();
+        let mut stack = vec![(doc.root_id, (0.0, 0.0))];
+        while let Some((id, (x, y))) = stack.pop() {
+            let Some(t) = taffy_of(id) else { continue };
+            let location = doc.taffy_tree.layout(t).unwrap().location;
+            let (x, y) = (x + location.x, y + location.y);
+            out.push((id, (x, y)));
+            let children: Vec<_> = doc.layout_children(id).collect();
+            for c in children.into_iter().rev() {
+                let Some(ct) = taffy_of(c) else { continue };
+                let (ax, ay) = layout::anonymous_box_offset(&doc.taffy_tree, t, ct);
+                stack.push((c, (x + ax, y + ay)));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_line_height_agrees_across_measure_and_render() {
+        for (decl, expected) in [("line-height: 1.5", 30.0), ("line-height: 2em", 40.0), ("line-height: 25px", 25.0)] {
+            let mut doc = html::parse_html(&format!(
+                "<div style='width: 120px; font-size: 20px; {decl}'><p>one two three four five six seven eight</p></div>"
+            ));
+            css::compute_styles(&mut doc, &css::StyleSheet::default());
+            let mut font_system = cosmic_text::FontSystem::new();
+            let mut buffer_cache = std::collections::HashMap::new();
+            layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+            let (text_id, text) = doc
+                .nodes
+                .iter()
+                .find_map(|(id, n)| match n {
+                    dom::Node::Text(t) => Some((id, t)),
+                    _ => None,
+                })
+                .unwrap();
+            // The <p> inherits the declared value; `2em` was resolved against the div.
+            assert_eq!(layout::resolved_line_height(text.computed.line_height, text.computed.font_size), expected);
+
+            // Buffer metrics, which fix where `render` places each run.
+            let buffer = &buffer_cache[&text_id];
+            assert_eq!(buffer.metrics().line_height, expected, "{decl}");
+            let line_ys: Vec<f32> = buffer.layout_runs().map(|r| r.line_y).collect();
+            assert!(line_ys.len() > 1, "text should wrap at 120px");
+            for pair in line_ys.windows(2) {
+                assert!((pair[1] - pair[0] - expected).abs() < 0.01, "{decl}: run spacing {line_ys:?}");
+            }
+
+            // Measured box height.
+            let height = doc.taffy_tree.layout(text.taffy_node.unwrap()).unwrap().size.height;
+            assert!((height - expected * line_ys.len() as f32).abs() < 0.01, "{decl}: height {height}");
+        }
+    }
+
+    #[test]
+    fn test_relayout_rewraps_without_rebuilding() {
+        let mut doc = html::parse_html("<div style='width: 50%'><p>one two three four five six seven eight nine ten</p></div>");
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        let root = layout::compute_layout(&mut doc, 1600.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let (text_id, text_taffy) = doc
+            .nodes
+            .iter()
+            .find_map(|(id, n)| match n {
+                dom::Node::Text(t) => Some((id, t.taffy_node.unwrap())),
+                _ => None,
+            })
+            .unwrap();
+        let lines = |cache: &std::collections::HashMap<dom::NodeId, cosmic_text::Buffer>| {
+            cache[&text_id].layout_runs().count()
+        };
+        assert_eq!(lines(&buffer_cache), 1);
+        let wide_height = doc.taffy_tree.layout(text_taffy).unwrap().size.height;
+        let node_count = doc.taffy_tree.total_node_count();
+
+        assert_eq!(layout::relayout(&mut doc, 200.0, 600.0, &mut font_system, &mut buffer_cache), Some(root));
+        assert!(lines(&buffer_cache) > 1, "text should rewrap in a 100px column");
+        assert!(doc.taffy_tree.layout(text_taffy).unwrap().size.height > wide_height);
+        assert_eq!(doc.taffy_tree.total_node_count(), node_count, "relayout must not rebuild the tree");
+
+        assert_eq!(layout::relayout(&mut html::parse_html("<p>x</p>"), 200.0, 600.0, &mut font_system, &mut buffer_cache), None);
+    }
+
+    #[test]
+    fn test_content_visibility_hidden_skips_subtree() {
+        let mut doc = html::parse_html(
+            "<div id='w'><div id='lazy' style='content-visibility: hidden'><p id='inner'>never shaped</p><p>nor this</p></div>\
+             <p id='after'>shown</p></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let id = |k: &str| *doc.id_map.get(k).unwrap();
+        assert_eq!(buffer_cache.len(), 1, "only the visible paragraph's text is shaped");
+        let inner = doc.first_child_of(id("inner")).unwrap();
+        assert!(!buffer_cache.contains_key(&inner));
+        assert!(layout::absolute_rect(&doc, id("inner")).is_none());
+
+        let lazy = layout::absolute_rect(&doc, id("lazy")).unwrap();
+        assert_eq!(lazy.height, 0.0, "hidden contents contribute no size");
+        assert_eq!(layout::absolute_rect(&doc, id("after")).unwrap().y, lazy.y);
+        assert!(layout::contents_skipped(&doc, id("lazy"), None));
+
+        let mut backend = RecordingBackend::default();
+        let w_taffy = match doc.nodes.get(id("w")) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected element"),
+        };
+        render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, id("w"), w_taffy, 0.0, 0.0, &buffer_cache);
+        assert_eq!(backend.ops.iter().filter(|&&op| op == "glyphs").count(), 1);
+    }
+
+    #[test]
+    fn test_font_family_list_falls_back() {
+        let decls = css::parse_inline_declarations("font-family: \"Foo\", Times New Roman, sans-serif");
+        assert_eq!(decls[0].value.to_css_string(), r#""Foo", "Times New Roman", sans-serif"#);
+
+        let mut f = LayoutFixture::new("<p>shaped text</p>");
+        let installed = f.font_system.db().faces().next().map(|face| face.families[0].0.clone());
+        let family_of = |f: &mut LayoutFixture, css: &str| {
+            f.load(&format!("<p style='font-family: {css}'>shaped text</p>"), &css::StyleSheet::default());
+            let buffer = f.text_buffer();
+            assert!(buffer.layout_runs().next().is_some_and(|run| !run.glyphs.is_empty()), "shaping failed for {css}");
+            cosmic_text::FamilyOwned::new(buffer.lines[0].attrs_list().defaults().family)
+        };
+
+        assert_eq!(family_of(&mut f, "\"Foo\", sans-serif"), cosmic_text::FamilyOwned::SansSerif);
+        assert_eq!(family_of(&mut f, "Foo, monospace, serif"), cosmic_text::FamilyOwned::Monospace);
+        if let Some(name) = installed {
+            assert_eq!(family_of(&mut f, &format!("Foo, \"{name}\", serif")), cosmic_text::FamilyOwned::Name(name));
+        }
+    }
+
+    #[test]
+    fn test_br_breaks_lines() {
+        let text_tops = |src: &str| {
+            let doc = LayoutFixture::new(src).doc;
+            absolute_positions(&doc)
+                .into_iter()
+                .filter(|(id, _)| matches!(doc.nodes.get(*id), Some(dom::Node::Text(_))))
+                .map(|(_, (_, y))| y)
+                .collect::<Vec<_>>()
+        };
+        let line = 16.0 * 1.2;
+        let tops = text_tops("<div>a<br>b</div>");
+        assert_eq!(tops.len(), 2);
+        assert!((tops[1] - tops[0] - line).abs() < 1.0, "b should sit one line below a: {tops:?}");
+
+        // The second <br> contributes an empty line.
+        let tops = text_tops("<div>a<br><br>b</div>");
+        assert!((tops[1] - tops[0] - 2.0 * line).abs() < 1.5, "expected a blank line between a and b: {tops:?}");
+    }
+
+    #[test]
+    fn test_direction_rtl_aligns_text_right() {
+        let glyph_runs = |dir: &str| {
+            let mut doc = html::parse_html(&format!("<div style='width: 300px; direction: {dir}'><p>abc</p></div>"));
+            css::compute_styles(&mut doc, &css::StyleSheet::default());
+            let mut font_system = cosmic_text::FontSystem::new();
+            let mut buffer_cache = std::collections::HashMap::new();
+            layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+            let p = doc.first_child_of(doc.first_child_of(doc.root_id).unwrap()).unwrap();
+            let text = doc.first_child_of(p).unwrap();
+            match doc.nodes.get(text) {
+                Some(dom::Node::Text(t)) => assert_eq!(t.text, "abc"),
+                _ => panic!("expected text"),
+            }
+            let run = buffer_cache[&text].layout_runs().next().expect("one line");
+            run.glyphs.iter().map(|g| (g.x, g.start)).collect::<Vec<_>>()
+        };
+
+        let ltr = glyph_runs("ltr");
+        let rtl = glyph_runs("rtl");
+        assert!(ltr[0].0 < 1.0, "ltr text starts at the left edge: {ltr:?}");
+        let right_edge = rtl.last().unwrap().0;
+        assert!(rtl[0].0 > 200.0 && right_edge < 300.0, "rtl text hugs the right edge: {rtl:?}");
+        // Latin runs keep their logical order inside an rtl line.
+        assert!(rtl.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1), "{rtl:?}");
+    }
+
+    #[test]
+    fn test_css_math_functions() {
+        let mut doc = html::parse_html(
+            "<div style='width: 200px; height: 100px'>\
+               <div id='clamped' style='width: clamp(50px, 100%, 80px); height: max(10px, min(50%, 2em))'></div>\
+               <div id='low' style='width: clamp(50px, 10%, 80px); height: min(40px, 1rem)'></div>\
+               <div id='nested' style='width: 50%'><div id='inner' style='width: max(10px, 50%)'></div></div>\
+             </div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let size = |id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => {
+                let s = doc.taffy_tree.layout(d.taffy_node.unwrap()).unwrap().size;
+                (s.width, s.height)
+            }
+            _ => panic!("expected element"),
+        };
+        assert_eq!(size("clamped"), (80.0, 32.0));
+        assert_eq!(size("low"), (50.0, 16.0));
+        assert_eq!(size("inner").0, 50.0, "percentages resolve against the parent's final width");
+
+        assert_eq!(
+            css::parse_style_value("clamp(50px,100%,min(80px,5em))").to_string(),
+            "clamp(50px, 100%, min(80px, 5em))"
+        );
+        assert!(matches!(css::parse_style_value("clamp(1px,2px)"), dom::StyleValue::Keyword(_)));
+        assert!(matches!(css::parse_style_value("min(red)"), dom::StyleValue::Keyword(_)));
+    }
+
+    #[test]
+    fn test_inline_spans_flow_on_one_line() {
+        let mut doc = html::parse_html("<div><span id='a'>one</span><span id='b'>two</span></div><p id='p'>next</p>");
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        let positions: std::collections::HashMap<_, _> = absolute_positions(&doc).into_iter().collect();
+        let (ax, ay) = positions[&doc.id_map["a"]];
+        let (bx, by) = positions[&doc.id_map["b"]];
+        assert_eq!(ay, by, "spans should share a line");
+        assert!(bx > ax, "second span should follow the first horizontally");
+        // Block siblings still stack below the line.
+        assert!(positions[&doc.id_map["p"]].1 > ay);
+
+        // Hit testing sees the span at its flowed position.
+        let hit = doc.hit_test(bx + 1.0, by + 1.0).unwrap();
+        assert!(hit == doc.id_map["b"] || doc.parent_of(hit) == Some(doc.id_map["b"]));
+    }
+
+    #[test]
+    fn test_pointer_events_none_passes_hits_through() {
+        let hit_at = |overlay_style: &str, x: f32, y: f32| {
+            let mut doc = html::parse_html(&format!(
+                "<div style='position: relative; width: 200px; height: 200px'>\
+                   <div id='overlay' style='position: absolute; top: 0; left: 0; width: 200px; height: 200px; {overlay_style}'>\
+                     <div id='button' style='pointer-events: auto; width: 20px; height: 20px'></div>\
+                   </div>\
+                   <div id='below' style='width: 200px; height: 200px'></div>\
+                 </div>"
+            ));
+            css::compute_styles(&mut doc, &css::StyleSheet::default());
+            let mut font_system = cosmic_text::FontSystem::new();
+            let mut buffer_cache = std::collections::HashMap::new();
+            layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+            let hit = doc.hit_test(x, y).expect("point is inside the document");
+            doc.id_map.iter().find(|(_, id)| **id == hit).map(|(k, _)| k.clone())
+        };
+        assert_eq!(hit_at("", 100.0, 100.0).as_deref(), Some("overlay"));
+        assert_eq!(hit_at("pointer-events: none", 100.0, 100.0).as_deref(), Some("below"));
+        // Descendants inherit `none` but can opt back in with `auto`.
+        assert_eq!(hit_at("pointer-events: none", 5.0, 5.0).as_deref(), Some("button"));
+    }
+
+    #[test]
+    fn test_cursor_at_hit_element() {
+        let mut doc = html::parse_html(
+            "<div style='width: 200px; height: 100px; cursor: pointer'>\
+               <span style='cursor: url(hand.png), text'>label</span>\
+               <div style='width: 20px; height: 20px; cursor: inherit'></div>\
+             </div>\
+             <div style='width: 200px; height: 100px'></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+
+        assert_eq!(layout::cursor_at(&doc, 150.0, 90.0).as_deref(), Some("pointer"));
+        // Text takes its element's cursor; only the final keyword of a list counts.
+        assert_eq!(layout::cursor_at(&doc, 2.0, 2.0).as_deref(), Some("text"));
+        assert_eq!(layout::cursor_at(&doc, 150.0, 150.0).as_deref(), Some("auto"));
+        assert_eq!(layout::cursor_at(&doc, 700.0, 500.0), None);
+    }
+
+    /// Structural dump (tags, attributes, classes, text) for comparing parses.
+    fn dump_tree(doc: &dom::Document) -> String {
+        let mut out = String::new();
+        let mut stack = vec![(doc.root_id, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            match doc.nodes.get(id) {
+                Some(dom::Node::Element(d)) => {
+                    out += &format!("{}<{} {:?} {:?}>\n", " ".repeat(depth), &*d.tag_name, d.attributes, d.classes)
+                }
+                Some(dom::Node::Text(d)) => out += &format!("{}{:?}\n", " ".repeat(depth), d.text),
+                _ => {}
+            }
+            let children: Vec<_> = std::iter::successors(doc.first_child_of(id), |&c| doc.next_sibling_of(c)).collect();
+            stack.extend(children.into_iter().rev().map(|c| (c, depth + 1)));
+        }
+        out
+    }
+
+    #[test]
+    fn test_streaming_parse_matches_one_shot() {
+        let src = "<div id='a' class='x' title=\"1 > 0\">h\u{e9}llo <b>w\u{f6}rld</b><!-- a > b --></div><p>tail</p>";
+        let bytes = src.as_bytes();
+        // Split inside the quoted `>`, inside the two-byte `é`, and mid-comment.
+        let first = src.find("> 0").unwrap() + 1;
+        let second = src.find('\u{e9}').unwrap() + 1;
+        let third = src.find("a > b").unwrap() + 3;
+
+        let mut parser = html::HtmlParser::new();
+        parser.feed(&bytes[..first]);
+        parser.feed(&bytes[first..second]);
+        parser.feed(&bytes[second..third]);
+        parser.feed(&bytes[third..]);
+        let streamed = parser.finish();
+
+        let one_shot = html::parse_html(src);
+        assert_eq!(dump_tree(&streamed), dump_tree(&one_shot));
+        assert!(dump_tree(&one_shot).contains("\"h\u{e9}llo \""));
+    }
+
+    #[test]
+    fn test_parse_html_with_limit_stops_at_cap() {
+        let src = "<p>x</p>".repeat(100);
+
+        let doc = html::parse_html_with_limit(&src, 25);
+        assert!(doc.truncated);
+        // Root plus the capped number of parsed nodes.
+        assert_eq!(doc.nodes.len(), 26);
+        // Every created node is still linked into the tree.
+        let linked = doc
+            .nodes
+            .iter()
+            .filter(|&(id, _)| id == doc.root_id || doc.parent_of(id).is_some())
+            .count();
+        assert_eq!(linked, 26);
+
+        let full = html::parse_html_with_limit(&src, 1000);
+        assert!(!full.truncated);
+        assert_eq!(full.nodes.len(), 201);
+    }
+
+    #[test]
+    fn test_parse_html_bytes_decodes_latin1() {
+        let text_of = |doc: &dom::Document| {
+            doc.nodes
+                .iter()
+                .find_map(|(_, n)| match n {
+                    dom::Node::Text(t) => Some(t.text.clone()),
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        // 0xE9 is `é` in Latin-1 and an invalid byte in UTF-8.
+        let meta = b"<meta charset=\"ISO-8859-1\"><p>caf\xE9</p>";
+        assert_eq!(text_of(&html::parse_html_bytes(meta, None)), "caf\u{e9}");
+
+        let bare = b"<p>caf\xE9</p>";
+        assert_eq!(text_of(&html::parse_html_bytes(bare, Some("latin1"))), "caf\u{e9}");
+        assert_eq!(text_of(&html::parse_html_bytes(bare, None)), "caf\u{fffd}");
+
+        // A BOM wins over the declared charset.
+        let bom = b"\xEF\xBB\xBF<p>caf\xC3\xA9</p>";
+        assert_eq!(text_of(&html::parse_html_bytes(bom, Some("latin1"))), "caf\u{e9}");
+    }
+
     #[test]
     fn test_local_name_phf_tags() {
         assert!(matches!(
@@ -237,6 +2538,68 @@ mod tests {
         assert!(doc.nodes.get(grandchild).is_none());
     }
 
+    #[test]
+    fn test_create_nodes_builds_tree() {
+        let mut doc = dom::Document::new();
+        let list = doc.create_element("UL");
+        let items: Vec<_> = ["a", "b"]
+            .iter()
+            .map(|text| {
+                let li = doc.create_element("li");
+                let t = doc.create_text(text);
+                assert!(doc.append_child(li, t));
+                assert!(doc.append_child(list, li));
+                li
+            })
+            .collect();
+        assert_eq!(doc.parent_of(list), None, "created nodes start detached");
+        assert!(doc.append_child(doc.root_id, list));
+
+        let Some(dom::Node::Element(data)) = doc.nodes.get(list) else { panic!("expected element") };
+        assert_eq!(&*data.tag_name, "ul");
+        let children: Vec<_> = std::iter::successors(doc.first_child_of(list), |&c| doc.next_sibling_of(c)).collect();
+        assert_eq!(children, items);
+        assert_eq!(doc.text_content(list), "ab");
+        doc.validate().unwrap();
+    }
+
+    #[test]
+    fn test_remove_node_wide_deep_subtree() {
+        // 40 branches, each a 30-deep chain with an id'd leaf.
+        let mut src = String::from("<div id='before'></div><div id='target'>");
+        for b in 0..40 {
+            src += &"<div>".repeat(30);
+            src += &format!("<span id='leaf{b}'>x</span>");
+            src += &"</div>".repeat(30);
+        }
+        src += "</div><div id='after'><p>kept</p></div>";
+        let mut doc = html::parse_html(&src);
+
+        let target = doc.id_map["target"];
+        let mut descendants = Vec::new();
+        let mut stack = vec![target];
+        while let Some(id) = stack.pop() {
+            descendants.push(id);
+            stack.extend(std::iter::successors(doc.first_child_of(id), |&c| doc.next_sibling_of(c)));
+        }
+        assert_eq!(descendants.len(), 1 + 40 * 32);
+        let before = doc.id_map["before"];
+        let after = doc.id_map["after"];
+        let total = doc.nodes.len();
+
+        doc.remove_node(target);
+
+        assert!(descendants.iter().all(|&id| doc.nodes.get(id).is_none()));
+        assert_eq!(doc.nodes.len(), total - descendants.len());
+        assert!((0..40).all(|b| !doc.id_map.contains_key(&format!("leaf{b}"))));
+        assert!(!doc.id_map.contains_key("target"));
+        // The surviving siblings are relinked to each other and keep their subtree.
+        assert_eq!(doc.next_sibling_of(before), Some(after));
+        assert_eq!(doc.prev_sibling_of(after), Some(before));
+        assert_eq!(doc.parent_of(after), Some(doc.root_id));
+        assert!(doc.first_child_of(after).is_some());
+    }
+
     #[test]
     fn test_html_keeps_inline_whitespace_text_nodes() {
         let doc = html::parse_html("<div><span>A</span> <span>B</span></div>");
@@ -270,6 +2633,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_emphasis_tag_defaults() {
+        let mut doc = html::parse_html(
+            "<strong id='s'>hi</strong><em id='e'>it</em><b id='b' class='plain'>no</b>",
+        );
+        css::compute_styles(&mut doc, &css::parse_stylesheet(".plain { font-weight: normal; }"));
+        let computed = |id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d.computed.clone(),
+            _ => panic!("expected element"),
+        };
+
+        assert_eq!(computed("s").font_weight, 700);
+        assert_eq!(computed("e").font_style, dom::FontStyleKeyword::Italic);
+        assert_eq!(computed("e").font_weight, 400);
+        // Author rules win over the built-in default.
+        assert_eq!(computed("b").font_weight, 400);
+
+        let strong_text = doc.first_child_of(doc.id_map["s"]).unwrap();
+        let Some(dom::Node::Text(t)) = doc.nodes.get(strong_text) else { panic!() };
+        assert_eq!(t.computed.font_weight, 700);
+    }
+
+    #[test]
+    fn test_style_value_css_serialization() {
+        use dom::StyleValue;
+        let cases = [
+            (StyleValue::LengthPx(10.0), "10px"),
+            (StyleValue::LengthPx(1.5), "1.5px"),
+            (StyleValue::Percent(50.0), "50%"),
+            (StyleValue::ViewportWidth(100.0), "100vw"),
+            (StyleValue::ViewportHeight(25.0), "25vh"),
+            (StyleValue::Em(2.0), "2em"),
+            (StyleValue::Rem(0.5), "0.5rem"),
+            (StyleValue::Number(3.0), "3"),
+            (StyleValue::Keyword("flex".into()), "flex"),
+            (StyleValue::Color(255, 0, 0, 255), "#ff0000"),
+            (StyleValue::Color(0, 0, 0, 0), "rgba(0,0,0,0)"),
+            (StyleValue::Auto, "auto"),
+            (StyleValue::None, "none"),
+            (StyleValue::String("a\"b".into()), "\"a\\\"b\""),
+        ];
+        for (value, css) in cases {
+            assert_eq!(value.to_css_string(), css);
+        }
+        // Parsing the serialized form yields the same value.
+        assert_eq!(css::parse_style_value(&StyleValue::Color(18, 52, 86, 255).to_string()), StyleValue::Color(18, 52, 86, 255));
+
+        let color = render::Color { r: 0, g: 128, b: 255, a: 255 };
+        assert_eq!(color.to_css_string(), "#0080ff");
+    }
+
+    #[test]
+    fn test_color_blend_over() {
+        let rgba = |r, g, b, a| render::Color { r, g, b, a };
+        let white = rgba(255, 255, 255, 255);
+        assert_eq!(rgba(255, 0, 0, 128).blend_over(white), rgba(255, 127, 127, 255), "half red over white is pink");
+        assert_eq!(rgba(10, 20, 30, 255).blend_over(white), rgba(10, 20, 30, 255), "opaque source wins");
+        assert_eq!(rgba(10, 20, 30, 0).blend_over(white), white, "transparent source leaves the background");
+        assert_eq!(rgba(0, 0, 255, 128).blend_over(rgba(0, 0, 0, 0)), rgba(0, 0, 255, 128));
+        assert_eq!(rgba(1, 2, 3, 0).blend_over(rgba(4, 5, 6, 0)), rgba(0, 0, 0, 0));
+        let over_translucent = rgba(255, 0, 0, 128).blend_over(rgba(0, 0, 255, 128));
+        assert_eq!(over_translucent.a, 192);
+        assert!(over_translucent.r > over_translucent.b, "source weighs more: {over_translucent:?}");
+    }
+
     #[test]
     fn test_css_combinators() {
         let text = "<html><body><div class=\"parent\"><p><span>Text</span></p></div></body></html>";
@@ -402,7 +2830,12 @@ mod tests {
         
         let children = doc.taffy_tree.children(root_taffy_node).unwrap();
         assert!(!children.is_empty(), "Root should have children in Taffy");
-        let span_taffy = children[0];
+        // The span sits in an anonymous inline row under the root.
+        let span_taffy = match doc.nodes.get(doc.first_child_of(doc.root_id).unwrap()) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected span"),
+        };
+        assert_eq!(doc.taffy_tree.parent(span_taffy), Some(children[0]));
         let style = doc.taffy_tree.style(span_taffy).unwrap();
         
         // Should be normalized to Block
@@ -448,7 +2881,487 @@ mod tests {
             panic!("Expected Element");
         }
     }
-    
+
+    #[test]
+    fn test_rem_tracks_root_font_size() {
+        let mut f = LayoutFixture::new(
+            "<div id='w'><div id='a' style='width: 3rem; font-size: 2rem'></div><p id='p'>x</p></div>",
+        );
+        let measure = |f: &mut LayoutFixture| {
+            f.update(&css::StyleSheet::default());
+            let doc = &f.doc;
+            let Some(dom::Node::Element(p)) = doc.nodes.get(doc.id_map["p"]) else { panic!("expected element") };
+            let Some(dom::Node::Element(a)) = doc.nodes.get(doc.id_map["a"]) else { panic!("expected element") };
+            (p.computed.font_size, a.computed.font_size, layout::absolute_rect(doc, doc.id_map["a"]).unwrap().width)
+        };
+        assert_eq!(measure(&mut f), (16.0, 32.0, 48.0));
+
+        // The configured default sizes unstyled text and scales `rem`.
+        f.doc.set_root_font_size(20.0);
+        assert_eq!(measure(&mut f), (20.0, 40.0, 60.0));
+
+        // A font size on the document element overrides it for `rem`, even
+        // though only that element's style changed.
+        let w = f.doc.id_map["w"];
+        f.doc.set_attribute(w, "style", "font-size: 0.5rem".into());
+        assert_eq!(measure(&mut f), (10.0, 20.0, 30.0));
+        assert_eq!(f.doc.rem_base, 10.0);
+    }
+
+    #[test]
+    fn test_border_and_outline_widths_use_root_font_size() {
+        let mut doc = html::parse_html(
+            "<div id='w'><div id='b' style='font-size: 40px; border-width: 0.5rem 0.25em 0 0; \
+             outline: solid; outline-width: 0.5rem'></div>\
+             <div id='e' style='font-size: 40px; outline-width: 0.25em'></div></div>",
+        );
+        doc.set_root_font_size(20.0);
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        let element = |id: &str| match doc.nodes.get(doc.id_map[id]) {
+            Some(dom::Node::Element(d)) => d,
+            _ => panic!("expected element"),
+        };
+        let border = doc.taffy_tree.layout(element("b").taffy_node.unwrap()).unwrap().border;
+        assert_eq!((border.top, border.right), (10.0, 10.0), "rem uses the root size, em the element's");
+        assert_eq!(element("b").computed.outline_width, dom::StyleValue::LengthPx(10.0));
+        assert_eq!(element("e").computed.outline_width, dom::StyleValue::LengthPx(10.0));
+    }
+
+    #[test]
+    fn test_author_origin_beats_user_agent_specificity() {
+        let mut doc = html::parse_html("<div id='x' class='a'></div>");
+        let ua = css::parse_stylesheet("div#x.a { color: #ff0000; }");
+        let author = css::parse_stylesheet("div { color: #0000ff; }");
+        css::compute_styles_ex(
+            &mut doc,
+            &[(css::Origin::UserAgent, &ua), (css::Origin::Author, &author)],
+            (800.0, 600.0),
+        );
+        let div_id = doc.first_child_of(doc.root_id).unwrap();
+        if let crate::dom::Node::Element(data) = doc.nodes.get(div_id).unwrap() {
+            assert_eq!(data.computed.color, (0, 0, 255, 255), "author origin must win over UA specificity");
+        } else {
+            panic!("Expected Element");
+        }
+    }
+
+    #[test]
+    fn test_css_wide_keywords() {
+        let mut doc = html::parse_html(
+            "<div style='color: #ff0000;'><span id='i' style='color: inherit;'>a</span><p id='n' style='display: initial;'>b</p></div>",
+        );
+        let sheet = css::parse_stylesheet("span { color: #0000ff; } p { display: flex; }");
+        css::compute_styles(&mut doc, &sheet);
+        let computed = |k: &str| match doc.nodes.get(*doc.id_map.get(k).unwrap()) {
+            Some(dom::Node::Element(d)) => d.computed.clone(),
+            _ => panic!("expected element"),
+        };
+        assert_eq!(computed("i").color, (255, 0, 0, 255), "inherit should take the parent's color");
+        assert_eq!(computed("n").display, dom::DisplayKeyword::Block, "initial should reset display");
+    }
+
+    #[test]
+    fn test_root_pseudo_class() {
+        let mut doc = html::parse_html("<html id='h'><body><p id='p' class='x'>text</p></body></html>");
+        let sheet = css::parse_stylesheet(":root { color: #ff0000; } .x:root { font-size: 40px; }");
+        css::compute_styles(&mut doc, &sheet);
+        let computed = |k: &str| match doc.nodes.get(*doc.id_map.get(k).unwrap()) {
+            Some(dom::Node::Element(d)) => d.computed.clone(),
+            _ => panic!("expected element"),
+        };
+        assert_eq!(computed("h").color, (255, 0, 0, 255));
+        assert_eq!(computed("p").color, (255, 0, 0, 255), "descendants inherit from :root");
+        assert_eq!(computed("p").font_size, 16.0, ":root must not match non-root elements");
+    }
+
+    #[test]
+    fn test_empty_pseudo_class() {
+        let mut doc = html::parse_html(
+            "<div id='e'></div><div id='c'><!-- note --></div><div id='w'> </div><div id='s'><span></span></div>",
+        );
+        let sheet = css::parse_stylesheet("div:empty { color: #ff0000; }");
+        css::compute_styles(&mut doc, &sheet);
+        let red = |doc: &dom::Document, k: &str| match doc.nodes.get(doc.id_map[k]) {
+            Some(dom::Node::Element(d)) => d.computed.color == (255, 0, 0, 255),
+            _ => panic!("expected element"),
+        };
+        assert!(red(&doc, "e") && red(&doc, "c"));
+        assert!(!red(&doc, "w"), "whitespace text makes an element non-empty");
+        assert!(!red(&doc, "s"));
+
+        // Gaining or losing the last child restyles the element.
+        let (e, s) = (doc.id_map["e"], doc.id_map["s"]);
+        let span = doc.first_child_of(s).unwrap();
+        doc.append_child(e, span);
+        css::compute_styles(&mut doc, &sheet);
+        assert!(!red(&doc, "e") && red(&doc, "s"));
+    }
+
+    #[test]
+    fn test_cached_element_id() {
+        let doc = html::parse_html("<div id='a'></div>");
+        let engine = js::JsEngine::try_new(doc).expect("JsEngine::try_new");
+        engine
+            .execute_script("document.getElementById('a').setAttribute('id', 'b')")
+            .unwrap();
+        let mut doc = engine.document.borrow_mut();
+        assert!(doc.id_map.get("a").is_none(), "old id must leave the id map");
+        let node = *doc.id_map.get("b").expect("new id must enter the id map");
+        match doc.nodes.get(node) {
+            Some(dom::Node::Element(d)) => assert_eq!(d.id(), Some("b")),
+            _ => panic!("expected element"),
+        }
+        let sheet = css::parse_stylesheet("#a { font-size: 30px; } #b { color: #00ff00; }");
+        css::compute_styles(&mut doc, &sheet);
+        match doc.nodes.get(node) {
+            Some(dom::Node::Element(d)) => {
+                assert_eq!(d.computed.color, (0, 255, 0, 255), "#b must match the updated id");
+                assert_eq!(d.computed.font_size, 16.0, "#a must no longer match");
+            }
+            _ => panic!("expected element"),
+        }
+
+        // Editing the public list directly must not panic or misreport.
+        let Some(dom::Node::Element(d)) = doc.nodes.get_mut(node) else { panic!("expected element") };
+        d.set_attribute("title", "t".into());
+        d.attributes.remove(0);
+        assert_eq!(d.id(), None, "the cached slot now holds `title`");
+        d.attributes.insert(0, ("id".into(), "c".into()));
+        assert_eq!(d.id(), Some("c"));
+        d.attributes.clear();
+        assert_eq!(d.id(), None);
+    }
+
+    #[test]
+    fn test_all_shorthand_resets() {
+        let mut doc = html::parse_html(
+            "<div style='color: #ff0000; font-size: 20px'>\
+               <p id='u' class='c' style='all: unset'>a</p>\
+               <p id='i' class='c' style='all: initial'>b</p>\
+               <b id='r' class='c' style='all: revert'>c</b>\
+             </div>",
+        );
+        let sheet = css::parse_stylesheet(".c { color: #0000ff; background-color: #00ff00; font-weight: 300; }");
+        css::compute_styles(&mut doc, &sheet);
+        let computed = |k: &str| match doc.nodes.get(*doc.id_map.get(k).unwrap()) {
+            Some(dom::Node::Element(d)) => d.computed.clone(),
+            _ => panic!("expected element"),
+        };
+
+        // `unset` inherits inherited properties and resets the rest.
+        let unset = computed("u");
+        assert_eq!(unset.color, (255, 0, 0, 255));
+        assert_eq!(unset.font_size, 20.0);
+        assert_eq!(unset.bg_color, None);
+
+        // `initial` clears the inherited color back to the default.
+        let initial = computed("i");
+        assert_eq!(initial.color, dom::ComputedStyle::default().color);
+        assert_eq!(initial.font_size, 16.0);
+        assert_eq!(initial.bg_color, None);
+
+        // `revert` drops author declarations but keeps built-in tag defaults.
+        let revert = computed("r");
+        assert_eq!(revert.color, (255, 0, 0, 255));
+        assert_eq!(revert.font_weight, 700);
+        assert_eq!(revert.bg_color, None);
+    }
+
+    #[test]
+    fn test_prepared_sheet_reused_across_applies() {
+        let mut doc = html::parse_html("<style>.a { color: red; }</style><div id='d' class='a'></div>");
+        let base = css::parse_stylesheet(".a { color: blue; } div { font-size: 20px; }");
+        let compiled = css::prepare_sheet(&doc, &base);
+        assert_eq!(compiled.next_rule_index, 3);
+
+        let d = doc.id_map["d"];
+        let parses = css::stylesheet_parse_count();
+        for _ in 0..2 {
+            css::apply_styles(&mut doc, &compiled);
+            let Some(dom::Node::Element(data)) = doc.nodes.get(d) else { panic!() };
+            // Same specificity: the document rule comes later and wins.
+            assert_eq!(data.computed.color, (255, 0, 0, 255));
+            assert_eq!(data.computed.font_size, 20.0);
+            if let Some(dom::Node::Element(data)) = doc.nodes.get_mut(d) {
+                data.styles_dirty = true;
+            }
+        }
+        css::compute_styles(&mut doc, &base);
+        css::compute_styles(&mut doc, &base);
+        assert_eq!(css::stylesheet_parse_count(), parses, "no style text is parsed again");
+        assert!(!doc.stylesheet.by_class.is_empty(), "compute_styles hands the document sheet back");
+
+        // The counter does see a re-parse when a <style> element changes.
+        doc.styles_dirty = true;
+        doc.rebuild_styles();
+        assert_eq!(css::stylesheet_parse_count(), parses + 1);
+    }
+
+    #[test]
+    fn test_cascade_trace_records_winner() {
+        let mut doc = html::parse_html("<div id='t' class='a'></div>");
+        let sheet = css::parse_stylesheet("div.a { color: #0000ff; } div { color: #ff0000; }");
+        let trace = css::compute_styles_debug(&mut doc, &[(css::Origin::Author, &sheet)], (800.0, 600.0));
+
+        let entries = &trace[doc.id_map.get("t").unwrap()];
+        let colors: Vec<_> = entries
+            .iter()
+            .filter(|e| e.property == dom::PropertyName::Color)
+            .collect();
+        assert_eq!(colors.len(), 2);
+        let winner = colors.iter().find(|e| !e.overridden).unwrap();
+        assert_eq!(winner.selector.as_deref(), Some("div.a"));
+        assert_eq!(winner.specificity, (0, 1, 1));
+        assert!(colors.iter().any(|e| e.overridden && e.selector.as_deref() == Some("div")));
+    }
+
+    #[test]
+    fn test_matched_rules_in_cascade_order() {
+        let doc = html::parse_html("<div id='d' class='box box'></div>");
+        let sheet = css::parse_stylesheet(
+            ".box { color: red; } div { color: blue; } p { color: green; } .box::before { content: 'x'; }",
+        );
+        let matched = css::matched_rules(&doc, &sheet, doc.id_map["d"]);
+        let summary: Vec<_> = matched.iter().map(|r| (r.selector.to_string(), r.selector.specificity)).collect();
+        assert_eq!(
+            summary,
+            [
+                ("div".to_string(), (0, 0, 1)),
+                (".box".to_string(), (0, 1, 0)),
+                (".box::before".to_string(), (0, 1, 1)),
+            ]
+        );
+        assert!(css::matched_rules(&doc, &sheet, doc.root_id).is_empty());
+    }
+
+    #[test]
+    fn test_keyframes_sampling() {
+        let sheet = css::parse_stylesheet(
+            "@keyframes grow { from { width: 100px; color: #000000 } 50% { opacity: 0.5 } to { width: 200px; color: #ffffff } } \
+             div { animation-name: grow; animation-duration: 500ms; width: 10px }",
+        );
+        let sample = |progress: f32| {
+            css::sample_animation(&sheet, "grow", progress)
+                .into_iter()
+                .map(|d| (d.name, d.value))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sample(0.5),
+            [
+                (dom::PropertyName::Width, dom::StyleValue::LengthPx(150.0)),
+                (dom::PropertyName::Color, dom::StyleValue::Color(128, 128, 128, 255)),
+                (dom::PropertyName::Opacity, dom::StyleValue::Number(0.5)),
+            ]
+        );
+        assert_eq!(sample(0.25)[0], (dom::PropertyName::Width, dom::StyleValue::LengthPx(125.0)));
+        assert_eq!(sample(2.0)[0], (dom::PropertyName::Width, dom::StyleValue::LengthPx(200.0)));
+        assert!(css::sample_animation(&sheet, "missing", 0.5).is_empty());
+
+        // The at-rule does not leak into the selector rules.
+        let mut doc = html::parse_html("<div id='d'></div>");
+        assert_eq!(css::matched_rules(&doc, &sheet, doc.id_map["d"]).len(), 1);
+        css::compute_styles(&mut doc, &sheet);
+        let Some(dom::Node::Element(d)) = doc.nodes.get(doc.id_map["d"]) else {
+            panic!("expected element");
+        };
+        assert_eq!(d.computed.animation_name.as_deref(), Some("grow"));
+        assert_eq!(d.computed.animation_duration, 0.5);
+        assert_eq!(d.computed.width, dom::StyleValue::LengthPx(10.0));
+    }
+
+    #[test]
+    fn test_transition_values_interpolate() {
+        let sheet = css::parse_stylesheet(
+            "div { transition: width 200ms linear, color 1s; width: 0 } .wide { width: 100px; color: #ff0000; height: 5px }",
+        );
+        let mut doc = html::parse_html("<div id='d'></div>");
+        css::compute_styles(&mut doc, &sheet);
+        let d = doc.id_map["d"];
+        let computed = |doc: &dom::Document| match doc.nodes.get(d) {
+            Some(dom::Node::Element(e)) => e.computed.clone(),
+            _ => panic!("expected element"),
+        };
+        let old = computed(&doc);
+        doc.set_attribute(d, "class", "wide".to_string());
+        css::compute_styles(&mut doc, &sheet);
+        let new = computed(&doc);
+        assert_eq!(
+            new.transitions.as_ref(),
+            [
+                dom::TransitionSpec { property: Some(dom::PropertyName::Width), duration: 0.2 },
+                dom::TransitionSpec { property: Some(dom::PropertyName::Color), duration: 1.0 },
+            ]
+        );
+
+        let at = |elapsed: f32| {
+            css::transition_values(&old, &new, elapsed)
+                .into_iter()
+                .map(|d| (d.name, d.value))
+                .collect::<Vec<_>>()
+        };
+        // `height` changed too but does not transition.
+        assert_eq!(
+            at(0.1),
+            [
+                (dom::PropertyName::Width, dom::StyleValue::LengthPx(50.0)),
+                (dom::PropertyName::Color, dom::StyleValue::Color(26, 0, 0, 255)),
+            ]
+        );
+        assert_eq!(at(0.5), [(dom::PropertyName::Color, dom::StyleValue::Color(128, 0, 0, 255))]);
+        assert!(at(1.0).is_empty());
+    }
+
+    #[test]
+    fn test_compound_class_specificity_sums() {
+        let sheet = css::parse_stylesheet(
+            "#x { color: blue; } .a.b { color: red; } div.a .b { color: green; } div.a > p.a.b:first-child { color: red; }",
+        );
+        let mut doc = html::parse_html("<div class='a'><p id='x' class='a b'>t</p></div>");
+        let matched = css::matched_rules(&doc, &sheet, doc.id_map["x"]);
+        let summary: Vec<_> = matched.iter().map(|r| (r.selector.to_string(), r.selector.specificity)).collect();
+        assert_eq!(
+            summary,
+            [
+                (".a.b".to_string(), (0, 2, 0)),
+                ("div.a .b".to_string(), (0, 2, 1)),
+                ("div.a > p.a.b:first-child".to_string(), (0, 4, 2)),
+                ("#x".to_string(), (1, 0, 0)),
+            ]
+        );
+
+        // An id beats any number of classes, regardless of source order.
+        css::compute_styles(&mut doc, &sheet);
+        let Some(dom::Node::Element(p)) = doc.nodes.get(doc.id_map["x"]) else {
+            panic!("expected element");
+        };
+        assert_eq!(p.computed.color, (0, 0, 255, 255));
+    }
+
+    #[test]
+    fn test_quirks_mode_unitless_lengths() {
+        let body = "<div id='d' style='width: 100; height: 20; margin-left: 0'></div>";
+        let width_of = |html: &str| {
+            let mut doc = html::parse_html(html);
+            css::compute_styles(&mut doc, &css::StyleSheet::default());
+            let mut font_system = cosmic_text::FontSystem::new();
+            let mut buffer_cache = std::collections::HashMap::new();
+            layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+            (doc.quirks_mode, layout::absolute_rect(&doc, doc.id_map["d"]).unwrap().width)
+        };
+        assert_eq!(width_of(body), (true, 100.0));
+        assert_eq!(width_of(&format!("<!DOCTYPE html>{body}")), (false, 800.0));
+
+        let quirky = |doctype: &str| html::parse_html(&format!("{doctype}<p></p>")).quirks_mode;
+        assert!(!quirky("<!doctype HTML>"));
+        assert!(!quirky(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN" "http://www.w3.org/TR/html4/loose.dtd">"#));
+        assert!(quirky(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">"#));
+        assert!(quirky(r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 3.2 Final//EN">"#));
+        assert!(quirky("<!DOCTYPE svg>"));
+        // A doctype after the first element comes too late.
+        assert!(quirky("<p></p><!DOCTYPE html>"));
+    }
+
+    #[test]
+    fn test_visibility_collapse_flex_item() {
+        let third_offset = |visibility: &str, display: &str| {
+            let mut doc = html::parse_html(&format!(
+                "<div id='row' style='display: {display}; flex-direction: row'>\
+                 <div style='width: 50px; height: 10px'></div>\
+                 <div id='m' style='width: 50px; height: 10px; visibility: {visibility}'></div>\
+                 <div id='c' style='width: 50px; height: 10px'></div></div>"
+            ));
+            css::compute_styles(&mut doc, &css::StyleSheet::default());
+            let mut font_system = cosmic_text::FontSystem::new();
+            let mut buffer_cache = std::collections::HashMap::new();
+            layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+            let rect = layout::absolute_rect(&doc, doc.id_map["c"]).unwrap();
+            if display == "flex" { rect.x } else { rect.y }
+        };
+        assert_eq!(third_offset("hidden", "flex"), 100.0, "hidden items keep their space");
+        assert_eq!(third_offset("collapse", "flex"), 50.0, "collapsed items are removed");
+        assert_eq!(third_offset("collapse", "block"), 20.0, "outside flex, collapse is hidden");
+    }
+
+    #[test]
+    fn test_display_contents_hoists_children() {
+        let mut doc = html::parse_html(
+            "<div id='w' style='display: flex; align-items: flex-start'>\
+             <div id='a' style='width: 10px; height: 5px'></div>\
+             <div id='c' style='display: contents; padding: 50px; background-color: red; color: #0000ff'>\
+             <div id='b' style='width: 20px; height: 5px; background-color: #00ff00'></div>hi</div></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let mut font_system = cosmic_text::FontSystem::new();
+        let mut buffer_cache = std::collections::HashMap::new();
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        assert!(doc.validate().is_ok());
+
+        let rect = |doc: &dom::Document, id: &str| layout::absolute_rect(doc, doc.id_map[id]);
+        let text = doc.first_child_of(doc.id_map["c"]).and_then(|b| doc.next_sibling_of(b)).unwrap();
+        assert!(rect(&doc, "c").is_none(), "no box for the contents element");
+        assert_eq!(rect(&doc, "b").map(|r| (r.x, r.y)), Some((10.0, 0.0)), "flex item of the grandparent");
+        let text_rect = layout::absolute_rect(&doc, text).unwrap();
+        assert_eq!(text_rect.x, 30.0, "text under the wrapper is an item too");
+        let Some(dom::Node::Text(data)) = doc.nodes.get(text) else { panic!("expected text") };
+        assert_eq!(data.computed.color, (0, 0, 255, 255), "styles still inherit through the wrapper");
+        assert_eq!(doc.hit_test(15.0, 2.0), Some(doc.id_map["b"]));
+
+        let w_taffy = match doc.nodes.get(doc.id_map["w"]) {
+            Some(dom::Node::Element(d)) => d.taffy_node.unwrap(),
+            _ => panic!("expected element"),
+        };
+        let mut backend = RecordingBackend::default();
+        render::draw_layout_tree(&mut backend, &doc, &doc.taffy_tree, doc.id_map["w"], w_taffy, 0.0, 0.0, &buffer_cache);
+        assert_eq!(backend.fills.len(), 1, "the wrapper paints no background");
+        assert_eq!((backend.fills[0].0, backend.fills[0].4), (10.0, render::Color { r: 0, g: 255, b: 0, a: 255 }));
+
+        let c = doc.id_map["c"];
+        doc.set_attribute(c, "style", "padding: 50px".to_string());
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        layout::compute_layout(&mut doc, 800.0, 600.0, &mut font_system, &mut buffer_cache);
+        assert!(doc.validate().is_ok());
+        assert_eq!(rect(&doc, "c").map(|r| r.x), Some(10.0));
+        assert_eq!(rect(&doc, "b").map(|r| (r.x, r.y)), Some((60.0, 50.0)), "back inside the wrapper's box");
+    }
+
+    #[test]
+    fn test_flex_flow_shorthand() {
+        let mut doc = html::parse_html(
+            "<div id='a' style='display: flex; flex-wrap: wrap; flex-flow: column nowrap'></div>\
+             <div id='b' style='display: flex; flex-direction: column; flex-flow: wrap'></div>\
+             <div id='c' style='display: flex; flex-flow: wrap column; flex-flow: row row'></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let flow = |id: &str| {
+            let Some(dom::Node::Element(d)) = doc.nodes.get(doc.id_map[id]) else { panic!("expected element") };
+            (d.computed.flex_direction.clone(), d.computed.flex_wrap.clone())
+        };
+        assert_eq!(flow("a"), (dom::FlexDirectionKeyword::Column, dom::FlexWrapKeyword::NoWrap));
+        assert_eq!(flow("b"), (dom::FlexDirectionKeyword::Row, dom::FlexWrapKeyword::Wrap), "omitted direction resets");
+        assert_eq!(flow("c"), (dom::FlexDirectionKeyword::Column, dom::FlexWrapKeyword::Wrap), "invalid value is dropped");
+    }
+
+    #[test]
+    fn test_two_axis_shorthands() {
+        let mut doc = html::parse_html(
+            "<div id='s' style='overflow: hidden auto; inset: 0; gap: 4px 8px; place-items: center end;'></div>",
+        );
+        css::compute_styles(&mut doc, &css::StyleSheet::default());
+        let Some(dom::Node::Element(d)) = doc.nodes.get(*doc.id_map.get("s").unwrap()) else {
+            panic!("expected element");
+        };
+        let c = &d.computed;
+        assert_eq!(c.overflow, [dom::OverflowKeyword::Hidden, dom::OverflowKeyword::Auto]);
+        assert!(c.inset.iter().all(|v| *v == dom::StyleValue::Number(0.0)), "inset: {:?}", c.inset);
+        assert_eq!((&c.row_gap, &c.column_gap), (&dom::StyleValue::LengthPx(4.0), &dom::StyleValue::LengthPx(8.0)));
+        assert_eq!(c.align_items, dom::AlignItemsKeyword::Center);
+        assert_eq!(c.justify_items, Some(dom::AlignItemsKeyword::FlexEnd));
+    }
+
     #[test]
     fn test_get_attribute_class_style() {
         // Test getAttribute for "class" and "style" attributes
@@ -472,6 +3385,245 @@ mod tests {
         );
     }
     
+    #[test]
+    fn test_console_log_variadic() {
+        let engine = js::JsEngine::try_new(html::parse_html("<div></div>")).expect("JsEngine::try_new");
+        let lines = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let sink = lines.clone();
+        engine.set_console_sink(Some(Box::new(move |level, line: &str| {
+            sink.borrow_mut().push((level, line.to_string()))
+        })));
+        engine
+            .execute_script(
+                "console.log('a', 1, true); \
+                 console.warn(null, undefined, 1.5, [1, 'x', [2]], { k: 'v', n: { m: 1 } }); \
+                 console.error(); console.log(Symbol('s'), Symbol(), function f() {}, new Error('boom'))",
+            )
+            .expect("console calls accept any number of arguments of any type");
+        use js::ConsoleLevel::{Error, Log, Warn};
+        assert_eq!(
+            *lines.borrow(),
+            [
+                (Log, "a 1 true".to_string()),
+                (Warn, "null undefined 1.5 [1, 'x', [Array]] { k: 'v', n: [Object] }".to_string()),
+                (Error, String::new()),
+                (Log, "Symbol(s) Symbol() [Function] Error: boom".to_string()),
+            ]
+        );
+
+        engine.set_console_sink(None);
+        engine.execute_script("console.log('back to stdout')").unwrap();
+        assert_eq!(lines.borrow().len(), 4);
+    }
+
+    #[test]
+    fn test_append_children_batch() {
+        let mut doc = html::parse_html("<div id='p'><i id='first'></i></div><b id='moved'></b>");
+        let parent = doc.id_map["p"];
+        let moved = doc.id_map["moved"];
+        let mut batch: Vec<_> = (0..100)
+            .map(|_| doc.add_node(dom::Node::Text(dom::TextData::new("x".to_string()))))
+            .collect();
+        // A parser-created node with no JS handle is reparented, not freed.
+        batch.insert(50, moved);
+        assert!(doc.append_children(parent, &batch));
+
+        let children: Vec<_> =
+            std::iter::successors(doc.first_child_of(parent), |&c| doc.next_sibling_of(c)).collect();
+        assert_eq!(children.len(), 102);
+        assert_eq!(children[0], doc.id_map["first"]);
+        assert_eq!(children[1..], batch[..]);
+        assert!(children.iter().all(|&c| doc.parent_of(c) == Some(parent)));
+        assert!(children.windows(2).all(|w| doc.prev_sibling_of(w[1]) == Some(w[0])));
+        assert_eq!(doc.last_child_of(parent), Some(batch[100]));
+        assert!(doc.nodes.contains(moved) && doc.id_map.get("moved") == Some(&moved));
+        assert_eq!(doc.first_child_of(doc.root_id).and_then(|c| doc.next_sibling_of(c)), None);
+
+        // Cycles are rejected before anything is touched.
+        assert!(!doc.append_children(batch[0], &[]));
+        assert!(!doc.append_children(moved, &[batch[1], parent]));
+        assert_eq!(doc.parent_of(batch[1]), Some(parent));
+
+        let engine = js::JsEngine::try_new(html::parse_html("<ul id='list'></ul>")).expect("JsEngine::try_new");
+        let count = engine
+            .execute_script(
+                "const list = document.getElementById('list'); \
+                 const items = []; for (let i = 0; i < 100; i++) items.push(document.createElement('li')); \
+                 list.append(...items); \
+                 let n = 0; for (let c = list.firstChild; c; c = c.nextSibling) { if (c !== items[n]) throw 'order'; n++; } n",
+            )
+            .unwrap();
+        assert_eq!(count, "100");
+        assert!(engine.execute_script("document.createElement('img').append(document.createElement('b'))").is_err());
+    }
+
+    #[test]
+    fn test_attribute_observer() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        type Change = (dom::NodeId, String, Option<String>, Option<String>);
+        let changes: Rc<RefCell<Vec<Change>>> = Rc::default();
+        let mut doc = html::parse_html("<div id='a' title='old' class='x'></div>");
+        let div = doc.id_map["a"];
+        let sink = changes.clone();
+        doc.attribute_observer = Some(Box::new(move |id, name, old, new| {
+            sink.borrow_mut().push((id, name.to_string(), old.map(str::to_string), new.map(str::to_string)));
+        }));
+
+        let engine = js::JsEngine::try_new(doc).expect("JsEngine::try_new");
+        engine
+            .execute_script(
+                "const d = document.getElementById('a'); \
+                 d.setAttribute('title', 'new'); d.setAttribute('data-k', 'v'); \
+                 d.setAttribute('class', 'y'); d.setAttribute('id', 'b');",
+            )
+            .unwrap();
+        let expect = |name: &str, old: Option<&str>, new: &str| {
+            (div, name.to_string(), old.map(str::to_string), Some(new.to_string()))
+        };
+        assert_eq!(
+            *changes.borrow(),
+            [
+                expect("title", Some("old"), "new"),
+                expect("data-k", None, "v"),
+                expect("class", Some("x"), "y"),
+                expect("id", Some("a"), "b"),
+            ]
+        );
+        let doc = engine.document.borrow();
+        assert_eq!(doc.id_map.get("b"), Some(&div));
+        assert!(!doc.id_map.contains_key("a"));
+        assert_eq!(doc.get_attribute(div, "class").as_deref(), Some("y"));
+    }
+
+    #[test]
+    fn test_attribute_api_keeps_indexes_consistent() {
+        let mut doc = html::parse_html("<div id='a' class='x' style='width: 5px' title='t'></div>");
+        let div = doc.id_map["a"];
+        let sheet = css::parse_stylesheet(".y { display: none; }");
+
+        assert!(doc.set_attribute(div, "id", "b".into()));
+        assert!(doc.set_attribute(div, "class", "y z".into()));
+        assert_eq!(doc.id_map.get("b"), Some(&div));
+        assert!(!doc.id_map.contains_key("a"));
+        css::compute_styles(&mut doc, &sheet);
+        let Some(dom::Node::Element(data)) = doc.nodes.get(div) else { panic!() };
+        assert_eq!(data.id(), Some("b"));
+        assert_eq!(data.classes, "y z");
+        assert_eq!(data.computed.display, dom::DisplayKeyword::None);
+
+        for key in ["id", "class", "style", "title"] {
+            assert!(doc.remove_attribute(div, key));
+            assert!(!doc.remove_attribute(div, key));
+            assert_eq!(doc.get_attribute(div, key), None);
+        }
+        assert!(doc.id_map.is_empty());
+        css::compute_styles(&mut doc, &sheet);
+        let Some(dom::Node::Element(data)) = doc.nodes.get(div) else { panic!() };
+        assert_eq!(data.id(), None);
+        assert!(data.classes.is_empty() && data.attributes.is_empty());
+        assert_ne!(data.computed.display, dom::DisplayKeyword::None);
+        assert_eq!(doc.validate(), Ok(()));
+
+        let engine = js::JsEngine::try_new(html::parse_html("<p id='p' data-user-id='1'></p>")).unwrap();
+        let out = engine
+            .execute_script(
+                "const p = document.getElementById('p'); delete p.dataset.userId; \
+                 p.removeAttribute('id'); [p.getAttribute('data-user-id'), document.getElementById('p')].join()",
+            )
+            .unwrap();
+        assert_eq!(out, ",");
+    }
+
+    #[test]
+    fn test_insert_before_positions() {
+        let mut doc = html::parse_html("<ul id='l'><li id='a'></li><li id='b'></li><li id='c'></li></ul><p id='p'><i id='x'></i></p>");
+        let id = |doc: &dom::Document, name: &str| doc.id_map[name];
+        let (list, a, b, c, p, x) = (id(&doc, "l"), id(&doc, "a"), id(&doc, "b"), id(&doc, "c"), id(&doc, "p"), id(&doc, "x"));
+        let order = |doc: &dom::Document, parent| -> Vec<dom::NodeId> {
+            std::iter::successors(doc.first_child_of(parent), |&n| doc.next_sibling_of(n)).collect()
+        };
+        let new = |doc: &mut dom::Document| doc.add_node(dom::Node::Element(dom::ElementData::new(dom::LocalName::new("li"))));
+
+        // Fresh nodes before the first child, before the last child, and at the end.
+        let first = new(&mut doc);
+        assert!(doc.insert_before(list, first, Some(a)));
+        assert_eq!(doc.validate(), Ok(()));
+        let before_last = new(&mut doc);
+        assert!(doc.insert_before(list, before_last, Some(c)));
+        assert_eq!(doc.validate(), Ok(()));
+        let end = new(&mut doc);
+        assert!(doc.insert_before(list, end, None));
+        assert_eq!(doc.validate(), Ok(()));
+        assert_eq!(order(&doc, list), [first, a, b, before_last, c, end]);
+        assert_eq!(doc.last_child_of(list), Some(end));
+
+        // Moving attached nodes within the parent: last to first, first to last, and onto itself.
+        assert!(doc.insert_before(list, end, Some(first)));
+        assert_eq!(doc.validate(), Ok(()));
+        assert!(doc.insert_before(list, end, None));
+        assert_eq!(doc.validate(), Ok(()));
+        assert!(doc.insert_before(list, b, Some(b)));
+        assert!(doc.insert_before(list, c, Some(before_last)));
+        assert_eq!(doc.validate(), Ok(()));
+        assert_eq!(order(&doc, list), [first, a, b, c, before_last, end]);
+
+        // Moving an only child from another parent, and into an emptied one.
+        assert!(doc.insert_before(list, x, Some(a)));
+        assert_eq!(doc.validate(), Ok(()));
+        assert_eq!(doc.first_child_of(p), None);
+        assert!(doc.insert_before(p, a, None));
+        assert_eq!(doc.validate(), Ok(()));
+        assert_eq!(order(&doc, list), [first, x, b, c, before_last, end]);
+        assert_eq!((doc.first_child_of(p), doc.last_child_of(p)), (Some(a), Some(a)));
+
+        // Rejected: a reference from another parent, and cycles.
+        assert!(!doc.insert_before(list, b, Some(a)));
+        assert!(!doc.insert_before(x, list, None));
+        assert_eq!(doc.validate(), Ok(()));
+        assert_eq!(order(&doc, list), [first, x, b, c, before_last, end]);
+    }
+
+    #[test]
+    fn test_document_validate() {
+        let mut doc = html::parse_html("<ul id='l'><li>a</li><li>b</li></ul><div id='d'>x<b>y</b>z</div>");
+        css::compute_styles(&mut doc, &css::parse_stylesheet("div::after { content: \"!\"; }"));
+        let (list, div) = (doc.id_map["l"], doc.id_map["d"]);
+        let items: Vec<_> = std::iter::successors(doc.first_child_of(list), |&c| doc.next_sibling_of(c)).collect();
+        assert!(doc.append_children(div, &items));
+        let x = doc.first_child_of(div).unwrap();
+        doc.remove_child(div, x);
+        doc.set_text_content(list, "empty");
+        assert_eq!(doc.validate(), Ok(()));
+
+        let b = doc.first_child_of(div).unwrap();
+        let z = doc.next_sibling_of(b).unwrap();
+        let set = |doc: &mut dom::Document, id, f: &dyn Fn(&mut dom::ElementData)| {
+            if let Some(dom::Node::Element(d)) = doc.nodes.get_mut(id) {
+                f(d)
+            }
+        };
+        if let Some(dom::Node::Text(t)) = doc.nodes.get_mut(z) {
+            t.prev_sibling = None;
+        }
+        let err = doc.validate().unwrap_err();
+        assert!(err.contains("prev_sibling"), "{err}");
+        if let Some(dom::Node::Text(t)) = doc.nodes.get_mut(z) {
+            t.prev_sibling = Some(b);
+        }
+
+        set(&mut doc, div, &|d| d.last_child = Some(b));
+        let err = doc.validate().unwrap_err();
+        assert!(err.contains("last_child"), "{err}");
+
+        // `b` names itself as parent.
+        set(&mut doc, div, &|d| d.last_child = Some(items[1]));
+        set(&mut doc, b, &|d| d.parent = Some(b));
+        let err = doc.validate().unwrap_err();
+        assert!(err.contains("parent"), "{err}");
+    }
+
     #[test]
     fn test_utf8_truncation_safety() {
         use crate::dom::MAX_ATTRIBUTE_VALUE_LEN;
@@ -4,8 +4,33 @@
 //! explicit stack to prevent overflow on deep DOM trees. Issues draw commands
 //! to an abstract renderer backend. Text is rendered via pre-shaped
 //! `cosmic_text::LayoutGlyph` iterators rather than raw strings.
-//! Draw properties (`bg_color`, `border_color`, `font_size`, `color`) are
-//! read directly from `ComputedStyle` embedded in each arena node.
+//! Draw properties (`bg_color`, `border_color`, `outline_*`, `font_size`, `color`)
+//! are read directly from `ComputedStyle` embedded in each arena node. Outlines
+//! are stroked outside the layout box and never affect geometry.
+//! Children are painted in document order even when `order` places flex or
+//! grid items elsewhere.
+//! `box-shadow` entries go to `draw_shadow` with the border box they are
+//! cast from: outer shadows before the background, inset ones after it,
+//! each list bottommost first.
+//! `transform` brackets an element's subtree with `push_transform` /
+//! `pop_transform`; it changes paint position only, never layout.
+//! `<img>` boxes are passed to `draw_image` after `object-fit`, using the
+//! `width`/`height` attributes as the natural size; images overflowing the
+//! box are bracketed with `push_clip` / `pop_clip`.
+//! A text node's `::first-letter` span is drawn as its own glyph run, with
+//! the span's size and color.
+//! Descendants of a `content-visibility: hidden` element are not painted.
+//! `visibility: hidden` skips an element's own box and text but not its
+//! subtree (the value is inherited, so descendants are hidden unless they
+//! reset it). `opacity` is multiplied down the walk and applied to each
+//! draw's alpha; there are no offscreen groups, so overlapping descendants
+//! of a translucent element blend with each other.
+//! Scroll containers (`overflow` other than `visible`) bracket their children
+//! with `push_clip` / `pop_clip` on the padding box and shift them by
+//! `Document::scroll_offset`.
+//! [`build_display_list`] records the same walk as a flat `Vec<DrawCommand>`
+//! that hosts can cache, diff between frames and replay with
+//! [`replay_display_list`]; [`draw_layout_tree`] is exactly that pair.
 //! `inoda-core` does not depend on any graphics APIs; platform binaries
 //! implement the `RendererBackend` trait using their own raster target.
 //! The renderer is decoupled from the shaping system, receiving pre-shaped
@@ -15,6 +40,8 @@
 use cosmic_text::Buffer;
 use std::collections::HashMap;
 
+pub use crate::dom::Transform;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Color {
     pub r: u8,
@@ -23,6 +50,53 @@ pub struct Color {
     pub a: u8,
 }
 
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::dom::write_css_color(f, (self.r, self.g, self.b, self.a))
+    }
+}
+
+impl Color {
+    /// CSS text for this color: `#rrggbb`, or `rgba(...)` when translucent.
+    pub fn to_css_string(&self) -> String {
+        self.to_string()
+    }
+
+    /// This color composited over `background` with source-over blending on
+    /// straight (non-premultiplied) alpha, for backends that cannot draw
+    /// translucent fills themselves. Fully transparent results are
+    /// transparent black.
+    pub fn blend_over(&self, background: Color) -> Color {
+        let src_a = self.a as f32 / 255.0;
+        let dst_a = background.a as f32 / 255.0 * (1.0 - src_a);
+        let out_a = src_a + dst_a;
+        if out_a <= 0.0 {
+            return Color { r: 0, g: 0, b: 0, a: 0 };
+        }
+        let channel = |src: u8, dst: u8| {
+            ((src as f32 * src_a + dst as f32 * dst_a) / out_a).round().clamp(0.0, 255.0) as u8
+        };
+        Color {
+            r: channel(self.r, background.r),
+            g: channel(self.g, background.g),
+            b: channel(self.b, background.b),
+            a: (out_a * 255.0).round().clamp(0.0, 255.0) as u8,
+        }
+    }
+}
+
+/// A resolved `box-shadow` entry for `RendererBackend::draw_shadow`, with
+/// lengths in px and `currentColor` and `opacity` already applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shadow {
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub blur: f32,
+    pub spread: f32,
+    pub color: Color,
+    pub inset: bool,
+}
+
 pub trait RendererBackend {
     fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: Color);
     fn stroke_rect(&mut self, x: f32, y: f32, w: f32, h: f32, line_width: f32, color: Color);
@@ -35,66 +109,350 @@ pub trait RendererBackend {
         color: Color,
     );
     fn draw_image(&mut self, _x: f32, _y: f32, _w: f32, _h: f32, _url: &str) {}
+    /// Paint `shadow` cast by the border box `(x, y, w, h)`. An outer shadow
+    /// is the box moved by the offsets, grown by `spread` and blurred by
+    /// `blur`; an inset one is drawn inside the box. Backends without blur
+    /// may fill the spread rectangle.
+    fn draw_shadow(&mut self, _x: f32, _y: f32, _w: f32, _h: f32, _shadow: &Shadow) {}
+    /// Apply `transform` (in absolute coordinates) to everything drawn until the
+    /// matching `pop_transform`, composed inside any transform already active.
+    fn push_transform(&mut self, _transform: &Transform) {}
+    fn pop_transform(&mut self) {}
+    /// Clip everything drawn until the matching `pop_clip` to the rectangle,
+    /// intersected with any clip already active. Given in the same space as
+    /// draw calls, so any active transform applies to it.
+    fn push_clip(&mut self, _x: f32, _y: f32, _w: f32, _h: f32) {}
+    fn pop_clip(&mut self) {}
+    /// Width and line height of `text` set on one line at `size` px, for
+    /// backends whose fonts differ from cosmic-text's. `None` (the default)
+    /// keeps cosmic-text's metrics; only `layout::compute_layout_with_metrics`
+    /// asks.
+    fn measure_text(&self, _text: &str, _size: f32) -> Option<(f32, f32)> {
+        None
+    }
+}
+
+/// One recorded backend call, in absolute coordinates. Each variant mirrors
+/// the `RendererBackend` method of the same name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawCommand {
+    FillRect { x: f32, y: f32, w: f32, h: f32, color: Color },
+    StrokeRect { x: f32, y: f32, w: f32, h: f32, line_width: f32, color: Color },
+    Text { x: f32, y: f32, glyphs: Glyphs, size: f32, color: Color },
+    Image { x: f32, y: f32, w: f32, h: f32, url: String },
+    Shadow { x: f32, y: f32, w: f32, h: f32, shadow: Shadow },
+    PushTransform(Transform),
+    PopTransform,
+    PushClip { x: f32, y: f32, w: f32, h: f32 },
+    PopClip,
+}
+
+/// An owned glyph run for [`DrawCommand::Text`]. `LayoutGlyph` has no
+/// `PartialEq`, so runs compare by font, glyph id, size and position.
+#[derive(Debug, Clone)]
+pub struct Glyphs(pub Vec<cosmic_text::LayoutGlyph>);
+
+impl PartialEq for Glyphs {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(&other.0).all(|(a, b)| {
+                (a.font_id, a.glyph_id, a.start, a.end) == (b.font_id, b.glyph_id, b.start, b.end)
+                    && (a.font_size, a.x, a.y, a.w, a.x_offset, a.y_offset)
+                        == (b.font_size, b.x, b.y, b.w, b.x_offset, b.y_offset)
+            })
+    }
+}
+
+/// Collects backend calls as `DrawCommand`s.
+struct DisplayListRecorder(Vec<DrawCommand>);
+
+impl RendererBackend for DisplayListRecorder {
+    fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: Color) {
+        self.0.push(DrawCommand::FillRect { x, y, w, h, color });
+    }
+    fn stroke_rect(&mut self, x: f32, y: f32, w: f32, h: f32, line_width: f32, color: Color) {
+        self.0.push(DrawCommand::StrokeRect { x, y, w, h, line_width, color });
+    }
+    fn draw_glyphs(&mut self, x: f32, y: f32, glyphs: &[cosmic_text::LayoutGlyph], size: f32, color: Color) {
+        self.0.push(DrawCommand::Text { x, y, glyphs: Glyphs(glyphs.to_vec()), size, color });
+    }
+    fn draw_image(&mut self, x: f32, y: f32, w: f32, h: f32, url: &str) {
+        self.0.push(DrawCommand::Image { x, y, w, h, url: url.to_string() });
+    }
+    fn draw_shadow(&mut self, x: f32, y: f32, w: f32, h: f32, shadow: &Shadow) {
+        self.0.push(DrawCommand::Shadow { x, y, w, h, shadow: *shadow });
+    }
+    fn push_transform(&mut self, transform: &Transform) {
+        self.0.push(DrawCommand::PushTransform(*transform));
+    }
+    fn pop_transform(&mut self) {
+        self.0.push(DrawCommand::PopTransform);
+    }
+    fn push_clip(&mut self, x: f32, y: f32, w: f32, h: f32) {
+        self.0.push(DrawCommand::PushClip { x, y, w, h });
+    }
+    fn pop_clip(&mut self) {
+        self.0.push(DrawCommand::PopClip);
+    }
+}
+
+/// Render stack entry. The pop entries close a transformed or clipped
+/// element's subtree; `Node`'s last field is the product of the ancestors'
+/// `opacity`.
+enum Entry {
+    Node(crate::dom::NodeId, taffy::NodeId, f32, f32, f32),
+    PopTransform,
+    PopClip,
+}
+
+/// `color` with its alpha scaled by `opacity`.
+fn faded((r, g, b, a): (u8, u8, u8, u8), opacity: f32) -> Color {
+    Color { r, g, b, a: (a as f32 * opacity).round() as u8 }
+}
+
+/// Issues the element's `inset` (or outer) shadows, bottommost first.
+fn draw_shadows(
+    renderer: &mut impl RendererBackend,
+    computed: &crate::dom::ComputedStyle,
+    (x, y, w, h): (f32, f32, f32, f32),
+    opacity: f32,
+    inset: bool,
+) {
+    for shadow in computed.box_shadow.iter().rev().filter(|s| s.inset == inset) {
+        let shadow = Shadow {
+            offset_x: shadow.offset_x,
+            offset_y: shadow.offset_y,
+            blur: shadow.blur,
+            spread: shadow.spread,
+            color: faded(shadow.color.unwrap_or(computed.color), opacity),
+            inset,
+        };
+        renderer.draw_shadow(x, y, w, h, &shadow);
+    }
+}
+
+/// Destination rectangle, relative to a `w`x`h` box, for an image of
+/// `natural` size under `fit`, centered as with the default
+/// `object-position`. Without a natural size the image fills the box.
+fn object_fit_rect(
+    fit: crate::dom::ObjectFitKeyword,
+    w: f32,
+    h: f32,
+    natural: Option<(f32, f32)>,
+) -> (f32, f32, f32, f32) {
+    let Some((nw, nh)) = natural.filter(|&(nw, nh)| nw > 0.0 && nh > 0.0) else {
+        return (0.0, 0.0, w, h);
+    };
+    let contain = (w / nw).min(h / nh);
+    let scale = match fit {
+        crate::dom::ObjectFitKeyword::Fill => return (0.0, 0.0, w, h),
+        crate::dom::ObjectFitKeyword::Contain => contain,
+        crate::dom::ObjectFitKeyword::Cover => (w / nw).max(h / nh),
+        crate::dom::ObjectFitKeyword::None => 1.0,
+        crate::dom::ObjectFitKeyword::ScaleDown => contain.min(1.0),
+    };
+    let (dw, dh) = (nw * scale, nh * scale);
+    ((w - dw) / 2.0, (h - dh) / 2.0, dw, dh)
 }
 
+/// Paints the subtree at `root_node_id`: builds its display list and replays
+/// it into `renderer`.
 pub fn draw_layout_tree<R: RendererBackend>(
     renderer: &mut R,
     document: &crate::dom::Document,
-    layout_tree: &taffy::TaffyTree,
+    layout_tree: &taffy::TaffyTree<crate::dom::TextMeasureContext>,
     root_node_id: crate::dom::NodeId,
     root_layout_node_id: taffy::NodeId,
     root_offset_x: f32,
     root_offset_y: f32,
     buffer_cache: &HashMap<crate::dom::NodeId, Buffer>,
 ) {
+    let list = build_display_list(
+        document,
+        layout_tree,
+        root_node_id,
+        root_layout_node_id,
+        root_offset_x,
+        root_offset_y,
+        buffer_cache,
+    );
+    replay_display_list(renderer, &list);
+}
+
+/// Issues each command to `renderer` in order.
+pub fn replay_display_list<R: RendererBackend>(renderer: &mut R, commands: &[DrawCommand]) {
+    for command in commands {
+        match command {
+            DrawCommand::FillRect { x, y, w, h, color } => renderer.fill_rect(*x, *y, *w, *h, *color),
+            DrawCommand::StrokeRect { x, y, w, h, line_width, color } => {
+                renderer.stroke_rect(*x, *y, *w, *h, *line_width, *color)
+            }
+            DrawCommand::Text { x, y, glyphs, size, color } => renderer.draw_glyphs(*x, *y, &glyphs.0, *size, *color),
+            DrawCommand::Image { x, y, w, h, url } => renderer.draw_image(*x, *y, *w, *h, url),
+            DrawCommand::Shadow { x, y, w, h, shadow } => renderer.draw_shadow(*x, *y, *w, *h, shadow),
+            DrawCommand::PushTransform(transform) => renderer.push_transform(transform),
+            DrawCommand::PopTransform => renderer.pop_transform(),
+            DrawCommand::PushClip { x, y, w, h } => renderer.push_clip(*x, *y, *w, *h),
+            DrawCommand::PopClip => renderer.pop_clip(),
+        }
+    }
+}
+
+/// The draw calls for the subtree at `root_node_id`, in paint order, as
+/// `draw_layout_tree` would issue them. Glyph runs are copied out of the
+/// buffer cache so the list outlives it.
+pub fn build_display_list(
+    document: &crate::dom::Document,
+    layout_tree: &taffy::TaffyTree<crate::dom::TextMeasureContext>,
+    root_node_id: crate::dom::NodeId,
+    root_layout_node_id: taffy::NodeId,
+    root_offset_x: f32,
+    root_offset_y: f32,
+    buffer_cache: &HashMap<crate::dom::NodeId, Buffer>,
+) -> Vec<DrawCommand> {
+    let mut recorder = DisplayListRecorder(Vec::new());
+    let renderer = &mut recorder;
     // Reusable scratch buffer for collecting child tuples — avoids a
     // per-element `Vec::new()` allocation on every iteration (Item 6).
-    let mut children_buf: Vec<(crate::dom::NodeId, taffy::NodeId, f32, f32)> = Vec::new();
-    let mut stack = vec![(root_node_id, root_layout_node_id, root_offset_x, root_offset_y)];
+    let mut children_buf: Vec<(crate::dom::NodeId, taffy::NodeId, f32, f32, f32)> = Vec::new();
+    let mut stack = vec![Entry::Node(root_node_id, root_layout_node_id, root_offset_x, root_offset_y, 1.0)];
 
-    while let Some((node_id, layout_node_id, offset_x, offset_y)) = stack.pop() {
+    while let Some(entry) = stack.pop() {
+        let (node_id, layout_node_id, offset_x, offset_y, parent_opacity) = match entry {
+            Entry::Node(node_id, layout_node_id, x, y, opacity) => (node_id, layout_node_id, x, y, opacity),
+            Entry::PopTransform => {
+                renderer.pop_transform();
+                continue;
+            }
+            Entry::PopClip => {
+                renderer.pop_clip();
+                continue;
+            }
+        };
         if let Ok(layout) = layout_tree.layout(layout_node_id) {
             let abs_x = offset_x + layout.location.x;
             let abs_y = offset_y + layout.location.y;
 
             match document.nodes.get(node_id) {
                 Some(crate::dom::Node::Element(data)) => {
-                    // Skip display:none elements and their entire subtree
-                    if data.computed.display == crate::dom::DisplayKeyword::None {
+                    // Skip display:none elements and their entire subtree, as
+                    // well as collapsed flex items, which layout removed.
+                    if data.computed.display == crate::dom::DisplayKeyword::None
+                        || layout_tree.style(layout_node_id).is_ok_and(|s| s.display == taffy::style::Display::None)
+                    {
+                        continue;
+                    }
+                    let opacity = parent_opacity * data.computed.opacity;
+                    if opacity <= 0.0 {
                         continue;
                     }
+                    // `visibility` is inherited through the cascade, so a hidden
+                    // element only skips its own box; visible descendants still paint.
+                    let visible = data.computed.visibility == crate::dom::VisibilityKeyword::Visible;
+
+                    if let Some(t) = &data.computed.transform {
+                        // Transform about the border-box centre, resolved to
+                        // absolute coordinates. Layout is unaffected.
+                        let origin_x = abs_x + layout.size.width / 2.0;
+                        let origin_y = abs_y + layout.size.height / 2.0;
+                        renderer.push_transform(&Transform {
+                            translate_x: origin_x - t.scale_x * origin_x + t.translate_x,
+                            translate_y: origin_y - t.scale_y * origin_y + t.translate_y,
+                            scale_x: t.scale_x,
+                            scale_y: t.scale_y,
+                        });
+                        stack.push(Entry::PopTransform);
+                    }
 
-                    if let Some((r, g, b, a)) = data.computed.bg_color {
+                    let border_box = (abs_x, abs_y, layout.size.width, layout.size.height);
+                    if visible {
+                        draw_shadows(renderer, &data.computed, border_box, opacity, false);
+                    }
+
+                    if let Some(color) = data.computed.bg_color.filter(|_| visible) {
                         renderer.fill_rect(
                             abs_x,
                             abs_y,
                             layout.size.width,
                             layout.size.height,
-                            Color { r, g, b, a },
+                            faded(color, opacity),
                         );
                     }
 
-                    if let Some((r, g, b, a)) = data.computed.border_color {
+                    if visible {
+                        draw_shadows(renderer, &data.computed, border_box, opacity, true);
+                    }
+
+                    if let Some(color) = data.computed.border_color.filter(|_| visible) {
                         renderer.stroke_rect(
                             abs_x,
                             abs_y,
                             layout.size.width,
                             layout.size.height,
                             1.0,
-                            Color { r, g, b, a },
+                            faded(color, opacity),
                         );
                     }
 
-                    if &*data.tag_name == "img" {
+                    if visible && data.computed.outline_style != crate::dom::OutlineStyleKeyword::None {
+                        // The cascade resolves every unit to px.
+                        let width = match data.computed.outline_width {
+                            crate::dom::StyleValue::LengthPx(px) => px,
+                            _ => 0.0,
+                        };
+                        if width > 0.0 {
+                            let color = data.computed.outline_color.unwrap_or(data.computed.color);
+                            // Centre the stroke half a width outside the border box so
+                            // the painted band sits entirely outside it.
+                            let inset = width / 2.0;
+                            renderer.stroke_rect(
+                                abs_x - inset,
+                                abs_y - inset,
+                                layout.size.width + width,
+                                layout.size.height + width,
+                                width,
+                                faded(color, opacity),
+                            );
+                        }
+                    }
+
+                    if visible && &*data.tag_name == "img" {
                         if let Some((_, src)) = data.attributes.iter().find(|(k, _)| k == "src") {
-                            renderer.draw_image(abs_x, abs_y, layout.size.width, layout.size.height, src);
+                            let (w, h) = (layout.size.width, layout.size.height);
+                            let natural = crate::layout::image_attribute_size(data);
+                            let (dx, dy, dw, dh) = object_fit_rect(data.computed.object_fit, w, h, natural);
+                            // Overflowing images (`cover`, `none`) are cropped to the box.
+                            let crop = dw > w || dh > h;
+                            if crop {
+                                renderer.push_clip(abs_x, abs_y, w, h);
+                            }
+                            renderer.draw_image(abs_x + dx, abs_y + dy, dw, dh, src);
+                            if crop {
+                                renderer.pop_clip();
+                            }
                         }
                     }
 
+                    // `content-visibility: hidden` paints the box but not its contents.
+                    if data.computed.content_visibility == crate::dom::ContentVisibilityKeyword::Hidden {
+                        continue;
+                    }
+
+                    let (scroll_x, scroll_y) = document.scroll_offset(node_id);
+                    if data.computed.overflow.iter().any(|&k| k != crate::dom::OverflowKeyword::Visible) {
+                        let border = layout.border;
+                        renderer.push_clip(
+                            abs_x + border.left,
+                            abs_y + border.top,
+                            layout.size.width - border.left - border.right,
+                            layout.size.height - border.top - border.bottom,
+                        );
+                        stack.push(Entry::PopClip);
+                    }
+
                     // Collect children into the reusable scratch buffer
                     children_buf.clear();
-                    let mut dom_child_id = document.first_child_of(node_id);
-                    while let Some(c) = dom_child_id {
+                    for c in document.box_children(node_id) {
                         let t_node = match document.nodes.get(c) {
                             Some(crate::dom::Node::Element(d)) => d.taffy_node,
                             Some(crate::dom::Node::Text(d)) => d.taffy_node,
@@ -103,37 +461,43 @@ pub fn draw_layout_tree<R: RendererBackend>(
                         };
 
                         if let Some(tn) = t_node {
-                            children_buf.push((c, tn, abs_x, abs_y));
+                            let (ax, ay) = crate::layout::anonymous_box_offset(layout_tree, layout_node_id, tn);
+                            children_buf.push((c, tn, abs_x + ax - scroll_x, abs_y + ay - scroll_y, opacity));
                         }
-                        dom_child_id = document.next_sibling_of(c);
                     }
 
                     // Push in reverse order so that the first child is popped first
-                    for child in children_buf.iter().rev() {
-                        stack.push(*child);
+                    for &(c, tn, x, y, opacity) in children_buf.iter().rev() {
+                        stack.push(Entry::Node(c, tn, x, y, opacity));
                     }
                 }
                 Some(crate::dom::Node::Text(data)) => {
+                    if data.computed.visibility != crate::dom::VisibilityKeyword::Visible {
+                        continue;
+                    }
                     let Some(buffer) = buffer_cache.get(&node_id) else { continue; };
-                    let color = Color {
-                        r: data.computed.color.0,
-                        g: data.computed.color.1,
-                        b: data.computed.color.2,
-                        a: data.computed.color.3,
-                    };
+                    let color = faded(data.computed.color, parent_opacity);
 
                     for run in buffer.layout_runs() {
-                        renderer.draw_glyphs(
-                            abs_x,
-                            abs_y + run.line_y,
-                            run.glyphs,
-                            data.computed.font_size,
-                            color,
-                        );
+                        let spaced = crate::layout::spaced_glyphs(&run, &data.computed);
+                        let glyphs = spaced.as_deref().unwrap_or(run.glyphs);
+                        if data.first_letter.is_none() {
+                            renderer.draw_glyphs(abs_x, abs_y + run.line_y, glyphs, data.computed.font_size, color);
+                            continue;
+                        }
+                        // The `::first-letter` span carries its own size and color.
+                        for span in glyphs.chunk_by(|a, b| (a.font_size, a.color_opt) == (b.font_size, b.color_opt)) {
+                            let color = match span[0].color_opt {
+                                Some(c) => faded(c.as_rgba_tuple(), parent_opacity),
+                                None => color,
+                            };
+                            renderer.draw_glyphs(abs_x, abs_y + run.line_y, span, span[0].font_size, color);
+                        }
                     }
                 }
                 _ => continue,
             }
         }
     }
+    recorder.0
 }